@@ -0,0 +1,84 @@
+use bevy::window::WindowResolution;
+use bevy::{prelude::*, render::render_resource::*};
+use bevy_terrain::prelude::*;
+
+const RADIUS: f64 = 6371000.0;
+
+/// Tints the terrain by its height using the gradient and shades its relief.
+fn topographic_material(
+    gradient: Handle<Image>,
+    contours: Option<ContourLines>,
+) -> TerrainTopographicMaterial {
+    TerrainTopographicMaterial {
+        base: TerrainPbrMaterial::default(),
+        extension: TerrainTopographicExtension {
+            gradient: Some(gradient),
+            contours,
+            hillshade: Some(Hillshade::default()),
+            ..default()
+        },
+    }
+}
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        resolution: WindowResolution::new(1920.0, 1080.0),
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .build()
+                .disable::<TransformPlugin>(),
+            TerrainPlugin,
+            TerrainMaterialPlugin::<TerrainTopographicMaterial>::default(),
+            TerrainDebugPlugin, // enable debug settings and controls
+            TerrainPickingPlugin,
+        ))
+        .insert_resource(TerrainSettings::new(vec![]))
+        .add_systems(Startup, initialize)
+        .run();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn initialize(
+    mut commands: Commands,
+    mut images: ResMut<LoadingImages>,
+    asset_server: Res<AssetServer>,
+) {
+    let gradient1 = asset_server.load("textures/gradient1.png");
+    images.load_image(
+        &gradient1,
+        TextureDimension::D2,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    let mut view = Entity::PLACEHOLDER;
+
+    commands.spawn_big_space(Grid::default(), |root| {
+        view = root
+            .spawn_spatial((
+                Transform::from_translation(-Vec3::X * RADIUS as f32 * 3.0)
+                    .looking_to(Vec3::X, Vec3::Y),
+                DebugCameraController::new(RADIUS),
+                OrbitalCameraController::default(),
+            ))
+            .id();
+    });
+
+    commands.spawn_terrain(
+        asset_server.load("terrains/earth/config.tc.ron"),
+        TerrainViewConfig::default(),
+        topographic_material(
+            gradient1.clone(),
+            Some(ContourLines {
+                interval: 1000.0,
+                ..default()
+            }),
+        ),
+        view,
+    );
+}
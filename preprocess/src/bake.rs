@@ -0,0 +1,221 @@
+use crate::{
+    derive::{DerivedLayer, HeightGrid, bake_curvature_tile},
+    gdal_extension::{CountingProgressCallback, ProgressCallback},
+    result::{PreprocessError, PreprocessResult},
+};
+use bevy_terrain::prelude::*;
+use gdal::{
+    DriverManager,
+    raster::{Buffer, ColorInterpretation, GdalType, RasterCreationOptions},
+};
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::{fs, iter, path::Path};
+
+/// Bakes the material rules into the tiles of a splat attachment, based on the height attachment.
+/// Each tile is baked independently, using its neighbours for the slope at the border
+/// and its ancestors as a fallback.
+pub(crate) fn bake_rules(
+    config: &TerrainConfig,
+    rules: &TerrainMaterialRules,
+    terrain_path: &Path,
+    label: &AttachmentLabel,
+    attachment: &AttachmentConfig,
+    progress_callback: Option<&ProgressCallback>,
+) -> PreprocessResult<()> {
+    bake_tiles(
+        config,
+        terrain_path,
+        label,
+        attachment,
+        [
+            ColorInterpretation::RedBand,
+            ColorInterpretation::GreenBand,
+            ColorInterpretation::BlueBand,
+            ColorInterpretation::AlphaBand,
+        ],
+        |tile_atlas, tile| rules.bake_tile(tile_atlas, tile, attachment),
+        progress_callback,
+    )
+}
+
+/// Bakes the normals of the height attachment into the tiles of a normal attachment.
+/// Each tile is baked independently, using its neighbours for the normals at the border
+/// (including the neighbours on adjacent cube faces) and its ancestors as a fallback.
+pub(crate) fn bake_normals(
+    config: &TerrainConfig,
+    terrain_path: &Path,
+    label: &AttachmentLabel,
+    attachment: &AttachmentConfig,
+    progress_callback: Option<&ProgressCallback>,
+) -> PreprocessResult<()> {
+    bake_tiles(
+        config,
+        terrain_path,
+        label,
+        attachment,
+        [ColorInterpretation::Undefined; 2],
+        |tile_atlas, tile| tile_atlas.bake_normal_tile(tile, attachment),
+        progress_callback,
+    )
+}
+
+/// Bakes a layer derived from the height attachment into the tiles of a new attachment.
+/// The occlusion and flow layers take the heights within the `radius` (in pixels) around each tile into account,
+/// which may not exceed the size of the neighbouring tiles.
+pub(crate) fn bake_derived(
+    config: &TerrainConfig,
+    terrain_path: &Path,
+    layer: DerivedLayer,
+    radius: u32,
+    label: &AttachmentLabel,
+    attachment: &AttachmentConfig,
+    progress_callback: Option<&ProgressCallback>,
+) -> PreprocessResult<()> {
+    if radius > attachment.center_size() {
+        return Err(PreprocessError::RadiusTooLarge {
+            radius,
+            center_size: attachment.center_size(),
+        });
+    }
+
+    let shape = config.shape;
+
+    match layer {
+        DerivedLayer::Occlusion => bake_tiles(
+            config,
+            terrain_path,
+            label,
+            attachment,
+            [ColorInterpretation::GrayIndex],
+            |tile_atlas, tile| {
+                HeightGrid::from_tile(tile_atlas, shape, tile, attachment, radius)
+                    .occlusion(radius)
+                    .into_iter()
+                    .map(|visibility| [(visibility * u8::MAX as f32).round() as u8])
+                    .collect()
+            },
+            progress_callback,
+        ),
+        DerivedLayer::Curvature => bake_tiles(
+            config,
+            terrain_path,
+            label,
+            attachment,
+            [ColorInterpretation::Undefined],
+            |tile_atlas, tile| {
+                bake_curvature_tile(tile_atlas, shape, tile, attachment)
+                    .into_iter()
+                    .map(|curvature| [curvature])
+                    .collect()
+            },
+            progress_callback,
+        ),
+        DerivedLayer::Flow => bake_tiles(
+            config,
+            terrain_path,
+            label,
+            attachment,
+            [ColorInterpretation::Undefined],
+            |tile_atlas, tile| {
+                HeightGrid::from_tile(tile_atlas, shape, tile, attachment, radius)
+                    .flow()
+                    .into_iter()
+                    .map(|area| [area])
+                    .collect()
+            },
+            progress_callback,
+        ),
+    }
+}
+
+fn bake_tiles<T: GdalType + Copy + Send, const N: usize>(
+    config: &TerrainConfig,
+    terrain_path: &Path,
+    label: &AttachmentLabel,
+    attachment: &AttachmentConfig,
+    color_interpretations: [ColorInterpretation; N],
+    bake_tile: impl Fn(&TileAtlas, TileCoordinate) -> Vec<[T; N]> + Sync,
+    progress_callback: Option<&ProgressCallback>,
+) -> PreprocessResult<()> {
+    let progress_callback =
+        CountingProgressCallback::new(config.tiles.len() as u64, progress_callback);
+
+    let spherical = config.shape.is_spherical();
+    let tile_dir = terrain_path.join(String::from(label));
+
+    let mut height_config = config.clone();
+    height_config
+        .attachments
+        .retain(|label, _| *label == AttachmentLabel::Height);
+
+    config.tiles.par_iter().try_for_each(|&tile| {
+        let tiles = iter::once(tile)
+            .chain(
+                tile.neighbours(spherical)
+                    .map(|(neighbour, _)| neighbour)
+                    .filter(|&neighbour| neighbour != TileCoordinate::INVALID),
+            )
+            .chain(iter::successors(tile.parent(), |tile| tile.parent()))
+            .collect_vec();
+
+        let settings = TerrainSettings {
+            attachments: vec![AttachmentLabel::Height],
+            atlas_size: tiles.len() as u32,
+            cpu_attachments: vec![AttachmentLabel::Height],
+        };
+
+        let mut tile_atlas = TileAtlas::new(&height_config, &mut Default::default(), &settings)?;
+        tile_atlas
+            .load_tiles_blocking(terrain_path, tiles)
+            .expect("Failed to load the height tiles.");
+
+        let data = bake_tile(&tile_atlas, tile);
+
+        write_tile(
+            &tile.path(&tile_dir),
+            attachment.texture_size,
+            &data,
+            color_interpretations,
+        )?;
+
+        progress_callback.increment();
+
+        Ok(())
+    })
+}
+
+fn write_tile<T: GdalType + Copy, const N: usize>(
+    tile_path: &Path,
+    size: u32,
+    data: &[[T; N]],
+    color_interpretations: [ColorInterpretation; N],
+) -> PreprocessResult<()> {
+    fs::create_dir_all(tile_path.parent().unwrap()).unwrap(); // make sure the parent directories do exist
+
+    let driver = DriverManager::get_driver_by_name("GTiff")?;
+    let options = RasterCreationOptions::from_iter(
+        [
+            "TILED=YES",
+            "BLOCKXSIZE=512",
+            "BLOCKYSIZE=512",
+            "INTERLEAVE=PIXEL",
+        ]
+        .into_iter(),
+    );
+
+    let dataset = driver
+        .create_with_band_type_with_options::<T, _>(tile_path, size as _, size as _, N, &options)?;
+
+    for (i, color_interpretation) in color_interpretations.into_iter().enumerate() {
+        let mut band = dataset.rasterband(i + 1)?;
+        band.set_color_interpretation(color_interpretation)?;
+
+        let band_data = data.iter().map(|pixel| pixel[i]).collect_vec();
+        let mut buffer = Buffer::new((size as usize, size as usize), band_data);
+
+        band.write((0, 0), (size as usize, size as usize), &mut buffer)?;
+    }
+
+    Ok(())
+}
@@ -1,16 +1,44 @@
 use crate::{
     dataset::{PreprocessDataType, PreprocessNoData},
+    derive::DerivedLayer,
     gdal_extension::ProgressCallback,
 };
 use bevy_terrain::prelude::*;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::path::PathBuf;
 
 const BAR_SIZE: u64 = 10000;
 
 #[derive(Parser, Debug)]
-#[command(name = "btpp", author, version, about)]
+#[command(
+    name = "btpp",
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Btpp {
+    #[command(subcommand)]
+    pub command: Option<BtppCommand>,
+    #[command(flatten)]
+    pub preprocess: Option<Cli>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BtppCommand {
+    /// Exports a region of a preprocessed terrain to glTF or OBJ.
+    Export(ExportCli),
+    /// Bakes material rules into a splat attachment of a preprocessed terrain.
+    BakeRules(BakeRulesCli),
+    /// Bakes the normals of the height attachment into a normal attachment of a preprocessed terrain.
+    BakeNormals(BakeNormalsCli),
+    /// Bakes a layer derived from the height attachment (occlusion, curvature or flow) into a new attachment of a preprocessed terrain.
+    BakeDerived(BakeDerivedCli),
+}
+
+#[derive(Args, Debug)]
 pub struct Cli {
     #[arg(required = true)]
     pub src_path: Vec<PathBuf>,
@@ -42,10 +70,86 @@ pub struct Cli {
     pub border_size: u32,
     #[arg(short, long = "m", default_value_t = 1)]
     pub mip_level_count: u32,
-    #[arg(default_value = "ru16")]
+    /// The format of the attachment (r8u, rg8u, rgb8u, rgba8u, r16u, r16i, rg16u, r16f, rg16f,
+    /// rgba16f, r32f, rg32f, rgba32f, bc1, bc4, bc5 or bc7).
+    /// Note that rg8u now selects two 8 bit channels, use rgb8u for three channel color data.
+    #[arg(default_value = "r16u")]
     pub format: AttachmentFormat,
 }
 
+#[derive(Args, Debug)]
+pub struct ExportCli {
+    /// The directory of the preprocessed terrain.
+    pub terrain_path: PathBuf,
+    /// The output file, whose extension selects the format (.gltf or .obj).
+    pub output_path: PathBuf,
+    #[arg(long, allow_hyphen_values = true)]
+    pub lat_min: f64,
+    #[arg(long, allow_hyphen_values = true)]
+    pub lat_max: f64,
+    #[arg(long, allow_hyphen_values = true)]
+    pub lon_min: f64,
+    #[arg(long, allow_hyphen_values = true)]
+    pub lon_max: f64,
+    #[arg(short, long, default_value_t = 10)]
+    pub lod: u32,
+    #[arg(short, long, default_value_t = 32)]
+    pub grid_size: u32,
+    /// The custom attachment baked into the albedo texture.
+    #[arg(long, default_value = None)]
+    pub albedo: Option<AttachmentLabel>,
+    #[arg(long, default_value_t = 2048)]
+    pub texture_size: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct BakeRulesCli {
+    /// The directory of the preprocessed terrain.
+    pub terrain_path: PathBuf,
+    /// The material rules (.tr.ron) to bake.
+    pub rules_path: PathBuf,
+    #[arg(default_value = "splat")]
+    pub attachment_label: AttachmentLabel,
+    #[arg(short, long = "ts", default_value_t = 512)]
+    pub texture_size: u32,
+    #[arg(short, long = "bs", default_value_t = 1)]
+    pub border_size: u32,
+    #[arg(short, long = "m", default_value_t = 1)]
+    pub mip_level_count: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct BakeNormalsCli {
+    /// The directory of the preprocessed terrain.
+    pub terrain_path: PathBuf,
+    #[arg(short, long = "ts", default_value_t = 512)]
+    pub texture_size: u32,
+    #[arg(short, long = "bs", default_value_t = 1)]
+    pub border_size: u32,
+    #[arg(short, long = "m", default_value_t = 4)]
+    pub mip_level_count: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct BakeDerivedCli {
+    /// The directory of the preprocessed terrain.
+    pub terrain_path: PathBuf,
+    /// The layer to derive from the height attachment.
+    pub layer: DerivedLayer,
+    /// Defaults to the name of the layer.
+    pub attachment_label: Option<AttachmentLabel>,
+    #[arg(short, long = "ts", default_value_t = 512)]
+    pub texture_size: u32,
+    #[arg(short, long = "bs", default_value_t = 1)]
+    pub border_size: u32,
+    #[arg(short, long = "m", default_value_t = 1)]
+    pub mip_level_count: u32,
+    /// The radius (in pixels) around each pixel, which is searched for the horizon (occlusion),
+    /// or around each tile, whose area drains into the tile (flow).
+    #[arg(short, long, default_value_t = 64)]
+    pub radius: u32,
+}
+
 pub(crate) struct PreprocessBar<'a> {
     name: String,
     bar: ProgressBar,
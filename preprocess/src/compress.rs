@@ -0,0 +1,209 @@
+use crate::{
+    dataset::PreprocessContext,
+    gdal_extension::{CountingProgressCallback, ProgressCallback},
+    result::{PreprocessError, PreprocessResult},
+};
+use bevy_terrain::{math::TileCoordinate, terrain_data::AttachmentFormat};
+use gdal::{Dataset, raster::Buffer};
+use intel_tex_2::{RSurface, RgSurface, RgbaSurface, bc1, bc4, bc5, bc7};
+use rayon::prelude::*;
+use std::fs;
+
+fn channel_count(format: AttachmentFormat) -> usize {
+    match format {
+        AttachmentFormat::Bc4 => 1,
+        AttachmentFormat::Bc5 => 2,
+        _ => 4,
+    }
+}
+
+/// Reads the rasterbands of the tile as interleaved 8 bit channels.
+/// Missing color channels are set to zero and a missing alpha channel is opaque.
+fn read_pixels(dataset: &Dataset, channel_count: usize) -> PreprocessResult<Vec<u8>> {
+    let (width, height) = dataset.raster_size();
+
+    let mut pixels = vec![0; width * height * channel_count];
+
+    if channel_count == 4 {
+        pixels.iter_mut().skip(3).step_by(4).for_each(|a| *a = 255);
+    }
+
+    for (channel, band) in dataset.rasterbands().take(channel_count).enumerate() {
+        let band_data: Buffer<u8> = band?.read_band_as()?;
+
+        for (pixel, &value) in pixels.chunks_exact_mut(channel_count).zip(band_data.data()) {
+            pixel[channel] = value;
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Halves the size of the image by averaging each 2x2 block of pixels.
+fn downsample(pixels: &[u8], size: usize, channel_count: usize) -> Vec<u8> {
+    let child_size = size / 2;
+
+    let mut child = vec![0; child_size * child_size * channel_count];
+
+    for y in 0..child_size {
+        for x in 0..child_size {
+            for channel in 0..channel_count {
+                let value = |dx, dy| {
+                    pixels[((2 * y + dy) * size + 2 * x + dx) * channel_count + channel] as u32
+                };
+
+                let sum = value(0, 0) + value(1, 0) + value(0, 1) + value(1, 1);
+
+                child[(y * child_size + x) * channel_count + channel] = ((sum + 2) / 4) as u8;
+            }
+        }
+    }
+
+    child
+}
+
+fn compress_blocks(pixels: &[u8], size: usize, format: AttachmentFormat) -> Vec<u8> {
+    let width = size as u32;
+    let height = size as u32;
+    let stride = width * channel_count(format) as u32;
+
+    match format {
+        AttachmentFormat::Bc1 => bc1::compress_blocks(&RgbaSurface {
+            data: pixels,
+            width,
+            height,
+            stride,
+        }),
+        AttachmentFormat::Bc4 => bc4::compress_blocks(&RSurface {
+            data: pixels,
+            width,
+            height,
+            stride,
+        }),
+        AttachmentFormat::Bc5 => bc5::compress_blocks(&RgSurface {
+            data: pixels,
+            width,
+            height,
+            stride,
+        }),
+        AttachmentFormat::Bc7 => bc7::compress_blocks(
+            &bc7::opaque_basic_settings(),
+            &RgbaSurface {
+                data: pixels,
+                width,
+                height,
+                stride,
+            },
+        ),
+        _ => unreachable!(),
+    }
+}
+
+/// Encodes the pixels and their mip chain into a sequence of compressed mip levels.
+fn compress_mip_chain(
+    mut pixels: Vec<u8>,
+    texture_size: u32,
+    mip_level_count: u32,
+    format: AttachmentFormat,
+) -> Vec<u8> {
+    let channel_count = channel_count(format);
+    let mut size = texture_size as usize;
+    let mut blocks = Vec::new();
+
+    for mip_level in 0..mip_level_count {
+        if mip_level > 0 {
+            pixels = downsample(&pixels, size, channel_count);
+            size /= 2;
+        }
+
+        blocks.extend(compress_blocks(&pixels, size, format));
+    }
+
+    blocks
+}
+
+/// Replaces the tiles with their block compressed counterparts, including all mip levels,
+/// since the mipmaps of compressed attachments can not be generated on the GPU.
+pub(crate) fn compress_tiles(
+    tiles: &[TileCoordinate],
+    context: &PreprocessContext,
+    progress_callback: Option<&ProgressCallback>,
+) -> PreprocessResult<()> {
+    let progress_callback = CountingProgressCallback::new(tiles.len() as u64, progress_callback);
+
+    let format = context.attachment.format;
+
+    tiles.par_iter().try_for_each(|&tile| {
+        let tile_path = tile.path(&context.tile_dir);
+
+        let pixels = read_pixels(&Dataset::open(&tile_path)?, channel_count(format))?;
+
+        let blocks = compress_mip_chain(
+            pixels,
+            context.attachment.texture_size,
+            context.attachment.mip_level_count,
+            format,
+        );
+
+        fs::write(tile_path.with_extension(format.file_extension()), blocks)?;
+        fs::remove_file(tile_path)?;
+
+        progress_callback.increment();
+
+        Ok::<(), PreprocessError>(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compressed_mip_chain_size() {
+        let texture_size = 16;
+        let pixels = vec![128; 16 * 16 * 4];
+
+        let blocks = compress_mip_chain(pixels, texture_size, 3, AttachmentFormat::Bc1);
+
+        // 4x4, 2x2 and 1x1 blocks of 8 bytes
+        assert_eq!(blocks.len(), (16 + 4 + 1) * 8);
+    }
+
+    #[test]
+    fn compressed_block_sizes() {
+        let pixels = |format| vec![128; 4 * 4 * channel_count(format)];
+
+        // BC1 and BC4 use 8 bytes, BC5 and BC7 16 bytes per block
+        for (format, block_size) in [
+            (AttachmentFormat::Bc1, 8),
+            (AttachmentFormat::Bc4, 8),
+            (AttachmentFormat::Bc5, 16),
+            (AttachmentFormat::Bc7, 16),
+        ] {
+            let blocks = compress_blocks(&pixels(format), 4, format);
+
+            assert_eq!(blocks.len(), block_size, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn downsample_averages_quads() {
+        #[rustfmt::skip]
+        let pixels: [u8; 16] = [
+            0, 10,   4,  4,
+            2,  0,   4,  4,
+            9,  9, 255,  0,
+            9,  9,   0,  0,
+        ];
+
+        // the averages are rounded to the nearest value
+        assert_eq!(downsample(&pixels, 4, 1), [3, 4, 9, 64]);
+    }
+
+    #[test]
+    fn downsample_keeps_channels_apart() {
+        let pixels: Vec<u8> = [[255, 0], [255, 0], [255, 0], [255, 0]].concat();
+
+        assert_eq!(downsample(&pixels, 2, 2), [255, 0]);
+    }
+}
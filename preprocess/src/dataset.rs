@@ -104,17 +104,20 @@ impl PreprocessContext {
             format,
         } = args;
 
+        let attachment = AttachmentConfig {
+            texture_size,
+            border_size,
+            mip_level_count,
+            mask: create_mask,
+            format,
+        };
+        attachment.validate()?;
+
         PreprocessContext::initialize(
             terrain_path,
             lod_count,
             attachment_label,
-            AttachmentConfig {
-                texture_size,
-                border_size,
-                mip_level_count,
-                mask: create_mask,
-                format,
-            },
+            attachment,
             src_path,
             temp_path,
             no_data,
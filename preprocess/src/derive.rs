@@ -0,0 +1,290 @@
+use bevy_terrain::prelude::*;
+use clap::ValueEnum;
+use glam::{DVec2, DVec3, IVec2};
+use itertools::{Itertools, iproduct};
+use std::f64::consts::TAU;
+
+/// The number of directions in which the horizon is searched for the occlusion.
+const HORIZON_DIRECTIONS: usize = 16;
+
+/// A raster derived from the height attachment, which can be baked into a new attachment.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DerivedLayer {
+    /// The cosine weighted visibility of the sky (ambient occlusion), stored as `R8U`.
+    /// One is fully visible and zero is fully occluded.
+    Occlusion,
+    /// The mean curvature of the terrain in 1/m, stored as `R32F`.
+    /// Convex regions (e.g. hill tops) are positive and concave regions (e.g. valleys) are negative.
+    Curvature,
+    /// The area in m² draining through each pixel (D8 flow accumulation), stored as `R32F`.
+    /// Only the area within the search radius around the tile is accounted for.
+    Flow,
+}
+
+impl DerivedLayer {
+    pub fn name(self) -> &'static str {
+        match self {
+            DerivedLayer::Occlusion => "occlusion",
+            DerivedLayer::Curvature => "curvature",
+            DerivedLayer::Flow => "flow",
+        }
+    }
+
+    pub fn format(self) -> AttachmentFormat {
+        match self {
+            DerivedLayer::Occlusion => AttachmentFormat::R8U,
+            DerivedLayer::Curvature | DerivedLayer::Flow => AttachmentFormat::R32F,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    /// The local position of the terrain shape (without the height).
+    base: DVec3,
+    /// The normal of the terrain shape.
+    up: DVec3,
+    /// The height of the terrain, if available.
+    height: Option<f32>,
+}
+
+impl Cell {
+    fn position(self) -> Option<DVec3> {
+        self.height
+            .map(|height| self.base + height as f64 * self.up)
+    }
+}
+
+/// The heights of a tile (including its border), extended by a margin of pixels on each side,
+/// which are sampled from the neighbouring tiles (also across cube faces).
+pub(crate) struct HeightGrid {
+    size: i32,
+    margin: i32,
+    cells: Vec<Cell>,
+}
+
+impl HeightGrid {
+    fn new(size: i32, margin: i32, cell: impl Fn(IVec2) -> Cell) -> Self {
+        let cells = iproduct!(0..size, 0..size)
+            .map(|(y, x)| cell(IVec2::new(x, y) - margin))
+            .collect();
+
+        Self {
+            size,
+            margin,
+            cells,
+        }
+    }
+
+    /// Samples the height grid of the `tile` from the best loaded tiles with a lod of at most `tile.lod`.
+    pub(crate) fn from_tile(
+        tile_atlas: &TileAtlas,
+        shape: TerrainShape,
+        tile: TileCoordinate,
+        attachment: &AttachmentConfig,
+        margin: u32,
+    ) -> Self {
+        let size = (attachment.texture_size + 2 * margin) as i32;
+
+        Self::new(size, margin as i32, |pixel| {
+            let coordinate = attachment.pixel_coordinate(tile, pixel, shape.is_spherical());
+            let unit_position = coordinate.unit_position(shape.is_spherical());
+            let base = shape.position_unit_to_local(unit_position, 0.0);
+            let up = shape.position_unit_to_local(unit_position, 1.0) - base;
+
+            Cell {
+                base,
+                up,
+                height: tile_atlas
+                    .sample_height_lod(coordinate, tile.lod)
+                    .map(|(height, _)| height),
+            }
+        })
+    }
+
+    fn index(&self, pixel: IVec2) -> Option<usize> {
+        let cell = pixel + self.margin;
+
+        (cell.cmpge(IVec2::ZERO).all() && cell.cmplt(IVec2::splat(self.size)).all())
+            .then(|| (cell.y * self.size + cell.x) as usize)
+    }
+
+    fn cell(&self, pixel: IVec2) -> Option<Cell> {
+        self.index(pixel).map(|index| self.cells[index])
+    }
+
+    /// The pixels of the tile (including its border) in row major order.
+    fn tile_pixels(&self) -> impl Iterator<Item = IVec2> {
+        let texture_size = self.size - 2 * self.margin;
+
+        iproduct!(0..texture_size, 0..texture_size).map(|(y, x)| IVec2::new(x, y))
+    }
+
+    /// Computes the cosine weighted sky visibility of each pixel of the tile,
+    /// by searching the horizon within the `radius` (in pixels) in several directions.
+    pub(crate) fn occlusion(&self, radius: u32) -> Vec<f32> {
+        self.tile_pixels()
+            .map(|pixel| {
+                let cell = self.cell(pixel).unwrap();
+                let Some(position) = cell.position() else {
+                    return 1.0;
+                };
+
+                let occlusion = (0..HORIZON_DIRECTIONS)
+                    .map(|i| {
+                        let direction =
+                            DVec2::from_angle(TAU * i as f64 / HORIZON_DIRECTIONS as f64);
+
+                        // sine of the elevation angle of the horizon
+                        let horizon = (1..=radius)
+                            .filter_map(|step| {
+                                let offset = (direction * step as f64).round().as_ivec2();
+                                self.cell(pixel + offset)?.position()
+                            })
+                            .map(|sample| {
+                                let offset = sample - position;
+                                offset.dot(cell.up) / offset.length()
+                            })
+                            .fold(0.0, f64::max);
+
+                        horizon * horizon
+                    })
+                    .sum::<f64>()
+                    / HORIZON_DIRECTIONS as f64;
+
+                (1.0 - occlusion) as f32
+            })
+            .collect()
+    }
+
+    /// Computes the area draining through each pixel of the tile.
+    /// Each cell of the grid passes its area and all its inflow to its steepest downhill neighbour (D8).
+    pub(crate) fn flow(&self) -> Vec<f32> {
+        let pixels = iproduct!(0..self.size, 0..self.size)
+            .map(|(y, x)| IVec2::new(x, y) - self.margin)
+            .collect_vec();
+
+        let area = |pixel: IVec2| {
+            let distance = |offset: IVec2| {
+                let cell = self.cell(pixel).unwrap();
+                let [a, b] = [pixel - offset, pixel + offset]
+                    .map(|neighbour| self.cell(neighbour).unwrap_or(cell).base);
+                a.distance(b) / 2.0
+            };
+
+            distance(IVec2::X) * distance(IVec2::Y)
+        };
+
+        let receiver = |pixel: IVec2| {
+            let cell = self.cell(pixel).unwrap();
+            let height = cell.height?;
+
+            iproduct!(-1..=1, -1..=1)
+                .map(|(y, x)| IVec2::new(x, y))
+                .filter(|&offset| offset != IVec2::ZERO)
+                .filter_map(|offset| {
+                    let neighbour = self.cell(pixel + offset)?;
+                    let drop = (height - neighbour.height?) as f64;
+                    let gradient = drop / cell.base.distance(neighbour.base);
+
+                    (gradient > 0.0).then_some((pixel + offset, gradient))
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(neighbour, _)| neighbour)
+        };
+
+        let mut accumulation = pixels
+            .iter()
+            .map(|&pixel| match self.cell(pixel).unwrap().height {
+                Some(_) => area(pixel),
+                None => 0.0,
+            })
+            .collect_vec();
+
+        // process the cells from top to bottom, so that all inflow is known before passing it on
+        let order = pixels
+            .iter()
+            .copied()
+            .filter(|&pixel| self.cell(pixel).unwrap().height.is_some())
+            .sorted_by(|&a, &b| {
+                let height = |pixel| self.cell(pixel).unwrap().height.unwrap();
+                height(b).total_cmp(&height(a))
+            });
+
+        for pixel in order {
+            if let Some(neighbour) = receiver(pixel) {
+                let flow = accumulation[self.index(pixel).unwrap()];
+                accumulation[self.index(neighbour).unwrap()] += flow;
+            }
+        }
+
+        self.tile_pixels()
+            .map(|pixel| accumulation[self.index(pixel).unwrap()] as f32)
+            .collect()
+    }
+}
+
+/// Computes the mean curvature of each pixel of the tile (including its border).
+/// Near the edges of a cube face, the pixels and their finite differences sample the adjacent face,
+/// so that the curvature is continuous across the seams. Pixels without height data are flat.
+pub(crate) fn bake_curvature_tile(
+    tile_atlas: &TileAtlas,
+    shape: TerrainShape,
+    tile: TileCoordinate,
+    attachment: &AttachmentConfig,
+) -> Vec<f32> {
+    iproduct!(0..attachment.texture_size, 0..attachment.texture_size)
+        .map(|(y, x)| {
+            let pixel = IVec2::new(x as i32, y as i32);
+            let coordinate = attachment.pixel_coordinate(tile, pixel, shape.is_spherical());
+
+            tile_atlas
+                .sample_surface_lod(coordinate, tile.lod)
+                .map_or(0.0, |sample| sample.curvature as f32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn planar_grid(size: i32, margin: i32, height: impl Fn(IVec2) -> f32) -> HeightGrid {
+        HeightGrid::new(size, margin, |pixel| Cell {
+            base: DVec3::new(pixel.x as f64, 0.0, pixel.y as f64),
+            up: DVec3::Y,
+            height: Some(height(pixel)),
+        })
+    }
+
+    #[test]
+    fn flat_terrain_is_unoccluded() {
+        let grid = planar_grid(12, 4, |_| 10.0);
+
+        assert!(
+            grid.occlusion(4)
+                .iter()
+                .all(|&visibility| visibility == 1.0)
+        );
+    }
+
+    #[test]
+    fn valley_is_occluded() {
+        // a v-shaped valley along the y axis
+        let grid = planar_grid(12, 4, |pixel| (pixel.x - 2).abs() as f32);
+        let occlusion = grid.occlusion(4);
+
+        assert!(occlusion[2] < 1.0);
+        assert!(occlusion[2] < occlusion[0]);
+    }
+
+    #[test]
+    fn flow_accumulates_downhill() {
+        // an inclined plane descending towards positive x
+        let grid = planar_grid(4, 0, |pixel| -pixel.x as f32);
+        let flow = grid.flow();
+
+        // each row drains independently along x, the cells at the edges only cover half a pixel
+        assert_eq!(flow[0..4], [0.25, 0.75, 1.25, 1.5]);
+    }
+}
@@ -1,5 +1,8 @@
+mod bake;
 mod cli;
+mod compress;
 mod dataset;
+mod derive;
 mod downsample;
 mod fill_no_data;
 mod gdal_extension;
@@ -10,7 +13,9 @@ mod stitch;
 mod transformers;
 
 use crate::{
-    cli::PreprocessBar,
+    bake::{bake_derived, bake_normals, bake_rules},
+    cli::{BakeDerivedCli, BakeNormalsCli, BakeRulesCli, ExportCli, PreprocessBar},
+    compress::compress_tiles,
     dataset::{PreprocessContext, clear_directory, delete_directory},
     downsample::downsample_and_stitch,
     fill_no_data::create_mask_and_fill_no_data,
@@ -27,9 +32,11 @@ use std::time::Instant;
 
 pub mod prelude {
     pub use crate::{
-        cli::Cli,
+        bake_derived_layer, bake_material_rules, bake_normal_map,
+        cli::{BakeDerivedCli, BakeNormalsCli, BakeRulesCli, Btpp, BtppCommand, Cli, ExportCli},
         dataset::{PreprocessContext, PreprocessDataType, PreprocessNoData},
-        preprocess,
+        derive::DerivedLayer,
+        export, preprocess,
     };
 }
 
@@ -61,6 +68,12 @@ fn preprocess_gen<T: Copy + GdalType + PartialEq + NumCast>(
     create_mask_and_fill_no_data(&tiles, context, Some(progress_bar.callback())).unwrap();
     progress_bar.finish();
 
+    if context.attachment.format.is_compressed() {
+        let progress_bar = PreprocessBar::new("Compressing".to_string());
+        compress_tiles(&tiles, context, Some(progress_bar.callback())).unwrap();
+        progress_bar.finish();
+    }
+
     delete_directory(&context.temp_dir);
 
     save_terrain_config(tiles, context);
@@ -90,6 +103,157 @@ pub fn preprocess(src_dataset: Dataset, context: &mut PreprocessContext) {
     };
 }
 
+pub fn export(args: ExportCli) {
+    let ExportCli {
+        terrain_path,
+        output_path,
+        lat_min,
+        lat_max,
+        lon_min,
+        lon_max,
+        lod,
+        grid_size,
+        albedo,
+        texture_size,
+    } = args;
+
+    let config = TerrainConfig::load_file(terrain_path.join("config.tc.ron")).unwrap();
+
+    let settings = ExportSettings {
+        region: TerrainRegion::from_lat_lon(
+            config.shape,
+            (lat_min, lat_max),
+            (lon_min, lon_max),
+            lod,
+        ),
+        lod,
+        grid_size,
+        albedo,
+        texture_size,
+    };
+
+    let start_export = Instant::now();
+
+    export_terrain_from_disk(&terrain_path, &settings, &output_path).unwrap();
+
+    println!("Export took: {:?}", start_export.elapsed());
+}
+
+pub fn bake_material_rules(args: BakeRulesCli) {
+    let BakeRulesCli {
+        terrain_path,
+        rules_path,
+        attachment_label,
+        texture_size,
+        border_size,
+        mip_level_count,
+    } = args;
+
+    let config_path = terrain_path.join("config.tc.ron");
+    let mut config = TerrainConfig::load_file(&config_path).unwrap();
+    let rules = TerrainMaterialRules::load_file(&rules_path).unwrap();
+
+    let attachment = AttachmentConfig {
+        texture_size,
+        border_size,
+        mip_level_count,
+        mask: false,
+        format: AttachmentFormat::Rgba8U,
+    };
+
+    let progress_bar = PreprocessBar::new("Baking".to_string());
+    bake_rules(
+        &config,
+        &rules,
+        &terrain_path,
+        &attachment_label,
+        &attachment,
+        Some(progress_bar.callback()),
+    )
+    .unwrap();
+    progress_bar.finish();
+
+    config.add_attachment(attachment_label, attachment);
+    config.save_file(&config_path).unwrap();
+}
+
+pub fn bake_normal_map(args: BakeNormalsCli) {
+    let BakeNormalsCli {
+        terrain_path,
+        texture_size,
+        border_size,
+        mip_level_count,
+    } = args;
+
+    let config_path = terrain_path.join("config.tc.ron");
+    let mut config = TerrainConfig::load_file(&config_path).unwrap();
+
+    // the normals are encoded relative to the surface and thus lie in the upper hemisphere,
+    // where the octahedral encoding is continuous and can be averaged into mipmaps
+    let attachment = AttachmentConfig {
+        texture_size,
+        border_size,
+        mip_level_count,
+        mask: false,
+        format: AttachmentFormat::Rg16U,
+    };
+
+    let progress_bar = PreprocessBar::new("Baking".to_string());
+    bake_normals(
+        &config,
+        &terrain_path,
+        &AttachmentLabel::Normal,
+        &attachment,
+        Some(progress_bar.callback()),
+    )
+    .unwrap();
+    progress_bar.finish();
+
+    config.add_attachment(AttachmentLabel::Normal, attachment);
+    config.save_file(&config_path).unwrap();
+}
+
+pub fn bake_derived_layer(args: BakeDerivedCli) {
+    let BakeDerivedCli {
+        terrain_path,
+        layer,
+        attachment_label,
+        texture_size,
+        border_size,
+        mip_level_count,
+        radius,
+    } = args;
+
+    let config_path = terrain_path.join("config.tc.ron");
+    let mut config = TerrainConfig::load_file(&config_path).unwrap();
+    let attachment_label =
+        attachment_label.unwrap_or_else(|| AttachmentLabel::Custom(layer.name().to_string()));
+
+    let attachment = AttachmentConfig {
+        texture_size,
+        border_size,
+        mip_level_count,
+        mask: false,
+        format: layer.format(),
+    };
+
+    let progress_bar = PreprocessBar::new("Baking".to_string());
+    bake_derived(
+        &config,
+        &terrain_path,
+        layer,
+        radius,
+        &attachment_label,
+        &attachment,
+        Some(progress_bar.callback()),
+    )
+    .unwrap();
+    progress_bar.finish();
+
+    config.add_attachment(attachment_label, attachment);
+    config.save_file(&config_path).unwrap();
+}
+
 fn save_terrain_config(tiles: Vec<TileCoordinate>, context: &PreprocessContext) {
     let file_path = context.terrain_path.join("config.tc.ron");
 
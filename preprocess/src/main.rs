@@ -13,8 +13,20 @@ fn main() {
         }
     }
 
-    let args = Cli::parse();
-    let (src_dataset, mut context) = PreprocessContext::from_cli(args).unwrap();
+    let Btpp {
+        command,
+        preprocess: args,
+    } = Btpp::parse();
 
-    preprocess(src_dataset, &mut context);
+    match command {
+        Some(BtppCommand::Export(args)) => export(args),
+        Some(BtppCommand::BakeRules(args)) => bake_material_rules(args),
+        Some(BtppCommand::BakeNormals(args)) => bake_normal_map(args),
+        Some(BtppCommand::BakeDerived(args)) => bake_derived_layer(args),
+        None => {
+            let (src_dataset, mut context) = PreprocessContext::from_cli(args.unwrap()).unwrap();
+
+            preprocess(src_dataset, &mut context);
+        }
+    }
 }
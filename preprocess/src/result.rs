@@ -1,5 +1,6 @@
+use bevy_terrain::terrain_data::AttachmentError;
 use gdal::errors::GdalError;
-use std::num::ParseFloatError;
+use std::{io, num::ParseFloatError, sync::Arc};
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -14,6 +15,20 @@ pub enum PreprocessError {
     Gdal(#[from] GdalError),
     #[error("Parse error")]
     Parse(#[from] ParseFloatError),
+    #[error("IO error")]
+    Io(#[from] Arc<io::Error>),
+    #[error("Invalid attachment: {0}")]
+    Attachment(#[from] AttachmentError),
+    #[error(
+        "The radius of {radius} pixels exceeds the size of the neighbouring tiles ({center_size} pixels)."
+    )]
+    RadiusTooLarge { radius: u32, center_size: u32 },
+}
+
+impl From<io::Error> for PreprocessError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(Arc::new(error))
+    }
 }
 
 pub type PreprocessResult<T> = Result<T, PreprocessError>;
@@ -0,0 +1,713 @@
+//! Precomputed atmospheric scattering for spherical terrains.
+//!
+//! The scattering of the sun light in the atmosphere is modeled with Rayleigh scattering (air),
+//! Mie scattering (aerosols) and ozone absorption, following the approach of Hillaire (2020).
+//! The transmittance and the multiple scattering are precomputed into two small LUTs,
+//! which are only updated once the parameters of the [`TerrainAtmosphere`] change.
+//!
+//! The atmosphere is applied to the terrain fragments as aerial perspective, using the
+//! high-precision view-relative positions of the terrain, and is rendered as the sky and the
+//! limb of the planet behind the terrain by the [`TerrainSkyPass`].
+
+use crate::{
+    render::{GpuTerrainView, TerrainPass},
+    shaders::{ATMOSPHERE_LUT_SHADER, ATMOSPHERE_SKY_SHADER},
+    terrain::TerrainComponents,
+    terrain_data::TileAtlas,
+    terrain_view::TerrainViewComponents,
+    util::GpuBuffer,
+};
+use bevy::{
+    core_pipeline::{
+        core_3d::{
+            CORE_3D_DEPTH_FORMAT,
+            graph::{Core3d, Node3d},
+        },
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        Extract, Render, RenderApp, RenderSet,
+        camera::ExtractedCamera,
+        graph::CameraDriverLabel,
+        render_graph::{
+            self, NodeRunError, RenderGraph, RenderGraphApp, RenderGraphContext, RenderLabel,
+            ViewNode, ViewNodeRunner,
+        },
+        render_phase::TrackedRenderPass,
+        render_resource::{binding_types::*, *},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+        view::{
+            ExtractedView, ViewDepthTexture, ViewTarget, ViewUniform, ViewUniformOffset,
+            ViewUniforms,
+        },
+    },
+};
+use itertools::Itertools;
+
+const TRANSMITTANCE_LUT_SIZE: UVec2 = UVec2::new(256, 64);
+const MULTISCATTERING_LUT_SIZE: UVec2 = UVec2::new(32, 32);
+const LUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// The atmosphere of a spherical terrain.
+///
+/// Add this component to the terrain entity, to apply aerial perspective to the terrain and to render
+/// the sky. The sun is the [`DirectionalLight`] marked with the [`AtmosphereSun`].
+/// The aerial perspective is applied by `apply_terrain_lighting` of `bevy_terrain::pbr_fragment`,
+/// which is used by the built-in materials.
+/// The default parameters approximate the atmosphere of the earth.
+/// All coefficients are per meter and all heights in meters.
+#[derive(Component, Reflect, Clone, Debug, PartialEq)]
+#[reflect(Component, Default, Debug)]
+pub struct TerrainAtmosphere {
+    /// The height of the top of the atmosphere above the surface.
+    pub height: f32,
+    /// The Rayleigh scattering coefficient at the surface.
+    pub rayleigh_scattering: Vec3,
+    /// The height at which the density of the Rayleigh scattering decreases by a factor of e.
+    pub rayleigh_scale_height: f32,
+    /// The Mie scattering coefficient at the surface.
+    pub mie_scattering: f32,
+    /// The Mie absorption coefficient at the surface.
+    pub mie_absorption: f32,
+    /// The height at which the density of the Mie scattering decreases by a factor of e.
+    pub mie_scale_height: f32,
+    /// The asymmetry of the Mie phase function in the range (-1, 1).
+    pub mie_asymmetry: f32,
+    /// The ozone absorption coefficient at the peak of the ozone layer.
+    pub ozone_absorption: Vec3,
+    /// The height of the peak of the ozone layer.
+    pub ozone_center: f32,
+    /// The half width of the ozone layer.
+    pub ozone_width: f32,
+    /// The average albedo of the ground, which reflects light back into the atmosphere.
+    pub ground_albedo: Vec3,
+}
+
+impl Default for TerrainAtmosphere {
+    fn default() -> Self {
+        Self::EARTH
+    }
+}
+
+impl TerrainAtmosphere {
+    pub const EARTH: Self = Self {
+        height: 100_000.0,
+        rayleigh_scattering: Vec3::new(5.802e-6, 13.558e-6, 33.1e-6),
+        rayleigh_scale_height: 8_000.0,
+        mie_scattering: 3.996e-6,
+        mie_absorption: 4.4e-6,
+        mie_scale_height: 1_200.0,
+        mie_asymmetry: 0.8,
+        ozone_absorption: Vec3::new(0.65e-6, 1.881e-6, 0.085e-6),
+        ozone_center: 25_000.0,
+        ozone_width: 15_000.0,
+        ground_albedo: Vec3::splat(0.3),
+    };
+}
+
+/// Marks the [`DirectionalLight`], which illuminates all [`TerrainAtmosphere`]s.
+///
+/// If no light or more than one light is marked, the atmospheres are not illuminated by a sun.
+#[derive(Component, Reflect, Clone, Copy, Default, Debug)]
+#[reflect(Component, Default, Debug)]
+pub struct AtmosphereSun;
+
+/// The atmosphere data that is available in shaders.
+#[derive(Clone, Default, ShaderType)]
+pub struct AtmosphereUniform {
+    center: Vec3,
+    bottom_radius: f32,
+    top_radius: f32,
+    rayleigh_scattering: Vec3,
+    rayleigh_scale_height: f32,
+    mie_scattering: f32,
+    mie_absorption: f32,
+    mie_scale_height: f32,
+    mie_asymmetry: f32,
+    ozone_absorption: Vec3,
+    ozone_center: f32,
+    ozone_width: f32,
+    ground_albedo: Vec3,
+    sun_direction: Vec3,
+    sun_illuminance: Vec3,
+}
+
+impl AtmosphereUniform {
+    fn new(
+        atmosphere: &TerrainAtmosphere,
+        radius: f32,
+        center: Vec3,
+        sun: Option<(&DirectionalLight, &GlobalTransform)>,
+    ) -> Self {
+        let (sun_direction, sun_illuminance) =
+            sun.map_or((Vec3::Y, Vec3::ZERO), |(light, transform)| {
+                (
+                    transform.back().as_vec3(),
+                    light.color.to_linear().to_vec3() * light.illuminance,
+                )
+            });
+
+        Self {
+            center,
+            bottom_radius: radius,
+            top_radius: radius + atmosphere.height,
+            rayleigh_scattering: atmosphere.rayleigh_scattering,
+            rayleigh_scale_height: atmosphere.rayleigh_scale_height,
+            mie_scattering: atmosphere.mie_scattering,
+            mie_absorption: atmosphere.mie_absorption,
+            mie_scale_height: atmosphere.mie_scale_height,
+            mie_asymmetry: atmosphere.mie_asymmetry,
+            ozone_absorption: atmosphere.ozone_absorption,
+            ozone_center: atmosphere.ozone_center,
+            ozone_width: atmosphere.ozone_width,
+            ground_albedo: atmosphere.ground_albedo,
+            sun_direction,
+            sun_illuminance,
+        }
+    }
+}
+
+/// The layout of the bind group of an atmosphere, which is used by the sky pipeline and by the terrain
+/// pipelines of terrains with an atmosphere.
+pub(crate) fn atmosphere_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        "atmosphere_layout",
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::FRAGMENT,
+            (
+                uniform_buffer::<AtmosphereUniform>(false),
+                texture_2d(TextureSampleType::Float { filterable: true }),
+                texture_2d(TextureSampleType::Float { filterable: true }),
+                sampler(SamplerBindingType::Filtering),
+            ),
+        ),
+    )
+}
+
+fn create_lut(device: &RenderDevice, label: &str, size: UVec2) -> TextureView {
+    device
+        .create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: LUT_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&default())
+}
+
+/// The render world representation of a [`TerrainAtmosphere`].
+pub struct GpuAtmosphere {
+    atmosphere_buffer: GpuBuffer<AtmosphereUniform>,
+    transmittance_bind_group: BindGroup,
+    multiscattering_bind_group: BindGroup,
+    /// Binds the atmosphere and its LUTs to the sky and to the terrain pipelines.
+    pub(crate) bind_group: BindGroup,
+    /// The parameters the LUTs are (or will be) computed for.
+    parameters: (TerrainAtmosphere, f32),
+    /// Whether the LUTs have to be recomputed.
+    luts_pending: bool,
+    /// Whether the LUTs are computed this frame.
+    compute_luts: bool,
+}
+
+impl GpuAtmosphere {
+    fn new(
+        device: &RenderDevice,
+        pipelines: &AtmospherePipelines,
+        atmosphere: &TerrainAtmosphere,
+        radius: f32,
+    ) -> Self {
+        let atmosphere_buffer = GpuBuffer::empty_labeled(
+            "atmosphere_buffer",
+            device,
+            BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        );
+        let transmittance_lut = create_lut(device, "transmittance_lut", TRANSMITTANCE_LUT_SIZE);
+        let multiscattering_lut =
+            create_lut(device, "multiscattering_lut", MULTISCATTERING_LUT_SIZE);
+
+        let transmittance_bind_group = device.create_bind_group(
+            "transmittance_bind_group",
+            &pipelines.transmittance_layout,
+            &BindGroupEntries::sequential((&atmosphere_buffer, &transmittance_lut)),
+        );
+        let multiscattering_bind_group = device.create_bind_group(
+            "multiscattering_bind_group",
+            &pipelines.multiscattering_layout,
+            &BindGroupEntries::with_indices((
+                (0, &atmosphere_buffer),
+                (2, &transmittance_lut),
+                (3, &pipelines.lut_sampler),
+                (4, &multiscattering_lut),
+            )),
+        );
+        let bind_group = device.create_bind_group(
+            "atmosphere_bind_group",
+            &pipelines.atmosphere_layout,
+            &BindGroupEntries::sequential((
+                &atmosphere_buffer,
+                &transmittance_lut,
+                &multiscattering_lut,
+                &pipelines.lut_sampler,
+            )),
+        );
+
+        Self {
+            atmosphere_buffer,
+            transmittance_bind_group,
+            multiscattering_bind_group,
+            bind_group,
+            parameters: (atmosphere.clone(), radius),
+            luts_pending: true,
+            compute_luts: false,
+        }
+    }
+
+    pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        pipelines: Res<AtmospherePipelines>,
+        mut gpu_atmospheres: ResMut<TerrainComponents<GpuAtmosphere>>,
+        atmospheres: Extract<Query<(Entity, &TerrainAtmosphere, &TileAtlas, &GlobalTransform)>>,
+        suns: Extract<Query<(&DirectionalLight, &GlobalTransform), With<AtmosphereSun>>>,
+    ) {
+        let sun = suns.single().ok();
+
+        gpu_atmospheres.retain(|terrain, _| atmospheres.contains(*terrain));
+
+        for (terrain, atmosphere, tile_atlas, global_transform) in &atmospheres {
+            if !tile_atlas.shape.is_spherical() {
+                continue;
+            }
+
+            let radius = tile_atlas.shape.scale().x as f32;
+
+            let gpu_atmosphere = gpu_atmospheres
+                .entry(terrain)
+                .or_insert_with(|| GpuAtmosphere::new(&device, &pipelines, atmosphere, radius));
+
+            let parameters = (atmosphere.clone(), radius);
+            if gpu_atmosphere.parameters != parameters {
+                gpu_atmosphere.parameters = parameters;
+                gpu_atmosphere.luts_pending = true;
+            }
+
+            gpu_atmosphere
+                .atmosphere_buffer
+                .set_value(AtmosphereUniform::new(
+                    atmosphere,
+                    radius,
+                    global_transform.translation(),
+                    sun,
+                ));
+        }
+    }
+
+    pub(crate) fn prepare(
+        queue: Res<RenderQueue>,
+        pipeline_cache: Res<PipelineCache>,
+        pipelines: Res<AtmospherePipelines>,
+        mut gpu_atmospheres: ResMut<TerrainComponents<GpuAtmosphere>>,
+    ) {
+        let pipelines_ready = pipelines.lut_pipelines(&pipeline_cache).is_some();
+
+        for gpu_atmosphere in gpu_atmospheres.values_mut() {
+            gpu_atmosphere.atmosphere_buffer.update(&queue);
+
+            gpu_atmosphere.compute_luts = gpu_atmosphere.luts_pending && pipelines_ready;
+            if gpu_atmosphere.compute_luts {
+                gpu_atmosphere.luts_pending = false;
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct AtmospherePipelines {
+    transmittance_layout: BindGroupLayout,
+    multiscattering_layout: BindGroupLayout,
+    view_layout: BindGroupLayout,
+    atmosphere_layout: BindGroupLayout,
+    lut_sampler: Sampler,
+    transmittance_pipeline: CachedComputePipelineId,
+    multiscattering_pipeline: CachedComputePipelineId,
+    sky_shader: Handle<Shader>,
+}
+
+impl FromWorld for AtmospherePipelines {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let transmittance_layout = device.create_bind_group_layout(
+            "transmittance_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    uniform_buffer::<AtmosphereUniform>(false),
+                    texture_storage_2d(LUT_FORMAT, StorageTextureAccess::WriteOnly),
+                ),
+            ),
+        );
+        let multiscattering_layout = device.create_bind_group_layout(
+            "multiscattering_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::COMPUTE,
+                (
+                    (0, uniform_buffer::<AtmosphereUniform>(false)),
+                    (2, texture_2d(TextureSampleType::Float { filterable: true })),
+                    (3, sampler(SamplerBindingType::Filtering)),
+                    (
+                        4,
+                        texture_storage_2d(LUT_FORMAT, StorageTextureAccess::WriteOnly),
+                    ),
+                ),
+            ),
+        );
+        let view_layout = device.create_bind_group_layout(
+            "atmosphere_view_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::FRAGMENT,
+                uniform_buffer::<ViewUniform>(true),
+            ),
+        );
+        let atmosphere_layout = atmosphere_layout(device);
+
+        let lut_sampler = device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+
+        let lut_shader = world.load_asset(ATMOSPHERE_LUT_SHADER);
+
+        let transmittance_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("transmittance_pipeline".into()),
+                layout: vec![transmittance_layout.clone()],
+                push_constant_ranges: default(),
+                shader: lut_shader.clone(),
+                shader_defs: vec![],
+                entry_point: "transmittance".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+        let multiscattering_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("multiscattering_pipeline".into()),
+                layout: vec![multiscattering_layout.clone()],
+                push_constant_ranges: default(),
+                shader: lut_shader,
+                shader_defs: vec![],
+                entry_point: "multiscattering".into(),
+                zero_initialize_workgroup_memory: false,
+            });
+
+        Self {
+            transmittance_layout,
+            multiscattering_layout,
+            view_layout,
+            atmosphere_layout,
+            lut_sampler,
+            transmittance_pipeline,
+            multiscattering_pipeline,
+            sky_shader: world.load_asset(ATMOSPHERE_SKY_SHADER),
+        }
+    }
+}
+
+impl AtmospherePipelines {
+    fn lut_pipelines<'a>(
+        &'a self,
+        pipeline_cache: &'a PipelineCache,
+    ) -> Option<(&'a ComputePipeline, &'a ComputePipeline)> {
+        Some((
+            pipeline_cache.get_compute_pipeline(self.transmittance_pipeline)?,
+            pipeline_cache.get_compute_pipeline(self.multiscattering_pipeline)?,
+        ))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Hash)]
+pub struct AtmosphereSkyKey {
+    pub msaa_samples: u32,
+    pub hdr: bool,
+}
+
+impl SpecializedRenderPipeline for AtmospherePipelines {
+    type Key = AtmosphereSkyKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let format = if key.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("atmosphere_sky_pipeline".into()),
+            layout: vec![self.view_layout.clone(), self.atmosphere_layout.clone()],
+            push_constant_ranges: default(),
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.sky_shader.clone(),
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: default(),
+            // only draw the sky, where nothing has been rendered yet
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: MultisampleState {
+                count: key.msaa_samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// The sky pipeline of a terrain view.
+pub(crate) struct AtmosphereSkyItem {
+    pipeline: CachedRenderPipelineId,
+}
+
+/// Queues the sky pipelines of all terrain views, whose terrain has an atmosphere.
+pub(crate) fn queue_atmosphere_sky(
+    pipeline_cache: Res<PipelineCache>,
+    atmosphere_pipelines: Res<AtmospherePipelines>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<AtmospherePipelines>>,
+    mut sky_items: ResMut<TerrainViewComponents<AtmosphereSkyItem>>,
+    gpu_atmospheres: Res<TerrainComponents<GpuAtmosphere>>,
+    gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
+    views: Query<(MainEntity, &ExtractedView, &Msaa)>,
+) {
+    sky_items.clear();
+
+    for (view, extracted_view, msaa) in &views {
+        for &terrain in gpu_atmospheres.keys() {
+            if !gpu_terrain_views.contains_key(&(terrain, view)) {
+                continue;
+            }
+
+            let key = AtmosphereSkyKey {
+                msaa_samples: msaa.samples(),
+                hdr: extracted_view.hdr,
+            };
+
+            let pipeline = pipelines.specialize(&pipeline_cache, &atmosphere_pipelines, key);
+
+            sky_items.insert((terrain, view), AtmosphereSkyItem { pipeline });
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct AtmosphereLutPass;
+
+impl render_graph::Node for AtmosphereLutPass {
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipelines = world.resource::<AtmospherePipelines>();
+        let gpu_atmospheres = world.resource::<TerrainComponents<GpuAtmosphere>>();
+
+        let Some((transmittance_pipeline, multiscattering_pipeline)) =
+            pipelines.lut_pipelines(pipeline_cache)
+        else {
+            return Ok(());
+        };
+
+        if !gpu_atmospheres
+            .values()
+            .any(|gpu_atmosphere| gpu_atmosphere.compute_luts)
+        {
+            return Ok(());
+        }
+
+        context.add_command_buffer_generation_task(move |device| {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+            for gpu_atmosphere in gpu_atmospheres.values() {
+                if !gpu_atmosphere.compute_luts {
+                    continue;
+                }
+
+                let workgroups = (TRANSMITTANCE_LUT_SIZE + 7) / 8;
+                pass.set_pipeline(transmittance_pipeline);
+                pass.set_bind_group(0, &gpu_atmosphere.transmittance_bind_group, &[]);
+                pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+
+                let workgroups = (MULTISCATTERING_LUT_SIZE + 7) / 8;
+                pass.set_pipeline(multiscattering_pipeline);
+                pass.set_bind_group(0, &gpu_atmosphere.multiscattering_bind_group, &[]);
+                pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+            }
+
+            drop(pass);
+
+            encoder.finish()
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct TerrainSkyPass;
+
+/// Draws the sky of all atmospheres behind the terrain.
+#[derive(Default)]
+pub struct TerrainSkyPassNode;
+
+impl ViewNode for TerrainSkyPassNode {
+    type ViewQuery = (
+        MainEntity,
+        &'static ExtractedCamera,
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static ViewUniformOffset,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        (main_view, camera, target, depth, view_uniform_offset): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let atmosphere_pipelines = world.resource::<AtmospherePipelines>();
+        let sky_items = world.resource::<TerrainViewComponents<AtmosphereSkyItem>>();
+        let gpu_atmospheres = world.resource::<TerrainComponents<GpuAtmosphere>>();
+
+        let draws = sky_items
+            .iter()
+            .filter(|&(&(_, view), _)| view == main_view)
+            .filter_map(|(&(terrain, _), sky_item)| {
+                let pipeline = pipeline_cache.get_render_pipeline(sky_item.pipeline)?;
+                let gpu_atmosphere = gpu_atmospheres.get(&terrain)?;
+
+                Some((pipeline, &gpu_atmosphere.bind_group))
+            })
+            .collect_vec();
+
+        if draws.is_empty() {
+            return Ok(());
+        }
+
+        let Some(view_binding) = world.resource::<ViewUniforms>().uniforms.binding() else {
+            return Ok(());
+        };
+
+        // Todo: prepare this in a separate system
+        let view_bind_group = device.create_bind_group(
+            "atmosphere_view_bind_group",
+            &atmosphere_pipelines.view_layout,
+            &BindGroupEntries::single(view_binding),
+        );
+        let view_offset = view_uniform_offset.offset;
+
+        // call this here, otherwise the order between passes is incorrect
+        let color_attachments = [Some(target.get_color_attachment())];
+        let depth_stencil_attachment = Some(depth.get_attachment(StoreOp::Store));
+
+        context.add_command_buffer_generation_task(move |device| {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+
+            let pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("terrain_sky_pass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+                ..default()
+            });
+            let mut pass = TrackedRenderPass::new(&device, pass);
+
+            if let Some(viewport) = camera.viewport.as_ref() {
+                pass.set_camera_viewport(viewport);
+            }
+
+            pass.set_bind_group(0, &view_bind_group, &[view_offset]);
+
+            for (pipeline, sky_bind_group) in draws {
+                pass.set_render_pipeline(pipeline);
+                pass.set_bind_group(1, sky_bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+
+            drop(pass);
+
+            encoder.finish()
+        });
+
+        Ok(())
+    }
+}
+
+/// Adds precomputed atmospheric scattering to all spherical terrains with a [`TerrainAtmosphere`].
+/// This plugin has to be added after the [`TerrainPlugin`](crate::plugin::TerrainPlugin).
+pub struct TerrainAtmospherePlugin;
+
+impl Plugin for TerrainAtmospherePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TerrainAtmosphere>()
+            .register_type::<AtmosphereSun>();
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainComponents<GpuAtmosphere>>()
+            .init_resource::<TerrainViewComponents<AtmosphereSkyItem>>()
+            .init_resource::<SpecializedRenderPipelines<AtmospherePipelines>>()
+            .add_systems(ExtractSchedule, GpuAtmosphere::extract)
+            .add_systems(
+                Render,
+                (
+                    GpuAtmosphere::prepare.in_set(RenderSet::Prepare),
+                    queue_atmosphere_sky.in_set(RenderSet::Queue),
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<TerrainSkyPassNode>>(Core3d, TerrainSkyPass)
+            .add_render_graph_edges(
+                Core3d,
+                (TerrainPass, TerrainSkyPass, Node3d::MainOpaquePass),
+            );
+
+        let mut render_graph = app
+            .sub_app_mut(RenderApp)
+            .world_mut()
+            .resource_mut::<RenderGraph>();
+        render_graph.add_node(AtmosphereLutPass, AtmosphereLutPass);
+        render_graph.add_node_edge(AtmosphereLutPass, CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<AtmospherePipelines>();
+    }
+}
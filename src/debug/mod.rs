@@ -1,6 +1,7 @@
 //! Contains a debug resource and systems controlling it to visualize different internal
 //! data of the plugin.
 use crate::{
+    atmosphere::AtmosphereSun,
     debug::{debug_camera_controller, debug_surface_approximation, orbital_camera_controller},
     terrain_data::{TileAtlas, TileTree},
     terrain_view::TerrainViewComponents,
@@ -305,6 +306,7 @@ pub(crate) fn debug_lighting(mut commands: Commands) {
             ..default()
         },
         Transform::from_xyz(-1.0, 1.0, -3.0).looking_at(Vec3::ZERO, Vec3::Y),
+        AtmosphereSun,
     ));
     commands.insert_resource(AmbientLight {
         brightness: 100.0,
@@ -0,0 +1,203 @@
+use crate::export::{ExportMesh, obj::file_name};
+use bevy::math::Vec3;
+use bytemuck::cast_slice;
+use serde_json::{Value, json};
+use std::{fs, io, path::Path};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Writes the mesh as a glTF 2.0 file with a separate `.bin` buffer.
+/// If a texture is provided, it is referenced as the base color texture of the material.
+pub(crate) fn write_gltf(
+    mesh: &ExportMesh,
+    texture_path: Option<&Path>,
+    path: &Path,
+) -> io::Result<()> {
+    if mesh.positions.is_empty() || mesh.indices.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "can not export an empty mesh to glTF",
+        ));
+    }
+
+    let buffer_path = path.with_extension("bin");
+
+    let views = [
+        (cast_slice::<_, u8>(&mesh.positions), ARRAY_BUFFER),
+        (cast_slice(&mesh.normals), ARRAY_BUFFER),
+        (cast_slice(&mesh.uvs), ARRAY_BUFFER),
+        (cast_slice(&mesh.indices), ELEMENT_ARRAY_BUFFER),
+    ];
+
+    let mut buffer = Vec::new();
+    let mut buffer_views = Vec::new();
+
+    for (data, target) in views {
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        buffer.extend_from_slice(data);
+    }
+
+    fs::write(&buffer_path, &buffer)?;
+
+    let (min, max) = mesh
+        .positions
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), &position| {
+            (min.min(position), max.max(position))
+        });
+
+    let vertex_count = mesh.positions.len();
+    let accessors = json!([
+        {
+            "bufferView": 0,
+            "componentType": FLOAT,
+            "count": vertex_count,
+            "type": "VEC3",
+            "min": min.to_array(),
+            "max": max.to_array(),
+        },
+        { "bufferView": 1, "componentType": FLOAT, "count": vertex_count, "type": "VEC3" },
+        { "bufferView": 2, "componentType": FLOAT, "count": vertex_count, "type": "VEC2" },
+        {
+            "bufferView": 3,
+            "componentType": UNSIGNED_INT,
+            "count": mesh.indices.len(),
+            "type": "SCALAR",
+        },
+    ]);
+
+    let mut material = json!({
+        "pbrMetallicRoughness": { "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+    });
+
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "bevy_terrain" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": "terrain" }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 },
+                "indices": 3,
+                "material": 0,
+            }],
+        }],
+        "buffers": [{ "uri": uri(&buffer_path), "byteLength": buffer.len() }],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+    });
+
+    if let Some(texture_path) = texture_path {
+        material["pbrMetallicRoughness"]["baseColorTexture"] = json!({ "index": 0 });
+
+        gltf["textures"] = json!([{ "source": 0, "sampler": 0 }]);
+        gltf["images"] = json!([{ "uri": uri(texture_path) }]);
+        gltf["samplers"] = json!([{
+            "magFilter": 9729,
+            "minFilter": 9987,
+            "wrapS": 33071,
+            "wrapT": 33071,
+        }]);
+    }
+
+    gltf["materials"] = Value::Array(vec![material]);
+
+    fs::write(path, serde_json::to_vec_pretty(&gltf)?)
+}
+
+/// Converts the file name of the path into a relative URI, by percent-encoding all reserved characters.
+fn uri(path: &Path) -> String {
+    file_name(path)
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::math::Vec2;
+    use std::{env, fs};
+
+    fn quad() -> ExportMesh {
+        ExportMesh {
+            positions: vec![
+                Vec3::new(-1.0, 0.0, -1.0),
+                Vec3::new(1.0, 0.5, -1.0),
+                Vec3::new(-1.0, 0.0, 1.0),
+                Vec3::new(1.0, 2.0, 1.0),
+            ],
+            normals: vec![Vec3::Y; 4],
+            uvs: vec![Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE],
+            indices: vec![0, 2, 1, 1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn write_and_parse_gltf() {
+        let directory = env::temp_dir().join(format!("bevy_terrain_gltf_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("terrain.gltf");
+        let texture_path = directory.join("albedo \"map\".png");
+        write_gltf(&quad(), Some(&texture_path), &path).unwrap();
+
+        let gltf: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let buffer = fs::read(path.with_extension("bin")).unwrap();
+
+        assert_eq!(gltf["buffers"][0]["uri"], "terrain.bin");
+        assert_eq!(gltf["buffers"][0]["byteLength"], buffer.len());
+        assert_eq!(buffer.len(), 4 * (12 + 12 + 8) + 6 * 4);
+
+        let accessors = gltf["accessors"].as_array().unwrap();
+        assert_eq!(accessors[0]["count"], 4);
+        assert_eq!(accessors[0]["min"], json!([-1.0, 0.0, -1.0]));
+        assert_eq!(accessors[0]["max"], json!([1.0, 2.0, 1.0]));
+        assert_eq!(accessors[3]["count"], 6);
+
+        // the buffer views cover the buffer without gaps
+        let views = gltf["bufferViews"].as_array().unwrap();
+        let end = views.iter().fold(0, |offset, view| {
+            assert_eq!(view["byteOffset"], offset);
+            offset + view["byteLength"].as_u64().unwrap()
+        });
+        assert_eq!(end, buffer.len() as u64);
+
+        // file names are escaped as URIs
+        assert_eq!(gltf["images"][0]["uri"], "albedo%20%22map%22.png");
+        assert_eq!(
+            gltf["materials"][0]["pbrMetallicRoughness"]["baseColorTexture"]["index"],
+            0
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn reject_empty_mesh() {
+        let mesh = ExportMesh {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        };
+        let path = env::temp_dir().join("bevy_terrain_empty.gltf");
+
+        let error = write_gltf(&mesh, None, &path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
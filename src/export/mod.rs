@@ -0,0 +1,311 @@
+//! Exports terrain regions to common 3D file formats (glTF and OBJ).
+//!
+//! The exported geometry is expressed in a local east-north-up (ENU) frame centered on the region,
+//! which avoids the float precision loss of planet-scale coordinates.
+//! Since both formats are conventionally y-up, the ENU axes are mapped to `(east, up, -north)`.
+
+mod gltf;
+mod obj;
+
+use crate::{
+    math::{Coordinate, TerrainShape, TileCoordinate},
+    mesh::{TerrainMeshData, TerrainRegion},
+    plugin::TerrainSettings,
+    terrain::TerrainConfig,
+    terrain_data::{AttachmentLabel, TileAtlas},
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    color::{ColorToComponents, ColorToPacked, LinearRgba, Srgba},
+    math::{DVec2, DVec3},
+    platform::collections::HashSet,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use itertools::Itertools;
+use std::{io, iter, path::Path};
+
+/// The file format of an export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// glTF 2.0 (`.gltf` with a separate `.bin` buffer)
+    Gltf,
+    /// Wavefront OBJ (`.obj` with an optional `.mtl` material)
+    Obj,
+}
+
+impl ExportFormat {
+    /// Selects the format based on the extension of the path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gltf" => Some(Self::Gltf),
+            "obj" => Some(Self::Obj),
+            _ => None,
+        }
+    }
+}
+
+/// Configures the export of a terrain region.
+#[derive(Clone, Debug)]
+pub struct ExportSettings {
+    /// The region to export.
+    pub region: TerrainRegion,
+    /// The lod of the exported geometry.
+    pub lod: u32,
+    /// The number of quads along each side of a tile.
+    pub grid_size: u32,
+    /// The attachment baked into the albedo texture, if any.
+    pub albedo: Option<AttachmentLabel>,
+    /// The resolution of the baked albedo texture.
+    pub texture_size: u32,
+}
+
+/// A local east-north-up frame on the terrain surface.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalFrame {
+    /// The local position of the frame origin.
+    pub origin: DVec3,
+    pub east: DVec3,
+    pub north: DVec3,
+    pub up: DVec3,
+}
+
+impl LocalFrame {
+    /// Creates the east-north-up frame at the local position `origin`.
+    /// The north direction of spherical terrains points towards the positive y-axis (the north pole),
+    /// while the one of planar terrains points along the negative z-axis.
+    pub fn new(shape: TerrainShape, origin: DVec3) -> Self {
+        let coordinate = Coordinate::from_local_position(origin, shape);
+        let up = (coordinate.local_position(shape, 1.0) - coordinate.local_position(shape, 0.0))
+            .normalize();
+
+        let pole = if shape.is_spherical() {
+            DVec3::Y
+        } else {
+            DVec3::NEG_Z
+        };
+
+        let north = (pole - up * pole.dot(up))
+            .try_normalize()
+            .unwrap_or_else(|| up.any_orthonormal_vector());
+        let east = north.cross(up);
+
+        Self {
+            origin,
+            east,
+            north,
+            up,
+        }
+    }
+
+    /// Converts the local `position` into the y-up export space of the frame.
+    pub fn position_to_frame(&self, position: DVec3) -> Vec3 {
+        self.direction_to_frame(position - self.origin)
+    }
+
+    /// Converts the local `direction` into the y-up export space of the frame.
+    pub fn direction_to_frame(&self, direction: DVec3) -> Vec3 {
+        DVec3::new(
+            direction.dot(self.east),
+            direction.dot(self.up),
+            -direction.dot(self.north),
+        )
+        .as_vec3()
+    }
+
+    /// Converts the `position` in the y-up export space of the frame back into local space.
+    pub fn frame_to_position(&self, position: DVec3) -> DVec3 {
+        self.origin + position.x * self.east + position.y * self.up - position.z * self.north
+    }
+}
+
+/// The geometry of an export in the local frame.
+pub(crate) struct ExportMesh {
+    pub(crate) positions: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) uvs: Vec<Vec2>,
+    pub(crate) indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    /// Transforms the mesh data into the local frame and generates top-down projected uvs.
+    fn new(data: &TerrainMeshData, frame: &LocalFrame) -> (Self, DVec2, DVec2) {
+        let positions = data
+            .positions
+            .iter()
+            .map(|&position| frame.position_to_frame(data.origin + position.as_dvec3()))
+            .collect_vec();
+        let normals = data
+            .normals
+            .iter()
+            .map(|&normal| frame.direction_to_frame(normal.as_dvec3()).normalize())
+            .collect_vec();
+
+        let (min, max) = positions
+            .iter()
+            .fold((DVec2::MAX, DVec2::MIN), |(min, max), position| {
+                let xz = position.xz().as_dvec2();
+                (min.min(xz), max.max(xz))
+            });
+        let extent = (max - min).max(DVec2::splat(f64::EPSILON));
+
+        let uvs = positions
+            .iter()
+            .map(|position| ((position.xz().as_dvec2() - min) / extent).as_vec2())
+            .collect_vec();
+
+        let mesh = Self {
+            positions,
+            normals,
+            uvs,
+            indices: data.indices.clone(),
+        };
+
+        (mesh, min, max)
+    }
+}
+
+/// Exports the region of the terrain to the `path`, using the data currently loaded in the tile atlas.
+///
+/// The format is selected based on the extension of the path (`.gltf` or `.obj`).
+/// If an albedo attachment is configured, its data is baked into a top-down projected texture,
+/// which is written as a PNG next to the exported file.
+pub fn export_terrain(
+    tile_atlas: &TileAtlas,
+    settings: &ExportSettings,
+    path: &Path,
+) -> io::Result<()> {
+    let Some(format) = ExportFormat::from_path(path) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported export format, expected a .gltf or .obj file",
+        ));
+    };
+
+    let data = TerrainMeshData::extract(
+        tile_atlas,
+        &settings.region,
+        settings.lod,
+        settings.grid_size,
+    );
+
+    if data.indices.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the export region does not contain any loaded terrain",
+        ));
+    }
+
+    let frame = LocalFrame::new(tile_atlas.shape, data.origin);
+    let (mesh, min, max) = ExportMesh::new(&data, &frame);
+
+    let texture_path = match &settings.albedo {
+        Some(label) => {
+            let texture_path = path.with_extension("png");
+            bake_texture(tile_atlas, settings, label, &frame, min, max)
+                .try_into_dynamic()
+                .map_err(io::Error::other)?
+                .save(&texture_path)
+                .map_err(io::Error::other)?;
+            Some(texture_path)
+        }
+        None => None,
+    };
+
+    match format {
+        ExportFormat::Gltf => gltf::write_gltf(&mesh, texture_path.as_deref(), path),
+        ExportFormat::Obj => obj::write_obj(&mesh, texture_path.as_deref(), path),
+    }
+}
+
+/// Exports the region of a preprocessed terrain from disk, without a running Bevy app.
+///
+/// This loads the required tiles from the terrain directory and then calls [`export_terrain`].
+pub fn export_terrain_from_disk(
+    terrain_path: &Path,
+    settings: &ExportSettings,
+    path: &Path,
+) -> io::Result<()> {
+    let mut config = TerrainConfig::load_file(terrain_path.join("config.tc.ron"))
+        .map_err(|error| io::Error::other(error.to_string()))?;
+
+    let labels = [Some(AttachmentLabel::Height), settings.albedo.clone()]
+        .into_iter()
+        .flatten()
+        .collect_vec();
+    config.attachments.retain(|label, _| labels.contains(label));
+
+    let spherical = config.shape.is_spherical();
+    let lod = settings.lod.min(config.lod_count - 1);
+
+    // the region, its neighbours for the normals at the border and all ancestors as a fallback
+    let mut tiles = HashSet::<_>::default();
+
+    for tile in settings.region.tiles(lod) {
+        tiles.insert(tile);
+        tiles.extend(
+            tile.neighbours(spherical)
+                .map(|(neighbour, _)| neighbour)
+                .filter(|&neighbour| neighbour != TileCoordinate::INVALID),
+        );
+    }
+
+    let ancestors = tiles
+        .iter()
+        .flat_map(|&tile| iter::successors(tile.parent(), |tile| tile.parent()))
+        .collect_vec();
+    tiles.extend(ancestors);
+
+    let terrain_settings = TerrainSettings {
+        attachments: labels.clone(),
+        atlas_size: tiles.len() as u32,
+        cpu_attachments: labels,
+    };
+
+    let mut tile_atlas =
+        TileAtlas::new(&config, &mut default(), &terrain_settings).map_err(io::Error::other)?;
+    tile_atlas.load_tiles_blocking(terrain_path, tiles)?;
+
+    export_terrain(&tile_atlas, settings, path)
+}
+
+/// Bakes the attachment into a top-down projected texture covering the rectangle between `min` and `max`
+/// in the xz-plane of the frame.
+fn bake_texture(
+    tile_atlas: &TileAtlas,
+    settings: &ExportSettings,
+    label: &AttachmentLabel,
+    frame: &LocalFrame,
+    min: DVec2,
+    max: DVec2,
+) -> Image {
+    let size = settings.texture_size;
+
+    let data = (0..size)
+        .cartesian_product(0..size)
+        .flat_map(|(y, x)| {
+            let uv = (DVec2::new(x as f64, y as f64) + 0.5) / size as f64;
+            let xz = min + (max - min) * uv;
+            let position = frame.frame_to_position(DVec3::new(xz.x, 0.0, xz.y));
+            let coordinate = Coordinate::from_local_position(position, tile_atlas.shape);
+
+            let value = tile_atlas
+                .sample_attachment_lod(label, coordinate, settings.lod)
+                .map_or(Vec4::ZERO, |(value, _)| value);
+
+            Srgba::from(LinearRgba::from_vec4(value.with_w(1.0))).to_u8_array()
+        })
+        .collect_vec();
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD,
+    )
+}
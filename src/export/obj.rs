@@ -0,0 +1,135 @@
+use crate::export::ExportMesh;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the mesh as a Wavefront OBJ file.
+/// If a texture is provided, a material referencing it is written to a `.mtl` file next to it.
+pub(crate) fn write_obj(
+    mesh: &ExportMesh,
+    texture_path: Option<&Path>,
+    path: &Path,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "# exported by bevy_terrain")?;
+
+    if let Some(texture_path) = texture_path {
+        let material_path = path.with_extension("mtl");
+        let mut material = BufWriter::new(File::create(&material_path)?);
+
+        writeln!(material, "newmtl terrain")?;
+        writeln!(material, "Kd 1.0 1.0 1.0")?;
+        writeln!(material, "map_Kd {}", file_name(texture_path))?;
+        material.flush()?;
+
+        writeln!(writer, "mtllib {}", file_name(&material_path))?;
+        writeln!(writer, "usemtl terrain")?;
+    }
+
+    for position in &mesh.positions {
+        writeln!(writer, "v {} {} {}", position.x, position.y, position.z)?;
+    }
+
+    for normal in &mesh.normals {
+        writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+
+    // the v axis of OBJ texture coordinates points upwards
+    for uv in &mesh.uvs {
+        writeln!(writer, "vt {} {}", uv.x, 1.0 - uv.y)?;
+    }
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+        writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+
+    writer.flush()
+}
+
+pub(crate) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::math::{Vec2, Vec3};
+    use std::{env, fs};
+
+    fn parse<const N: usize>(line: &str) -> [f32; N] {
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect::<Vec<f32>>();
+        values.try_into().unwrap()
+    }
+
+    #[test]
+    fn write_and_parse_obj() {
+        let mesh = ExportMesh {
+            positions: vec![
+                Vec3::new(-1.0, 0.0, -1.0),
+                Vec3::new(1.0, 0.5, -1.0),
+                Vec3::new(-1.0, 0.0, 1.0),
+                Vec3::new(1.0, 2.0, 1.0),
+            ],
+            normals: vec![Vec3::Y, Vec3::X, Vec3::Z, Vec3::Y],
+            uvs: vec![Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::new(0.25, 0.75)],
+            indices: vec![0, 2, 1, 1, 2, 3],
+        };
+
+        let directory = env::temp_dir().join(format!("bevy_terrain_obj_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("terrain.obj");
+        write_obj(&mesh, Some(&directory.join("albedo.png")), &path).unwrap();
+
+        let (mut positions, mut normals, mut uvs, mut indices) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut material = None;
+
+        for line in fs::read_to_string(&path).unwrap().lines() {
+            let (keyword, rest) = line.split_once(' ').unwrap();
+
+            match keyword {
+                "v" => positions.push(Vec3::from_array(parse(rest))),
+                "vn" => normals.push(Vec3::from_array(parse(rest))),
+                "vt" => uvs.push(Vec2::from_array(parse(rest))),
+                "f" => {
+                    for vertex in rest.split_whitespace() {
+                        let [v, vt, vn]: [u32; 3] = vertex
+                            .split('/')
+                            .map(|index| index.parse().unwrap())
+                            .collect::<Vec<_>>()
+                            .try_into()
+                            .unwrap();
+                        assert!(v == vt && v == vn);
+                        indices.push(v - 1);
+                    }
+                }
+                "mtllib" => material = Some(rest.to_string()),
+                _ => {}
+            }
+        }
+
+        assert_eq!(positions, mesh.positions);
+        assert_eq!(normals, mesh.normals);
+        assert_eq!(indices, mesh.indices);
+
+        // the v axis of the texture coordinates is flipped
+        let flipped = uvs.iter().map(|uv| Vec2::new(uv.x, 1.0 - uv.y));
+        assert!(flipped.eq(mesh.uvs.iter().copied()));
+
+        assert_eq!(material.as_deref(), Some("terrain.mtl"));
+        let material = fs::read_to_string(directory.join("terrain.mtl")).unwrap();
+        assert!(material.contains("map_Kd albedo.png"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
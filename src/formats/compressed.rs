@@ -0,0 +1,46 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    image::ImageLoaderError,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+/// Loads block compressed tiles, which are stored as raw blocks of all mip levels.
+///
+/// The dimensions and the format are not stored in the file, since they are already known
+/// from the attachment config.
+#[derive(Default)]
+pub struct CompressedLoader;
+impl AssetLoader for CompressedLoader {
+    type Asset = Image;
+    type Settings = ();
+    type Error = ImageLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Image, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut image = Image::new_uninit(
+            Extent3d::default(),
+            TextureDimension::D2,
+            TextureFormat::bevy_default(),
+            RenderAssetUsages::MAIN_WORLD,
+        );
+
+        // Avoid Image::new size assert
+        image.data = Some(bytes);
+
+        Ok(image)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bc"]
+    }
+}
@@ -1,3 +1,6 @@
+mod compressed;
 mod tiff;
 
-pub use self::tiff::TiffLoader;
+pub use self::{compressed::CompressedLoader, tiff::TiffLoader};
+
+pub(crate) use self::tiff::decode_tiff;
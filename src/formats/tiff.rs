@@ -9,7 +9,10 @@ use bevy::{
 };
 use bytemuck::cast_slice;
 use std::io::Cursor;
-use tiff::decoder::{Decoder, DecodingResult};
+use tiff::{
+    TiffResult,
+    decoder::{Decoder, DecodingResult},
+};
 
 #[derive(Default)]
 pub struct TiffLoader;
@@ -26,23 +29,7 @@ impl AssetLoader for TiffLoader {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
 
-        let mut decoder = Decoder::new(Cursor::new(bytes)).unwrap();
-
-        let (width, height) = decoder.dimensions().unwrap();
-
-        let data = match decoder.read_image().unwrap() {
-            DecodingResult::U8(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U16(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U64(data) => cast_slice(&data).to_vec(),
-            DecodingResult::F16(_) => panic!("TIFF F16 format is not supported in Bevy"),
-            DecodingResult::F32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::F64(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I8(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I16(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I64(data) => cast_slice(&data).to_vec(),
-        };
+        let (width, height, data) = decode_tiff(bytes).unwrap();
 
         let mut image = Image::new_uninit(
             Extent3d {
@@ -65,3 +52,26 @@ impl AssetLoader for TiffLoader {
         &["tif", "tiff"]
     }
 }
+
+/// Decodes a TIFF image into its dimensions and raw pixel bytes.
+pub(crate) fn decode_tiff(bytes: Vec<u8>) -> TiffResult<(u32, u32, Vec<u8>)> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))?;
+
+    let (width, height) = decoder.dimensions()?;
+
+    let data = match decoder.read_image()? {
+        DecodingResult::U8(data) => cast_slice(&data).to_vec(),
+        DecodingResult::U16(data) => cast_slice(&data).to_vec(),
+        DecodingResult::U32(data) => cast_slice(&data).to_vec(),
+        DecodingResult::U64(data) => cast_slice(&data).to_vec(),
+        DecodingResult::F16(data) => cast_slice(&data).to_vec(),
+        DecodingResult::F32(data) => cast_slice(&data).to_vec(),
+        DecodingResult::F64(data) => cast_slice(&data).to_vec(),
+        DecodingResult::I8(data) => cast_slice(&data).to_vec(),
+        DecodingResult::I16(data) => cast_slice(&data).to_vec(),
+        DecodingResult::I32(data) => cast_slice(&data).to_vec(),
+        DecodingResult::I64(data) => cast_slice(&data).to_vec(),
+    };
+
+    Ok((width, height, data))
+}
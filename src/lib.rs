@@ -37,46 +37,87 @@
 //! project specific problem and thus there does not exist a one-size-fits-all solution.
 //! You can define your own terrain [Material](bevy::prelude::Material) and shader with all the
 //! detail textures tailored to your application.
-//! In the future this plugin will provide modular shader functions to make techniques like splat
-//! mapping, triplane mapping, etc. easier.
-//! Additionally a virtual texturing solution might be integrated to achieve better performance.
+//! The [`TerrainPbrMaterial`](material::TerrainPbrMaterial) shades the terrain with the
+//! physically based lighting of Bevy and can be extended to override only parts of the shading.
+//! The [`TerrainSplatMaterial`](material::TerrainSplatMaterial) blends detail materials based on a
+//! splat map, using the modular shader functions of `bevy_terrain::detail`.
+//! These include triplanar mapping and stochastic sampling, which are enabled with the
+//! [`TerrainPipelineFlags`](render::TerrainPipelineFlags) of the material plugin.
+//! Regions without splat data are textured by [`TerrainMaterialRules`](material::TerrainMaterialRules)
+//! based on the slope, height and latitude of the terrain.
+//! The [`TerrainTopographicMaterial`](material::TerrainTopographicMaterial) renders topographic maps
+//! with hypsometric tinting, hillshading and contour lines.
+//! Spherical terrains can be surrounded by a [`TerrainAtmosphere`](atmosphere::TerrainAtmosphere),
+//! which adds aerial perspective and the sky.
+//! Oceans, lakes and rivers are rendered on top of the terrain with a [`TerrainWater`](water::TerrainWater).
+//! Roads, borders and selection polygons are draped onto the surface with a
+//! [`TerrainOverlay`](overlay::TerrainOverlay).
+//! Vegetation and rocks are placed on the GPU with a [`TerrainScatter`](scatter::TerrainScatter).
+//! Imagery with a higher resolution than the geometry tiles is streamed with a
+//! [`TerrainVirtualTexture`](virtual_texture::TerrainVirtualTexture).
 //!
 //! [^note]: Some of these claims are not yet fully implemented.
 
+pub mod atmosphere;
 pub mod debug;
+pub mod export;
 pub mod formats;
+pub mod material;
 pub mod math;
+pub mod mesh;
+pub mod overlay;
+pub mod physics;
 pub mod picking;
 pub mod plugin;
 pub mod preprocess;
 pub mod render;
+pub mod scatter;
 pub mod shaders;
 pub mod spawn;
 pub mod terrain;
 pub mod terrain_data;
 pub mod terrain_view;
 pub mod util;
+pub mod virtual_texture;
+pub mod water;
 
 #[doc(hidden)]
 pub mod prelude {
     //! `use bevy_terrain::prelude::*;` to import common components, bundles, and plugins.
 
     pub use crate::{
+        atmosphere::{AtmosphereSun, TerrainAtmosphere, TerrainAtmospherePlugin},
         debug::{
             DebugCameraController, DebugTerrainMaterial, LoadingImages, OrbitalCameraController,
             TerrainDebugPlugin,
         },
-        math::{TerrainShape, TileCoordinate},
-        picking::{PickingData, TerrainPickingPlugin},
+        export::{ExportFormat, ExportSettings, export_terrain, export_terrain_from_disk},
+        material::{
+            ContourLines, Hillshade, MaterialRule, SplatLayer, TerrainMaterialRules,
+            TerrainPbrMaterial, TerrainSplatExtension, TerrainSplatMaterial,
+            TerrainTopographicExtension, TerrainTopographicMaterial,
+        },
+        math::{Coordinate, SurfaceSample, TerrainShape, TileCoordinate},
+        mesh::{TerrainMeshData, TerrainRegion, extract_mesh},
+        overlay::{OverlayGeometry, OverlayShape, TerrainOverlay, TerrainOverlayPlugin},
+        physics::{
+            TerrainCollider, TerrainColliderFocus, TerrainColliderPlugin, TerrainColliderSettings,
+        },
+        picking::{PickingData, PickingQuery, PickingResult, TerrainPickingPlugin},
         plugin::{TerrainPlugin, TerrainSettings},
         // preprocess::{PreprocessDataset, Preprocessor, SphericalDataset, TerrainPreprocessPlugin},
-        render::TerrainMaterialPlugin,
+        render::{TerrainMaterialPlugin, TerrainPipelineFlags, TerrainShadowSettings},
+        scatter::{ScatterLayer, TerrainScatter, TerrainScatterPlugin},
         spawn::SpawnTerrainCommandsExt,
         terrain::TerrainConfig,
         terrain_data::{
             AttachmentConfig, AttachmentFormat, AttachmentLabel, GpuTileAtlas, TileAtlas, TileTree,
         },
-        terrain_view::{TerrainViewComponents, TerrainViewConfig},
+        terrain_view::{
+            TerrainCamera, TerrainProjection, TerrainViewComponents, TerrainViewConfig,
+        },
+        virtual_texture::{TerrainVirtualTexture, TerrainVirtualTexturePlugin},
+        water::{TerrainWater, TerrainWaterPlugin},
     };
     pub use big_space::{commands::BigSpaceCommands, grid::Grid};
 }
@@ -0,0 +1,38 @@
+//! Terrain materials, which shade the terrain with the physically based lighting of Bevy.
+//!
+//! The [`TerrainPbrMaterial`] mirrors the [`StandardMaterial`] for terrains.
+//! Just like the standard material, it can be extended using the
+//! [`ExtendedMaterial`](bevy::pbr::ExtendedMaterial) of Bevy, by overriding only the parts
+//! of the shading required.
+//! The fragment shader of an extension imports `pbr_input_from_terrain_material` and
+//! `apply_terrain_lighting` from `bevy_terrain::pbr_fragment`, modifies the returned pbr input
+//! (e.g. the base color) and then applies the lighting.
+//! The bindings of the extension start at binding 100 of the material bind group.
+//!
+//! The [`TerrainSplatMaterial`] is such an extension, which blends detail materials based on a splat map.
+//! The shader functions it is built with (`bevy_terrain::detail`) can be reused by custom materials.
+//! In regions without splat data, it falls back to the procedural [`TerrainMaterialRules`],
+//! which can also be baked into a splat attachment by the preprocessor.
+//!
+//! The [`TerrainTopographicMaterial`] visualizes the terrain like a topographic map, with a
+//! hypsometric tint, hillshading and contour lines (`bevy_terrain::topographic`).
+
+mod pbr;
+mod rules;
+mod splat;
+mod topographic;
+
+pub use self::{
+    pbr::{TerrainPbrMaterial, TerrainPbrMaterialUniform},
+    rules::{
+        GpuMaterialRule, GpuMaterialRules, MAX_MATERIAL_RULES, MaterialRule, TerrainMaterialRules,
+    },
+    splat::{
+        GpuSplatLayer, MAX_SPLAT_LAYERS, SplatLayer, TerrainSplatExtension, TerrainSplatMaterial,
+        TerrainSplatUniform,
+    },
+    topographic::{
+        ContourLines, Hillshade, TerrainTopographicExtension, TerrainTopographicMaterial,
+        TerrainTopographicUniform,
+    },
+};
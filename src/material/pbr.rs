@@ -0,0 +1,79 @@
+use crate::shaders::PBR_SHADER;
+use bevy::{
+    prelude::*,
+    render::{render_asset::RenderAssets, render_resource::*, texture::GpuImage},
+};
+
+const FOG_ENABLED_BIT: u32 = 1;
+
+/// A terrain material with the physically based inputs of the [`StandardMaterial`].
+///
+/// The normal of the terrain is computed from the gradient of the height attachment.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+#[uniform(0, TerrainPbrMaterialUniform)]
+#[reflect(Default, Debug)]
+pub struct TerrainPbrMaterial {
+    /// The base color of the terrain.
+    pub base_color: Color,
+    /// The emitted light of the terrain.
+    pub emissive: LinearRgba,
+    /// The perceived roughness of the terrain, in the range `[0.089, 1.0]`.
+    pub perceptual_roughness: f32,
+    /// How metallic the terrain appears, in the range `[0.0, 1.0]`.
+    pub metallic: f32,
+    /// The specular intensity of non-metals, in the range `[0.0, 1.0]`.
+    pub reflectance: f32,
+    /// Whether the terrain is affected by the distance fog of the view.
+    pub fog_enabled: bool,
+}
+
+impl Default for TerrainPbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Color::srgb(0.5, 0.5, 0.5),
+            emissive: LinearRgba::BLACK,
+            perceptual_roughness: 1.0,
+            metallic: 0.0,
+            reflectance: 0.0,
+            fog_enabled: true,
+        }
+    }
+}
+
+impl Material for TerrainPbrMaterial {
+    fn fragment_shader() -> ShaderRef {
+        PBR_SHADER.into()
+    }
+}
+
+/// The GPU representation of the [`TerrainPbrMaterial`].
+#[derive(Clone, Default, ShaderType)]
+pub struct TerrainPbrMaterialUniform {
+    pub base_color: Vec4,
+    pub emissive: Vec4,
+    pub perceptual_roughness: f32,
+    pub metallic: f32,
+    pub reflectance: f32,
+    pub flags: u32,
+}
+
+impl AsBindGroupShaderType<TerrainPbrMaterialUniform> for TerrainPbrMaterial {
+    fn as_bind_group_shader_type(
+        &self,
+        _images: &RenderAssets<GpuImage>,
+    ) -> TerrainPbrMaterialUniform {
+        let mut flags = 0;
+        if self.fog_enabled {
+            flags |= FOG_ENABLED_BIT;
+        }
+
+        TerrainPbrMaterialUniform {
+            base_color: LinearRgba::from(self.base_color).to_vec4(),
+            emissive: self.emissive.to_vec4(),
+            perceptual_roughness: self.perceptual_roughness,
+            metallic: self.metallic,
+            reflectance: self.reflectance,
+            flags,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    math::TileCoordinate,
+    terrain_data::{AttachmentConfig, TileAtlas},
+};
+use bevy::{
+    asset::ron,
+    color::{ColorToPacked, LinearRgba, Srgba},
+    math::Vec4,
+    prelude::*,
+    render::render_resource::ShaderType,
+};
+use itertools::iproduct;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// The maximum number of rules evaluated on the GPU.
+pub const MAX_MATERIAL_RULES: usize = 8;
+
+/// A rule of the [`TerrainMaterialRules`], which paints its layer where all of its conditions apply.
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone)]
+#[serde(default)]
+#[reflect(Default, Debug)]
+pub struct MaterialRule {
+    /// The splat layer painted by this rule.
+    pub layer: u32,
+    /// The range of the slope in degrees.
+    pub slope: (f32, f32),
+    /// The range of the height in meters.
+    pub height: (f32, f32),
+    /// Lowers the height range towards the poles, in meters per degree of absolute latitude.
+    /// This is used to model e.g. the snow line.
+    pub latitude_falloff: f32,
+    /// The width of the transition at the borders of the slope range in degrees.
+    pub slope_blend: f32,
+    /// The width of the transition at the borders of the height range in meters.
+    pub height_blend: f32,
+}
+
+impl Default for MaterialRule {
+    fn default() -> Self {
+        Self {
+            layer: 0,
+            slope: (0.0, 90.0),
+            height: (f32::MIN, f32::MAX),
+            latitude_falloff: 0.0,
+            slope_blend: 5.0,
+            height_blend: 100.0,
+        }
+    }
+}
+
+impl MaterialRule {
+    /// Computes how much the rule applies, in the range [0, 1].
+    /// Mirrors `rule_coverage` in `splat.wgsl`.
+    pub fn coverage(&self, slope: f32, height: f32, latitude: f32) -> f32 {
+        let height = height + self.latitude_falloff * latitude.abs();
+
+        range_weight(slope, self.slope, self.slope_blend)
+            * range_weight(height, self.height, self.height_blend)
+    }
+}
+
+fn range_weight(value: f32, (min, max): (f32, f32), blend: f32) -> f32 {
+    let blend = blend.max(f32::EPSILON);
+
+    ((value - min) / blend + 1.0).clamp(0.0, 1.0) * ((max - value) / blend + 1.0).clamp(0.0, 1.0)
+}
+
+/// A set of rules, which procedurally assigns the layers of a splat material based on the slope,
+/// height and geodetic latitude of the terrain, e.g. rock on steep slopes, snow above an altitude
+/// and grass elsewhere.
+///
+/// The rules are evaluated in order, with later rules painting over earlier ones.
+/// They are stored alongside the [`TerrainConfig`](crate::terrain::TerrainConfig) as a `.tr.ron` asset.
+/// The [`TerrainSplatMaterial`](super::TerrainSplatMaterial) evaluates them per fragment
+/// in regions without splat data, while the preprocessor can bake them into a splat attachment.
+#[derive(Serialize, Deserialize, Asset, Reflect, Debug, Clone, Default)]
+#[serde(default)]
+#[reflect(Default, Debug)]
+pub struct TerrainMaterialRules {
+    /// The layer used where no rule applies.
+    pub base_layer: u32,
+    /// The rules in the order of their evaluation.
+    pub rules: Vec<MaterialRule>,
+}
+
+impl TerrainMaterialRules {
+    /// Evaluates the weights of the four layers, given the slope in degrees, the height in meters
+    /// and the latitude in degrees.
+    /// Mirrors `evaluate_rules` in `splat.wgsl`.
+    pub fn evaluate(&self, slope: f32, height: f32, latitude: f32) -> Vec4 {
+        let layer = |layer: u32| {
+            Vec4::AXES
+                .get(layer as usize)
+                .copied()
+                .unwrap_or(Vec4::ZERO)
+        };
+
+        self.rules
+            .iter()
+            .take(MAX_MATERIAL_RULES)
+            .fold(layer(self.base_layer), |weights, rule| {
+                weights.lerp(layer(rule.layer), rule.coverage(slope, height, latitude))
+            })
+    }
+
+    /// Bakes the rules into the data of a splat attachment tile (including its border), using the
+    /// height data of the tile atlas.
+    /// The weights are encoded in sRGB, since `Rgba8U` attachments are sampled as sRGB textures.
+    /// Pixels without height data use the base layer.
+    pub fn bake_tile(
+        &self,
+        tile_atlas: &TileAtlas,
+        tile: TileCoordinate,
+        attachment: &AttachmentConfig,
+    ) -> Vec<[u8; 4]> {
+        let spherical = tile_atlas.shape.is_spherical();
+
+        iproduct!(0..attachment.texture_size, 0..attachment.texture_size)
+            .map(|(y, x)| {
+                let pixel = IVec2::new(x as i32, y as i32);
+                let coordinate = attachment.pixel_coordinate(tile, pixel, spherical);
+
+                let weights = match tile_atlas.sample_surface_lod(coordinate, tile.lod) {
+                    Some(sample) => {
+                        let latitude = if spherical {
+                            coordinate.lat_lon(tile_atlas.shape).x as f32
+                        } else {
+                            0.0
+                        };
+
+                        self.evaluate(sample.slope.to_degrees() as f32, sample.height, latitude)
+                    }
+                    None => self.evaluate(0.0, f32::MIN, 0.0),
+                };
+
+                Srgba::from(LinearRgba::from_vec4(weights)).to_u8_array()
+            })
+            .collect()
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let encoded = fs::read_to_string(path)?;
+        Ok(ron::from_str(&encoded)?)
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let encoded = ron::ser::to_string_pretty(self, default())?;
+        Ok(fs::write(path, encoded)?)
+    }
+}
+
+/// The GPU representation of a [`MaterialRule`].
+#[derive(Clone, Default, ShaderType)]
+pub struct GpuMaterialRule {
+    pub slope: Vec2,
+    pub height: Vec2,
+    pub layer: u32,
+    pub latitude_falloff: f32,
+    pub slope_blend: f32,
+    pub height_blend: f32,
+}
+
+/// The GPU representation of the [`TerrainMaterialRules`].
+#[derive(Clone, Default, ShaderType)]
+pub struct GpuMaterialRules {
+    pub rules: [GpuMaterialRule; MAX_MATERIAL_RULES],
+    pub rule_count: u32,
+    pub base_layer: u32,
+}
+
+impl From<&TerrainMaterialRules> for GpuMaterialRules {
+    fn from(rules: &TerrainMaterialRules) -> Self {
+        let mut gpu_rules = <[GpuMaterialRule; MAX_MATERIAL_RULES]>::default();
+
+        for (gpu_rule, rule) in gpu_rules.iter_mut().zip(&rules.rules) {
+            *gpu_rule = GpuMaterialRule {
+                slope: rule.slope.into(),
+                height: rule.height.into(),
+                layer: rule.layer,
+                latitude_falloff: rule.latitude_falloff,
+                slope_blend: rule.slope_blend.max(f32::EPSILON),
+                height_blend: rule.height_blend.max(f32::EPSILON),
+            };
+        }
+
+        Self {
+            rules: gpu_rules,
+            rule_count: rules.rules.len().min(MAX_MATERIAL_RULES) as u32,
+            base_layer: rules.base_layer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn alpine_rules() -> TerrainMaterialRules {
+        TerrainMaterialRules {
+            base_layer: 0,
+            rules: vec![
+                MaterialRule {
+                    layer: 1,
+                    slope: (35.0, 90.0),
+                    ..default()
+                },
+                MaterialRule {
+                    layer: 2,
+                    height: (3000.0, f32::MAX),
+                    latitude_falloff: 40.0,
+                    ..default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn evaluate_rules() {
+        let rules = alpine_rules();
+
+        // grass on flat lowlands
+        assert_eq!(rules.evaluate(10.0, 500.0, 0.0), Vec4::X);
+        // rock on steep slopes
+        assert_eq!(rules.evaluate(60.0, 500.0, 0.0), Vec4::Y);
+        // snow above the snow line
+        assert_eq!(rules.evaluate(60.0, 3500.0, 0.0), Vec4::Z);
+        // the snow line is lower towards the poles
+        assert_eq!(rules.evaluate(10.0, 2000.0, 0.0), Vec4::X);
+        assert_eq!(rules.evaluate(10.0, 2000.0, -60.0), Vec4::Z);
+
+        // the layers are blended at the borders of the ranges
+        let weights = rules.evaluate(32.5, 500.0, 0.0);
+        assert!((weights - Vec4::new(0.5, 0.5, 0.0, 0.0)).length() < 1e-6);
+        assert!((weights.element_sum() - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    material::{GpuMaterialRules, TerrainMaterialRules, TerrainPbrMaterial},
+    shaders::SPLAT_SHADER,
+};
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::{render_asset::RenderAssets, render_resource::*, texture::GpuImage},
+};
+
+/// The maximum number of layers of the [`TerrainSplatExtension`].
+pub const MAX_SPLAT_LAYERS: usize = 4;
+
+const ALBEDO_TEXTURES_BIT: u32 = 1;
+const NORMAL_TEXTURES_BIT: u32 = 2;
+
+/// A terrain material, which blends up to four detail materials based on a splat map.
+pub type TerrainSplatMaterial = ExtendedMaterial<TerrainPbrMaterial, TerrainSplatExtension>;
+
+/// A detail material of the [`TerrainSplatExtension`].
+#[derive(Reflect, Debug, Clone)]
+#[reflect(Default, Debug)]
+pub struct SplatLayer {
+    /// The color of the layer. It tints the albedo texture and is used on its own
+    /// once the detail textures have faded out.
+    pub color: Color,
+    /// The world space size of one repetition of the detail textures.
+    /// This is snapped to a power of two subdivision of the terrain faces.
+    pub scale: f32,
+    /// The perceived roughness of the layer.
+    pub perceptual_roughness: f32,
+    /// How metallic the layer appears.
+    pub metallic: f32,
+}
+
+impl Default for SplatLayer {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            scale: 10.0,
+            perceptual_roughness: 1.0,
+            metallic: 0.0,
+        }
+    }
+}
+
+/// Extends the [`TerrainPbrMaterial`] with splat map texturing.
+///
+/// The weights of the layers are read from the four channels of the `splat` attachment
+/// (e.g. an [`Rgba8U`](crate::terrain_data::AttachmentFormat::Rgba8U) attachment),
+/// which has to be part of the [`TerrainSettings`](crate::plugin::TerrainSettings).
+/// Each layer samples its slice of the albedo and normal texture arrays, with the alpha channel of
+/// the albedo texture storing the height used for the height based blending.
+/// Both texture arrays should use a repeating sampler.
+///
+/// Steep slopes can be textured with a triplanar projection and visible repetitions can be hidden
+/// with stochastic sampling, by enabling [`TerrainPipelineFlags::TRIPLANAR_MAPPING`](crate::render::TerrainPipelineFlags::TRIPLANAR_MAPPING)
+/// and [`TerrainPipelineFlags::STOCHASTIC_SAMPLING`](crate::render::TerrainPipelineFlags::STOCHASTIC_SAMPLING)
+/// in the [`TerrainMaterialPlugin`](crate::render::TerrainMaterialPlugin).
+///
+/// Where the splat map contains no weights, the layers are assigned by the [`TerrainMaterialRules`] instead.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+#[uniform(100, TerrainSplatUniform)]
+#[reflect(Default, Debug)]
+pub struct TerrainSplatExtension {
+    /// The detail materials, at most [`MAX_SPLAT_LAYERS`].
+    pub layers: Vec<SplatLayer>,
+    /// The albedo (rgb) and height (a) textures of the layers.
+    #[texture(101, dimension = "2d_array")]
+    #[sampler(102)]
+    pub albedo_textures: Option<Handle<Image>>,
+    /// The tangent space normal textures of the layers.
+    #[texture(103, dimension = "2d_array")]
+    pub normal_textures: Option<Handle<Image>>,
+    /// The height difference over which layers are blended. Smaller values result in sharper transitions.
+    pub blend_depth: f32,
+    /// The view distance at which the detail textures start to fade out.
+    pub fade_start: f32,
+    /// The view distance at which the detail textures have faded out.
+    pub fade_end: f32,
+    /// The rules used in regions without splat data.
+    pub rules: TerrainMaterialRules,
+}
+
+impl Default for TerrainSplatExtension {
+    fn default() -> Self {
+        Self {
+            layers: vec![default(); MAX_SPLAT_LAYERS],
+            albedo_textures: None,
+            normal_textures: None,
+            blend_depth: 0.2,
+            fade_start: 500.0,
+            fade_end: 2000.0,
+            rules: default(),
+        }
+    }
+}
+
+impl MaterialExtension for TerrainSplatExtension {
+    fn fragment_shader() -> ShaderRef {
+        SPLAT_SHADER.into()
+    }
+}
+
+#[derive(Clone, Default, ShaderType)]
+pub struct GpuSplatLayer {
+    pub color: Vec4,
+    pub scale: f32,
+    pub perceptual_roughness: f32,
+    pub metallic: f32,
+}
+
+/// The GPU representation of the [`TerrainSplatExtension`].
+#[derive(Clone, Default, ShaderType)]
+pub struct TerrainSplatUniform {
+    pub layers: [GpuSplatLayer; MAX_SPLAT_LAYERS],
+    pub blend_depth: f32,
+    pub fade_start: f32,
+    pub fade_end: f32,
+    pub flags: u32,
+    pub rules: GpuMaterialRules,
+}
+
+impl AsBindGroupShaderType<TerrainSplatUniform> for TerrainSplatExtension {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<GpuImage>) -> TerrainSplatUniform {
+        let mut layers = <[GpuSplatLayer; MAX_SPLAT_LAYERS]>::default();
+
+        for (gpu_layer, layer) in layers.iter_mut().zip(&self.layers) {
+            *gpu_layer = GpuSplatLayer {
+                color: LinearRgba::from(layer.color).to_vec4(),
+                scale: layer.scale,
+                perceptual_roughness: layer.perceptual_roughness,
+                metallic: layer.metallic,
+            };
+        }
+
+        let mut flags = 0;
+        if self.albedo_textures.is_some() {
+            flags |= ALBEDO_TEXTURES_BIT;
+        }
+        if self.normal_textures.is_some() {
+            flags |= NORMAL_TEXTURES_BIT;
+        }
+
+        TerrainSplatUniform {
+            layers,
+            blend_depth: self.blend_depth,
+            fade_start: self.fade_start,
+            fade_end: self.fade_end,
+            flags,
+            rules: GpuMaterialRules::from(&self.rules),
+        }
+    }
+}
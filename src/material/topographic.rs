@@ -0,0 +1,150 @@
+use crate::{material::TerrainPbrMaterial, shaders::TOPOGRAPHIC_SHADER};
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::{render_asset::RenderAssets, render_resource::*, texture::GpuImage},
+};
+
+const GRADIENT_BIT: u32 = 1;
+const TINT_RANGE_BIT: u32 = 2;
+const CONTOURS_BIT: u32 = 4;
+const HILLSHADE_BIT: u32 = 8;
+
+/// A terrain material for topographic maps, with hypsometric tinting, hillshading and contour lines.
+pub type TerrainTopographicMaterial =
+    ExtendedMaterial<TerrainPbrMaterial, TerrainTopographicExtension>;
+
+/// The contour lines of the [`TerrainTopographicExtension`].
+#[derive(Reflect, Debug, Clone)]
+#[reflect(Default, Debug)]
+pub struct ContourLines {
+    /// The height difference between neighbouring lines in meters.
+    pub interval: f32,
+    /// Every n-th line is drawn as a major line.
+    pub major_every: u32,
+    /// The width of the minor lines in pixels.
+    pub width: f32,
+    /// The width of the major lines in pixels.
+    pub major_width: f32,
+    /// The color of the minor lines.
+    pub color: Color,
+    /// The color of the major lines.
+    pub major_color: Color,
+}
+
+impl Default for ContourLines {
+    fn default() -> Self {
+        Self {
+            interval: 100.0,
+            major_every: 5,
+            width: 1.0,
+            major_width: 2.0,
+            color: Color::srgba(0.25, 0.15, 0.05, 0.5),
+            major_color: Color::srgba(0.25, 0.15, 0.05, 0.9),
+        }
+    }
+}
+
+/// The hillshading of the [`TerrainTopographicExtension`].
+#[derive(Reflect, Debug, Clone)]
+#[reflect(Default, Debug)]
+pub struct Hillshade {
+    /// The direction of the light in degrees, measured clockwise from north.
+    pub azimuth: f32,
+    /// The angle of the light above the horizon in degrees.
+    pub altitude: f32,
+    /// How much the shading darkens the color, in the range `[0.0, 1.0]`.
+    pub strength: f32,
+}
+
+impl Default for Hillshade {
+    fn default() -> Self {
+        Self {
+            azimuth: 315.0,
+            altitude: 45.0,
+            strength: 1.0,
+        }
+    }
+}
+
+/// Extends the [`TerrainPbrMaterial`] with topographic visualizations.
+///
+/// The base color is replaced by the hypsometric tint of the gradient, shaded by the hillshade and
+/// overlaid with contour lines. Each of them is optional.
+/// The shader functions are part of `bevy_terrain::topographic` and can be reused by custom materials.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+#[uniform(100, TerrainTopographicUniform)]
+#[reflect(Default, Debug)]
+pub struct TerrainTopographicExtension {
+    /// The gradient texture of the hypsometric tint, which maps the height range from left to right.
+    #[texture(101)]
+    #[sampler(102)]
+    pub gradient: Option<Handle<Image>>,
+    /// The heights in meters, which are mapped to the ends of the gradient.
+    /// Defaults to the height range of the terrain.
+    pub tint_range: Option<Vec2>,
+    /// The contour lines drawn on top of the terrain.
+    pub contours: Option<ContourLines>,
+    /// The hillshading, which darkens the slopes facing away from the light.
+    pub hillshade: Option<Hillshade>,
+}
+
+impl MaterialExtension for TerrainTopographicExtension {
+    fn fragment_shader() -> ShaderRef {
+        TOPOGRAPHIC_SHADER.into()
+    }
+}
+
+/// The GPU representation of the [`TerrainTopographicExtension`].
+#[derive(Clone, Default, ShaderType)]
+pub struct TerrainTopographicUniform {
+    pub contour_color: Vec4,
+    pub major_contour_color: Vec4,
+    pub tint_range: Vec2,
+    pub contour_interval: f32,
+    pub major_contour_every: u32,
+    pub contour_width: f32,
+    pub major_contour_width: f32,
+    pub hillshade_azimuth: f32,
+    pub hillshade_altitude: f32,
+    pub hillshade_strength: f32,
+    pub flags: u32,
+}
+
+impl AsBindGroupShaderType<TerrainTopographicUniform> for TerrainTopographicExtension {
+    fn as_bind_group_shader_type(
+        &self,
+        _images: &RenderAssets<GpuImage>,
+    ) -> TerrainTopographicUniform {
+        let mut uniform = TerrainTopographicUniform::default();
+
+        if self.gradient.is_some() {
+            uniform.flags |= GRADIENT_BIT;
+        }
+        if let Some(tint_range) = self.tint_range {
+            uniform.flags |= TINT_RANGE_BIT;
+            uniform.tint_range = tint_range;
+        }
+        if let Some(contours) = self
+            .contours
+            .as_ref()
+            .filter(|contours| contours.interval > 0.0)
+        {
+            uniform.flags |= CONTOURS_BIT;
+            uniform.contour_color = LinearRgba::from(contours.color).to_vec4();
+            uniform.major_contour_color = LinearRgba::from(contours.major_color).to_vec4();
+            uniform.contour_interval = contours.interval;
+            uniform.major_contour_every = contours.major_every;
+            uniform.contour_width = contours.width;
+            uniform.major_contour_width = contours.major_width;
+        }
+        if let Some(hillshade) = &self.hillshade {
+            uniform.flags |= HILLSHADE_BIT;
+            uniform.hillshade_azimuth = hillshade.azimuth.to_radians();
+            uniform.hillshade_altitude = hillshade.altitude.to_radians();
+            uniform.hillshade_strength = hillshade.strength;
+        }
+
+        uniform
+    }
+}
@@ -42,9 +42,7 @@ impl Coordinate {
             };
 
             let abc = INVERSE_FACE_MATRICES[face as usize] * unit_position;
-            let xy = abc.yz() / abc.x;
-
-            let uv = 0.5 * xy * ((1.0 + SIGMA) / (1.0 + SIGMA * xy * xy)).powf(0.5) + 0.5;
+            let uv = Self::face_uv(abc);
 
             Self { face, uv }
         } else {
@@ -55,6 +53,31 @@ impl Coordinate {
         }
     }
 
+    /// Creates the coordinate at the `uv` of the `face`, which may lie beyond the edges of the face.
+    /// On spherical terrains such coordinates are wrapped onto the adjacent cube face,
+    /// while on planar terrains they are clamped to the border of the face.
+    pub fn wrapped(face: u32, uv: DVec2, is_spherical: bool) -> Self {
+        Self::from_unit_position(
+            Self::new(face, uv).unit_position(is_spherical),
+            is_spherical,
+        )
+    }
+
+    /// Projects the unit position onto the plane of the cube face.
+    /// Positions beyond the edges of the face result in uv coordinates outside of the unit square,
+    /// while positions on the opposite hemisphere can not be projected.
+    pub(crate) fn project_to_face_plane(unit_position: DVec3, face: u32) -> Option<DVec2> {
+        let abc = INVERSE_FACE_MATRICES[face as usize] * unit_position;
+
+        (abc.x > 0.0).then(|| Self::face_uv(abc))
+    }
+
+    fn face_uv(abc: DVec3) -> DVec2 {
+        let xy = abc.yz() / abc.x;
+
+        0.5 * xy * ((1.0 + SIGMA) / (1.0 + SIGMA * xy * xy)).powf(0.5) + 0.5
+    }
+
     pub fn unit_position(self, is_spherical: bool) -> DVec3 {
         if is_spherical {
             let xy =
@@ -78,6 +101,29 @@ impl Coordinate {
         shape.position_unit_to_local(unit_position, height as f64)
     }
 
+    /// Creates the coordinate of the geodetic latitude and longitude (in degrees) on a spherical terrain.
+    /// Uses the same convention as the preprocessor.
+    pub fn from_lat_lon(lat: f64, lon: f64, shape: TerrainShape) -> Self {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        let normal = DVec3::new(-lat.cos() * lon.cos(), lat.sin(), lat.cos() * lon.sin());
+
+        // the surface normal of the shape is proportional to the unit position divided by the scale
+        let unit_position = (normal * shape.scale()).normalize();
+
+        Self::from_unit_position(unit_position, true)
+    }
+
+    /// Returns the geodetic latitude and longitude (in degrees) of the coordinate on a spherical terrain.
+    /// The geodetic latitude is the angle between the surface normal of the shape and the equatorial plane.
+    pub fn lat_lon(self, shape: TerrainShape) -> DVec2 {
+        let normal = self.unit_position(true) / shape.scale();
+
+        let lat = normal.y.atan2(normal.xz().length());
+        let lon = normal.z.atan2(-normal.x);
+
+        DVec2::new(lat.to_degrees(), lon.to_degrees())
+    }
+
     /// Projects the coordinate onto one of the six cube faces.
     /// Thereby it chooses the closest location on this face to the original coordinate.
     pub fn project_to_face(self, face: u32) -> Self {
@@ -203,3 +249,87 @@ impl ViewCoordinate {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LAT_LONS: [(f64, f64); 5] = [
+        (0.0, 0.0),
+        (45.0, -120.0),
+        (-60.0, 170.0),
+        (10.0, 90.0),
+        (89.0, 30.0),
+    ];
+
+    #[test]
+    fn lat_lon_round_trip() {
+        for shape in [
+            TerrainShape::Sphere { radius: 6371000.0 },
+            TerrainShape::WGS84,
+        ] {
+            for (lat, lon) in LAT_LONS {
+                let lat_lon = Coordinate::from_lat_lon(lat, lon, shape).lat_lon(shape);
+
+                assert!((lat_lon - DVec2::new(lat, lon)).abs().max_element() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn lat_lon_is_geodetic() {
+        let shape = TerrainShape::WGS84;
+        let TerrainShape::Spheroid {
+            major_axis,
+            minor_axis,
+        } = shape
+        else {
+            unreachable!()
+        };
+        let eccentricity_squared = 1.0 - (minor_axis / major_axis).powi(2);
+
+        for (lat, lon) in LAT_LONS {
+            let coordinate = Coordinate::from_lat_lon(lat, lon, shape);
+            let position = coordinate.local_position(shape, 0.0);
+
+            // the geodetic latitude of a point on the surface of the ellipsoid
+            let geodetic = (position.y / (1.0 - eccentricity_squared))
+                .atan2(position.xz().length())
+                .to_degrees();
+            // the geocentric latitude differs by up to 0.19° on WGS84
+            let geocentric = position.y.atan2(position.xz().length()).to_degrees();
+
+            assert!((coordinate.lat_lon(shape).x - geodetic).abs() < 1e-9);
+            assert!(lat.abs() == 0.0 || (lat - geocentric).abs() > 1e-3);
+        }
+    }
+
+    #[test]
+    fn wrap_onto_adjacent_faces() {
+        for face in 0..6 {
+            for uv in [DVec2::new(-0.01, 0.5), DVec2::new(0.3, 1.02)] {
+                let coordinate = Coordinate::wrapped(face, uv, true);
+
+                // the coordinate lies on another face, at the position beyond the edge
+                assert_ne!(coordinate.face, face);
+                assert!(coordinate.uv.cmpge(DVec2::ZERO).all());
+                assert!(coordinate.uv.cmple(DVec2::ONE).all());
+                assert!(
+                    coordinate
+                        .unit_position(true)
+                        .distance(Coordinate::new(face, uv).unit_position(true))
+                        < 1e-9
+                );
+            }
+
+            let inside = Coordinate::wrapped(face, DVec2::new(0.2, 0.7), true);
+            assert_eq!(inside.face, face);
+            assert!(inside.uv.distance(DVec2::new(0.2, 0.7)) < 1e-9);
+        }
+
+        // planar terrains have a single face, thus the coordinates are clamped
+        let coordinate = Coordinate::wrapped(0, DVec2::new(-0.5, 0.5), false);
+        assert_eq!(coordinate.face, 0);
+        assert!(coordinate.uv.distance(DVec2::new(0.0, 0.5)) < 1e-9);
+    }
+}
@@ -3,15 +3,22 @@ use std::mem;
 
 mod coordinate;
 mod spheroid;
+mod surface;
 mod surface_approximation;
 mod terrain_shape;
 
 pub use self::{
     coordinate::{Coordinate, TileCoordinate, ViewCoordinate},
+    surface::{
+        SurfaceSample, compute_normal_frame, compute_slope, compute_surface_gradient,
+        compute_surface_normal, decode_octahedral, encode_octahedral,
+    },
     surface_approximation::SurfaceApproximation,
     terrain_shape::TerrainShape,
 };
 
+pub(crate) use self::surface::compute_surface_sample;
+
 /// The square of the parameter c of the algebraic sigmoid function, used to convert between uv and st coordinates.
 const SIGMA: f64 = 0.87 * 0.87;
 
@@ -0,0 +1,459 @@
+//! CPU equivalents of the surface functions in `attachments.wgsl`.
+//!
+//! The shader describes the terrain surface with the normal of the underlying [`TerrainShape`]
+//! (the `world_normal`) and the surface gradient of the height field, which lies in the tangent plane.
+//! The functions in this module follow the same conventions, so that the CPU and the GPU agree
+//! on the normal and slope of the terrain.
+
+use crate::math::{Coordinate, FACE_MATRICES, TerrainShape};
+use bevy::math::{DMat3, DVec2, DVec3};
+
+/// Computes the normal of the terrain surface from the normal of the terrain shape and
+/// the surface gradient of the height field.
+pub fn compute_surface_normal(world_normal: DVec3, surface_gradient: DVec3) -> DVec3 {
+    (world_normal - surface_gradient).normalize()
+}
+
+/// Inverse of [`compute_surface_normal`].
+/// Computes the surface gradient, that tilts the `world_normal` towards the surface `normal`.
+pub fn compute_surface_gradient(world_normal: DVec3, normal: DVec3) -> DVec3 {
+    world_normal - normal / normal.dot(world_normal)
+}
+
+/// Computes the slope of the terrain in radians.
+/// Mirrors `compute_slope` in `attachments.wgsl`.
+pub fn compute_slope(world_normal: DVec3, surface_gradient: DVec3) -> f64 {
+    let normal = compute_surface_normal(world_normal, surface_gradient);
+    let cos_slope = normal.dot(world_normal).min(1.0); // avoid artifacts
+    cos_slope.acos()
+}
+
+/// The local geometry of the terrain surface at a [`Coordinate`].
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceSample {
+    /// The height of the terrain above the terrain shape.
+    pub height: f32,
+    /// The local position of the terrain surface.
+    pub position: DVec3,
+    /// The normal of the terrain shape (without the height).
+    pub world_normal: DVec3,
+    /// The normal of the terrain surface.
+    pub normal: DVec3,
+    /// The surface gradient of the height field, which lies in the tangent plane.
+    pub surface_gradient: DVec3,
+    /// The slope of the terrain in radians.
+    pub slope: f64,
+    /// The mean curvature of the height field in 1/m.
+    /// Convex regions (e.g. hill tops) are positive and concave regions (e.g. valleys) are negative.
+    pub curvature: f64,
+}
+
+/// Computes the [`SurfaceSample`] at the `coordinate` by evaluating the height field
+/// at neighbouring coordinates, which are `step` apart in uv space.
+///
+/// Neighbours beyond the edges of the face are wrapped onto the adjacent face on spherical terrains
+/// (see [`Coordinate::wrapped`]). On planar terrains they are clamped to the border of the face,
+/// which results in one-sided differences.
+/// Returns `None` if any required height is unavailable.
+pub(crate) fn compute_surface_sample(
+    shape: TerrainShape,
+    coordinate: Coordinate,
+    step: f64,
+    height: impl Fn(Coordinate) -> Option<f32>,
+) -> Option<SurfaceSample> {
+    let offset = |offset: DVec2| {
+        Coordinate::wrapped(
+            coordinate.face,
+            coordinate.uv + offset,
+            shape.is_spherical(),
+        )
+    };
+
+    let coordinate_u0 = offset(DVec2::new(-step, 0.0));
+    let coordinate_u1 = offset(DVec2::new(step, 0.0));
+    let coordinate_v0 = offset(DVec2::new(0.0, -step));
+    let coordinate_v1 = offset(DVec2::new(0.0, step));
+
+    let center_height = height(coordinate)?;
+    let height_u0 = height(coordinate_u0)?;
+    let height_u1 = height(coordinate_u1)?;
+    let height_v0 = height(coordinate_v0)?;
+    let height_v1 = height(coordinate_v1)?;
+
+    let unit_position = coordinate.unit_position(shape.is_spherical());
+    let up = shape.position_unit_to_local(unit_position, 1.0)
+        - shape.position_unit_to_local(unit_position, 0.0);
+
+    // tangent space of the terrain shape
+    let base = coordinate.local_position(shape, 0.0);
+    let base_u0 = coordinate_u0.local_position(shape, 0.0);
+    let base_u1 = coordinate_u1.local_position(shape, 0.0);
+    let base_v0 = coordinate_v0.local_position(shape, 0.0);
+    let base_v1 = coordinate_v1.local_position(shape, 0.0);
+
+    let world_normal = orient((base_v1 - base_v0).cross(base_u1 - base_u0).normalize(), up);
+
+    // tangent space of the terrain surface
+    let position_u0 = coordinate_u0.local_position(shape, height_u0);
+    let position_u1 = coordinate_u1.local_position(shape, height_u1);
+    let position_v0 = coordinate_v0.local_position(shape, height_v0);
+    let position_v1 = coordinate_v1.local_position(shape, height_v1);
+
+    let normal = orient(
+        (position_v1 - position_v0)
+            .cross(position_u1 - position_u0)
+            .normalize(),
+        world_normal,
+    );
+
+    let surface_gradient = compute_surface_gradient(world_normal, normal);
+    let slope = compute_slope(world_normal, surface_gradient);
+
+    let height_dx2_u = second_derivative(
+        height_u0 as f64,
+        center_height as f64,
+        height_u1 as f64,
+        base.distance(base_u0),
+        base.distance(base_u1),
+    );
+    let height_dx2_v = second_derivative(
+        height_v0 as f64,
+        center_height as f64,
+        height_v1 as f64,
+        base.distance(base_v0),
+        base.distance(base_v1),
+    );
+
+    let curvature = -0.5 * (height_dx2_u + height_dx2_v);
+
+    Some(SurfaceSample {
+        height: center_height,
+        position: coordinate.local_position(shape, center_height),
+        world_normal,
+        normal,
+        surface_gradient,
+        slope,
+        curvature,
+    })
+}
+
+/// Computes the tangent frame (tangent, bitangent and normal as columns), in which the normals
+/// of the normal attachment are stored.
+/// The tangent follows the u axis of the cube face, projected onto the tangent plane of the terrain shape.
+/// Mirrors `compute_normal_frame` in `attachments.wgsl`.
+pub fn compute_normal_frame(
+    shape: TerrainShape,
+    coordinate: Coordinate,
+    world_normal: DVec3,
+) -> DMat3 {
+    let face_tangent = if shape.is_spherical() {
+        FACE_MATRICES[coordinate.face as usize].y_axis
+    } else {
+        DVec3::X
+    };
+
+    let tangent = shape.scale() * face_tangent;
+    let tangent = (tangent - tangent.dot(world_normal) * world_normal).normalize();
+    let bitangent = world_normal.cross(tangent);
+
+    DMat3::from_cols(tangent, bitangent, world_normal)
+}
+
+/// Encodes the unit `vector` with the octahedral mapping into the square [-1, 1]².
+/// Mirrors `decode_octahedral` in `attachments.wgsl`.
+pub fn encode_octahedral(vector: DVec3) -> DVec2 {
+    let projected = DVec2::new(vector.x, vector.y) / vector.abs().element_sum();
+
+    if vector.z < 0.0 {
+        (1.0 - DVec2::new(projected.y, projected.x).abs()) * sign_not_zero(projected)
+    } else {
+        projected
+    }
+}
+
+/// Inverse of [`encode_octahedral`].
+pub fn decode_octahedral(encoded: DVec2) -> DVec3 {
+    let z = 1.0 - encoded.x.abs() - encoded.y.abs();
+
+    let xy = if z < 0.0 {
+        (1.0 - DVec2::new(encoded.y, encoded.x).abs()) * sign_not_zero(encoded)
+    } else {
+        encoded
+    };
+
+    xy.extend(z).normalize()
+}
+
+fn sign_not_zero(vector: DVec2) -> DVec2 {
+    DVec2::select(vector.cmpge(DVec2::ZERO), DVec2::ONE, DVec2::NEG_ONE)
+}
+
+/// Flips the `vector` to face the same hemisphere as `reference`.
+fn orient(vector: DVec3, reference: DVec3) -> DVec3 {
+    if vector.dot(reference) < 0.0 {
+        -vector
+    } else {
+        vector
+    }
+}
+
+/// Computes the second derivative using a (possibly non-uniform) central difference.
+/// One-sided stencils (where a neighbour was clamped onto the center) yield zero.
+fn second_derivative(
+    value_0: f64,
+    value: f64,
+    value_1: f64,
+    distance_0: f64,
+    distance_1: f64,
+) -> f64 {
+    if distance_0 <= f64::EPSILON || distance_1 <= f64::EPSILON {
+        return 0.0;
+    }
+
+    2.0 * (value_0 * distance_1 + value_1 * distance_0 - value * (distance_0 + distance_1))
+        / (distance_0 * distance_1 * (distance_0 + distance_1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    const EPSILON: f64 = 1e-6;
+
+    /// Transcription of `compute_tangent_space` (`functions.wgsl`) and the end of `sample_surface_gradient`
+    /// (`attachments.wgsl`) with a height scale of one.
+    /// The screen space derivatives of the shader are replaced by derivatives along the u and v axes of the face.
+    fn shader_surface_gradient(
+        world_normal: DVec3,
+        position_dx: DVec3,
+        position_dy: DVec3,
+        height_dx: f64,
+        height_dy: f64,
+    ) -> DVec3 {
+        let tangent_x = position_dy.cross(world_normal);
+        let tangent_y = world_normal.cross(position_dx);
+        let scale = 1.0 / position_dx.dot(tangent_x);
+
+        scale * (height_dx * tangent_x + height_dy * tangent_y)
+    }
+
+    #[test]
+    fn surface_gradient_matches_shader() {
+        let step = 1e-4;
+        let height = |coordinate: Coordinate| {
+            let uv = coordinate.uv;
+            Some((40.0 * (5.0 * uv.x).sin() + 60.0 * uv.y * uv.y) as f32)
+        };
+
+        // the shader assumes a constant shape normal across the derivative,
+        // which is exact for planes and deviates by about height / radius on spheres
+        for (shape, tolerance) in [
+            (
+                TerrainShape::Plane {
+                    side_length: 1000.0,
+                },
+                1e-6,
+            ),
+            (TerrainShape::Sphere { radius: 1e6 }, 1e-3),
+        ] {
+            let face_count = if shape.is_spherical() { 6 } else { 1 };
+
+            for face in 0..face_count {
+                for uv in [DVec2::new(0.5, 0.5), DVec2::new(0.2, 0.7)] {
+                    let coordinate = Coordinate::new(face, uv);
+                    let sample = compute_surface_sample(shape, coordinate, step, height).unwrap();
+
+                    let derivative = |offset: DVec2| {
+                        let [coordinate_0, coordinate_1] =
+                            [uv - offset, uv + offset].map(|uv| Coordinate::new(face, uv));
+                        let position_d = coordinate_1.local_position(shape, 0.0)
+                            - coordinate_0.local_position(shape, 0.0);
+                        let height_d =
+                            (height(coordinate_1).unwrap() - height(coordinate_0).unwrap()) as f64;
+
+                        (position_d / (2.0 * step), height_d / (2.0 * step))
+                    };
+
+                    let (position_dx, height_dx) = derivative(DVec2::new(step, 0.0));
+                    let (position_dy, height_dy) = derivative(DVec2::new(0.0, step));
+
+                    let surface_gradient = shader_surface_gradient(
+                        sample.world_normal,
+                        position_dx,
+                        position_dy,
+                        height_dx,
+                        height_dy,
+                    );
+
+                    let error = surface_gradient.distance(sample.surface_gradient);
+                    assert!(error <= tolerance * surface_gradient.length());
+
+                    let slope = compute_slope(sample.world_normal, surface_gradient);
+                    assert!((slope - sample.slope).abs() <= tolerance * slope);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slope_conventions_agree() {
+        let world_normal = DVec3::new(0.3, 0.9, -0.2).normalize();
+        let tangent = world_normal.any_orthonormal_vector();
+
+        for strength in [0.0, 0.1, 0.5, 1.0, 4.0] {
+            let surface_gradient = strength * tangent;
+            let normal = compute_surface_normal(world_normal, surface_gradient);
+
+            // the slope is the angle between the shape normal and the surface normal
+            let slope = compute_slope(world_normal, surface_gradient);
+            assert!((slope - normal.dot(world_normal).min(1.0).acos()).abs() < EPSILON);
+            assert!((slope - strength.atan()).abs() < EPSILON);
+
+            // the surface gradient can be recovered from the surface normal
+            let recovered = compute_surface_gradient(world_normal, normal);
+            assert!(recovered.distance(surface_gradient) < EPSILON);
+        }
+    }
+
+    #[test]
+    fn octahedral_round_trip() {
+        for vector in [
+            DVec3::Z,
+            DVec3::NEG_Z,
+            DVec3::new(0.3, -0.5, 0.8),
+            DVec3::new(-0.7, 0.2, -0.4),
+            DVec3::new(0.0, -1.0, 0.0),
+        ] {
+            let vector = vector.normalize();
+            let encoded = encode_octahedral(vector);
+
+            assert!(encoded.abs().max_element() <= 1.0);
+            assert!(decode_octahedral(encoded).distance(vector) < EPSILON);
+        }
+    }
+
+    #[test]
+    fn normal_frame_is_orthonormal() {
+        let shape = TerrainShape::Spheroid {
+            major_axis: 1100.0,
+            minor_axis: 1000.0,
+        };
+
+        for face in 0..6 {
+            let coordinate = Coordinate::new(face, DVec2::new(0.3, 0.8));
+            let sample = compute_surface_sample(shape, coordinate, 1e-4, |_| Some(0.0)).unwrap();
+            let frame = compute_normal_frame(shape, coordinate, sample.world_normal);
+
+            assert!((frame.determinant() - 1.0).abs() < EPSILON);
+            assert!((frame.transpose() * frame).abs_diff_eq(DMat3::IDENTITY, EPSILON));
+
+            // the tangent points along the u axis of the face
+            let coordinate_u = Coordinate::new(face, DVec2::new(0.31, 0.8));
+            let direction = coordinate_u.local_position(shape, 0.0) - sample.position;
+            assert!(direction.normalize().dot(frame.x_axis) > 0.99);
+        }
+    }
+
+    #[test]
+    fn planar_ramp() {
+        let side_length = 1000.0;
+        let shape = TerrainShape::Plane { side_length };
+
+        // the height rises by 100m over the side of the terrain in u direction
+        let height = |coordinate: Coordinate| Some(100.0 * coordinate.uv.x as f32);
+
+        for uv in [
+            DVec2::new(0.5, 0.5),
+            DVec2::new(0.0, 0.3),
+            DVec2::new(1.0, 1.0),
+        ] {
+            let sample =
+                compute_surface_sample(shape, Coordinate::new(0, uv), 0.001, height).unwrap();
+
+            assert!(sample.world_normal.distance(DVec3::Y) < EPSILON);
+            assert!(sample.normal.y > 0.0 && sample.normal.x < 0.0);
+            assert!((sample.slope - (100.0 / side_length).atan()).abs() < 1e-4);
+            assert!(sample.curvature.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn planar_diagonal_slope() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+
+        // 45 degree slope along u and v, which results in atan(sqrt(2)) overall
+        let height =
+            |coordinate: Coordinate| Some(1000.0 * (coordinate.uv.x + coordinate.uv.y) as f32);
+
+        let sample =
+            compute_surface_sample(shape, Coordinate::new(0, DVec2::splat(0.5)), 0.001, height)
+                .unwrap();
+
+        assert!((sample.slope - 2.0_f64.sqrt().atan()).abs() < 1e-4);
+        assert!(sample.slope > FRAC_PI_4);
+    }
+
+    #[test]
+    fn planar_curvature() {
+        let side_length = 1000.0;
+        let shape = TerrainShape::Plane { side_length };
+
+        // a paraboloid hill h = -a * (x^2 + z^2) has a mean curvature of 2a at its top
+        let a = 0.001;
+        let height = |coordinate: Coordinate| {
+            let xz = (coordinate.uv - 0.5) * side_length;
+            Some((-a * xz.length_squared()) as f32)
+        };
+
+        let sample =
+            compute_surface_sample(shape, Coordinate::new(0, DVec2::splat(0.5)), 0.001, height)
+                .unwrap();
+
+        assert!((sample.curvature - 2.0 * a).abs() < 1e-5);
+        assert!(sample.slope.abs() < 1e-4);
+    }
+
+    #[test]
+    fn spherical_normal() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let height = |_: Coordinate| Some(10.0);
+
+        for face in 0..6 {
+            for uv in [DVec2::new(0.5, 0.5), DVec2::new(0.2, 0.7)] {
+                let coordinate = Coordinate::new(face, uv);
+                let sample = compute_surface_sample(shape, coordinate, 1e-4, height).unwrap();
+
+                // the normal points outwards
+                let unit_position = coordinate.unit_position(true);
+                assert!(sample.world_normal.distance(unit_position) < 1e-6);
+                assert!(sample.normal.distance(unit_position) < 1e-6);
+                assert!(sample.slope < 1e-4);
+            }
+        }
+    }
+    #[test]
+    fn spherical_seams() {
+        let shape = TerrainShape::Sphere { radius: 10000.0 };
+        let height = |coordinate: Coordinate| {
+            let unit_position = coordinate.unit_position(true);
+            Some((500.0 * unit_position.y * unit_position.y + 200.0 * unit_position.x) as f32)
+        };
+
+        for face in 0..6 {
+            // the same location on the edge of the face, once from each side of the seam
+            let edge = Coordinate::new(face, DVec2::new(0.0, 0.4));
+            let adjacent = Coordinate::wrapped(face, DVec2::new(-1e-12, 0.4), true);
+            assert_ne!(adjacent.face, face);
+
+            let sample = compute_surface_sample(shape, edge, 1e-3, height).unwrap();
+            let adjacent_sample = compute_surface_sample(shape, adjacent, 1e-3, height).unwrap();
+
+            assert!(sample.normal.distance(adjacent_sample.normal) < 1e-3);
+            // the curvature of the height field is in the order of 1e-5
+            assert!((sample.curvature - adjacent_sample.curvature).abs() < 5e-7);
+        }
+    }
+}
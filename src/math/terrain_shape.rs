@@ -40,7 +40,7 @@ impl TerrainShape {
     pub fn transform(self) -> Transform {
         Transform::from_scale(self.scale().as_vec3())
     }
-    pub(crate) fn is_spherical(self) -> bool {
+    pub fn is_spherical(self) -> bool {
         match self {
             TerrainShape::Plane { .. } => false,
             TerrainShape::Sphere { .. } => true,
@@ -0,0 +1,430 @@
+//! Extracts the terrain geometry of a region on the CPU.
+//!
+//! During rendering, the terrain geometry only exists transiently on the GPU.
+//! For exports, navmesh baking or offline analysis, the [`TerrainMeshData`] reconstructs
+//! an actual triangle mesh from the height data of the [`TileAtlas`].
+
+use crate::{
+    math::{Coordinate, TerrainShape, TileCoordinate},
+    terrain_data::TileAtlas,
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    math::{DVec2, DVec3, I64Vec3},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use itertools::Itertools;
+
+/// The quantization used to weld vertices that share the same position on the unit cube sphere.
+const WELD_PRECISION: f64 = (1u64 << 32) as f64;
+
+/// The highest lod, whose tile coordinates can be represented by an [`IVec2`].
+const MAX_LOD: u32 = 30;
+
+/// A region of the terrain.
+#[derive(Clone, Debug)]
+pub enum TerrainRegion {
+    /// The rectangle between `start` and `end` in uv space of a single face.
+    Bounds { face: u32, start: DVec2, end: DVec2 },
+    /// A set of tiles. Tiles of a different lod than the target lod are converted
+    /// to their descendants or ancestor at the target lod.
+    Tiles(Vec<TileCoordinate>),
+}
+
+impl TerrainRegion {
+    /// Creates the region covering the geodetic latitude and longitude bounds (in degrees) on a spherical terrain.
+    /// The region consists of all tiles at the `lod` (at most 30), that intersect the bounds.
+    pub fn from_lat_lon(
+        shape: TerrainShape,
+        lat_range: (f64, f64),
+        lon_range: (f64, f64),
+        lod: u32,
+    ) -> Self {
+        let lod = lod.min(MAX_LOD);
+
+        // sample the bounds at least twice per tile, which is roughly 90 / 2^lod degrees wide
+        let tile_angle = 90.0 / (1u64 << lod) as f64;
+        let samples = |(start, end): (f64, f64)| {
+            let count = ((end - start).abs() / tile_angle * 2.0).ceil().max(1.0) as u32;
+            (0..=count).map(move |i| start + (end - start) * i as f64 / count as f64)
+        };
+
+        let tiles = samples(lat_range)
+            .cartesian_product(samples(lon_range).collect_vec())
+            .map(|(lat, lon)| {
+                let coordinate = Coordinate::from_lat_lon(lat, lon, shape);
+                let tile_count = 1 << lod;
+                let xy = (coordinate.uv * tile_count as f64)
+                    .as_ivec2()
+                    .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+
+                TileCoordinate::new(coordinate.face, lod, xy)
+            })
+            .unique()
+            .collect();
+
+        TerrainRegion::Tiles(tiles)
+    }
+
+    /// Returns the tiles at the `lod` (at most 30), that intersect the region.
+    pub fn tiles(&self, lod: u32) -> Vec<TileCoordinate> {
+        let lod = lod.min(MAX_LOD);
+        let tile_count = 1 << lod;
+
+        match self {
+            &TerrainRegion::Bounds { face, start, end } => {
+                let start = (start * tile_count as f64)
+                    .floor()
+                    .as_ivec2()
+                    .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+                let end = (end * tile_count as f64)
+                    .ceil()
+                    .as_ivec2()
+                    .clamp(start + 1, IVec2::splat(tile_count));
+
+                (start.y..end.y)
+                    .cartesian_product(start.x..end.x)
+                    .map(|(y, x)| TileCoordinate::new(face, lod, IVec2::new(x, y)))
+                    .collect()
+            }
+            TerrainRegion::Tiles(tiles) => tiles
+                .iter()
+                .flat_map(|&tile| tile_at_lod(tile, lod))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .sorted_by_key(|tile| (tile.face, tile.xy.y, tile.xy.x))
+                .collect(),
+        }
+    }
+
+    /// Returns the uv rectangles of the region with their face, subdivided at the `lod`.
+    fn rectangles(&self, lod: u32) -> Vec<(u32, DVec2, DVec2)> {
+        match self {
+            &TerrainRegion::Bounds { face, start, end } => vec![(face, start, end)],
+            TerrainRegion::Tiles(_) => {
+                let tile_count = (1u64 << lod) as f64;
+
+                self.tiles(lod)
+                    .into_iter()
+                    .map(|tile| {
+                        let start = tile.xy.as_dvec2() / tile_count;
+                        let end = (tile.xy + 1).as_dvec2() / tile_count;
+                        (tile.face, start, end)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Converts the tile to the tiles covering the same area at the `lod` (at most [`MAX_LOD`]).
+fn tile_at_lod(tile: TileCoordinate, lod: u32) -> Vec<TileCoordinate> {
+    if tile.lod > lod {
+        let shift = (tile.lod - lod).min(MAX_LOD);
+        vec![TileCoordinate::new(tile.face, lod, tile.xy >> shift as i32)]
+    } else {
+        let count = 1 << (lod - tile.lod);
+        (0..count)
+            .cartesian_product(0..count)
+            .map(|(y, x)| TileCoordinate::new(tile.face, lod, tile.xy * count + IVec2::new(x, y)))
+            .collect()
+    }
+}
+
+/// The triangle mesh of a terrain region.
+#[derive(Clone, Debug, Default)]
+pub struct TerrainMeshData {
+    /// The local position of the center of the region, which all positions are relative to.
+    pub origin: DVec3,
+    /// The coordinate of each vertex.
+    pub coordinates: Vec<Coordinate>,
+    /// The position of each vertex relative to the `origin`.
+    pub positions: Vec<Vec3>,
+    /// The normal of each vertex.
+    pub normals: Vec<Vec3>,
+    /// The triangle list, with counter-clockwise winding seen from above.
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMeshData {
+    /// Extracts the mesh of the `region` from the height data of the tile atlas.
+    ///
+    /// Each tile at the `lod` is covered by `grid_size` x `grid_size` quads.
+    /// Vertices along shared tile and face edges are welded, so that the resulting mesh is seam-free.
+    /// Heights that are unavailable are set to zero.
+    /// Normals that can not be computed, fall back to the normal of the terrain shape.
+    pub fn extract(
+        tile_atlas: &TileAtlas,
+        region: &TerrainRegion,
+        lod: u32,
+        grid_size: u32,
+    ) -> Self {
+        let shape = tile_atlas.shape;
+        let spherical = shape.is_spherical();
+        let lod = lod.min(tile_atlas.lod_count - 1);
+        let tile_count = (1u64 << lod) as f64;
+        let rectangles = region.rectangles(lod);
+
+        let Some(origin) = Self::region_center(&rectangles, spherical)
+            .map(|coordinate| coordinate.local_position(shape, 0.0))
+        else {
+            return default();
+        };
+
+        let mut vertices = HashMap::<I64Vec3, u32>::default();
+        let mut coordinates = Vec::new();
+        let mut indices = Vec::new();
+
+        for (face, start, end) in rectangles {
+            let resolution = ((end - start) * tile_count * grid_size as f64)
+                .ceil()
+                .max(DVec2::ONE)
+                .as_uvec2();
+
+            let grid = (0..=resolution.y)
+                .cartesian_product(0..=resolution.x)
+                .map(|(y, x)| {
+                    let t = UVec2::new(x, y).as_dvec2() / resolution.as_dvec2();
+                    let coordinate = Coordinate::new(face, start * (1.0 - t) + end * t);
+
+                    let unit_position = coordinate.unit_position(spherical);
+                    let key = (unit_position * WELD_PRECISION).round().as_i64vec3();
+
+                    *vertices.entry(key).or_insert_with(|| {
+                        coordinates.push(coordinate);
+                        coordinates.len() as u32 - 1
+                    })
+                })
+                .collect_vec();
+
+            // orient the triangles, so that they face away from the terrain
+            let center = Coordinate::new(face, 0.5 * (start + end));
+            let up = center.local_position(shape, 1.0) - center.local_position(shape, 0.0);
+            let tangent_u = Coordinate::new(face, DVec2::new(end.x, start.y))
+                .local_position(shape, 0.0)
+                - Coordinate::new(face, start).local_position(shape, 0.0);
+            let tangent_v = Coordinate::new(face, DVec2::new(start.x, end.y))
+                .local_position(shape, 0.0)
+                - Coordinate::new(face, start).local_position(shape, 0.0);
+            let flip = tangent_v.cross(tangent_u).dot(up) < 0.0;
+
+            let row = resolution.x as usize + 1;
+
+            for (y, x) in (0..resolution.y as usize).cartesian_product(0..resolution.x as usize) {
+                let a = grid[y * row + x];
+                let b = grid[y * row + x + 1];
+                let c = grid[(y + 1) * row + x];
+                let d = grid[(y + 1) * row + x + 1];
+
+                if flip {
+                    indices.extend([a, b, c, b, d, c]);
+                } else {
+                    indices.extend([a, c, b, b, c, d]);
+                }
+            }
+        }
+
+        let (positions, normals) = coordinates
+            .iter()
+            .map(
+                |&coordinate| match tile_atlas.sample_surface_lod(coordinate, lod) {
+                    Some(sample) => (
+                        (sample.position - origin).as_vec3(),
+                        sample.normal.as_vec3(),
+                    ),
+                    None => {
+                        // fall back to the normal of the terrain shape, e.g. at the border of the loaded data
+                        let height = tile_atlas
+                            .sample_height_lod(coordinate, lod)
+                            .map_or(0.0, |(height, _)| height);
+                        let position = coordinate.local_position(shape, height);
+                        let normal = coordinate.local_position(shape, height + 1.0) - position;
+                        ((position - origin).as_vec3(), normal.normalize().as_vec3())
+                    }
+                },
+            )
+            .unzip();
+
+        Self {
+            origin,
+            coordinates,
+            positions,
+            normals,
+            indices,
+        }
+    }
+
+    /// Computes the center of the region, by averaging the centers of all rectangles on the unit cube sphere.
+    fn region_center(rectangles: &[(u32, DVec2, DVec2)], spherical: bool) -> Option<Coordinate> {
+        let center = rectangles
+            .iter()
+            .map(|&(face, start, end)| {
+                Coordinate::new(face, 0.5 * (start + end)).unit_position(spherical)
+            })
+            .reduce(|a, b| a + b)?
+            / rectangles.len() as f64;
+
+        Some(Coordinate::from_unit_position(
+            if spherical {
+                center.normalize()
+            } else {
+                center
+            },
+            spherical,
+        ))
+    }
+
+    /// Converts the mesh data into a [`Mesh`].
+    ///
+    /// The positions are relative to the `origin` and the uvs are the uv coordinates
+    /// of the vertices on their cube face.
+    pub fn to_mesh(&self) -> Mesh {
+        let uvs = self
+            .coordinates
+            .iter()
+            .map(|coordinate| coordinate.uv.as_vec2())
+            .collect_vec();
+
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(self.indices.clone()))
+    }
+}
+
+/// Extracts the [`Mesh`] of the `region` from the height data of the tile atlas.
+/// Returns the mesh together with the local position its vertices are relative to.
+///
+/// See [`TerrainMeshData::extract`] for details.
+pub fn extract_mesh(
+    tile_atlas: &TileAtlas,
+    region: &TerrainRegion,
+    lod: u32,
+    grid_size: u32,
+) -> (Mesh, DVec3) {
+    let data = TerrainMeshData::extract(tile_atlas, region, lod, grid_size);
+    (data.to_mesh(), data.origin)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        plugin::TerrainSettings,
+        terrain::TerrainConfig,
+        terrain_data::{AttachmentConfig, AttachmentFormat, AttachmentLabel},
+    };
+
+    fn tile_atlas(shape: TerrainShape) -> TileAtlas {
+        let mut config = TerrainConfig {
+            shape,
+            lod_count: 4,
+            ..default()
+        };
+        config.add_attachment(
+            AttachmentLabel::Height,
+            AttachmentConfig {
+                texture_size: 8,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::R32F,
+            },
+        );
+
+        TileAtlas::new(&config, &mut default(), &TerrainSettings::default()).unwrap()
+    }
+
+    /// Checks that the mesh is a single seam-free surface without holes,
+    /// whose outline consists of `boundary` quads along the border of the region.
+    fn assert_seam_free(data: &TerrainMeshData, boundary: usize) {
+        let mut edges = HashMap::<(u32, u32), u32>::default();
+
+        for triangle in data.indices.chunks(3) {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                let edge = (triangle[a].min(triangle[b]), triangle[a].max(triangle[b]));
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+
+        // interior edges are shared by two triangles, while edges at the outline belong to a single one
+        assert!(edges.values().all(|&count| count <= 2));
+        let outline = edges.values().filter(|&&count| count == 1).count();
+        assert_eq!(outline, boundary);
+
+        // a surface homeomorphic to a disk has an Euler characteristic of one
+        let vertices = data.positions.len() as i64;
+        let faces = data.indices.len() as i64 / 3;
+        assert_eq!(vertices - edges.len() as i64 + faces, 1);
+    }
+
+    #[test]
+    fn neighbouring_tiles_share_edges() {
+        let tile_atlas = tile_atlas(TerrainShape::Plane {
+            side_length: 1000.0,
+        });
+        let grid_size = 4;
+
+        let region = TerrainRegion::Tiles(vec![
+            TileCoordinate::new(0, 2, IVec2::new(1, 1)),
+            TileCoordinate::new(0, 2, IVec2::new(2, 1)),
+        ]);
+        let data = TerrainMeshData::extract(&tile_atlas, &region, 2, grid_size);
+
+        assert_eq!(data.positions.len(), (2 * 4 + 1) * (4 + 1));
+        assert_seam_free(&data, 2 * (2 + 1) * grid_size as usize);
+    }
+
+    #[test]
+    fn tiles_of_different_lods_share_edges() {
+        let tile_atlas = tile_atlas(TerrainShape::Plane {
+            side_length: 1000.0,
+        });
+        let grid_size = 2;
+
+        // a tile of lod one (four tiles of lod two) next to a single tile of lod two
+        let region = TerrainRegion::Tiles(vec![
+            TileCoordinate::new(0, 1, IVec2::ZERO),
+            TileCoordinate::new(0, 2, IVec2::new(2, 0)),
+        ]);
+        let data = TerrainMeshData::extract(&tile_atlas, &region, 2, grid_size);
+
+        assert_seam_free(&data, 10 * grid_size as usize);
+    }
+
+    #[test]
+    fn tiles_share_edges_across_faces() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let tile_atlas = tile_atlas(shape);
+        let grid_size = 4;
+
+        let tile = TileCoordinate::new(0, 1, IVec2::new(1, 0));
+        let (neighbour, _) = tile
+            .neighbours(true)
+            .find(|(neighbour, _)| neighbour.face != tile.face)
+            .unwrap();
+
+        let region = TerrainRegion::Tiles(vec![tile, neighbour]);
+        let data = TerrainMeshData::extract(&tile_atlas, &region, 1, grid_size);
+
+        assert_eq!(data.positions.len(), (2 * 4 + 1) * (4 + 1));
+        assert_seam_free(&data, 2 * (2 + 1) * grid_size as usize);
+    }
+
+    #[test]
+    fn large_lods_do_not_overflow() {
+        let tile = TileCoordinate::new(0, 30, IVec2::splat((1 << 30) - 1));
+
+        assert_eq!(
+            tile_at_lod(tile, 0),
+            [TileCoordinate::new(0, 0, IVec2::ZERO)]
+        );
+        assert_eq!(TerrainRegion::Tiles(vec![tile]).tiles(u32::MAX), [tile]);
+    }
+}
@@ -0,0 +1,490 @@
+//! Vector overlays draped onto the terrain.
+//!
+//! Roads, borders, flight paths or selection polygons are described as polylines and polygons
+//! of [`Coordinate`]s and shaded by the terrain fragment shader itself.
+//! Thus they follow the surface at any level of detail and never z-fight with the terrain.
+//!
+//! To keep the shading cheap, the segments of all shapes are sorted into a grid of cells on each
+//! cube face, whenever the [`TerrainOverlay`] changes.
+//! Each fragment then only evaluates the distance to the segments of its own cell, and decides
+//! whether it lies inside of a polygon by counting the polygon edges between itself and the center
+//! of the cell, whose side of the polygon is already known.
+//!
+//! The segments are stored relative to a cell and the fragments locate themselves relative to their
+//! cell, starting from the integer coordinate of their tile.
+//! This way the precision does not degrade with the size of the cube faces.
+
+use crate::{
+    math::{Coordinate, TerrainShape},
+    terrain::TerrainComponents,
+    terrain_data::TileAtlas,
+    util::GpuBuffer,
+};
+use bevy::{
+    math::{DVec2, DVec3},
+    prelude::*,
+    render::{
+        Extract, RenderApp,
+        render_resource::{binding_types::storage_buffer_read_only, *},
+        renderer::RenderDevice,
+    },
+};
+use std::{collections::BTreeSet, f64::consts::FRAC_PI_2};
+
+/// Marks the entries of a cell, whose center lies inside of a polygon.
+const POLYGON_CENTER_BIT: u32 = 1 << 31;
+/// Marks the shapes, which are filled polygons.
+const SHAPE_POLYGON_BIT: u32 = 1;
+
+const SEGMENT_SIZE: usize = 8;
+const SHAPE_SIZE: usize = 10;
+
+/// The geometry of an [`OverlayShape`].
+///
+/// The points of spherical terrains are connected along great circles.
+/// Geographic positions can be converted with [`Coordinate::from_lat_lon`].
+#[derive(Clone, Debug)]
+pub enum OverlayGeometry {
+    /// An open line through all points.
+    Polyline(Vec<Coordinate>),
+    /// A closed polygon, whose last point is connected to the first one.
+    /// Polygons have to be smaller than a hemisphere.
+    Polygon(Vec<Coordinate>),
+}
+
+/// A vector shape draped onto the terrain.
+#[derive(Clone, Debug)]
+pub struct OverlayShape {
+    pub geometry: OverlayGeometry,
+    /// The color of the area enclosed by a polygon.
+    pub fill: Color,
+    /// The color of the line or the outline of a polygon.
+    pub stroke: Color,
+    /// The width of the line or the outline of a polygon in meters.
+    /// Lines are never drawn thinner than a pixel, so that they stay visible from afar.
+    pub width: f32,
+}
+
+impl OverlayShape {
+    /// Creates a line through the points.
+    pub fn polyline(
+        points: impl IntoIterator<Item = Coordinate>,
+        width: f32,
+        color: Color,
+    ) -> Self {
+        Self {
+            geometry: OverlayGeometry::Polyline(points.into_iter().collect()),
+            fill: Color::NONE,
+            stroke: color,
+            width,
+        }
+    }
+
+    /// Creates a polygon filled with the color and without an outline.
+    pub fn polygon(points: impl IntoIterator<Item = Coordinate>, fill: Color) -> Self {
+        Self {
+            geometry: OverlayGeometry::Polygon(points.into_iter().collect()),
+            fill,
+            stroke: Color::NONE,
+            width: 0.0,
+        }
+    }
+
+    /// Adds an outline to the shape.
+    pub fn with_stroke(mut self, width: f32, color: Color) -> Self {
+        self.width = width;
+        self.stroke = color;
+        self
+    }
+}
+
+/// The vector overlay of a terrain.
+///
+/// Add this component to the terrain entity, to drape its shapes onto the terrain.
+/// The shapes are drawn in order, so later shapes cover earlier ones.
+#[derive(Component, Clone, Debug)]
+pub struct TerrainOverlay {
+    pub shapes: Vec<OverlayShape>,
+    /// The number of cells along each side of a cube face, into which the segments are sorted.
+    /// More cells speed up the shading of detailed shapes, but require more memory.
+    pub grid_size: u32,
+}
+
+impl Default for TerrainOverlay {
+    fn default() -> Self {
+        Self {
+            shapes: Vec::new(),
+            grid_size: 64,
+        }
+    }
+}
+
+/// A segment of a shape projected onto a cube face.
+struct Segment {
+    start: DVec2,
+    end: DVec2,
+    meters_per_uv: f64,
+    shape: u32,
+}
+
+/// The overlay data that is available in shaders.
+///
+/// The data array contains the entry range of every cell, followed by the entries, the segments and
+/// the shapes, starting at their respective offsets.
+/// Each segment consists of the cell its start lies in and the positions of its ends relative to
+/// that cell, measured in cells.
+#[derive(Clone, ShaderType)]
+pub(crate) struct OverlayData {
+    grid_size: u32,
+    face_count: u32,
+    entry_offset: u32,
+    segment_offset: u32,
+    shape_offset: u32,
+    #[size(runtime)]
+    data: Vec<u32>,
+}
+
+impl OverlayData {
+    pub(crate) fn new(overlay: &TerrainOverlay, terrain_shape: TerrainShape) -> Self {
+        let spherical = terrain_shape.is_spherical();
+        let grid_size = overlay.grid_size.max(1);
+        let face_count = if spherical { 6 } else { 1 };
+
+        let mut cells = vec![Vec::new(); (face_count * grid_size * grid_size) as usize];
+        let mut segments = Vec::new();
+        let mut shapes = Vec::new();
+
+        for overlay_shape in &overlay.shapes {
+            let (points, closed) = match &overlay_shape.geometry {
+                OverlayGeometry::Polyline(points) => (points, false),
+                OverlayGeometry::Polygon(points) => (points, true),
+            };
+
+            if points.len() < 2 {
+                continue;
+            }
+
+            let shape = (shapes.len() / SHAPE_SIZE) as u32;
+            let fill = LinearRgba::from(overlay_shape.fill);
+            let stroke = LinearRgba::from(overlay_shape.stroke);
+            shapes.extend(fill.to_f32_array().map(f32::to_bits));
+            shapes.extend(stroke.to_f32_array().map(f32::to_bits));
+            shapes.push(overlay_shape.width.to_bits());
+            shapes.push(if closed { SHAPE_POLYGON_BIT } else { 0 });
+
+            let unit_positions = subdivide(points, closed, spherical, grid_size);
+
+            let faces = if spherical {
+                unit_positions
+                    .iter()
+                    .map(|&unit_position| Coordinate::from_unit_position(unit_position, true).face)
+                    .collect()
+            } else {
+                BTreeSet::from([0])
+            };
+
+            for face in faces {
+                let mut edges = Vec::new();
+
+                for pair in unit_positions.windows(2) {
+                    let (Some(start), Some(end)) = (
+                        project_to_face(pair[0], face, spherical),
+                        project_to_face(pair[1], face, spherical),
+                    ) else {
+                        continue;
+                    };
+
+                    let uv_length = start.distance(end);
+                    if uv_length < f64::EPSILON {
+                        continue;
+                    }
+
+                    let length = terrain_shape
+                        .position_unit_to_local(pair[0], 0.0)
+                        .distance(terrain_shape.position_unit_to_local(pair[1], 0.0));
+
+                    let segment = Segment {
+                        start,
+                        end,
+                        meters_per_uv: length / uv_length,
+                        shape,
+                    };
+
+                    // lines thinner than a pixel are widened, which may reach into the next cell
+                    let margin = 0.5 * overlay_shape.width as f64 / segment.meters_per_uv
+                        + 0.5 / grid_size as f64;
+                    let index = segments.len() as u32;
+
+                    for_each_cell(
+                        start.min(end) - margin,
+                        start.max(end) + margin,
+                        grid_size,
+                        |cell| {
+                            cells[cell_index(face, cell, grid_size)].push(index);
+                        },
+                    );
+
+                    segments.push(segment);
+                    edges.push((start, end));
+                }
+
+                if closed {
+                    for cell in cells_inside_polygon(&edges, grid_size) {
+                        cells[cell_index(face, cell, grid_size)].push(POLYGON_CENTER_BIT | shape);
+                    }
+                }
+            }
+        }
+
+        let entry_offset = 2 * cells.len();
+        let entry_count = cells.iter().map(Vec::len).sum::<usize>();
+        let segment_offset = entry_offset + entry_count;
+        let shape_offset = segment_offset + SEGMENT_SIZE * segments.len();
+
+        let mut data = Vec::with_capacity(shape_offset + shapes.len() + 1);
+        let mut first = 0;
+
+        for entries in &cells {
+            data.extend([first as u32, entries.len() as u32]);
+            first += entries.len();
+        }
+
+        data.extend(cells.into_iter().flatten());
+
+        for segment in &segments {
+            let start = segment.start * grid_size as f64;
+            let end = segment.end * grid_size as f64;
+            let cell = start.floor();
+
+            data.extend([
+                (cell.x as i32) as u32,
+                (cell.y as i32) as u32,
+                ((start.x - cell.x) as f32).to_bits(),
+                ((start.y - cell.y) as f32).to_bits(),
+                ((end.x - cell.x) as f32).to_bits(),
+                ((end.y - cell.y) as f32).to_bits(),
+                ((segment.meters_per_uv / grid_size as f64) as f32).to_bits(),
+                segment.shape,
+            ]);
+        }
+
+        data.extend(shapes);
+        // runtime sized arrays may not be empty
+        data.push(0);
+
+        Self {
+            grid_size,
+            face_count,
+            entry_offset: entry_offset as u32,
+            segment_offset: segment_offset as u32,
+            shape_offset: shape_offset as u32,
+            data,
+        }
+    }
+}
+
+/// Converts the points to unit positions and inserts additional points, so that no segment is
+/// longer than a cell.
+fn subdivide(points: &[Coordinate], closed: bool, spherical: bool, grid_size: u32) -> Vec<DVec3> {
+    let max_length = if spherical { FRAC_PI_2 } else { 1.0 } / grid_size as f64;
+
+    let mut unit_positions: Vec<DVec3> = points
+        .iter()
+        .map(|point| point.unit_position(spherical))
+        .collect();
+
+    if closed {
+        unit_positions.push(unit_positions[0]);
+    }
+
+    let mut subdivided = vec![unit_positions[0]];
+
+    for pair in unit_positions.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+
+        let length = if spherical {
+            start.angle_between(end)
+        } else {
+            start.distance(end)
+        };
+        let count = (length / max_length).ceil().max(1.0) as u32;
+
+        for i in 1..=count {
+            let position = start.lerp(end, i as f64 / count as f64);
+            subdivided.push(if spherical {
+                position.normalize()
+            } else {
+                position
+            });
+        }
+    }
+
+    subdivided
+}
+
+fn project_to_face(unit_position: DVec3, face: u32, spherical: bool) -> Option<DVec2> {
+    if spherical {
+        Coordinate::project_to_face_plane(unit_position, face)
+    } else {
+        Some(DVec2::new(unit_position.x + 0.5, unit_position.z + 0.5))
+    }
+}
+
+fn cell_index(face: u32, cell: UVec2, grid_size: u32) -> usize {
+    ((face * grid_size + cell.y) * grid_size + cell.x) as usize
+}
+
+/// Calls the function for all cells overlapping the uv rectangle.
+fn for_each_cell(min: DVec2, max: DVec2, grid_size: u32, mut f: impl FnMut(UVec2)) {
+    let size = grid_size as f64;
+
+    if max.x < 0.0 || max.y < 0.0 || min.x >= 1.0 || min.y >= 1.0 {
+        return;
+    }
+
+    let min = (min * size).floor().max(DVec2::ZERO).as_uvec2();
+    let max = (max * size)
+        .floor()
+        .min(DVec2::splat(size - 1.0))
+        .as_uvec2();
+
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            f(UVec2::new(x, y));
+        }
+    }
+}
+
+/// Returns all cells, whose center lies inside of the polygon formed by the edges.
+fn cells_inside_polygon(edges: &[(DVec2, DVec2)], grid_size: u32) -> Vec<UVec2> {
+    let size = grid_size as f64;
+    let mut cells = Vec::new();
+
+    for y in 0..grid_size {
+        let v = (y as f64 + 0.5) / size;
+
+        let mut crossings: Vec<f64> = edges
+            .iter()
+            .filter(|(start, end)| (start.y <= v) != (end.y <= v))
+            .map(|(start, end)| start.x + (v - start.y) * (end.x - start.x) / (end.y - start.y))
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+
+        for span in crossings.chunks_exact(2) {
+            let first = (span[0] * size - 0.5).ceil().max(0.0) as u32;
+            let last = (span[1] * size - 0.5).ceil().min(size) as u32;
+
+            cells.extend((first..last).map(|x| UVec2::new(x, y)));
+        }
+    }
+
+    cells
+}
+
+/// The layout of the bind group of an overlay, which is used by the terrain pipelines of terrains
+/// with an overlay.
+pub(crate) fn overlay_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        "overlay_layout",
+        &BindGroupLayoutEntries::single(
+            ShaderStages::FRAGMENT,
+            storage_buffer_read_only::<OverlayData>(false),
+        ),
+    )
+}
+
+/// The render world representation of a [`TerrainOverlay`].
+pub struct GpuOverlay {
+    /// Binds the overlay to the terrain pipelines.
+    pub(crate) bind_group: BindGroup,
+}
+
+impl GpuOverlay {
+    pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        mut gpu_overlays: ResMut<TerrainComponents<GpuOverlay>>,
+        overlays: Extract<Query<(Entity, Ref<TerrainOverlay>, &TileAtlas)>>,
+    ) {
+        gpu_overlays.retain(|terrain, _| overlays.contains(*terrain));
+
+        for (terrain, overlay, tile_atlas) in &overlays {
+            if !overlay.is_changed() && gpu_overlays.contains_key(&terrain) {
+                continue;
+            }
+
+            let overlay_buffer = GpuBuffer::create_labeled(
+                "overlay_buffer",
+                &device,
+                &OverlayData::new(&overlay, tile_atlas.shape),
+                BufferUsages::STORAGE,
+            );
+            let bind_group = device.create_bind_group(
+                "overlay_bind_group",
+                &overlay_layout(&device),
+                &BindGroupEntries::single(&overlay_buffer),
+            );
+
+            gpu_overlays.insert(terrain, GpuOverlay { bind_group });
+        }
+    }
+}
+
+/// This plugin drapes the [`TerrainOverlay`] of each terrain onto its surface.
+///
+/// It has to be added after the [`TerrainPlugin`](crate::plugin::TerrainPlugin).
+pub struct TerrainOverlayPlugin;
+
+impl Plugin for TerrainOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainComponents<GpuOverlay>>()
+            .add_systems(ExtractSchedule, GpuOverlay::extract);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cell_entries(data: &OverlayData, cell: UVec2) -> &[u32] {
+        let index = cell_index(0, cell, data.grid_size);
+        let first = (data.entry_offset + data.data[2 * index]) as usize;
+        let count = data.data[2 * index + 1] as usize;
+
+        &data.data[first..first + count]
+    }
+
+    #[test]
+    fn sort_polygon_into_cells() {
+        let square = [(0.25, 0.25), (0.75, 0.25), (0.75, 0.75), (0.25, 0.75)]
+            .map(|(u, v)| Coordinate::new(0, DVec2::new(u, v)));
+
+        let overlay = TerrainOverlay {
+            shapes: vec![OverlayShape::polygon(square, Color::WHITE)],
+            grid_size: 8,
+        };
+        let data = OverlayData::new(
+            &overlay,
+            TerrainShape::Plane {
+                side_length: 1000.0,
+            },
+        );
+
+        // the center of the polygon lies inside and away from its edges
+        assert_eq!(cell_entries(&data, UVec2::new(3, 3)), &[POLYGON_CENTER_BIT]);
+        // the corner of the terrain lies outside and away from its edges
+        assert!(cell_entries(&data, UVec2::new(0, 0)).is_empty());
+        // the right edge runs close to this cell, whose center lies outside
+        assert_eq!(cell_entries(&data, UVec2::new(6, 4)), &[5, 6, 7]);
+
+        // the first segment of the bottom edge starts at the corner of the cell (2, 2) and spans it
+        let segment = &data.data[data.segment_offset as usize..][..SEGMENT_SIZE];
+        let [start_x, start_y, end_x, end_y, meters_per_cell] =
+            [2, 3, 4, 5, 6].map(|i| f32::from_bits(segment[i]));
+        assert_eq!(segment[..2], [2, 2]);
+        assert_eq!([start_x, start_y, end_x, end_y], [0.0, 0.0, 1.0, 0.0]);
+
+        // one cell spans an eighth of the plane
+        assert!((meters_per_cell - 125.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::physics::TerrainCollider;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+/// Inserts the avian colliders of newly spawned terrain colliders.
+pub(crate) fn insert_colliders(
+    mut commands: Commands,
+    colliders: Query<(Entity, &TerrainCollider), Added<TerrainCollider>>,
+) {
+    for (entity, collider) in &colliders {
+        let data = &collider.data;
+
+        let collider = if let Some(heightfield) = &data.heightfield {
+            let size = heightfield.size as usize;
+
+            // avian expects the rows along the x-axis and the columns along the z-axis
+            let heights = (0..size)
+                .map(|x| {
+                    (0..size)
+                        .map(|z| heightfield.heights[z * size + x])
+                        .collect()
+                })
+                .collect();
+
+            Collider::heightfield(heights, heightfield.scale)
+        } else {
+            Collider::trimesh(data.vertices.clone(), data.indices.clone())
+        };
+
+        commands
+            .entity(entity)
+            .insert((RigidBody::Static, collider));
+    }
+}
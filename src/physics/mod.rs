@@ -0,0 +1,414 @@
+//! Generates physics colliders for the terrain around a set of focus entities.
+//!
+//! Similar to how a [`TileTree`](crate::terrain_data::TileTree) requests the tiles around a camera,
+//! the colliders request the tiles around each [`TerrainColliderFocus`] at a fixed lod from the [`TileAtlas`].
+//! Once a tile is loaded, its [`TerrainColliderData`] is built and spawned as a [`TerrainCollider`] entity.
+//! Tiles that are no longer close to any focus are released and their colliders despawned.
+//!
+//! The collider data is engine-agnostic. Enable the `avian3d` or `rapier3d` feature to
+//! automatically insert the colliders of the corresponding physics crate.
+
+#[cfg(feature = "avian3d")]
+mod avian;
+#[cfg(feature = "rapier3d")]
+mod rapier;
+
+use crate::{
+    math::{Coordinate, TerrainShape, TileCoordinate},
+    terrain::TerrainComponents,
+    terrain_data::{TileAtlas, finish_loading, start_loading},
+};
+use bevy::{
+    math::{DVec2, DVec3},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use big_space::prelude::*;
+use itertools::Itertools;
+
+/// The type of collider generated for the terrain tiles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainColliderKind {
+    /// A heightfield collider.
+    /// This is only supported for planar terrains. Spherical terrains fall back to trimesh colliders.
+    Heightfield,
+    /// A triangle mesh collider.
+    #[default]
+    Trimesh,
+}
+
+/// Configures the generation of the terrain colliders.
+#[derive(Resource, Clone, Debug)]
+pub struct TerrainColliderSettings {
+    /// The lod of the tiles used for the colliders.
+    /// This is clamped to the highest lod of the terrain.
+    pub lod: u32,
+    /// The number of vertices along each side of a tile collider.
+    pub grid_size: u32,
+    /// The type of collider to generate.
+    pub kind: TerrainColliderKind,
+}
+
+impl Default for TerrainColliderSettings {
+    fn default() -> Self {
+        Self {
+            lod: u32::MAX,
+            grid_size: 33,
+            kind: default(),
+        }
+    }
+}
+
+/// Marks an entity (e.g. a dynamic rigid body), around which terrain colliders are generated.
+#[derive(Component, Clone, Copy, Debug)]
+#[require(Transform, GridCell)]
+pub struct TerrainColliderFocus {
+    /// The number of tiles around the tile of the focus, that receive a collider.
+    pub radius: u32,
+}
+
+impl Default for TerrainColliderFocus {
+    fn default() -> Self {
+        Self { radius: 1 }
+    }
+}
+
+/// The heightfield of a tile collider.
+#[derive(Clone, Debug)]
+pub struct TerrainHeightfield {
+    /// The heights in row-major order, where rows run along the z-axis and columns along the x-axis.
+    pub heights: Vec<f32>,
+    /// The number of rows and columns of the heightfield.
+    pub size: u32,
+    /// The extent of the heightfield, which is centered around the collider origin.
+    pub scale: Vec3,
+}
+
+/// The engine-agnostic collider data of a single terrain tile.
+#[derive(Clone, Debug)]
+pub struct TerrainColliderData {
+    /// The tile, the collider was generated for.
+    pub tile: TileCoordinate,
+    /// The local position of the collider, which all vertices are relative to.
+    pub origin: DVec3,
+    /// The vertex buffer of the triangle mesh.
+    pub vertices: Vec<Vec3>,
+    /// The index buffer of the triangle mesh, with counter-clockwise winding seen from above.
+    pub indices: Vec<[u32; 3]>,
+    /// The heightfield, if requested and supported by the terrain shape.
+    pub heightfield: Option<TerrainHeightfield>,
+}
+
+impl TerrainColliderData {
+    /// Builds the collider data of the tile from the height data of the tile atlas.
+    ///
+    /// Heights that are unavailable (e.g. masked out) are set to zero.
+    pub fn new(
+        tile_atlas: &TileAtlas,
+        tile: TileCoordinate,
+        grid_size: u32,
+        kind: TerrainColliderKind,
+    ) -> Self {
+        let shape = tile_atlas.shape;
+        let size = grid_size.max(2);
+        let tile_count = (1u64 << tile.lod) as f64;
+
+        let coordinate = |x: u32, y: u32| {
+            let offset = DVec2::new(x as f64, y as f64) / (size - 1) as f64;
+            Coordinate::new(tile.face, (tile.xy.as_dvec2() + offset) / tile_count)
+        };
+
+        let center = Coordinate::new(tile.face, (tile.xy.as_dvec2() + 0.5) / tile_count);
+        let origin = center.local_position(shape, 0.0);
+
+        let heights = (0..size)
+            .cartesian_product(0..size)
+            .map(|(y, x)| {
+                tile_atlas
+                    .sample_height_lod(coordinate(x, y), tile.lod)
+                    .map_or(0.0, |(height, _)| height)
+            })
+            .collect_vec();
+
+        let vertices = (0..size)
+            .cartesian_product(0..size)
+            .zip(&heights)
+            .map(|((y, x), &height)| {
+                (coordinate(x, y).local_position(shape, height) - origin).as_vec3()
+            })
+            .collect_vec();
+
+        // orient the triangles, so that they face away from the terrain
+        let up = center.local_position(shape, 1.0) - origin;
+        let tangent_u = coordinate(1, 0).local_position(shape, 0.0)
+            - coordinate(0, 0).local_position(shape, 0.0);
+        let tangent_v = coordinate(0, 1).local_position(shape, 0.0)
+            - coordinate(0, 0).local_position(shape, 0.0);
+        let flip = tangent_v.cross(tangent_u).dot(up) < 0.0;
+
+        let indices = (0..size - 1)
+            .cartesian_product(0..size - 1)
+            .flat_map(|(y, x)| {
+                let a = y * size + x;
+                let b = a + 1;
+                let c = a + size;
+                let d = c + 1;
+
+                if flip {
+                    [[a, b, c], [b, d, c]]
+                } else {
+                    [[a, c, b], [b, c, d]]
+                }
+            })
+            .collect_vec();
+
+        let heightfield =
+            (kind == TerrainColliderKind::Heightfield && !shape.is_spherical()).then(|| {
+                let side_length = (shape.scale().x / tile_count) as f32;
+
+                TerrainHeightfield {
+                    heights,
+                    size,
+                    scale: Vec3::new(side_length, 1.0, side_length),
+                }
+            });
+
+        Self {
+            tile,
+            origin,
+            vertices,
+            indices,
+            heightfield,
+        }
+    }
+}
+
+/// A collider of a single terrain tile.
+#[derive(Component, Clone, Debug)]
+#[require(Transform, GridCell)]
+pub struct TerrainCollider {
+    /// The terrain entity, the collider belongs to.
+    pub terrain: Entity,
+    pub data: TerrainColliderData,
+}
+
+/// The collider state of a terrain.
+#[derive(Default)]
+pub struct TerrainColliders {
+    /// The tiles currently requested from the tile atlas.
+    requested_tiles: HashSet<TileCoordinate>,
+    /// The spawned collider entities of the loaded tiles.
+    colliders: HashMap<TileCoordinate, Entity>,
+}
+
+impl TerrainColliders {
+    /// Requests the tiles around all focus entities from the tile atlas
+    /// and releases the ones that are no longer required.
+    pub(crate) fn update_requests(
+        mut commands: Commands,
+        settings: Res<TerrainColliderSettings>,
+        mut terrain_colliders: ResMut<TerrainComponents<TerrainColliders>>,
+        mut tile_atlases: Query<(Entity, &mut TileAtlas)>,
+        focuses: Query<(Entity, &TerrainColliderFocus, &Transform, &GridCell)>,
+        grids: Grids,
+    ) {
+        for (terrain, mut tile_atlas) in &mut tile_atlases {
+            let terrain_colliders = terrain_colliders.entry(terrain).or_default();
+
+            let lod = settings.lod.min(tile_atlas.lod_count - 1);
+
+            let mut tiles = HashSet::default();
+
+            for (focus, &TerrainColliderFocus { radius }, transform, cell) in &focuses {
+                let Some(grid) = grids.parent_grid(focus) else {
+                    continue;
+                };
+
+                let local_position = grid.grid_position_double(cell, transform);
+
+                tiles.extend(Self::focus_tiles(
+                    tile_atlas.shape,
+                    lod,
+                    local_position,
+                    radius,
+                ));
+            }
+
+            for &tile in terrain_colliders.requested_tiles.difference(&tiles) {
+                tile_atlas.release_tile(tile);
+
+                if let Some(collider) = terrain_colliders.colliders.remove(&tile) {
+                    commands.entity(collider).despawn();
+                }
+            }
+
+            for &tile in tiles.difference(&terrain_colliders.requested_tiles) {
+                tile_atlas.request_tile(tile);
+            }
+
+            terrain_colliders.requested_tiles = tiles;
+        }
+    }
+
+    /// Spawns the colliders of all requested tiles, that have finished loading.
+    pub(crate) fn spawn_colliders(
+        mut commands: Commands,
+        settings: Res<TerrainColliderSettings>,
+        mut terrain_colliders: ResMut<TerrainComponents<TerrainColliders>>,
+        tile_atlases: Query<(Entity, &TileAtlas, &ChildOf)>,
+        grids: Query<&Grid>,
+    ) {
+        for (terrain, tile_atlas, child_of) in &tile_atlases {
+            let Some(terrain_colliders) = terrain_colliders.get_mut(&terrain) else {
+                continue;
+            };
+            let Ok(grid) = grids.get(child_of.parent()) else {
+                continue;
+            };
+
+            let loaded_tiles = terrain_colliders
+                .requested_tiles
+                .iter()
+                .filter(|tile| {
+                    !terrain_colliders.colliders.contains_key(*tile) && tile_atlas.is_loaded(**tile)
+                })
+                .copied()
+                .collect_vec();
+
+            for tile in loaded_tiles {
+                let data =
+                    TerrainColliderData::new(tile_atlas, tile, settings.grid_size, settings.kind);
+                let (cell, translation) = grid.translation_to_grid(data.origin);
+
+                let collider = commands
+                    .spawn((
+                        TerrainCollider { terrain, data },
+                        Transform::from_translation(translation),
+                        cell,
+                    ))
+                    .id();
+
+                commands.entity(child_of.parent()).add_child(collider);
+                terrain_colliders.colliders.insert(tile, collider);
+            }
+        }
+    }
+
+    /// Selects the tiles of the `lod` within `radius` neighbour steps of the tile below the focus.
+    fn focus_tiles(
+        shape: TerrainShape,
+        lod: u32,
+        local_position: DVec3,
+        radius: u32,
+    ) -> HashSet<TileCoordinate> {
+        let coordinate = Coordinate::from_local_position(local_position, shape);
+
+        let tile_count = 1 << lod;
+        let xy = (coordinate.uv * tile_count as f64)
+            .as_ivec2()
+            .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+
+        Self::tiles_around(
+            TileCoordinate::new(coordinate.face, lod, xy),
+            radius,
+            shape.is_spherical(),
+        )
+    }
+
+    /// Collects all tiles within `radius` neighbour steps of the `center` tile.
+    fn tiles_around(
+        center: TileCoordinate,
+        radius: u32,
+        spherical: bool,
+    ) -> HashSet<TileCoordinate> {
+        let mut tiles = HashSet::from_iter([center]);
+        let mut frontier = vec![center];
+
+        for _ in 0..radius {
+            frontier = frontier
+                .into_iter()
+                .flat_map(|tile| tile.neighbours(spherical))
+                .map(|(neighbour, _)| neighbour)
+                .filter(|&neighbour| {
+                    neighbour != TileCoordinate::INVALID && tiles.insert(neighbour)
+                })
+                .collect();
+        }
+
+        tiles
+    }
+}
+
+/// Generates physics colliders for the terrain around all [`TerrainColliderFocus`] entities.
+pub struct TerrainColliderPlugin;
+
+impl Plugin for TerrainColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TerrainColliderSettings>()
+            .init_resource::<TerrainComponents<TerrainColliders>>()
+            .add_systems(
+                PostUpdate,
+                (
+                    TerrainColliders::spawn_colliders,
+                    TerrainColliders::update_requests,
+                )
+                    .chain()
+                    .after(finish_loading)
+                    .after(TileAtlas::update)
+                    .before(start_loading),
+            );
+
+        #[cfg(feature = "avian3d")]
+        app.add_systems(PostUpdate, avian::insert_colliders);
+
+        #[cfg(feature = "rapier3d")]
+        app.add_systems(PostUpdate, rapier::insert_colliders);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn planar_focus_tiles() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+        let lod = 3;
+
+        // the focus lies in the tile (4, 4) of the 8x8 tiles
+        let center = TerrainColliders::focus_tiles(shape, lod, DVec3::new(20.0, 5.0, 20.0), 1);
+        assert_eq!(center.len(), 9);
+        assert!(center.contains(&TileCoordinate::new(0, lod, IVec2::new(4, 4))));
+        assert!(
+            center
+                .iter()
+                .all(|tile| (tile.xy - IVec2::new(4, 4)).abs().max_element() <= 1)
+        );
+
+        // tiles outside of the terrain are skipped at its corner
+        let corner = TerrainColliders::focus_tiles(shape, lod, DVec3::new(-490.0, 0.0, -490.0), 1);
+        assert_eq!(corner.len(), 4);
+        assert!(corner.contains(&TileCoordinate::new(0, lod, IVec2::ZERO)));
+
+        // a radius of zero only selects the tile below the focus
+        let single = TerrainColliders::focus_tiles(shape, lod, DVec3::new(20.0, 5.0, 20.0), 0);
+        assert_eq!(single.len(), 1);
+    }
+
+    #[test]
+    fn spherical_focus_tiles_cross_faces() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let lod = 2;
+
+        // a focus at the edge of a face also selects tiles of the adjacent face
+        let coordinate = Coordinate::new(0, DVec2::new(0.99, 0.5));
+        let local_position = coordinate.local_position(shape, 0.0);
+        let tiles = TerrainColliders::focus_tiles(shape, lod, local_position, 1);
+
+        assert_eq!(tiles.len(), 9);
+        assert!(tiles.iter().all(|tile| tile.lod == lod));
+        assert!(tiles.iter().any(|tile| tile.face != coordinate.face));
+    }
+}
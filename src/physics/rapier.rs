@@ -0,0 +1,41 @@
+use crate::physics::TerrainCollider;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Inserts the rapier colliders of newly spawned terrain colliders.
+pub(crate) fn insert_colliders(
+    mut commands: Commands,
+    colliders: Query<(Entity, &TerrainCollider), Added<TerrainCollider>>,
+) {
+    for (entity, collider) in &colliders {
+        let data = &collider.data;
+
+        let collider = if let Some(heightfield) = &data.heightfield {
+            let size = heightfield.size as usize;
+
+            // rapier expects the heights in column-major order, with the rows along the z-axis
+            let heights = (0..size)
+                .flat_map(|x| (0..size).map(move |z| heightfield.heights[z * size + x]))
+                .collect();
+
+            Ok(Collider::heightfield(
+                heights,
+                size,
+                size,
+                heightfield.scale,
+            ))
+        } else {
+            Collider::trimesh(data.vertices.clone(), data.indices.clone())
+        };
+
+        match collider {
+            Ok(collider) => {
+                commands.entity(entity).insert((RigidBody::Fixed, collider));
+            }
+            Err(error) => warn!(
+                "Failed to build the collider of tile {}: {error}",
+                data.tile
+            ),
+        }
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
+    math::{Coordinate, TileCoordinate},
     render::{TerrainPass, TerrainViewDepthTexture},
     shaders::PICKING_SHADER,
+    terrain_data::{AttachmentLabel, TileAtlas, TileTree},
+    terrain_view::TerrainViewComponents,
 };
 use bevy::{
     asset::RenderAssetUsages,
     core_pipeline::core_3d::graph::Core3d,
     ecs::{component::HookContext, query::QueryItem, world::DeferredWorld},
+    math::DVec2,
+    platform::collections::HashMap,
     prelude::*,
     render::{
         RenderApp,
@@ -28,55 +33,229 @@ use bevy::{
 };
 use big_space::prelude::*;
 
+/// The maximum number of points, which can be picked per camera and frame.
+pub const MAX_PICKING_POINTS: usize = 64;
+
+/// A point on the render target of a camera, at which the terrain should be picked.
+#[derive(Clone, Copy, Debug)]
+pub struct PickingQuery {
+    /// The id of the request, under which the [`PickingResult`] is reported.
+    pub id: u32,
+    /// The logical position on the render target, e.g. [`Window::cursor_position`] or a touch position.
+    pub position: Vec2,
+}
+
+/// The terrain surface hit by a [`PickingQuery`].
+#[derive(Clone, Debug)]
+pub struct PickingResult {
+    /// The coordinates of the point relative to the viewport (from the bottom left).
+    pub coords: Vec2,
+    /// The translation of the hit relative to the cell of the floating origin (camera).
+    pub translation: Vec3,
+    /// The terrain that was hit.
+    pub terrain: Entity,
+    /// The coordinate of the hit on the terrain.
+    pub coordinate: Coordinate,
+    /// The geographic latitude and longitude (in degrees) of the hit on spherical terrains.
+    pub lat_lon: Option<DVec2>,
+    /// The height of the hit above the terrain shape.
+    pub height: f32,
+    /// The best loaded tile at the hit, whose lod is the atlas lod the terrain is sampled from.
+    pub tile: Option<TileCoordinate>,
+    /// The values of the attachments at the hit.
+    /// Only attachments retained on the CPU (see [`TerrainSettings::cpu_attachments`](crate::plugin::TerrainSettings)) are sampled.
+    pub attachments: HashMap<AttachmentLabel, Vec4>,
+}
+
+impl PickingResult {
+    /// Samples the terrain of the `tile_atlas` at the `coordinate` of a hit.
+    fn new(
+        tile_atlas: &TileAtlas,
+        terrain: Entity,
+        coords: Vec2,
+        translation: Vec3,
+        coordinate: Coordinate,
+        height: f32,
+    ) -> Self {
+        Self {
+            coords,
+            translation,
+            terrain,
+            coordinate,
+            lat_lon: tile_atlas
+                .shape
+                .is_spherical()
+                .then(|| coordinate.lat_lon(tile_atlas.shape)),
+            height,
+            tile: tile_atlas
+                .lookup_tile(coordinate, tile_atlas.lod_count - 1)
+                .map(|(tile, _)| tile),
+            attachments: tile_atlas
+                .attachments
+                .keys()
+                .filter_map(|label| {
+                    let value = tile_atlas.sample_attachment(label, coordinate)?;
+                    Some((label.clone(), value))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Uploads the picking points of each camera, which are the cursor of the primary window and its queries.
 pub fn picking_system(
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
     window: Query<&Window, With<PrimaryWindow>>,
     camera: Query<(&Camera, &GlobalTransform, &GridCell, &PickingData)>,
 ) {
-    let Ok(window) = window.single() else {
-        return;
-    };
-    let Some(position) = window.cursor_position() else {
-        return;
-    };
-    let cursor_coords = Vec2::new(position.x, window.size().y - position.y) / window.size();
+    let cursor_position = window
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position());
 
     for (camera, global_transform, &cell, picking_data) in &camera {
+        let (Some(viewport), Some(logical_target_size), Some(physical_target_size)) = (
+            camera.logical_viewport_rect(),
+            camera.logical_target_size(),
+            camera.physical_target_size(),
+        ) else {
+            continue;
+        };
+
+        let cursor = cursor_position.map(|position| PickingQuery {
+            id: PickingData::CURSOR_ID,
+            position,
+        });
+
+        let (points, count) = picking_points(
+            cursor.iter().chain(&picking_data.queries),
+            viewport,
+            logical_target_size,
+            physical_target_size,
+        );
+
         let buffer = buffers.get_mut(&picking_data.buffer).unwrap();
         let data = GpuPickingData {
-            cursor_coords,
-            depth: 0.0,
-            stencil: 255,
             world_from_clip: global_transform.compute_matrix() * camera.clip_from_view().inverse(),
             cell: IVec3::new(cell.x, cell.y, cell.z),
+            count,
+            points,
         };
         buffer.set_data(data);
     }
 }
 
+/// Converts the `queries` into the picking points of a camera with the logical `viewport`.
+/// Queries outside of the viewport and beyond the first [`MAX_PICKING_POINTS`] are skipped.
+fn picking_points<'a>(
+    queries: impl IntoIterator<Item = &'a PickingQuery>,
+    viewport: Rect,
+    logical_target_size: Vec2,
+    physical_target_size: UVec2,
+) -> ([GpuPickingPoint; MAX_PICKING_POINTS], u32) {
+    let mut points = [GpuPickingPoint::default(); MAX_PICKING_POINTS];
+    let mut count = 0;
+
+    for query in queries {
+        if !viewport.contains(query.position) || count == MAX_PICKING_POINTS {
+            continue;
+        }
+
+        let coords = (query.position - viewport.min) / viewport.size();
+
+        points[count] = GpuPickingPoint {
+            coords: Vec2::new(coords.x, 1.0 - coords.y),
+            pixel: depth_texture_pixel(query.position, logical_target_size, physical_target_size),
+            depth: 0.0,
+            stencil: 0,
+            id: query.id,
+        };
+        count += 1;
+    }
+
+    (points, count as u32)
+}
+
+/// Converts the logical `position` on the render target into the pixel of the depth texture,
+/// which covers the whole physical render target (not just the viewport of the camera).
+fn depth_texture_pixel(
+    position: Vec2,
+    logical_target_size: Vec2,
+    physical_target_size: UVec2,
+) -> UVec2 {
+    let pixel = position / logical_target_size * physical_target_size.as_vec2();
+
+    pixel.as_uvec2().min(physical_target_size - 1)
+}
+
 pub fn picking_readback(
     trigger: Trigger<ReadbackComplete>,
+    grids: Grids,
+    tile_trees: Res<TerrainViewComponents<TileTree>>,
+    tile_atlases: Query<&TileAtlas>,
     mut picking_data: Query<&mut PickingData>,
 ) {
+    let view = trigger.target();
+
     let GpuPickingData {
-        cursor_coords,
-        depth,
-        stencil: _stencil,
         world_from_clip,
         cell,
+        count,
+        points,
     } = trigger.event().to_shader_type();
 
-    let ndc_coords = (2.0 * cursor_coords - 1.0).extend(depth);
+    let cell = GridCell::new(cell.x, cell.y, cell.z);
+    let grid = grids.parent_grid(view);
 
-    let mut picking_data = picking_data.get_mut(trigger.target()).unwrap();
-    picking_data.cursor_coords = cursor_coords;
-    picking_data.cell = GridCell::new(cell.x, cell.y, cell.z);
-    picking_data.translation = (depth > 0.0).then(|| world_from_clip.project_point3(ndc_coords));
+    let mut picking_data = picking_data.get_mut(view).unwrap();
+    picking_data.cell = cell;
     picking_data.world_from_clip = world_from_clip;
+    picking_data.results.clear();
+
+    for point in &points[..count as usize] {
+        let ndc_coords = (2.0 * point.coords - 1.0).extend(point.depth);
+        let translation = (point.depth > 0.0).then(|| world_from_clip.project_point3(ndc_coords));
+
+        if point.id == PickingData::CURSOR_ID {
+            picking_data.cursor_coords = point.coords;
+            picking_data.translation = translation;
+        }
+
+        let Some(translation) = translation else {
+            continue;
+        };
+
+        // the stencil value is the order of the terrain in this view
+        let Some((&(terrain, _), tile_tree)) =
+            tile_trees.iter().find(|&(&(_, tile_view), tile_tree)| {
+                tile_view == view && tile_tree.order == point.stencil
+            })
+        else {
+            continue;
+        };
+        let (Some(grid), Ok(tile_atlas)) = (grid, tile_atlases.get(terrain)) else {
+            continue;
+        };
 
-    // dbg!(cursor_coords);
-    // dbg!(1.0 / depth);
-    // dbg!(stencil);
+        // Todo: take the transform of the terrain into account
+        let local_position =
+            grid.grid_position_double(&cell, &Transform::from_translation(translation));
+        let (coordinate, height) = tile_tree
+            .projection
+            .unproject(tile_atlas.shape, local_position);
+
+        picking_data.results.insert(
+            point.id,
+            PickingResult::new(
+                tile_atlas,
+                terrain,
+                point.coords,
+                translation,
+                coordinate,
+                height,
+            ),
+        );
+    }
 }
 
 pub fn picking_hook(mut world: DeferredWorld, context: HookContext) {
@@ -98,6 +277,9 @@ pub fn picking_hook(mut world: DeferredWorld, context: HookContext) {
     picking_data.buffer = buffer;
 }
 
+/// Picks the terrain under the cursor of the primary window and at arbitrary points of the camera.
+///
+/// The results arrive a few frames delayed, once the GPU readback has completed.
 #[derive(Default, Clone, Component)]
 #[component(on_add = picking_hook)]
 pub struct PickingData {
@@ -105,9 +287,24 @@ pub struct PickingData {
     pub cell: GridCell,            // cell of floating origin (camera)
     pub translation: Option<Vec3>, // relative to floating origin cell
     pub world_from_clip: Mat4,
+    /// The points that are picked each frame, in addition to the cursor.
+    /// At most [`MAX_PICKING_POINTS`] points (including the cursor) are picked per frame.
+    pub queries: Vec<PickingQuery>,
+    /// The hits of the last readback keyed by the request id. Points that missed the terrain have no entry.
+    pub results: HashMap<u32, PickingResult>,
     buffer: Handle<ShaderStorageBuffer>,
 }
 
+impl PickingData {
+    /// The request id of the cursor of the primary window.
+    pub const CURSOR_ID: u32 = u32::MAX;
+
+    /// The terrain surface under the cursor of the primary window.
+    pub fn cursor_result(&self) -> Option<&PickingResult> {
+        self.results.get(&Self::CURSOR_ID)
+    }
+}
+
 impl ExtractComponent for PickingData {
     type QueryData = &'static PickingData;
     type QueryFilter = ();
@@ -121,13 +318,34 @@ impl ExtractComponent for PickingData {
 #[derive(Component)]
 pub struct GpuPickingBuffer(AssetId<ShaderStorageBuffer>);
 
-#[derive(Default, Debug, Clone, ShaderType)]
-pub struct GpuPickingData {
-    pub cursor_coords: Vec2,
+#[derive(Default, Debug, Clone, Copy, ShaderType)]
+pub struct GpuPickingPoint {
+    /// The coordinates of the point relative to the viewport (from the bottom left).
+    pub coords: Vec2,
+    /// The pixel of the depth texture, at which the point is picked.
+    pub pixel: UVec2,
     pub depth: f32,
     pub stencil: u32,
+    pub id: u32,
+}
+
+#[derive(Debug, Clone, ShaderType)]
+pub struct GpuPickingData {
     pub world_from_clip: Mat4,
     pub cell: IVec3,
+    pub count: u32,
+    pub points: [GpuPickingPoint; MAX_PICKING_POINTS],
+}
+
+impl Default for GpuPickingData {
+    fn default() -> Self {
+        Self {
+            world_from_clip: Mat4::IDENTITY,
+            cell: IVec3::ZERO,
+            count: 0,
+            points: [GpuPickingPoint::default(); MAX_PICKING_POINTS],
+        }
+    }
 }
 
 #[derive(Resource)]
@@ -158,7 +376,10 @@ impl FromWorld for PickingPipeline {
             layout: vec![layout.clone()],
             push_constant_ranges: Vec::new(),
             shader: world.load_asset(PICKING_SHADER),
-            shader_defs: vec![],
+            shader_defs: vec![ShaderDefVal::UInt(
+                "MAX_PICKING_POINTS".into(),
+                MAX_PICKING_POINTS as u32,
+            )],
             entry_point: "pick".into(),
             zero_initialize_workgroup_memory: false,
         });
@@ -208,7 +429,7 @@ impl render_graph::ViewNode for PickingPass {
             let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
             pass.set_bind_group(0, &bind_group, &[]);
             pass.set_pipeline(pipeline);
-            pass.dispatch_workgroups(1, 1, 1);
+            pass.dispatch_workgroups(1, 1, 1); // a single workgroup covers all points
             drop(pass);
 
             encoder.finish()
@@ -237,3 +458,188 @@ impl Plugin for TerrainPickingPlugin {
             .init_resource::<PickingPipeline>();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::TerrainShape,
+        plugin::TerrainSettings,
+        terrain::TerrainConfig,
+        terrain_data::{AttachmentConfig, AttachmentData, AttachmentFormat},
+    };
+    use bevy::math::DVec3;
+    use itertools::Itertools;
+
+    fn tile_atlas(shape: TerrainShape, height: f32) -> TileAtlas {
+        let albedo = AttachmentLabel::Custom("albedo".into());
+        let tiles = (0..shape.face_count())
+            .map(|face| TileCoordinate::new(face, 0, IVec2::ZERO))
+            .collect();
+
+        let mut config = TerrainConfig {
+            shape,
+            lod_count: 1,
+            tiles,
+            ..default()
+        };
+        config.add_attachment(
+            AttachmentLabel::Height,
+            AttachmentConfig {
+                texture_size: 4,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::R32F,
+            },
+        );
+        config.add_attachment(
+            albedo.clone(),
+            AttachmentConfig {
+                texture_size: 4,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::Rgba8U,
+            },
+        );
+
+        // only the height is retained on the CPU
+        let settings = TerrainSettings {
+            attachments: vec![AttachmentLabel::Height, albedo],
+            atlas_size: 8,
+            cpu_attachments: vec![AttachmentLabel::Height],
+        };
+        let mut tile_atlas = TileAtlas::new(&config, &mut default(), &settings).unwrap();
+
+        for face in 0..shape.face_count() {
+            tile_atlas.request_tile(TileCoordinate::new(face, 0, IVec2::ZERO));
+        }
+
+        for tile in tile_atlas.to_load.drain(..).collect_vec() {
+            let format = tile_atlas.attachments[&tile.label].format;
+            let bytes = match format {
+                AttachmentFormat::R32F => height.to_le_bytes().repeat(16),
+                _ => vec![255; 4 * 16],
+            };
+            tile_atlas.tile_loaded(tile, AttachmentData::from_bytes(&bytes, format));
+        }
+
+        tile_atlas
+    }
+
+    fn result(tile_atlas: &TileAtlas, coordinate: Coordinate) -> PickingResult {
+        PickingResult::new(
+            tile_atlas,
+            Entity::PLACEHOLDER,
+            Vec2::splat(0.5),
+            Vec3::ZERO,
+            coordinate,
+            0.0,
+        )
+    }
+
+    #[test]
+    fn picking_points_within_viewport() {
+        // the right half of a render target with a scale factor of two
+        let viewport = Rect::new(640.0, 0.0, 1280.0, 720.0);
+        let logical_target_size = Vec2::new(1280.0, 720.0);
+        let physical_target_size = UVec2::new(2560, 1440);
+
+        let queries = [
+            PickingQuery {
+                id: 0,
+                position: Vec2::new(320.0, 360.0), // left of the viewport
+            },
+            PickingQuery {
+                id: 1,
+                position: Vec2::new(800.0, 180.0),
+            },
+        ];
+
+        let (points, count) = picking_points(
+            &queries,
+            viewport,
+            logical_target_size,
+            physical_target_size,
+        );
+
+        assert_eq!(count, 1);
+        assert_eq!(points[0].id, 1);
+        // the coordinates are relative to the viewport and start at the bottom left
+        assert_eq!(points[0].coords, Vec2::new(0.25, 0.75));
+        assert_eq!(points[0].pixel, UVec2::new(1600, 360));
+    }
+
+    #[test]
+    fn picking_points_are_limited() {
+        let viewport = Rect::new(0.0, 0.0, 100.0, 100.0);
+
+        let queries = (0..MAX_PICKING_POINTS as u32 + 8)
+            .map(|id| PickingQuery {
+                id,
+                position: Vec2::splat(50.0),
+            })
+            .collect_vec();
+
+        let (points, count) =
+            picking_points(&queries, viewport, viewport.size(), UVec2::splat(100));
+
+        // the first queries are picked
+        assert_eq!(count as usize, MAX_PICKING_POINTS);
+        assert_eq!(
+            points[MAX_PICKING_POINTS - 1].id,
+            MAX_PICKING_POINTS as u32 - 1
+        );
+    }
+
+    #[test]
+    fn planar_picking_result() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+        let tile_atlas = tile_atlas(shape, 0.25);
+
+        let result = result(&tile_atlas, Coordinate::new(0, DVec2::new(0.3, 0.7)));
+
+        assert_eq!(result.lat_lon, None);
+        assert_eq!(result.tile, Some(TileCoordinate::new(0, 0, IVec2::ZERO)));
+
+        // only the attachments retained on the CPU are sampled
+        assert_eq!(result.attachments.len(), 1);
+        assert_eq!(result.attachments[&AttachmentLabel::Height].x, 0.25);
+    }
+
+    #[test]
+    fn spherical_picking_result() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let tile_atlas = tile_atlas(shape, 0.0);
+
+        // the north pole
+        let coordinate = Coordinate::from_unit_position(DVec3::Y, true);
+        let result = result(&tile_atlas, coordinate);
+
+        let lat_lon = result.lat_lon.unwrap();
+        assert!((lat_lon.x - 90.0).abs() < 1e-9);
+        assert_eq!(result.tile.map(|tile| tile.face), Some(coordinate.face));
+        assert!(result.attachments.contains_key(&AttachmentLabel::Height));
+    }
+
+    #[test]
+    fn depth_texture_pixel_of_split_viewports() {
+        // two viewports side by side on a render target with a scale factor of two
+        let logical_target_size = Vec2::new(1280.0, 720.0);
+        let physical_target_size = UVec2::new(2560, 1440);
+
+        let pixel =
+            |position| depth_texture_pixel(position, logical_target_size, physical_target_size);
+
+        // the center of the left and the right viewport
+        assert_eq!(pixel(Vec2::new(320.0, 360.0)), UVec2::new(640, 720));
+        assert_eq!(pixel(Vec2::new(960.0, 360.0)), UVec2::new(1920, 720));
+
+        // the corners of the render target
+        assert_eq!(pixel(Vec2::ZERO), UVec2::ZERO);
+        assert_eq!(pixel(logical_target_size), physical_target_size - 1);
+    }
+}
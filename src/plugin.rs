@@ -1,20 +1,26 @@
 use crate::{
-    formats::TiffLoader,
+    formats::{CompressedLoader, TiffLoader},
+    material::TerrainMaterialRules,
     preprocess::{MipPipelines, MipPrepass},
     render::{
-        DepthCopyPipeline, GpuTerrain, GpuTerrainView, TerrainItem, TerrainPass,
-        TerrainTilingPrepassPipelines, TilingPrepass, TilingPrepassItem, extract_terrain_phases,
-        prepare_terrain_depth_textures, queue_tiling_prepass,
+        DepthCopyPipeline, GpuTerrain, GpuTerrainView, TerrainItem, TerrainPass, TerrainPrepass,
+        TerrainPrepassItem, TerrainPrepassNode, TerrainPrepassPipeline, TerrainShadowItem,
+        TerrainShadowPass, TerrainShadowPassNode, TerrainShadowPipeline, TerrainShadowSettings,
+        TerrainShadowView, TerrainTilingPrepassPipelines, TilingPrepass, TilingPrepassItem,
+        extract_terrain_phases, prepare_terrain_depth_textures,
+        prepare_terrain_prepass_view_bind_groups, queue_terrain_prepass, queue_terrain_shadows,
+        queue_tiling_prepass,
     },
     shaders::{InternalShaders, load_terrain_shaders},
     terrain::{TerrainComponents, TerrainConfig},
     terrain_data::{
         AttachmentLabel, GpuTileAtlas, TileAtlas, TileTree, finish_loading, start_loading,
     },
-    terrain_view::TerrainViewComponents,
+    terrain_view::{TerrainCamera, TerrainViewComponents},
 };
 use bevy::{
     core_pipeline::core_3d::graph::{Core3d, Node3d},
+    pbr::{SimulationLightSystems, graph::NodePbr},
     prelude::*,
     render::{
         Render, RenderApp, RenderSet,
@@ -31,6 +37,9 @@ use big_space::prelude::*;
 pub struct TerrainSettings {
     pub attachments: Vec<AttachmentLabel>,
     pub atlas_size: u32,
+    /// The attachments, whose data is retained on the CPU after loading.
+    /// These can be sampled with the CPU queries of the [`TileAtlas`].
+    pub cpu_attachments: Vec<AttachmentLabel>,
 }
 
 impl Default for TerrainSettings {
@@ -38,6 +47,7 @@ impl Default for TerrainSettings {
         Self {
             attachments: vec![AttachmentLabel::Height],
             atlas_size: 1028,
+            cpu_attachments: vec![AttachmentLabel::Height],
         }
     }
 }
@@ -48,12 +58,13 @@ impl TerrainSettings {
         attachments.extend(
             custom_attachments
                 .into_iter()
-                .map(|name| AttachmentLabel::Custom(name.to_string())),
+                .map(|name| name.parse().unwrap()),
         );
 
         Self {
             attachments,
             atlas_size: 1028,
+            cpu_attachments: vec![AttachmentLabel::Height],
         }
     }
 }
@@ -66,18 +77,27 @@ impl Plugin for TerrainPlugin {
         app.add_plugins(BigSpaceDefaultPlugins);
 
         app.add_plugins(RonAssetPlugin::<TerrainConfig>::new(&["tc.ron"]))
+            .add_plugins(RonAssetPlugin::<TerrainMaterialRules>::new(&["tr.ron"]))
             .init_asset::<TerrainConfig>()
+            .init_asset::<TerrainMaterialRules>()
             .init_resource::<InternalShaders>()
             .init_resource::<TerrainViewComponents<TileTree>>()
+            .init_resource::<TerrainViewComponents<TerrainShadowView>>()
             .init_resource::<TerrainSettings>()
+            .init_resource::<TerrainShadowSettings>()
             .init_asset_loader::<TiffLoader>()
+            .init_asset_loader::<CompressedLoader>()
             .add_systems(
                 PostUpdate,
                 (
                     // Todo: enable visibility checking again
                     // check_visibility::<With<TileAtlas>>.in_set(VisibilitySystems::CheckVisibility),
                     (
+                        TerrainCamera::update_views,
+                        TerrainShadowView::update_views,
                         TileTree::compute_requests,
+                        TerrainShadowView::compute_requests
+                            .after(SimulationLightSystems::UpdateDirectionalLightCascades),
                         finish_loading,
                         TileAtlas::update,
                         start_loading,
@@ -97,6 +117,11 @@ impl Plugin for TerrainPlugin {
             .init_resource::<TerrainComponents<GpuTerrain>>()
             .init_resource::<TerrainViewComponents<GpuTerrainView>>()
             .init_resource::<TerrainViewComponents<TilingPrepassItem>>()
+            .init_resource::<TerrainViewComponents<TerrainShadowView>>()
+            .init_resource::<TerrainComponents<TerrainShadowItem>>()
+            .init_resource::<SpecializedRenderPipelines<TerrainShadowPipeline>>()
+            .init_resource::<TerrainViewComponents<TerrainPrepassItem>>()
+            .init_resource::<SpecializedRenderPipelines<TerrainPrepassPipeline>>()
             .init_resource::<DrawFunctions<TerrainItem>>()
             .init_resource::<ViewSortedRenderPhases<TerrainItem>>()
             .add_systems(
@@ -107,6 +132,7 @@ impl Plugin for TerrainPlugin {
                     GpuTileAtlas::extract.after(GpuTileAtlas::initialize),
                     GpuTerrain::initialize.after(GpuTileAtlas::initialize),
                     GpuTerrainView::initialize,
+                    TerrainShadowView::extract,
                 ),
             )
             .add_systems(
@@ -122,7 +148,14 @@ impl Plugin for TerrainPlugin {
                         .in_set(RenderSet::Prepare),
                     sort_phase_system::<TerrainItem>.in_set(RenderSet::PhaseSort),
                     prepare_terrain_depth_textures.in_set(RenderSet::PrepareResources),
-                    (queue_tiling_prepass, GpuTileAtlas::queue).in_set(RenderSet::Queue),
+                    prepare_terrain_prepass_view_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                    (
+                        queue_tiling_prepass,
+                        queue_terrain_shadows,
+                        queue_terrain_prepass,
+                        GpuTileAtlas::queue,
+                    )
+                        .in_set(RenderSet::Queue),
                     GpuTileAtlas::_cleanup
                         .before(World::clear_entities)
                         .in_set(RenderSet::Cleanup),
@@ -132,6 +165,23 @@ impl Plugin for TerrainPlugin {
             .add_render_graph_edges(
                 Core3d,
                 (Node3d::StartMainPass, TerrainPass, Node3d::MainOpaquePass),
+            )
+            .add_render_graph_node::<ViewNodeRunner<TerrainPrepassNode>>(Core3d, TerrainPrepass)
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::LatePrepass, TerrainPrepass, Node3d::EndPrepasses),
+            )
+            .add_render_graph_node::<ViewNodeRunner<TerrainShadowPassNode>>(
+                Core3d,
+                TerrainShadowPass,
+            )
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    NodePbr::LateShadowPass,
+                    TerrainShadowPass,
+                    Node3d::StartMainPass,
+                ),
             );
 
         let mut render_graph = app
@@ -155,6 +205,8 @@ impl Plugin for TerrainPlugin {
 
         app.sub_app_mut(RenderApp)
             .init_resource::<TerrainTilingPrepassPipelines>()
+            .init_resource::<TerrainShadowPipeline>()
+            .init_resource::<TerrainPrepassPipeline>()
             .init_resource::<MipPipelines>()
             .init_resource::<DepthCopyPipeline>();
     }
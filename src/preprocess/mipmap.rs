@@ -25,7 +25,12 @@ pub(crate) fn create_mip_layout(
             ShaderStages::COMPUTE,
             (
                 uniform_buffer::<u32>(false), // atlas_index
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // parent
+                texture_2d_array(
+                    format
+                        .processing_format()
+                        .sample_type(None, Some(device.features()))
+                        .unwrap(),
+                ), // parent
                 texture_storage_2d_array(
                     format.processing_format(),
                     StorageTextureAccess::WriteOnly,
@@ -45,16 +50,32 @@ impl MipPipelineKey {
         let mut shader_defs = Vec::new();
 
         let format = match self.format {
+            AttachmentFormat::R8U => "R8U",
+            AttachmentFormat::Rg8U => "RG8U",
             AttachmentFormat::Rgb8U => "RGB8U",
             AttachmentFormat::Rgba8U => "RGBA8U",
             AttachmentFormat::R16U => "R16U",
             AttachmentFormat::R16I => "R16I",
             AttachmentFormat::Rg16U => "RG16U",
+            AttachmentFormat::R16F => "R16F",
+            AttachmentFormat::Rg16F => "RG16F",
+            AttachmentFormat::Rgba16F => "RGBA16F",
             AttachmentFormat::R32F => "R32F",
+            AttachmentFormat::Rg32F => "RG32F",
+            AttachmentFormat::Rgba32F => "RGBA32F",
+            _ => unreachable!("The mipmaps of compressed attachments are generated offline."),
         };
 
         shader_defs.push(format.into());
 
+        // the integer formats are processed as raw values and averaged in the shader
+        if matches!(
+            self.format.processing_format().sample_type(None, None),
+            Some(TextureSampleType::Uint)
+        ) {
+            shader_defs.push("UINT_TEXTURE".into());
+        }
+
         shader_defs
     }
 }
@@ -71,6 +92,7 @@ impl FromWorld for MipPipelines {
         let asset_server = world.resource::<AssetServer>();
 
         let mip_layouts = AttachmentFormat::iter()
+            .filter(|format| !format.is_compressed())
             .map(|format| (format, create_mip_layout(device, format)))
             .collect();
         let mip_shader = asset_server.load(MIP_SHADER);
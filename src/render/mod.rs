@@ -8,15 +8,19 @@
 mod terrain_bind_group;
 mod terrain_material;
 mod terrain_pass;
+mod terrain_prepass;
+mod terrain_shadow;
 mod terrain_view_bind_group;
 mod tiling_prepass;
 
 pub use self::{
     terrain_bind_group::GpuTerrain,
-    terrain_material::TerrainMaterialPlugin,
+    terrain_material::{TerrainMaterialPlugin, TerrainPipelineFlags},
+    terrain_shadow::{TerrainShadowSettings, TerrainShadowView},
     terrain_view_bind_group::{GpuTerrainView, TerrainViewBindGroup},
 };
 
 pub(crate) use self::{
-    terrain_bind_group::*, terrain_pass::*, terrain_view_bind_group::*, tiling_prepass::*,
+    terrain_bind_group::*, terrain_material::*, terrain_pass::*, terrain_prepass::*,
+    terrain_shadow::*, terrain_view_bind_group::*, tiling_prepass::*,
 };
@@ -1,12 +1,16 @@
 use crate::{
+    atmosphere::{GpuAtmosphere, atmosphere_layout},
+    overlay::{GpuOverlay, overlay_layout},
+    render::TerrainPipelineFlags,
     terrain::TerrainComponents,
     terrain_data::{GpuAttachment, GpuTileAtlas, TileAtlas},
     util::GpuBuffer,
+    virtual_texture::{GpuVirtualTexture, virtual_texture_layout},
 };
 use bevy::{
     ecs::{
         query::ROQueryItem,
-        system::{SystemParamItem, lifetimeless::SRes},
+        system::{SystemParam, SystemParamItem, lifetimeless::SRes},
     },
     math::Affine3,
     prelude::*,
@@ -211,7 +215,7 @@ impl GpuTerrain {
         buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
         mut gpu_terrains: ResMut<TerrainComponents<GpuTerrain>>,
     ) {
-        for gpu_terrain in &mut gpu_terrains.values_mut() {
+        for gpu_terrain in gpu_terrains.values_mut() {
             let terrain_buffer = buffers.get(&gpu_terrain.terrain_buffer).unwrap();
 
             // Todo: be smarter about bind group recreation
@@ -261,3 +265,193 @@ impl<const I: usize, P: PhaseItem> RenderCommand<P> for SetTerrainBindGroup<I> {
         }
     }
 }
+
+/// The index of the first bind group of the optional terrain features, which follow the view,
+/// the terrain, the material and the material bind groups of a terrain pipeline.
+pub const TERRAIN_FEATURE_BIND_GROUP: usize = 4;
+
+/// The bind group layouts of the optional terrain features, like the atmosphere, the overlay or the
+/// virtual texture.
+///
+/// The bind groups of the features are owned by their plugins and are bound after the bind groups
+/// of a terrain pipeline, starting at [`TERRAIN_FEATURE_BIND_GROUP`], in the order of
+/// [`TerrainFeatures::flags`], skipping the features the terrain does not use.
+/// The shaders receive the index of each bind group as a shader def (e.g. `ATMOSPHERE_BIND_GROUP`).
+///
+/// Each feature occupies its own bind group, so a device only fits as many features as its
+/// `max_bind_groups` limit leaves room for (none on WebGPU, which only guarantees four bind groups).
+/// The features that do not fit are disabled with an error.
+#[derive(Clone)]
+pub struct TerrainFeatureLayouts {
+    atmosphere: BindGroupLayout,
+    overlay: BindGroupLayout,
+    virtual_texture: BindGroupLayout,
+}
+
+impl TerrainFeatureLayouts {
+    pub fn new(device: &RenderDevice) -> Self {
+        Self {
+            atmosphere: atmosphere_layout(device),
+            overlay: overlay_layout(device),
+            virtual_texture: virtual_texture_layout(device),
+        }
+    }
+
+    /// Appends the layouts and the bind group indices of the features enabled by the `flags`.
+    pub fn specialize(
+        &self,
+        flags: TerrainPipelineFlags,
+        layouts: &mut Vec<BindGroupLayout>,
+        shader_defs: &mut Vec<ShaderDefVal>,
+    ) {
+        let features = [
+            (
+                TerrainPipelineFlags::ATMOSPHERE,
+                "ATMOSPHERE_BIND_GROUP",
+                &self.atmosphere,
+            ),
+            (
+                TerrainPipelineFlags::OVERLAY,
+                "OVERLAY_BIND_GROUP",
+                &self.overlay,
+            ),
+            (
+                TerrainPipelineFlags::VIRTUAL_TEXTURE,
+                "VIRTUAL_TEXTURE_BIND_GROUP",
+                &self.virtual_texture,
+            ),
+        ];
+
+        for (feature, shader_def, layout) in features {
+            if flags.contains(feature) {
+                shader_defs.push(ShaderDefVal::UInt(shader_def.into(), layouts.len() as u32));
+                layouts.push(layout.clone());
+            }
+        }
+    }
+}
+
+/// The render world representations of the optional terrain features.
+#[derive(SystemParam)]
+pub struct TerrainFeatures<'w> {
+    device: Res<'w, RenderDevice>,
+    gpu_atmospheres: Option<Res<'w, TerrainComponents<GpuAtmosphere>>>,
+    gpu_overlays: Option<Res<'w, TerrainComponents<GpuOverlay>>>,
+    gpu_virtual_textures: Option<Res<'w, TerrainComponents<GpuVirtualTexture>>>,
+}
+
+impl<'w> TerrainFeatures<'w> {
+    /// Returns the pipeline flags of the features used by the terrain.
+    ///
+    /// Features that exceed the `max_bind_groups` limit of the device are disabled.
+    pub fn flags(&self, terrain: Entity) -> TerrainPipelineFlags {
+        let mut flags = TerrainPipelineFlags::NONE;
+
+        if self
+            .gpu_atmospheres
+            .as_ref()
+            .is_some_and(|gpu_atmospheres| gpu_atmospheres.contains_key(&terrain))
+        {
+            flags |= TerrainPipelineFlags::ATMOSPHERE;
+        }
+
+        if self
+            .gpu_overlays
+            .as_ref()
+            .is_some_and(|gpu_overlays| gpu_overlays.contains_key(&terrain))
+        {
+            flags |= TerrainPipelineFlags::OVERLAY;
+        }
+
+        // the virtual texture is only bound, once its feedback buffer is prepared
+        if self
+            .gpu_virtual_textures
+            .as_ref()
+            .is_some_and(|gpu_virtual_textures| {
+                gpu_virtual_textures
+                    .get(&terrain)
+                    .is_some_and(|gpu_virtual_texture| gpu_virtual_texture.bind_group.is_some())
+            })
+        {
+            flags |= TerrainPipelineFlags::VIRTUAL_TEXTURE;
+        }
+
+        let max_features = (self.device.limits().max_bind_groups as usize)
+            .saturating_sub(TERRAIN_FEATURE_BIND_GROUP);
+
+        let features = [
+            TerrainPipelineFlags::ATMOSPHERE,
+            TerrainPipelineFlags::OVERLAY,
+            TerrainPipelineFlags::VIRTUAL_TEXTURE,
+        ];
+
+        let used = flags;
+
+        for feature in features
+            .into_iter()
+            .filter(|&feature| used.contains(feature))
+            .skip(max_features)
+        {
+            error_once!(
+                "The terrain feature {feature:?} exceeds the bind group limit of the device and is disabled."
+            );
+            flags.remove(feature);
+        }
+
+        flags
+    }
+
+    /// Returns the bind groups of the features used by the terrain, in the order of their indices.
+    fn bind_groups(self, terrain: Entity) -> impl Iterator<Item = &'w BindGroup> {
+        let flags = self.flags(terrain);
+
+        let atmosphere = self
+            .gpu_atmospheres
+            .filter(|_| flags.contains(TerrainPipelineFlags::ATMOSPHERE))
+            .and_then(|gpu_atmospheres| {
+                Some(&gpu_atmospheres.into_inner().get(&terrain)?.bind_group)
+            });
+
+        let overlay = self
+            .gpu_overlays
+            .filter(|_| flags.contains(TerrainPipelineFlags::OVERLAY))
+            .and_then(|gpu_overlays| Some(&gpu_overlays.into_inner().get(&terrain)?.bind_group));
+
+        let virtual_texture = self
+            .gpu_virtual_textures
+            .filter(|_| flags.contains(TerrainPipelineFlags::VIRTUAL_TEXTURE))
+            .and_then(|gpu_virtual_textures| {
+                gpu_virtual_textures
+                    .into_inner()
+                    .get(&terrain)?
+                    .bind_group
+                    .as_ref()
+            });
+
+        atmosphere.into_iter().chain(overlay).chain(virtual_texture)
+    }
+}
+
+/// Binds the bind groups of the optional terrain features, starting at [`TERRAIN_FEATURE_BIND_GROUP`].
+pub struct SetTerrainFeatureBindGroups;
+
+impl<P: PhaseItem> RenderCommand<P> for SetTerrainFeatureBindGroups {
+    type Param = TerrainFeatures<'static>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        _: ROQueryItem<'w, Self::ViewQuery>,
+        _: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        features: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        for (index, bind_group) in features.bind_groups(item.main_entity().id()).enumerate() {
+            pass.set_bind_group(TERRAIN_FEATURE_BIND_GROUP + index, bind_group, &[]);
+        }
+
+        RenderCommandResult::Success
+    }
+}
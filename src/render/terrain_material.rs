@@ -1,8 +1,9 @@
 use crate::{
     debug::DebugTerrain,
     render::{
-        DrawTerrainCommand, GpuTerrainView, SetTerrainBindGroup, SetTerrainViewBindGroup,
-        TERRAIN_DEPTH_FORMAT, TerrainItem, TerrainTilingPrepassPipelines,
+        DrawTerrainCommand, GpuTerrainView, SetTerrainBindGroup, SetTerrainFeatureBindGroups,
+        SetTerrainViewBindGroup, TERRAIN_DEPTH_FORMAT, TerrainFeatureLayouts, TerrainFeatures,
+        TerrainItem, TerrainTilingPrepassPipelines,
     },
     shaders::{DEFAULT_FRAGMENT_SHADER, DEFAULT_VERTEX_SHADER},
     spawn::{TerrainsToSpawn, spawn_terrains},
@@ -11,7 +12,10 @@ use crate::{
     terrain_view::TerrainViewComponents,
 };
 use bevy::{
-    pbr::{MeshPipeline, MeshPipelineViewLayoutKey, SetMaterialBindGroup, SetMeshViewBindGroup},
+    pbr::{
+        MeshPipeline, MeshPipelineViewLayoutKey, RenderViewLightProbes, SetMaterialBindGroup,
+        SetMeshViewBindGroup, ShadowFilteringMethod,
+    },
     prelude::*,
     render::{
         Render, RenderApp, RenderSet,
@@ -35,7 +39,7 @@ pub struct TerrainPipelineKey {
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     #[repr(transparent)]
-    pub struct TerrainPipelineFlags: u32 {
+    pub struct TerrainPipelineFlags: u64 {
         const NONE               = 0;
         const SPHERICAL          = 1 <<  0;
         const WIREFRAME          = 1 <<  1;
@@ -54,19 +58,40 @@ bitflags::bitflags! {
         const TEST1              = 1 << 14;
         const TEST2              = 1 << 15;
         const TEST3              = 1 << 16;
+        const ENVIRONMENT_MAP    = 1 << 17;
+        const SHADOW_FILTER_METHOD_HARDWARE_2X2 = 1 << 18;
+        const SHADOW_FILTER_METHOD_GAUSSIAN     = 1 << 19;
+        const SHADOW_FILTER_METHOD_TEMPORAL     = 1 << 20;
+        const TRIPLANAR_MAPPING   = 1 << 21;
+        const STOCHASTIC_SAMPLING = 1 << 22;
+        const ATMOSPHERE          = 1 << 23;
+        const WATER_MASK          = 1 << 24;
+        const OVERLAY             = 1 << 25;
+        const VIRTUAL_TEXTURE     = 1 << 26;
+        const NORMAL_MAP          = 1 << 27;
         const MSAA_RESERVED_BITS = TerrainPipelineFlags::MSAA_MASK_BITS << TerrainPipelineFlags::MSAA_SHIFT_BITS;
     }
 }
 
 impl TerrainPipelineFlags {
-    const MSAA_MASK_BITS: u32 = 0b111111;
-    const MSAA_SHIFT_BITS: u32 = 32 - 6;
+    const MSAA_MASK_BITS: u64 = 0b111111;
+    const MSAA_SHIFT_BITS: u64 = 64 - 6;
 
     pub fn from_msaa_samples(msaa_samples: u32) -> Self {
-        let msaa_bits = ((msaa_samples - 1) & Self::MSAA_MASK_BITS) << Self::MSAA_SHIFT_BITS;
+        let msaa_bits = ((msaa_samples as u64 - 1) & Self::MSAA_MASK_BITS) << Self::MSAA_SHIFT_BITS;
         TerrainPipelineFlags::from_bits(msaa_bits).unwrap()
     }
 
+    pub fn from_shadow_filter_method(method: ShadowFilteringMethod) -> Self {
+        match method {
+            ShadowFilteringMethod::Hardware2x2 => {
+                TerrainPipelineFlags::SHADOW_FILTER_METHOD_HARDWARE_2X2
+            }
+            ShadowFilteringMethod::Gaussian => TerrainPipelineFlags::SHADOW_FILTER_METHOD_GAUSSIAN,
+            ShadowFilteringMethod::Temporal => TerrainPipelineFlags::SHADOW_FILTER_METHOD_TEMPORAL,
+        }
+    }
+
     pub fn from_debug(debug: &DebugTerrain) -> Self {
         let mut key = TerrainPipelineFlags::NONE;
 
@@ -122,8 +147,40 @@ impl TerrainPipelineFlags {
         key
     }
 
+    /// The flags of the pipelines, which draw a terrain into the main pass of a view.
+    /// The flags of the optional terrain features are added separately.
+    pub(crate) fn main_pass(
+        msaa: &Msaa,
+        shadow_filter_method: Option<&ShadowFilteringMethod>,
+        environment_map: bool,
+        spherical: bool,
+        debug: Option<&DebugTerrain>,
+    ) -> Self {
+        let mut flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples());
+        if spherical {
+            flags |= TerrainPipelineFlags::SPHERICAL;
+        }
+        if environment_map {
+            flags |= TerrainPipelineFlags::ENVIRONMENT_MAP;
+        }
+        flags |= TerrainPipelineFlags::from_shadow_filter_method(
+            shadow_filter_method.copied().unwrap_or_default(),
+        );
+
+        if let Some(debug) = debug {
+            flags |= TerrainPipelineFlags::from_debug(debug);
+        } else {
+            flags |= TerrainPipelineFlags::LIGHTING
+                | TerrainPipelineFlags::MORPH
+                | TerrainPipelineFlags::BLEND
+                | TerrainPipelineFlags::SAMPLE_GRAD;
+        }
+
+        flags
+    }
+
     pub fn msaa_samples(&self) -> u32 {
-        ((self.bits() >> Self::MSAA_SHIFT_BITS) & Self::MSAA_MASK_BITS) + 1
+        (((self.bits() >> Self::MSAA_SHIFT_BITS) & Self::MSAA_MASK_BITS) + 1) as u32
     }
 
     pub fn polygon_mode(&self) -> PolygonMode {
@@ -185,6 +242,39 @@ impl TerrainPipelineFlags {
         if self.contains(TerrainPipelineFlags::TEST3) {
             shader_defs.push("TEST3".into());
         }
+        if self.contains(TerrainPipelineFlags::ENVIRONMENT_MAP) {
+            shader_defs.push("ENVIRONMENT_MAP".into());
+        }
+        if self.contains(TerrainPipelineFlags::SHADOW_FILTER_METHOD_HARDWARE_2X2) {
+            shader_defs.push("SHADOW_FILTER_METHOD_HARDWARE_2X2".into());
+        }
+        if self.contains(TerrainPipelineFlags::SHADOW_FILTER_METHOD_GAUSSIAN) {
+            shader_defs.push("SHADOW_FILTER_METHOD_GAUSSIAN".into());
+        }
+        if self.contains(TerrainPipelineFlags::SHADOW_FILTER_METHOD_TEMPORAL) {
+            shader_defs.push("SHADOW_FILTER_METHOD_TEMPORAL".into());
+        }
+        if self.contains(TerrainPipelineFlags::TRIPLANAR_MAPPING) {
+            shader_defs.push("TRIPLANAR_MAPPING".into());
+        }
+        if self.contains(TerrainPipelineFlags::STOCHASTIC_SAMPLING) {
+            shader_defs.push("STOCHASTIC_SAMPLING".into());
+        }
+        if self.contains(TerrainPipelineFlags::ATMOSPHERE) {
+            shader_defs.push("ATMOSPHERE".into());
+        }
+        if self.contains(TerrainPipelineFlags::WATER_MASK) {
+            shader_defs.push("WATER_MASK".into());
+        }
+        if self.contains(TerrainPipelineFlags::OVERLAY) {
+            shader_defs.push("OVERLAY".into());
+        }
+        if self.contains(TerrainPipelineFlags::VIRTUAL_TEXTURE) {
+            shader_defs.push("VIRTUAL_TEXTURE".into());
+        }
+        if self.contains(TerrainPipelineFlags::NORMAL_MAP) {
+            shader_defs.push("NORMAL_MAP".into());
+        }
 
         shader_defs
     }
@@ -198,8 +288,11 @@ pub struct TerrainRenderPipeline<M: Material> {
     terrain_layout: BindGroupLayout,
     terrain_view_layout: BindGroupLayout,
     material_layout: BindGroupLayout,
+    feature_layouts: TerrainFeatureLayouts,
     vertex_shader: Handle<Shader>,
     fragment_shader: Handle<Shader>,
+    /// The flags of the material, which are added to the flags of every pipeline key.
+    material_flags: TerrainPipelineFlags,
     marker: PhantomData<M>,
 }
 
@@ -231,8 +324,10 @@ impl<M: Material> FromWorld for TerrainRenderPipeline<M> {
             terrain_layout: prepass_pipelines.terrain_layout.clone(),
             terrain_view_layout: prepass_pipelines.terrain_view_layout.clone(),
             material_layout: M::bind_group_layout(device),
+            feature_layouts: TerrainFeatureLayouts::new(device),
             vertex_shader,
             fragment_shader,
+            material_flags: TerrainPipelineFlags::NONE,
             marker: PhantomData,
         }
     }
@@ -242,7 +337,7 @@ impl<M: Material> SpecializedRenderPipeline for TerrainRenderPipeline<M> {
     type Key = TerrainPipelineKey;
 
     fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
-        let mut shader_defs = key.flags.shader_defs();
+        let mut shader_defs = (key.flags | self.material_flags).shader_defs();
 
         let mut bind_group_layout = match key.flags.msaa_samples() {
             1 => vec![self.view_layout.clone()],
@@ -255,6 +350,8 @@ impl<M: Material> SpecializedRenderPipeline for TerrainRenderPipeline<M> {
         bind_group_layout.push(self.terrain_layout.clone());
         bind_group_layout.push(self.terrain_view_layout.clone());
         bind_group_layout.push(self.material_layout.clone());
+        self.feature_layouts
+            .specialize(key.flags, &mut bind_group_layout, &mut shader_defs);
 
         let mut vertex_shader_defs = shader_defs.clone();
         vertex_shader_defs.push("VERTEX".into());
@@ -325,11 +422,12 @@ pub(crate) type DrawTerrain<M> = (
     SetTerrainBindGroup<1>,
     SetTerrainViewBindGroup<2>,
     SetMaterialBindGroup<M, 3>,
+    SetTerrainFeatureBindGroups,
     DrawTerrainCommand,
 );
 
 /// Queses all terrain entities for rendering via the terrain pipeline.
-#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub(crate) fn queue_terrain<M: Material>(
     draw_functions: Res<DrawFunctions<TerrainItem>>,
     debug: Option<Res<DebugTerrain>>,
@@ -339,13 +437,19 @@ pub(crate) fn queue_terrain<M: Material>(
     mut terrain_phases: ResMut<ViewSortedRenderPhases<TerrainItem>>,
     gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
     gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
-    mut views: Query<(MainEntity, &Msaa)>,
+    terrain_features: TerrainFeatures,
+    mut views: Query<(
+        MainEntity,
+        &Msaa,
+        Option<&ShadowFilteringMethod>,
+        Has<RenderViewLightProbes<EnvironmentMapLight>>,
+    )>,
 ) where
     M::Data: PartialEq + Eq + Hash + Clone,
 {
     let draw_function = draw_functions.read().get_id::<DrawTerrain<M>>().unwrap();
 
-    for (view, msaa) in &mut views {
+    for (view, msaa, shadow_filter_method, environment_map) in &mut views {
         let Some(terrain_phase) = terrain_phases.get_mut(&RetainedViewEntity {
             main_entity: view.into(),
             auxiliary_entity: Entity::PLACEHOLDER.into(),
@@ -359,18 +463,15 @@ pub(crate) fn queue_terrain<M: Material>(
                 continue;
             };
 
-            let mut flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples());
-            if gpu_tile_atlas.is_spherical {
-                flags |= TerrainPipelineFlags::SPHERICAL;
-            }
-
-            if let Some(debug) = &debug {
-                flags |= TerrainPipelineFlags::from_debug(debug);
-            } else {
-                flags |= TerrainPipelineFlags::LIGHTING
-                    | TerrainPipelineFlags::MORPH
-                    | TerrainPipelineFlags::BLEND
-                    | TerrainPipelineFlags::SAMPLE_GRAD;
+            let mut flags = TerrainPipelineFlags::main_pass(
+                msaa,
+                shadow_filter_method,
+                environment_map,
+                gpu_tile_atlas.is_spherical,
+                debug.as_deref(),
+            ) | terrain_features.flags(terrain);
+            if gpu_tile_atlas.normal_map {
+                flags |= TerrainPipelineFlags::NORMAL_MAP;
             }
 
             let key = TerrainPipelineKey { flags };
@@ -392,11 +493,29 @@ pub(crate) fn queue_terrain<M: Material>(
 /// This plugin adds a custom material for a terrain.
 ///
 /// It can be used to render the terrain using a custom vertex and fragment shader.
-pub struct TerrainMaterialPlugin<M: Material>(PhantomData<M>);
+/// Optional shader features of the material, like [`TerrainPipelineFlags::TRIPLANAR_MAPPING`]
+/// and [`TerrainPipelineFlags::STOCHASTIC_SAMPLING`], are selected with [`TerrainMaterialPlugin::with_flags`].
+pub struct TerrainMaterialPlugin<M: Material> {
+    flags: TerrainPipelineFlags,
+    marker: PhantomData<M>,
+}
 
 impl<M: Material> Default for TerrainMaterialPlugin<M> {
     fn default() -> Self {
-        Self(default())
+        Self {
+            flags: TerrainPipelineFlags::NONE,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<M: Material> TerrainMaterialPlugin<M> {
+    /// Enables the shader defs of the `flags` for all pipelines of the material.
+    pub fn with_flags(flags: TerrainPipelineFlags) -> Self {
+        Self {
+            flags,
+            marker: PhantomData,
+        }
     }
 }
 
@@ -416,7 +535,10 @@ where
     }
 
     fn finish(&self, app: &mut App) {
-        app.sub_app_mut(RenderApp)
-            .init_resource::<TerrainRenderPipeline<M>>();
+        let render_app = app.sub_app_mut(RenderApp);
+
+        let mut pipeline = TerrainRenderPipeline::<M>::from_world(render_app.world_mut());
+        pipeline.material_flags = self.flags;
+        render_app.insert_resource(pipeline);
     }
 }
@@ -320,6 +320,17 @@ impl ViewNode for TerrainPass {
                 depth_stencil_attachment,
                 ..default()
             });
+            // only copy the depth of this camera, which might share its render target with others
+            if let Some(viewport) = camera.viewport.as_ref() {
+                pass.set_viewport(
+                    viewport.physical_position.x as f32,
+                    viewport.physical_position.y as f32,
+                    viewport.physical_size.x as f32,
+                    viewport.physical_size.y as f32,
+                    viewport.depth.start,
+                    viewport.depth.end,
+                );
+            }
             pass.set_bind_group(0, &depth_copy_bind_group, &[]);
             pass.set_pipeline(pipeline);
             pass.draw(0..3, 0..1);
@@ -0,0 +1,441 @@
+//! Renders the terrain into the prepass textures of Bevy.
+//!
+//! Post-processing effects like SSAO, TAA, SSR or depth of field rely on the depth, normal and
+//! motion vector prepass. Since the terrain is drawn by its own [`TerrainPass`](super::TerrainPass),
+//! it is additionally rendered into the [`ViewPrepassTextures`] of every view, that requests any of them.
+//!
+//! The motion vectors account for both the movement of the view and the change of the morph ratios
+//! caused by it. The selected tiles and their data are assumed to be the same as in the previous frame.
+
+use crate::{
+    debug::DebugTerrain,
+    render::{GpuTerrain, GpuTerrainView, TerrainPipelineFlags, TerrainTilingPrepassPipelines},
+    shaders::PREPASS_SHADER,
+    terrain::TerrainComponents,
+    terrain_data::GpuTileAtlas,
+    terrain_view::TerrainViewComponents,
+};
+use bevy::{
+    core_pipeline::{
+        core_3d::CORE_3D_DEPTH_FORMAT,
+        prepass::{
+            DepthPrepass, MOTION_VECTOR_PREPASS_FORMAT, MotionVectorPrepass, NORMAL_PREPASS_FORMAT,
+            NormalPrepass, PreviousViewData, PreviousViewUniformOffset, PreviousViewUniforms,
+            ViewPrepassTextures,
+        },
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_phase::TrackedRenderPass,
+        render_resource::{binding_types::uniform_buffer, *},
+        renderer::{RenderContext, RenderDevice},
+        sync_world::MainEntity,
+        view::{ViewDepthTexture, ViewUniform, ViewUniformOffset, ViewUniforms},
+    },
+};
+use itertools::Itertools;
+
+#[derive(PartialEq, Eq, Clone, Hash)]
+pub struct TerrainPrepassKey {
+    pub flags: TerrainPipelineFlags,
+    /// Whether the normals are written into the normal prepass texture.
+    pub normals: bool,
+    /// Whether the motion vectors are written into the motion vector prepass texture.
+    pub motion_vectors: bool,
+}
+
+/// The pipeline used to render the terrain into the prepass textures.
+#[derive(Resource)]
+pub struct TerrainPrepassPipeline {
+    view_layout: BindGroupLayout,
+    motion_vector_view_layout: BindGroupLayout,
+    terrain_layout: BindGroupLayout,
+    terrain_view_layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl FromWorld for TerrainPrepassPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let prepass_pipelines = world.resource::<TerrainTilingPrepassPipelines>();
+
+        let view_layout = device.create_bind_group_layout(
+            "terrain_prepass_view_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::VERTEX_FRAGMENT,
+                uniform_buffer::<ViewUniform>(true),
+            ),
+        );
+        let motion_vector_view_layout = device.create_bind_group_layout(
+            "terrain_prepass_motion_vector_view_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::VERTEX_FRAGMENT,
+                (
+                    uniform_buffer::<ViewUniform>(true),
+                    uniform_buffer::<PreviousViewData>(true),
+                ),
+            ),
+        );
+
+        Self {
+            view_layout,
+            motion_vector_view_layout,
+            terrain_layout: prepass_pipelines.terrain_layout.clone(),
+            terrain_view_layout: prepass_pipelines.terrain_view_layout.clone(),
+            shader: world.load_asset(PREPASS_SHADER),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for TerrainPrepassPipeline {
+    type Key = TerrainPrepassKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = key.flags.shader_defs();
+        shader_defs.push("TERRAIN_PREPASS".into());
+
+        let view_layout = if key.motion_vectors {
+            shader_defs.push("PREPASS_MOTION_VECTORS".into());
+            self.motion_vector_view_layout.clone()
+        } else {
+            self.view_layout.clone()
+        };
+
+        if key.normals {
+            shader_defs.push("PREPASS_NORMALS".into());
+        }
+
+        let mut targets = vec![
+            key.normals.then_some(ColorTargetState {
+                format: NORMAL_PREPASS_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            }),
+            key.motion_vectors.then_some(ColorTargetState {
+                format: MOTION_VECTOR_PREPASS_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            }),
+        ];
+
+        if targets.iter().all(Option::is_none) {
+            // the fragment shader only discards masked out fragments
+            targets.clear();
+        } else {
+            shader_defs.push("PREPASS_FRAGMENT".into());
+        }
+
+        let mut vertex_shader_defs = shader_defs.clone();
+        vertex_shader_defs.push("VERTEX".into());
+        let mut fragment_shader_defs = shader_defs;
+        fragment_shader_defs.push("FRAGMENT".into());
+
+        RenderPipelineDescriptor {
+            label: Some("terrain_prepass_pipeline".into()),
+            layout: vec![
+                view_layout,
+                self.terrain_layout.clone(),
+                self.terrain_view_layout.clone(),
+            ],
+            push_constant_ranges: default(),
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs: vertex_shader_defs,
+                buffers: Vec::new(),
+            },
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+            },
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: fragment_shader_defs,
+                entry_point: "fragment".into(),
+                targets,
+            }),
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: MultisampleState {
+                count: key.flags.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// The prepass pipeline of a terrain view.
+pub(crate) struct TerrainPrepassItem {
+    pipeline: CachedRenderPipelineId,
+}
+
+/// Queues the prepass pipelines of all terrain views, whose view uses any of the prepasses.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn queue_terrain_prepass(
+    debug: Option<Res<DebugTerrain>>,
+    pipeline_cache: Res<PipelineCache>,
+    prepass_pipeline: Res<TerrainPrepassPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TerrainPrepassPipeline>>,
+    mut prepass_items: ResMut<TerrainViewComponents<TerrainPrepassItem>>,
+    gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
+    gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
+    views: Query<
+        (
+            MainEntity,
+            &Msaa,
+            Has<NormalPrepass>,
+            Has<MotionVectorPrepass>,
+        ),
+        Or<(
+            With<DepthPrepass>,
+            With<NormalPrepass>,
+            With<MotionVectorPrepass>,
+        )>,
+    >,
+) {
+    prepass_items.clear();
+
+    for (view, msaa, normals, motion_vectors) in &views {
+        for (&terrain, gpu_tile_atlas) in gpu_tile_atlases.iter() {
+            if !gpu_terrain_views.contains_key(&(terrain, view)) {
+                continue;
+            }
+
+            let mut flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples());
+            if gpu_tile_atlas.is_spherical {
+                flags |= TerrainPipelineFlags::SPHERICAL;
+            }
+            if gpu_tile_atlas.normal_map {
+                flags |= TerrainPipelineFlags::NORMAL_MAP;
+            }
+
+            if let Some(debug) = &debug {
+                flags |= TerrainPipelineFlags::from_debug(debug);
+            } else {
+                flags |= TerrainPipelineFlags::MORPH
+                    | TerrainPipelineFlags::BLEND
+                    | TerrainPipelineFlags::SAMPLE_GRAD;
+            }
+
+            let key = TerrainPrepassKey {
+                flags,
+                normals,
+                motion_vectors,
+            };
+
+            let pipeline = pipelines.specialize(&pipeline_cache, &prepass_pipeline, key);
+
+            prepass_items.insert((terrain, view), TerrainPrepassItem { pipeline });
+        }
+    }
+}
+
+/// The view bind group of the terrain prepass.
+#[derive(Component)]
+pub struct TerrainPrepassViewBindGroup {
+    bind_group: BindGroup,
+    /// Whether the bind group contains the previous view uniforms used by the motion vectors.
+    motion_vectors: bool,
+}
+
+/// Prepares the view bind groups of all views, that use any of the prepasses.
+#[allow(clippy::type_complexity)]
+pub(crate) fn prepare_terrain_prepass_view_bind_groups(
+    mut commands: Commands,
+    device: Res<RenderDevice>,
+    prepass_pipeline: Res<TerrainPrepassPipeline>,
+    view_uniforms: Res<ViewUniforms>,
+    previous_view_uniforms: Option<Res<PreviousViewUniforms>>,
+    views: Query<
+        (Entity, Has<MotionVectorPrepass>),
+        Or<(
+            With<DepthPrepass>,
+            With<NormalPrepass>,
+            With<MotionVectorPrepass>,
+        )>,
+    >,
+) {
+    let Some(view_binding) = view_uniforms.uniforms.binding() else {
+        return;
+    };
+    let previous_view_binding = previous_view_uniforms
+        .as_ref()
+        .and_then(|uniforms| uniforms.uniforms.binding());
+
+    for (view, motion_vectors) in &views {
+        let bind_group = match (motion_vectors, previous_view_binding.clone()) {
+            (true, Some(previous_view_binding)) => device.create_bind_group(
+                "terrain_prepass_view_bind_group",
+                &prepass_pipeline.motion_vector_view_layout,
+                &BindGroupEntries::sequential((view_binding.clone(), previous_view_binding)),
+            ),
+            (true, None) => {
+                commands
+                    .entity(view)
+                    .remove::<TerrainPrepassViewBindGroup>();
+                continue;
+            }
+            (false, _) => device.create_bind_group(
+                "terrain_prepass_view_bind_group",
+                &prepass_pipeline.view_layout,
+                &BindGroupEntries::single(view_binding.clone()),
+            ),
+        };
+
+        commands.entity(view).insert(TerrainPrepassViewBindGroup {
+            bind_group,
+            motion_vectors,
+        });
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct TerrainPrepass;
+
+/// Draws the terrain into the depth, normal and motion vector prepass textures of the view.
+#[derive(Default)]
+pub struct TerrainPrepassNode;
+
+impl ViewNode for TerrainPrepassNode {
+    type ViewQuery = (
+        MainEntity,
+        &'static ExtractedCamera,
+        &'static ViewPrepassTextures,
+        &'static ViewDepthTexture,
+        &'static ViewUniformOffset,
+        Option<&'static PreviousViewUniformOffset>,
+        &'static TerrainPrepassViewBindGroup,
+    );
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        (
+            main_view,
+            camera,
+            prepass_textures,
+            depth,
+            view_uniform_offset,
+            previous_view_offset,
+            view_bind_group,
+        ): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let prepass_items = world.resource::<TerrainViewComponents<TerrainPrepassItem>>();
+        let gpu_terrains = world.resource::<TerrainComponents<GpuTerrain>>();
+        let gpu_terrain_views = world.resource::<TerrainViewComponents<GpuTerrainView>>();
+
+        let draws = prepass_items
+            .iter()
+            .filter(|&(&(_, view), _)| view == main_view)
+            .filter_map(|(&terrain_view @ (terrain, _), prepass_item)| {
+                let pipeline = pipeline_cache.get_render_pipeline(prepass_item.pipeline)?;
+                let terrain_bind_group = gpu_terrains.get(&terrain)?.terrain_bind_group.as_ref()?;
+                let gpu_terrain_view = gpu_terrain_views.get(&terrain_view)?;
+                let terrain_view_bind_group = gpu_terrain_view.terrain_view_bind_group.as_ref()?;
+
+                Some((
+                    pipeline,
+                    terrain_bind_group,
+                    terrain_view_bind_group,
+                    &gpu_terrain_view.indirect_buffer,
+                ))
+            })
+            .collect_vec();
+
+        if draws.is_empty() {
+            return Ok(());
+        }
+
+        let view_offsets = match (view_bind_group.motion_vectors, previous_view_offset) {
+            (true, Some(previous_view_offset)) => {
+                vec![view_uniform_offset.offset, previous_view_offset.offset]
+            }
+            (true, None) => return Ok(()),
+            (false, _) => vec![view_uniform_offset.offset],
+        };
+        let view_bind_group = view_bind_group.bind_group.clone();
+
+        let mut color_attachments = vec![
+            prepass_textures
+                .normal
+                .as_ref()
+                .map(|texture| texture.get_attachment()),
+            prepass_textures
+                .motion_vectors
+                .as_ref()
+                .map(|texture| texture.get_attachment()),
+        ];
+
+        if color_attachments.iter().all(Option::is_none) {
+            color_attachments.clear();
+        }
+
+        // call this here, otherwise the order between passes is incorrect
+        let depth_stencil_attachment = Some(depth.get_attachment(StoreOp::Store));
+        let depth_texture = depth.texture.clone();
+        let prepass_depth_texture = prepass_textures
+            .depth
+            .as_ref()
+            .map(|texture| texture.texture.texture.clone());
+        let size = prepass_textures.size;
+
+        context.add_command_buffer_generation_task(move |device| {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+
+            let pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("terrain_prepass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+                ..default()
+            });
+            let mut pass = TrackedRenderPass::new(&device, pass);
+
+            if let Some(viewport) = camera.viewport.as_ref() {
+                pass.set_camera_viewport(viewport);
+            }
+
+            pass.set_bind_group(0, &view_bind_group, &view_offsets);
+
+            for (pipeline, terrain_bind_group, terrain_view_bind_group, indirect_buffer) in draws {
+                pass.set_render_pipeline(pipeline);
+                pass.set_bind_group(1, terrain_bind_group, &[]);
+                pass.set_bind_group(2, terrain_view_bind_group, &[]);
+                pass.draw_indirect(indirect_buffer, 0);
+            }
+
+            drop(pass);
+
+            // the depth prepass texture has already been copied by the prepass of bevy,
+            // so copy it again, now that it contains the terrain as well
+            if let Some(prepass_depth_texture) = prepass_depth_texture {
+                encoder.copy_texture_to_texture(
+                    depth_texture.as_image_copy(),
+                    prepass_depth_texture.as_image_copy(),
+                    size,
+                );
+            }
+
+            encoder.finish()
+        });
+
+        Ok(())
+    }
+}
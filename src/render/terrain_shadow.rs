@@ -0,0 +1,612 @@
+//! Renders the terrain into the shadow maps of directional lights.
+//!
+//! The terrain is not drawn by the shadow passes of Bevy, since its geometry is generated by the
+//! tiling prepass. Instead, each shadow casting directional light gets its own terrain view
+//! (a [`TileTree`] and a [`GpuTerrainView`]) per camera, which is refined by the tiling prepass like any other view.
+//! Since Bevy computes the cascades of a light for every camera separately, each light view follows
+//! the level of detail of its camera, while its culling volume encloses the cascades of that camera.
+//! Afterwards, the [`TerrainShadowPassNode`] of each camera draws the refined geometry of its light views
+//! into every cascade of the light.
+
+use crate::{
+    debug::DebugTerrain,
+    render::{
+        GpuTerrain, GpuTerrainView, TerrainPipelineFlags, TerrainPipelineKey,
+        TerrainTilingPrepassPipelines,
+    },
+    shaders::DEFAULT_VERTEX_SHADER,
+    terrain::{TerrainComponents, TerrainConfig},
+    terrain_data::{GpuTileAtlas, TileAtlas, TileTree},
+    terrain_view::{TerrainViewComponents, TerrainViewConfig},
+};
+use bevy::{
+    core_pipeline::core_3d::CORE_3D_DEPTH_FORMAT,
+    ecs::query::QueryItem,
+    pbr::{CascadeShadowConfig, LightEntity, ShadowView, ViewLightEntities},
+    prelude::*,
+    render::{
+        Extract,
+        camera::CameraProjection,
+        primitives::Frustum,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{binding_types::uniform_buffer, *},
+        renderer::{RenderContext, RenderDevice},
+        settings::WgpuFeatures,
+        storage::ShaderStorageBuffer,
+        sync_world::MainEntity,
+        view::{ExtractedView, ViewUniform, ViewUniformOffset, ViewUniforms},
+    },
+};
+use big_space::prelude::*;
+use itertools::Itertools;
+
+/// Configures the shadows cast by the terrain.
+#[derive(Resource, Clone)]
+pub struct TerrainShadowSettings {
+    /// Whether the terrain casts shadows for directional lights with enabled shadows.
+    pub enabled: bool,
+    /// The view config of the light views.
+    pub view_config: TerrainViewConfig,
+}
+
+impl Default for TerrainShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            view_config: TerrainViewConfig::shadow(),
+        }
+    }
+}
+
+/// The view of a directional light onto a terrain, which covers the shadow cascades of a single camera.
+///
+/// Each light view is identified by its own view entity, which is spawned together with the view.
+#[derive(Clone, Debug)]
+pub struct TerrainShadowView {
+    /// The shadow casting directional light.
+    pub light: Entity,
+    /// The camera, whose shadow cascades are covered by the light view.
+    pub camera: Entity,
+}
+
+impl TerrainShadowView {
+    /// Creates the light views of all shadow casting directional lights for every camera view
+    /// and removes the ones of lights, that no longer cast shadows, and of removed camera views.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn update_views(
+        mut commands: Commands,
+        settings: Res<TerrainShadowSettings>,
+        mut shadow_views: ResMut<TerrainViewComponents<TerrainShadowView>>,
+        mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
+        mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+        mut tile_atlases: Query<&mut TileAtlas>,
+        lights: Query<(Entity, &DirectionalLight)>,
+        cameras: Query<(), With<Camera>>,
+    ) {
+        let removed_views = shadow_views
+            .iter()
+            .filter(|&(&(terrain, _), shadow_view)| {
+                !settings.enabled
+                    || !lights
+                        .get(shadow_view.light)
+                        .is_ok_and(|(_, light)| light.shadows_enabled)
+                    || !tile_trees.contains_key(&(terrain, shadow_view.camera))
+            })
+            .map(|(&terrain_view, _)| terrain_view)
+            .collect_vec();
+
+        for terrain_view @ (terrain, view) in removed_views {
+            shadow_views.remove(&terrain_view);
+
+            if let Some(tile_tree) = tile_trees.remove(&terrain_view) {
+                tile_tree.remove(tile_atlases.get_mut(terrain).ok(), &mut commands);
+            }
+
+            commands.entity(view).despawn();
+        }
+
+        if !settings.enabled {
+            return;
+        }
+
+        let camera_views = tile_trees
+            .keys()
+            .filter(|&&(_, view)| cameras.contains(view))
+            .copied()
+            .collect_vec();
+
+        for (terrain, camera) in camera_views {
+            let Ok(tile_atlas) = tile_atlases.get(terrain) else {
+                continue;
+            };
+
+            for (light, directional_light) in &lights {
+                if !directional_light.shadows_enabled
+                    || shadow_views
+                        .iter()
+                        .any(|(&(shadow_terrain, _), shadow_view)| {
+                            shadow_terrain == terrain
+                                && shadow_view.light == light
+                                && shadow_view.camera == camera
+                        })
+                {
+                    continue;
+                }
+
+                let view = commands.spawn_empty().id();
+
+                // the tile tree only depends on the shape and the lod count of the terrain
+                let config = TerrainConfig {
+                    shape: tile_atlas.shape,
+                    lod_count: tile_atlas.lod_count,
+                    ..default()
+                };
+
+                tile_trees.insert(
+                    (terrain, view),
+                    TileTree::new(
+                        &config,
+                        &settings.view_config,
+                        (terrain, view),
+                        &mut commands,
+                        &mut buffers,
+                    ),
+                );
+                shadow_views.insert((terrain, view), TerrainShadowView { light, camera });
+            }
+        }
+    }
+
+    /// Updates the tile trees of all light views, while selecting newly requested and released tiles.
+    pub(crate) fn compute_requests(
+        shadow_views: Res<TerrainViewComponents<TerrainShadowView>>,
+        mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
+        lights: Query<(&CascadeShadowConfig, &GlobalTransform)>,
+        views: Query<(&Transform, &GridCell, &GlobalTransform, &Projection)>,
+        grids: Grids,
+    ) {
+        for (terrain_view, shadow_view) in shadow_views.iter() {
+            let Some(tile_tree) = tile_trees.get_mut(terrain_view) else {
+                continue;
+            };
+            let Ok((cascade_config, light_transform)) = lights.get(shadow_view.light) else {
+                continue;
+            };
+            let Some(grid) = grids.parent_grid(shadow_view.camera) else {
+                continue;
+            };
+            let Ok((transform, cell, view_transform, projection)) = views.get(shadow_view.camera)
+            else {
+                continue;
+            };
+
+            // the level of detail is selected relative to the camera
+            tile_tree.view_local_position = grid.grid_position_double(cell, transform);
+            tile_tree.view_world_position = transform.translation;
+            tile_tree.half_spaces = Self::half_spaces(
+                cascade_config,
+                projection,
+                view_transform,
+                light_transform.rotation(),
+            );
+            tile_tree.update();
+        }
+    }
+
+    /// Computes the half spaces of the box, which encloses all cascades in light space.
+    /// The cascades are covered by the view frustum of the camera between the minimum distance
+    /// and the farthest bound of the cascade configuration, the same way Bevy builds them.
+    /// The plane facing the light is omitted, so that occluders between the light and the
+    /// cascades are not culled.
+    fn half_spaces(
+        cascade_config: &CascadeShadowConfig,
+        projection: &Projection,
+        view_transform: &GlobalTransform,
+        light_rotation: Quat,
+    ) -> [Vec4; 6] {
+        let light_from_world = Mat4::from_quat(light_rotation.inverse());
+        let light_from_view = light_from_world * view_transform.compute_matrix();
+
+        let (min, max) = cascade_config
+            .bounds
+            .last()
+            .into_iter()
+            .flat_map(|&far_bound| {
+                // the camera looks along its negative z-axis
+                projection.get_frustum_corners(-cascade_config.minimum_distance, -far_bound)
+            })
+            .map(|corner| light_from_view.transform_point3(corner.into()))
+            .fold((Vec3::MAX, Vec3::MIN), |(min, max), corner| {
+                (min.min(corner), max.max(corner))
+            });
+
+        if min.cmpgt(max).any() {
+            // there are no cascades, so nothing has to be culled
+            return [Vec4::W; 6];
+        }
+
+        // the light looks along its negative z-axis
+        let clip_from_light = Mat4::orthographic_rh(min.x, max.x, min.y, max.y, -max.z, -min.z);
+
+        let mut half_spaces = Frustum::from_clip_from_world(&(clip_from_light * light_from_world))
+            .half_spaces
+            .map(|space| space.normal_d());
+
+        // the near plane of the (not reverse-z) orthographic projection
+        half_spaces[5] = Vec4::W;
+
+        half_spaces
+    }
+
+    pub(crate) fn extract(
+        mut shadow_views: ResMut<TerrainViewComponents<TerrainShadowView>>,
+        extracted_shadow_views: Extract<Res<TerrainViewComponents<TerrainShadowView>>>,
+    ) {
+        shadow_views.clear();
+        shadow_views.extend(
+            extracted_shadow_views
+                .iter()
+                .map(|(&terrain_view, shadow_view)| (terrain_view, shadow_view.clone())),
+        );
+    }
+}
+
+/// The pipeline used to render the terrain into shadow maps.
+#[derive(Resource)]
+pub struct TerrainShadowPipeline {
+    view_layout: BindGroupLayout,
+    terrain_layout: BindGroupLayout,
+    terrain_view_layout: BindGroupLayout,
+    vertex_shader: Handle<Shader>,
+    unclipped_depth: bool,
+}
+
+impl FromWorld for TerrainShadowPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let prepass_pipelines = world.resource::<TerrainTilingPrepassPipelines>();
+
+        let view_layout = device.create_bind_group_layout(
+            "terrain_shadow_view_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::VERTEX,
+                uniform_buffer::<ViewUniform>(true),
+            ),
+        );
+
+        Self {
+            view_layout,
+            terrain_layout: prepass_pipelines.terrain_layout.clone(),
+            terrain_view_layout: prepass_pipelines.terrain_view_layout.clone(),
+            vertex_shader: world.load_asset(DEFAULT_VERTEX_SHADER),
+            unclipped_depth: device.features().contains(WgpuFeatures::DEPTH_CLIP_CONTROL),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for TerrainShadowPipeline {
+    type Key = TerrainPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = key.flags.shader_defs();
+        shader_defs.push("VERTEX".into());
+        shader_defs.push("SHADOW".into());
+
+        if !self.unclipped_depth {
+            // occluders in front of the near plane have to be clamped in the vertex shader instead
+            shader_defs.push("DEPTH_CLAMP_ORTHO".into());
+        }
+
+        RenderPipelineDescriptor {
+            label: Some("terrain_shadow_pipeline".into()),
+            layout: vec![
+                self.view_layout.clone(),
+                self.terrain_layout.clone(),
+                self.terrain_view_layout.clone(),
+            ],
+            push_constant_ranges: default(),
+            vertex: VertexState {
+                shader: self.vertex_shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs,
+                buffers: Vec::new(),
+            },
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: self.unclipped_depth,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+            },
+            fragment: None,
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: default(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// The shadow pipeline of a terrain.
+pub(crate) struct TerrainShadowItem {
+    pipeline: CachedRenderPipelineId,
+}
+
+/// Queues the shadow pipelines of all terrains.
+pub(crate) fn queue_terrain_shadows(
+    debug: Option<Res<DebugTerrain>>,
+    pipeline_cache: Res<PipelineCache>,
+    shadow_pipeline: Res<TerrainShadowPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TerrainShadowPipeline>>,
+    mut shadow_items: ResMut<TerrainComponents<TerrainShadowItem>>,
+    gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
+) {
+    for (&terrain, gpu_tile_atlas) in gpu_tile_atlases.iter() {
+        let mut flags = TerrainPipelineFlags::from_msaa_samples(1);
+        if gpu_tile_atlas.is_spherical {
+            flags |= TerrainPipelineFlags::SPHERICAL;
+        }
+
+        if let Some(debug) = &debug {
+            flags |= TerrainPipelineFlags::from_debug(debug);
+        } else {
+            flags |= TerrainPipelineFlags::MORPH | TerrainPipelineFlags::BLEND;
+        }
+
+        let pipeline = pipelines.specialize(
+            &pipeline_cache,
+            &shadow_pipeline,
+            TerrainPipelineKey { flags },
+        );
+
+        shadow_items.insert(terrain, TerrainShadowItem { pipeline });
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct TerrainShadowPass;
+
+/// Draws the terrain into the shadow maps of all directional lights of the view.
+pub struct TerrainShadowPassNode {
+    light_views: QueryState<(
+        &'static ShadowView,
+        &'static ExtractedView,
+        &'static ViewUniformOffset,
+        &'static LightEntity,
+    )>,
+}
+
+impl FromWorld for TerrainShadowPassNode {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            light_views: QueryState::new(world),
+        }
+    }
+}
+
+impl ViewNode for TerrainShadowPassNode {
+    type ViewQuery = (MainEntity, &'static ViewLightEntities);
+
+    fn update(&mut self, world: &mut World) {
+        self.light_views.update_archetypes(world);
+    }
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        (main_view, view_lights): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let device = world.resource::<RenderDevice>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let shadow_pipeline = world.resource::<TerrainShadowPipeline>();
+        let shadow_items = world.resource::<TerrainComponents<TerrainShadowItem>>();
+        let shadow_views = world.resource::<TerrainViewComponents<TerrainShadowView>>();
+        let gpu_terrains = world.resource::<TerrainComponents<GpuTerrain>>();
+        let gpu_terrain_views = world.resource::<TerrainViewComponents<GpuTerrainView>>();
+
+        let Some(view_binding) = world.resource::<ViewUniforms>().uniforms.binding() else {
+            return Ok(());
+        };
+
+        // Todo: prepare this in a separate system
+        let view_bind_group = device.create_bind_group(
+            "terrain_shadow_view_bind_group",
+            &shadow_pipeline.view_layout,
+            &BindGroupEntries::single(view_binding),
+        );
+
+        for &light_view in &view_lights.lights {
+            let Ok((shadow_view, extracted_view, view_uniform_offset, light_entity)) =
+                self.light_views.get_manual(world, light_view)
+            else {
+                continue;
+            };
+
+            if !matches!(light_entity, LightEntity::Directional { .. }) {
+                continue;
+            }
+
+            let light = extracted_view.retained_view_entity.main_entity.id();
+
+            let draws = shadow_views
+                .iter()
+                .filter(|(_, shadow_view)| {
+                    shadow_view.light == light && shadow_view.camera == main_view
+                })
+                .filter_map(|(&terrain_view @ (terrain, _), _)| {
+                    let shadow_item = shadow_items.get(&terrain)?;
+                    let pipeline = pipeline_cache.get_render_pipeline(shadow_item.pipeline)?;
+                    let terrain_bind_group =
+                        gpu_terrains.get(&terrain)?.terrain_bind_group.as_ref()?;
+                    let gpu_terrain_view = gpu_terrain_views.get(&terrain_view)?;
+                    let terrain_view_bind_group =
+                        gpu_terrain_view.terrain_view_bind_group.as_ref()?;
+
+                    Some((
+                        pipeline,
+                        terrain_bind_group,
+                        terrain_view_bind_group,
+                        &gpu_terrain_view.indirect_buffer,
+                    ))
+                })
+                .collect_vec();
+
+            if draws.is_empty() {
+                continue;
+            }
+
+            // the shadow map has already been cleared by the shadow pass of bevy
+            let depth_stencil_attachment =
+                Some(shadow_view.depth_attachment.get_attachment(StoreOp::Store));
+            let view_bind_group = view_bind_group.clone();
+            let view_offset = view_uniform_offset.offset;
+
+            context.add_command_buffer_generation_task(move |device| {
+                let mut encoder =
+                    device.create_command_encoder(&CommandEncoderDescriptor::default());
+
+                let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("terrain_shadow_pass"),
+                    depth_stencil_attachment,
+                    ..default()
+                });
+
+                pass.set_bind_group(0, &view_bind_group, &[view_offset]);
+
+                for (pipeline, terrain_bind_group, terrain_view_bind_group, indirect_buffer) in
+                    draws
+                {
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(1, terrain_bind_group, &[]);
+                    pass.set_bind_group(2, terrain_view_bind_group, &[]);
+                    pass.draw_indirect(indirect_buffer, 0);
+                }
+
+                drop(pass);
+
+                encoder.finish()
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::TerrainShape,
+        plugin::TerrainSettings,
+        terrain_data::{AttachmentConfig, AttachmentFormat, AttachmentLabel},
+        terrain_view::TerrainCamera,
+    };
+    use bevy::platform::collections::HashSet;
+
+    fn spawn_terrain(world: &mut World) -> Entity {
+        let mut config = TerrainConfig {
+            shape: TerrainShape::Plane {
+                side_length: 1000.0,
+            },
+            lod_count: 2,
+            ..default()
+        };
+        config.add_attachment(
+            AttachmentLabel::Height,
+            AttachmentConfig {
+                texture_size: 8,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::R32F,
+            },
+        );
+
+        let tile_atlas =
+            TileAtlas::new(&config, &mut default(), &TerrainSettings::default()).unwrap();
+
+        world.spawn(tile_atlas).id()
+    }
+
+    /// Runs the view update and returns the terrain, light and camera of every light view.
+    fn update(world: &mut World, schedule: &mut Schedule) -> HashSet<(Entity, Entity, Entity)> {
+        schedule.run(world);
+        world.clear_trackers();
+
+        let shadow_views = world.resource::<TerrainViewComponents<TerrainShadowView>>();
+        let tile_trees = world.resource::<TerrainViewComponents<TileTree>>();
+
+        shadow_views
+            .iter()
+            .map(|(&terrain_view @ (terrain, view), shadow_view)| {
+                // each light view has its own view entity and tile tree
+                assert!(world.get_entity(view).is_ok());
+                assert!(tile_trees.contains_key(&terrain_view));
+
+                (terrain, shadow_view.light, shadow_view.camera)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn light_views_per_camera() {
+        let mut world = World::new();
+        world.init_resource::<TerrainViewComponents<TileTree>>();
+        world.init_resource::<TerrainViewComponents<TerrainShadowView>>();
+        world.init_resource::<TerrainShadowSettings>();
+        world.init_resource::<Assets<ShaderStorageBuffer>>();
+
+        let mut schedule = Schedule::default();
+        schedule
+            .add_systems((TerrainCamera::update_views, TerrainShadowView::update_views).chain());
+
+        let terrain = spawn_terrain(&mut world);
+        let light = world
+            .spawn(DirectionalLight {
+                shadows_enabled: true,
+                ..default()
+            })
+            .id();
+        let camera = world
+            .spawn((Camera3d::default(), TerrainCamera::default()))
+            .id();
+        let other_camera = world
+            .spawn((Camera3d::default(), TerrainCamera::default()))
+            .id();
+
+        // the light gets a separate view for the cascades of each camera
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, light, camera), (terrain, light, other_camera)])
+        );
+
+        // the light view of a removed camera is removed together with its view entity
+        let (&(_, other_view), _) = world
+            .resource::<TerrainViewComponents<TerrainShadowView>>()
+            .iter()
+            .find(|(_, shadow_view)| shadow_view.camera == other_camera)
+            .unwrap();
+        world.entity_mut(other_camera).despawn();
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, light, camera)])
+        );
+        assert!(world.get_entity(other_view).is_err());
+
+        // lights without shadows have no views
+        world
+            .get_mut::<DirectionalLight>(light)
+            .unwrap()
+            .shadows_enabled = false;
+        assert!(update(&mut world, &mut schedule).is_empty());
+    }
+}
@@ -103,6 +103,8 @@ pub(crate) struct TerrainViewUniform {
     face: u32,
     lod: u32,
     coordinates: [ViewCoordinate; 6],
+    orthographic_distance: f32,
+    flat_projection: u32,
     world_position: Vec3,
     half_spaces: [Vec4; 6],
     surface_approximation: [crate::math::SurfaceApproximation; 6],
@@ -128,6 +130,8 @@ impl From<&TileTree> for TerrainViewUniform {
             coordinates: tile_tree
                 .view_coordinates
                 .map(|view_coordinate| ViewCoordinate::new(view_coordinate, tile_tree.view_lod)),
+            orthographic_distance: tile_tree.orthographic_distance.unwrap_or(0.0) as f32,
+            flat_projection: tile_tree.projection.is_flat(tile_tree.shape) as u32,
             world_position: tile_tree.view_world_position,
             half_spaces: tile_tree.half_spaces,
 
@@ -214,6 +218,8 @@ impl GpuTerrainView {
         mut gpu_terrain_views: ResMut<TerrainViewComponents<GpuTerrainView>>,
         tile_trees: Extract<Res<TerrainViewComponents<TileTree>>>,
     ) {
+        gpu_terrain_views.retain(|terrain_view, _| tile_trees.contains_key(terrain_view));
+
         for (&(terrain, view), tile_tree) in tile_trees.iter() {
             if gpu_terrain_views.contains_key(&(terrain, view)) {
                 continue;
@@ -301,6 +301,8 @@ pub(crate) fn queue_tiling_prepass(
     gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
     gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
 ) {
+    prepass_items.retain(|terrain_view, _| gpu_terrain_views.contains_key(terrain_view));
+
     for &(terrain, view) in gpu_terrain_views.keys() {
         let gpu_tile_atlas = &gpu_tile_atlases[&terrain];
 
@@ -0,0 +1,1027 @@
+//! GPU-driven scatter of instanced meshes (e.g. trees, grass and rocks) on the terrain surface.
+//!
+//! Each [`ScatterLayer`] places its instances on the tiles of a single lod of the tile tree,
+//! that lie within the scatter distance of the view. These tiles are selected in the main world,
+//! next to the tile trees. Every frame, a compute pass generates the instances of these tiles and
+//! culls them against the view frustum.
+//! The candidate positions are derived from a hash of the [`TileCoordinate`] and the index of the
+//! instance, so a tile always produces the same instances, no matter when it is (re)loaded or which
+//! view scatters it. The candidates are accepted based on the height and slope rules of the layer
+//! and optionally a channel of the `scatter_density` attachment.
+//! Finally, the accepted instances of each layer are drawn with a single indirect draw call.
+
+use crate::{
+    debug::DebugTerrain,
+    math::TileCoordinate,
+    render::{
+        GpuTerrain, GpuTerrainView, SetTerrainBindGroup, SetTerrainFeatureBindGroups,
+        SetTerrainViewBindGroup, TERRAIN_DEPTH_FORMAT, TerrainFeatureLayouts, TerrainFeatures,
+        TerrainItem, TerrainPipelineFlags, TerrainPipelineKey, TerrainTilingPrepassPipelines,
+        TilingPrepass, TilingPrepassPipelineKey,
+    },
+    shaders::{SCATTER_GENERATE_SHADER, SCATTER_RENDER_SHADER},
+    terrain::TerrainComponents,
+    terrain_data::{AttachmentLabel, GpuTileAtlas, TileTree},
+    terrain_view::TerrainViewComponents,
+    util::GpuBuffer,
+};
+use bevy::{
+    ecs::{
+        query::ROQueryItem,
+        system::{SystemParamItem, lifetimeless::SRes},
+    },
+    image::BevyDefault,
+    pbr::{
+        MeshPipeline, MeshPipelineViewLayoutKey, RenderViewLightProbes, SetMeshViewBindGroup,
+        ShadowFilteringMethod,
+    },
+    prelude::*,
+    render::{
+        Extract, Render, RenderApp, RenderSet,
+        graph::CameraDriverLabel,
+        mesh::{
+            MeshVertexBufferLayoutRef, RenderMesh, RenderMeshBufferInfo, allocator::MeshAllocator,
+        },
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            binding_types::{storage_buffer, storage_buffer_read_only, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+        view::RetainedViewEntity,
+    },
+};
+use bytemuck::cast_slice;
+
+const WORKGROUP_SIZE: u32 = 64;
+const NO_CHANNEL: u32 = u32::MAX;
+
+/// A layer of instances scattered on the terrain.
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default, Debug)]
+pub struct ScatterLayer {
+    /// The mesh of the instances, which requires positions and normals.
+    /// Its y axis is aligned with the up direction of the surface.
+    pub mesh: Handle<Mesh>,
+    /// The base color of the instances.
+    pub color: Color,
+    /// The perceived roughness of the instances.
+    pub perceptual_roughness: f32,
+    /// The lod of the tiles, on which the instances are placed.
+    /// Higher lods have smaller tiles and thus place the instances more densely.
+    /// The instances are placed using the data of this lod, once it is loaded,
+    /// so that they do not move when finer data is loaded.
+    pub lod: u32,
+    /// The number of candidate positions of each tile.
+    pub instances_per_tile: u32,
+    /// The distance to the view in meters, up to which instances are placed.
+    /// The instances thin out over the last fifth of it.
+    pub distance: f32,
+    /// The fraction of candidates, that are accepted.
+    pub density: f32,
+    /// The channel of the `scatter_density` attachment, which scales the density.
+    /// Requires the [`TerrainScatter::density_map`] to be enabled.
+    pub density_channel: Option<u32>,
+    /// The range of terrain heights in meters, in which instances are placed.
+    pub height_range: Vec2,
+    /// The range of surface slopes in degrees, in which instances are placed.
+    pub slope_range: Vec2,
+    /// The range of random scales of the instances.
+    pub scale_range: Vec2,
+    /// How far the instances lean towards the surface normal instead of pointing upwards,
+    /// in the range `[0.0, 1.0]`. Rocks usually align with the surface, while trees grow upwards.
+    pub align_to_normal: f32,
+    /// The radius of a sphere around the origin of the mesh, that bounds it at a scale of one.
+    /// It is used to cull the instances outside of the view frustum.
+    pub radius: f32,
+    /// Differentiates the placement of layers with the same lod and instance count.
+    pub seed: u32,
+}
+
+impl Default for ScatterLayer {
+    fn default() -> Self {
+        Self {
+            mesh: default(),
+            color: Color::WHITE,
+            perceptual_roughness: 0.8,
+            lod: 10,
+            instances_per_tile: 64,
+            distance: 500.0,
+            density: 1.0,
+            density_channel: None,
+            height_range: Vec2::new(f32::MIN, f32::MAX),
+            slope_range: Vec2::new(0.0, 90.0),
+            scale_range: Vec2::ONE,
+            align_to_normal: 0.0,
+            radius: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+/// The scattered instances of a terrain.
+///
+/// Add this component to the terrain entity, to place the instances of its layers on the surface.
+/// If the density map is enabled, the `scatter_density` attachment (e.g. an
+/// [`Rgba8U`](crate::terrain_data::AttachmentFormat::Rgba8U) attachment) has to be part of the
+/// [`TerrainSettings`](crate::plugin::TerrainSettings).
+#[derive(Component, Reflect, Clone, Debug, Default)]
+#[reflect(Component, Default, Debug)]
+pub struct TerrainScatter {
+    pub layers: Vec<ScatterLayer>,
+    /// Whether the layers may sample their density from the `scatter_density` attachment.
+    pub density_map: bool,
+}
+
+/// The tiles of each layer of a [`TerrainScatter`], that lie within the scatter distance of a view.
+#[derive(Default)]
+pub(crate) struct ScatterTiles {
+    layers: Vec<Vec<TileCoordinate>>,
+}
+
+impl ScatterTiles {
+    pub(crate) fn update(
+        mut scatter_tiles: ResMut<TerrainViewComponents<ScatterTiles>>,
+        tile_trees: Res<TerrainViewComponents<TileTree>>,
+        scatters: Query<&TerrainScatter>,
+    ) {
+        scatter_tiles.retain(|&(terrain, view), _| {
+            scatters.contains(terrain) && tile_trees.contains_key(&(terrain, view))
+        });
+
+        for (&(terrain, view), tile_tree) in tile_trees.iter() {
+            let Ok(scatter) = scatters.get(terrain) else {
+                continue;
+            };
+
+            scatter_tiles.entry((terrain, view)).or_default().layers = scatter
+                .layers
+                .iter()
+                .map(|layer| {
+                    let lod = layer.lod.min(tile_tree.lod_count - 1);
+                    tile_tree.tiles_within_distance(lod, layer.distance as f64)
+                })
+                .collect();
+        }
+    }
+}
+
+/// The scatter layer data that is available in shaders.
+#[derive(Clone, Default, ShaderType)]
+pub struct ScatterLayerUniform {
+    color: Vec4,
+    height_range: Vec2,
+    slope_range: Vec2,
+    scale_range: Vec2,
+    tile_count: u32,
+    instances_per_tile: u32,
+    distance: f32,
+    density: f32,
+    density_channel: u32,
+    align_to_normal: f32,
+    radius: f32,
+    perceptual_roughness: f32,
+    seed: u32,
+}
+
+impl ScatterLayerUniform {
+    fn new(layer: &ScatterLayer, tile_count: u32) -> Self {
+        Self {
+            color: LinearRgba::from(layer.color).to_vec4(),
+            height_range: layer.height_range,
+            slope_range: layer.slope_range * (std::f32::consts::PI / 180.0),
+            scale_range: layer.scale_range,
+            tile_count,
+            instances_per_tile: layer.instances_per_tile,
+            distance: layer.distance,
+            density: layer.density,
+            density_channel: layer.density_channel.unwrap_or(NO_CHANNEL),
+            align_to_normal: layer.align_to_normal.clamp(0.0, 1.0),
+            radius: layer.radius,
+            perceptual_roughness: layer.perceptual_roughness,
+            seed: layer.seed,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct ScatterInstance {
+    position: Vec3,
+    scale: f32,
+    up: Vec3,
+    rotation: f32,
+}
+
+/// The draw call of a layer, based on the buffers of its mesh.
+#[derive(Clone, Copy)]
+enum ScatterDraw {
+    Indexed(IndexFormat),
+    NonIndexed,
+}
+
+/// The render world representation of a [`ScatterLayer`] for one view.
+struct GpuScatterLayer {
+    mesh: AssetId<Mesh>,
+    tiles: Vec<TileCoordinate>,
+    instances_per_tile: u32,
+    /// The number of tiles, that fit into the tile and instance buffers.
+    tile_capacity: usize,
+    layer_buffer: GpuBuffer<ScatterLayerUniform>,
+    indirect_buffer: Buffer,
+    buffers: Option<(Buffer, Buffer)>,
+    scatter_bind_group: Option<BindGroup>,
+    instance_bind_group: Option<BindGroup>,
+    draw: Option<ScatterDraw>,
+}
+
+impl GpuScatterLayer {
+    fn new(device: &RenderDevice) -> Self {
+        Self {
+            mesh: AssetId::default(),
+            tiles: Vec::new(),
+            instances_per_tile: 0,
+            tile_capacity: 0,
+            layer_buffer: GpuBuffer::empty_labeled(
+                "scatter_layer_buffer",
+                device,
+                BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            ),
+            indirect_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("scatter_indirect_buffer"),
+                size: 5 * size_of::<u32>() as u64,
+                usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            buffers: None,
+            scatter_bind_group: None,
+            instance_bind_group: None,
+            draw: None,
+        }
+    }
+
+    fn resize(&mut self, device: &RenderDevice, pipelines: &TerrainScatterPipelines) {
+        if self.tiles.len() <= self.tile_capacity && self.buffers.is_some() {
+            return;
+        }
+
+        self.tile_capacity = self.tiles.len().next_power_of_two().max(16);
+
+        let tile_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("scatter_tile_buffer"),
+            size: TileCoordinate::min_size().get() * self.tile_capacity as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let instance_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("scatter_instance_buffer"),
+            size: ScatterInstance::min_size().get()
+                * (self.tile_capacity as u64 * self.instances_per_tile.max(1) as u64),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        self.scatter_bind_group = Some(device.create_bind_group(
+            "scatter_bind_group",
+            &pipelines.scatter_layout,
+            &BindGroupEntries::with_indices((
+                (100, &self.layer_buffer),
+                (101, tile_buffer.as_entire_binding()),
+                (102, instance_buffer.as_entire_binding()),
+                (103, self.indirect_buffer.as_entire_binding()),
+            )),
+        ));
+        self.instance_bind_group = Some(device.create_bind_group(
+            "scatter_instance_bind_group",
+            &pipelines.instance_layout,
+            &BindGroupEntries::with_indices((
+                (100, instance_buffer.as_entire_binding()),
+                (101, &self.layer_buffer),
+            )),
+        ));
+        self.buffers = Some((tile_buffer, instance_buffer));
+    }
+
+    fn prepare(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        pipelines: &TerrainScatterPipelines,
+        mesh_allocator: &MeshAllocator,
+        render_meshes: &RenderAssets<RenderMesh>,
+    ) {
+        self.resize(device, pipelines);
+        self.layer_buffer.update(queue);
+
+        let (tile_buffer, _) = self.buffers.as_ref().unwrap();
+        let tiles = self
+            .tiles
+            .iter()
+            .flat_map(|tile| [tile.face, tile.lod, tile.xy.x as u32, tile.xy.y as u32])
+            .collect::<Vec<_>>();
+        queue.write_buffer(tile_buffer, 0, cast_slice(&tiles));
+
+        // the instance count is reset every frame and incremented by the scatter pass
+        let (args, draw) = match (
+            render_meshes.get(self.mesh),
+            mesh_allocator.mesh_vertex_slice(&self.mesh),
+        ) {
+            (Some(render_mesh), Some(vertex_slice)) => match render_mesh.buffer_info {
+                RenderMeshBufferInfo::Indexed {
+                    count,
+                    index_format,
+                } => match mesh_allocator.mesh_index_slice(&self.mesh) {
+                    Some(index_slice) => (
+                        [
+                            count,
+                            0,
+                            index_slice.range.start,
+                            vertex_slice.range.start,
+                            0,
+                        ],
+                        Some(ScatterDraw::Indexed(index_format)),
+                    ),
+                    None => ([0; 5], None),
+                },
+                RenderMeshBufferInfo::NonIndexed => (
+                    [render_mesh.vertex_count, 0, vertex_slice.range.start, 0, 0],
+                    Some(ScatterDraw::NonIndexed),
+                ),
+            },
+            _ => ([0; 5], None),
+        };
+
+        queue.write_buffer(&self.indirect_buffer, 0, cast_slice(&args));
+        self.draw = draw;
+    }
+}
+
+/// The scattered layers of a terrain for one view.
+pub struct GpuScatter {
+    layers: Vec<GpuScatterLayer>,
+    density_map: bool,
+    scatter_pipeline: Option<CachedComputePipelineId>,
+}
+
+impl GpuScatter {
+    pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        mut gpu_scatters: ResMut<TerrainViewComponents<GpuScatter>>,
+        scatter_tiles: Extract<Res<TerrainViewComponents<ScatterTiles>>>,
+        scatters: Extract<Query<&TerrainScatter>>,
+    ) {
+        gpu_scatters.retain(|key, _| scatter_tiles.contains_key(key));
+
+        for (&(terrain, view), scatter_tiles) in scatter_tiles.iter() {
+            let Ok(scatter) = scatters.get(terrain) else {
+                continue;
+            };
+
+            let gpu_scatter = gpu_scatters
+                .entry((terrain, view))
+                .or_insert_with(|| GpuScatter {
+                    layers: Vec::new(),
+                    density_map: false,
+                    scatter_pipeline: None,
+                });
+
+            gpu_scatter.density_map = scatter.density_map;
+            gpu_scatter.layers.truncate(scatter.layers.len());
+            gpu_scatter.layers.extend(
+                (gpu_scatter.layers.len()..scatter.layers.len())
+                    .map(|_| GpuScatterLayer::new(&device)),
+            );
+
+            for ((gpu_layer, layer), tiles) in gpu_scatter
+                .layers
+                .iter_mut()
+                .zip(&scatter.layers)
+                .zip(&scatter_tiles.layers)
+            {
+                if gpu_layer.instances_per_tile != layer.instances_per_tile {
+                    gpu_layer.instances_per_tile = layer.instances_per_tile;
+                    gpu_layer.buffers = None;
+                }
+
+                gpu_layer.mesh = layer.mesh.id();
+                gpu_layer.tiles.clone_from(tiles);
+                gpu_layer.layer_buffer.set_value(ScatterLayerUniform::new(
+                    layer,
+                    gpu_layer.tiles.len() as u32,
+                ));
+            }
+        }
+    }
+
+    pub(crate) fn prepare(
+        device: Res<RenderDevice>,
+        queue: Res<RenderQueue>,
+        pipelines: Res<TerrainScatterPipelines>,
+        mesh_allocator: Res<MeshAllocator>,
+        render_meshes: Res<RenderAssets<RenderMesh>>,
+        mut gpu_scatters: ResMut<TerrainViewComponents<GpuScatter>>,
+    ) {
+        for gpu_scatter in gpu_scatters.values_mut() {
+            for gpu_layer in &mut gpu_scatter.layers {
+                gpu_layer.prepare(&device, &queue, &pipelines, &mesh_allocator, &render_meshes);
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ScatterPipelineKey {
+    prepass: TilingPrepassPipelineKey,
+    density_map: bool,
+}
+
+/// The pipelines used to generate and render the instances of the scatter layers.
+#[derive(Resource)]
+pub struct TerrainScatterPipelines {
+    view_layout: BindGroupLayout,
+    view_layout_multisampled: BindGroupLayout,
+    prepass_view_layout: BindGroupLayout,
+    terrain_layout: BindGroupLayout,
+    terrain_view_layout: BindGroupLayout,
+    scatter_layout: BindGroupLayout,
+    instance_layout: BindGroupLayout,
+    feature_layouts: TerrainFeatureLayouts,
+    generate_shader: Handle<Shader>,
+    render_shader: Handle<Shader>,
+}
+
+impl FromWorld for TerrainScatterPipelines {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let mesh_pipeline = world.resource::<MeshPipeline>();
+        let prepass_pipelines = world.resource::<TerrainTilingPrepassPipelines>();
+
+        let scatter_layout = device.create_bind_group_layout(
+            "scatter_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::COMPUTE,
+                (
+                    (100, uniform_buffer::<ScatterLayerUniform>(false)),
+                    (101, storage_buffer_read_only::<TileCoordinate>(false)),
+                    (102, storage_buffer::<ScatterInstance>(false)),
+                    (103, storage_buffer::<[u32; 5]>(false)),
+                ),
+            ),
+        );
+        let instance_layout = device.create_bind_group_layout(
+            "scatter_instance_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::VERTEX_FRAGMENT,
+                (
+                    (100, storage_buffer_read_only::<ScatterInstance>(false)),
+                    (101, uniform_buffer::<ScatterLayerUniform>(false)),
+                ),
+            ),
+        );
+
+        Self {
+            view_layout: mesh_pipeline
+                .get_view_layout(MeshPipelineViewLayoutKey::empty())
+                .clone(),
+            view_layout_multisampled: mesh_pipeline
+                .get_view_layout(MeshPipelineViewLayoutKey::MULTISAMPLED)
+                .clone(),
+            prepass_view_layout: prepass_pipelines.prepass_view_layout.clone(),
+            terrain_layout: prepass_pipelines.terrain_layout.clone(),
+            terrain_view_layout: prepass_pipelines.terrain_view_layout.clone(),
+            scatter_layout,
+            instance_layout,
+            feature_layouts: TerrainFeatureLayouts::new(device),
+            generate_shader: world.load_asset(SCATTER_GENERATE_SHADER),
+            render_shader: world.load_asset(SCATTER_RENDER_SHADER),
+        }
+    }
+}
+
+impl SpecializedComputePipeline for TerrainScatterPipelines {
+    type Key = ScatterPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
+        let mut shader_defs = key.prepass.shader_defs();
+        shader_defs.push(ShaderDefVal::UInt("WORKGROUP_SIZE".into(), WORKGROUP_SIZE));
+
+        if key.density_map {
+            shader_defs.push("SCATTER_DENSITY".into());
+        }
+
+        ComputePipelineDescriptor {
+            label: Some("scatter_pipeline".into()),
+            layout: vec![
+                self.prepass_view_layout.clone(),
+                self.terrain_layout.clone(),
+                self.scatter_layout.clone(),
+            ],
+            push_constant_ranges: default(),
+            shader: self.generate_shader.clone(),
+            shader_defs,
+            entry_point: "scatter".into(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for TerrainScatterPipelines {
+    type Key = TerrainPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut shader_defs = key.flags.shader_defs();
+
+        let mut bind_group_layout = match key.flags.msaa_samples() {
+            1 => vec![self.view_layout.clone()],
+            _ => {
+                shader_defs.push("MULTISAMPLED".into());
+                vec![self.view_layout_multisampled.clone()]
+            }
+        };
+
+        bind_group_layout.push(self.terrain_layout.clone());
+        bind_group_layout.push(self.terrain_view_layout.clone());
+        bind_group_layout.push(self.instance_layout.clone());
+        self.feature_layouts
+            .specialize(key.flags, &mut bind_group_layout, &mut shader_defs);
+
+        let vertex_buffer_layout = layout.0.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+        ])?;
+
+        let mut vertex_shader_defs = shader_defs.clone();
+        vertex_shader_defs.push("VERTEX".into());
+        let mut fragment_shader_defs = shader_defs;
+        fragment_shader_defs.push("FRAGMENT".into());
+
+        Ok(RenderPipelineDescriptor {
+            label: Some("terrain_scatter_pipeline".into()),
+            layout: bind_group_layout,
+            push_constant_ranges: default(),
+            vertex: VertexState {
+                shader: self.render_shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs: vertex_shader_defs,
+                buffers: vec![vertex_buffer_layout],
+            },
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: key.flags.polygon_mode(),
+                conservative: false,
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+            },
+            fragment: Some(FragmentState {
+                shader: self.render_shader.clone(),
+                shader_defs: fragment_shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            // the instances leave the stencil untouched, which orders the terrains among each other
+            depth_stencil: Some(DepthStencilState {
+                format: TERRAIN_DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Greater,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: key.flags.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            zero_initialize_workgroup_memory: false,
+        })
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct ScatterPass;
+
+impl render_graph::Node for ScatterPass {
+    fn run<'w>(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let gpu_scatters = world.resource::<TerrainViewComponents<GpuScatter>>();
+        let gpu_terrains = world.resource::<TerrainComponents<GpuTerrain>>();
+        let gpu_terrain_views = world.resource::<TerrainViewComponents<GpuTerrainView>>();
+
+        context.add_command_buffer_generation_task(move |device| {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+            for (&(terrain, view), gpu_scatter) in gpu_scatters.iter() {
+                let Some(pipeline) = gpu_scatter
+                    .scatter_pipeline
+                    .and_then(|pipeline| pipeline_cache.get_compute_pipeline(pipeline))
+                else {
+                    continue;
+                };
+                let Some(terrain_bind_group) = gpu_terrains
+                    .get(&terrain)
+                    .and_then(|gpu_terrain| gpu_terrain.terrain_bind_group.as_ref())
+                else {
+                    continue;
+                };
+                let Some(prepass_view_bind_group) = gpu_terrain_views
+                    .get(&(terrain, view))
+                    .and_then(|gpu_terrain_view| gpu_terrain_view.prepass_view_bind_group.as_ref())
+                else {
+                    continue;
+                };
+
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, prepass_view_bind_group, &[]);
+                pass.set_bind_group(1, terrain_bind_group, &[]);
+
+                for gpu_layer in &gpu_scatter.layers {
+                    let Some(scatter_bind_group) = &gpu_layer.scatter_bind_group else {
+                        continue;
+                    };
+
+                    if gpu_layer.draw.is_none() || gpu_layer.tiles.is_empty() {
+                        continue;
+                    }
+
+                    pass.set_bind_group(2, scatter_bind_group, &[]);
+                    pass.dispatch_workgroups(
+                        gpu_layer.instances_per_tile.div_ceil(WORKGROUP_SIZE),
+                        gpu_layer.tiles.len() as u32,
+                        1,
+                    );
+                }
+            }
+
+            drop(pass);
+
+            encoder.finish()
+        });
+
+        Ok(())
+    }
+}
+
+pub struct SetScatterBindGroup<const I: usize>;
+
+impl<const I: usize, P: PhaseItem> RenderCommand<P> for SetScatterBindGroup<I> {
+    type Param = SRes<TerrainViewComponents<GpuScatter>>;
+    type ViewQuery = MainEntity;
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        view: ROQueryItem<'w, Self::ViewQuery>,
+        _: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        gpu_scatters: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = gpu_scatters
+            .into_inner()
+            .get(&(item.main_entity().id(), view))
+            .and_then(|gpu_scatter| gpu_scatter.layers.get(item.batch_range().start as usize))
+            .and_then(|gpu_layer| gpu_layer.instance_bind_group.as_ref())
+        else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_bind_group(I, bind_group, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+pub(crate) struct DrawScatterCommand;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawScatterCommand {
+    type Param = (SRes<TerrainViewComponents<GpuScatter>>, SRes<MeshAllocator>);
+    type ViewQuery = MainEntity;
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        view: ROQueryItem<'w, Self::ViewQuery>,
+        _: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        (gpu_scatters, mesh_allocator): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let mesh_allocator = mesh_allocator.into_inner();
+
+        let Some(gpu_layer) = gpu_scatters
+            .into_inner()
+            .get(&(item.main_entity().id(), view))
+            .and_then(|gpu_scatter| gpu_scatter.layers.get(item.batch_range().start as usize))
+        else {
+            return RenderCommandResult::Skip;
+        };
+        let (Some(draw), Some(vertex_slice)) = (
+            gpu_layer.draw,
+            mesh_allocator.mesh_vertex_slice(&gpu_layer.mesh),
+        ) else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_vertex_buffer(0, vertex_slice.buffer.slice(..));
+
+        match draw {
+            ScatterDraw::Indexed(index_format) => {
+                let Some(index_slice) = mesh_allocator.mesh_index_slice(&gpu_layer.mesh) else {
+                    return RenderCommandResult::Skip;
+                };
+
+                pass.set_index_buffer(index_slice.buffer.slice(..), 0, index_format);
+                pass.draw_indexed_indirect(&gpu_layer.indirect_buffer, 0);
+            }
+            ScatterDraw::NonIndexed => pass.draw_indirect(&gpu_layer.indirect_buffer, 0),
+        }
+
+        RenderCommandResult::Success
+    }
+}
+
+/// The draw function of the scattered instances. The batch range of the item holds the index of the layer.
+pub(crate) type DrawTerrainScatter = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetTerrainBindGroup<1>,
+    SetTerrainViewBindGroup<2>,
+    SetScatterBindGroup<3>,
+    SetTerrainFeatureBindGroups,
+    DrawScatterCommand,
+);
+
+/// Queues the generation and the rendering of the scatter layers of all terrain entities with a [`TerrainScatter`].
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn queue_terrain_scatter(
+    draw_functions: Res<DrawFunctions<TerrainItem>>,
+    debug: Option<Res<DebugTerrain>>,
+    pipeline_cache: Res<PipelineCache>,
+    scatter_pipelines: Res<TerrainScatterPipelines>,
+    mut compute_pipelines: ResMut<SpecializedComputePipelines<TerrainScatterPipelines>>,
+    mut render_pipelines: ResMut<SpecializedMeshPipelines<TerrainScatterPipelines>>,
+    mut terrain_phases: ResMut<ViewSortedRenderPhases<TerrainItem>>,
+    render_meshes: Res<RenderAssets<RenderMesh>>,
+    mut gpu_scatters: ResMut<TerrainViewComponents<GpuScatter>>,
+    gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
+    gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
+    terrain_features: TerrainFeatures,
+    views: Query<(
+        MainEntity,
+        &Msaa,
+        Option<&ShadowFilteringMethod>,
+        Has<RenderViewLightProbes<EnvironmentMapLight>>,
+    )>,
+) {
+    let draw_function = draw_functions
+        .read()
+        .get_id::<DrawTerrainScatter>()
+        .unwrap();
+
+    for (view, msaa, shadow_filter_method, environment_map) in &views {
+        let Some(terrain_phase) = terrain_phases.get_mut(&RetainedViewEntity {
+            main_entity: view.into(),
+            auxiliary_entity: Entity::PLACEHOLDER.into(),
+            subview_index: 0,
+        }) else {
+            continue;
+        };
+
+        for (&(terrain, _), gpu_scatter) in gpu_scatters
+            .iter_mut()
+            .filter(|((_, scatter_view), _)| *scatter_view == view)
+        {
+            let (Some(gpu_tile_atlas), Some(gpu_terrain_view)) = (
+                gpu_tile_atlases.get(&terrain),
+                gpu_terrain_views.get(&(terrain, view)),
+            ) else {
+                continue;
+            };
+
+            let mut prepass = TilingPrepassPipelineKey::NONE;
+            if gpu_tile_atlas.is_spherical {
+                prepass |= TilingPrepassPipelineKey::SPHERICAL;
+            }
+            if let Some(debug) = &debug {
+                prepass |= TilingPrepassPipelineKey::from_debug(debug);
+            }
+
+            let density_map = gpu_scatter.density_map
+                && gpu_tile_atlas
+                    .attachments
+                    .contains_key(&AttachmentLabel::Custom("scatter_density".to_string()));
+
+            gpu_scatter.scatter_pipeline = Some(compute_pipelines.specialize(
+                &pipeline_cache,
+                &scatter_pipelines,
+                ScatterPipelineKey {
+                    prepass,
+                    density_map,
+                },
+            ));
+
+            let flags = TerrainPipelineFlags::main_pass(
+                msaa,
+                shadow_filter_method,
+                environment_map,
+                gpu_tile_atlas.is_spherical,
+                debug.as_deref(),
+            ) | terrain_features.flags(terrain);
+
+            for (index, gpu_layer) in gpu_scatter.layers.iter().enumerate() {
+                let Some(render_mesh) = render_meshes.get(gpu_layer.mesh) else {
+                    continue;
+                };
+
+                let Ok(pipeline) = render_pipelines.specialize(
+                    &pipeline_cache,
+                    &scatter_pipelines,
+                    TerrainPipelineKey { flags },
+                    &render_mesh.layout,
+                ) else {
+                    continue;
+                };
+
+                terrain_phase.add(TerrainItem {
+                    representative_entity: (terrain, terrain.into()), // technically wrong
+                    draw_function,
+                    pipeline,
+                    batch_range: index as u32..index as u32 + 1,
+                    extra_index: PhaseItemExtraIndex::None,
+                    order: gpu_terrain_view.order,
+                })
+            }
+        }
+    }
+}
+
+/// Scatters the instances of all terrains with a [`TerrainScatter`].
+/// This plugin has to be added after the [`TerrainPlugin`](crate::plugin::TerrainPlugin).
+pub struct TerrainScatterPlugin;
+
+impl Plugin for TerrainScatterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TerrainScatter>()
+            .init_resource::<TerrainViewComponents<ScatterTiles>>()
+            .add_systems(
+                PostUpdate,
+                ScatterTiles::update.after(TileTree::compute_requests),
+            );
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainViewComponents<GpuScatter>>()
+            .init_resource::<SpecializedComputePipelines<TerrainScatterPipelines>>()
+            .init_resource::<SpecializedMeshPipelines<TerrainScatterPipelines>>()
+            .add_render_command::<TerrainItem, DrawTerrainScatter>()
+            .add_systems(ExtractSchedule, GpuScatter::extract)
+            .add_systems(
+                Render,
+                (
+                    GpuScatter::prepare.in_set(RenderSet::PrepareResources),
+                    queue_terrain_scatter.in_set(RenderSet::QueueMeshes),
+                ),
+            );
+
+        let mut render_graph = app
+            .sub_app_mut(RenderApp)
+            .world_mut()
+            .resource_mut::<RenderGraph>();
+        render_graph.add_node(ScatterPass, ScatterPass);
+        render_graph.add_node_edge(TilingPrepass, ScatterPass);
+        render_graph.add_node_edge(ScatterPass, CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainScatterPipelines>();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{math::TerrainShape, terrain::TerrainConfig, terrain_view::TerrainViewConfig};
+    use bevy::{ecs::system::RunSystemOnce, math::DVec3};
+    use itertools::iproduct;
+
+    fn tile_tree(shape: TerrainShape, view_position: DVec3) -> TileTree {
+        let config = TerrainConfig {
+            shape,
+            lod_count: 8,
+            ..default()
+        };
+        let mut buffers = default();
+        let mut tile_tree =
+            TileTree::new_headless(&config, &TerrainViewConfig::default(), &mut buffers);
+
+        tile_tree.view_local_position = view_position;
+        tile_tree.update();
+        tile_tree
+    }
+
+    #[test]
+    fn select_tiles_within_distance() {
+        for (shape, view_position, distance) in [
+            (
+                TerrainShape::Plane {
+                    side_length: 1000.0,
+                },
+                DVec3::new(120.0, 10.0, -80.0),
+                100.0,
+            ),
+            (
+                TerrainShape::Sphere { radius: 1000.0 },
+                DVec3::new(700.0, 400.0, 300.0),
+                400.0,
+            ),
+        ] {
+            let tile_tree = tile_tree(shape, view_position);
+            let lod = 5;
+            let tile_count = 1 << lod;
+
+            let mut tiles = tile_tree.tiles_within_distance(lod, distance);
+
+            // compare against all tiles of the lod
+            let mut expected = iproduct!(0..shape.face_count(), 0..tile_count, 0..tile_count)
+                .map(|(face, x, y)| TileCoordinate::new(face, lod, IVec2::new(x, y)))
+                .filter(|&tile| {
+                    let view_coordinate = tile_tree.view_coordinates[tile.face as usize];
+                    tile_tree.compute_tile_distance(tile, view_coordinate) < distance
+                })
+                .collect::<Vec<_>>();
+
+            let key = |tile: &TileCoordinate| (tile.face, tile.xy.x, tile.xy.y);
+            tiles.sort_by_key(key);
+            expected.sort_by_key(key);
+
+            assert!(!tiles.is_empty());
+            assert_eq!(tiles, expected);
+        }
+    }
+
+    #[test]
+    fn update_scatter_tiles() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let tile_tree = tile_tree(shape, DVec3::new(700.0, 400.0, 300.0));
+
+        let mut world = World::new();
+        let terrain = world
+            .spawn(TerrainScatter {
+                layers: vec![
+                    ScatterLayer {
+                        lod: 5,
+                        distance: 400.0,
+                        ..default()
+                    },
+                    // the lod exceeds the lod count of the terrain
+                    ScatterLayer {
+                        lod: 20,
+                        distance: 50.0,
+                        ..default()
+                    },
+                ],
+                density_map: false,
+            })
+            .id();
+        let view = world.spawn_empty().id();
+
+        let expected = [(5, 400.0), (7, 50.0)]
+            .map(|(lod, distance)| tile_tree.tiles_within_distance(lod, distance));
+
+        let mut tile_trees = TerrainViewComponents::<TileTree>::default();
+        tile_trees.insert((terrain, view), tile_tree);
+        world.insert_resource(tile_trees);
+        world.init_resource::<TerrainViewComponents<ScatterTiles>>();
+
+        world.run_system_once(ScatterTiles::update).unwrap();
+
+        let scatter_tiles = world.resource::<TerrainViewComponents<ScatterTiles>>();
+        assert_eq!(scatter_tiles[&(terrain, view)].layers, expected);
+
+        // the tiles are removed together with the scatter
+        world.entity_mut(terrain).remove::<TerrainScatter>();
+        world.run_system_once(ScatterTiles::update).unwrap();
+
+        let scatter_tiles = world.resource::<TerrainViewComponents<ScatterTiles>>();
+        assert!(scatter_tiles.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+#define_import_path bevy_terrain::atmosphere
+
+#import bevy_terrain::types::Atmosphere
+
+const PI: f32 = 3.141592653589793;
+
+struct Medium {
+    rayleigh_scattering: vec3<f32>,
+    mie_scattering: vec3<f32>,
+    extinction: vec3<f32>,
+}
+
+struct Scattering {
+    // the light scattered towards the view
+    inscattering: vec3<f32>,
+    // the fraction of the light transmitted along the ray
+    transmittance: vec3<f32>,
+}
+
+// Returns the distances to the near and far intersection of the ray with the sphere
+// around the center of the planet or -1.0 if the ray misses it.
+fn ray_sphere_intersect(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b            = dot(origin, direction);
+    let c            = dot(origin, origin) - radius * radius;
+    let discriminant = b * b - c;
+
+    if (discriminant < 0.0) { return vec2<f32>(-1.0); }
+
+    let root = sqrt(discriminant);
+    return vec2<f32>(-b - root, -b + root);
+}
+
+fn sample_medium(atmosphere: Atmosphere, altitude: f32) -> Medium {
+    let rayleigh_density = exp(-altitude / atmosphere.rayleigh_scale_height);
+    let mie_density      = exp(-altitude / atmosphere.mie_scale_height);
+    let ozone_density    = max(0.0, 1.0 - abs(altitude - atmosphere.ozone_center) / atmosphere.ozone_width);
+
+    var medium: Medium;
+    medium.rayleigh_scattering = atmosphere.rayleigh_scattering * rayleigh_density;
+    medium.mie_scattering      = vec3<f32>(atmosphere.mie_scattering * mie_density);
+    medium.extinction          = medium.rayleigh_scattering +
+                                 vec3<f32>((atmosphere.mie_scattering + atmosphere.mie_absorption) * mie_density) +
+                                 atmosphere.ozone_absorption * ozone_density;
+    return medium;
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// The Cornette-Shanks phase function.
+fn mie_phase(cos_theta: f32, g: f32) -> f32 {
+    let g2          = g * g;
+    let denominator = (2.0 + g2) * pow(max(1.0 + g2 - 2.0 * g * cos_theta, 0.0001), 1.5);
+
+    return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cos_theta * cos_theta) / denominator;
+}
+
+// Maps the distance from the planet center `r` and the cosine of the zenith angle `mu`
+// to the uv of the transmittance LUT (Bruneton 2017).
+fn transmittance_lut_uv(atmosphere: Atmosphere, r: f32, mu: f32) -> vec2<f32> {
+    let top2    = atmosphere.top_radius * atmosphere.top_radius;
+    let bottom2 = atmosphere.bottom_radius * atmosphere.bottom_radius;
+    let h       = sqrt(top2 - bottom2);
+    let rho     = sqrt(max(r * r - bottom2, 0.0));
+
+    let discriminant = r * r * (mu * mu - 1.0) + top2;
+    let d            = max(0.0, -r * mu + sqrt(max(discriminant, 0.0)));
+    let d_min        = atmosphere.top_radius - r;
+    let d_max        = rho + h;
+
+    return vec2<f32>((d - d_min) / max(d_max - d_min, 0.0001), rho / h);
+}
+
+// The inverse of `transmittance_lut_uv`.
+fn transmittance_lut_r_mu(atmosphere: Atmosphere, uv: vec2<f32>) -> vec2<f32> {
+    let top2    = atmosphere.top_radius * atmosphere.top_radius;
+    let bottom2 = atmosphere.bottom_radius * atmosphere.bottom_radius;
+    let h       = sqrt(top2 - bottom2);
+    let rho     = h * uv.y;
+    let r       = sqrt(rho * rho + bottom2);
+
+    let d_min = atmosphere.top_radius - r;
+    let d_max = rho + h;
+    let d     = d_min + uv.x * (d_max - d_min);
+
+    var mu = 1.0;
+    if (d > 0.0) { mu = clamp((h * h - rho * rho - d * d) / (2.0 * r * d), -1.0, 1.0); }
+
+    return vec2<f32>(r, mu);
+}
+
+fn sample_transmittance(transmittance_lut: texture_2d<f32>, lut_sampler: sampler, atmosphere: Atmosphere, r: f32, mu: f32) -> vec3<f32> {
+    let uv = transmittance_lut_uv(atmosphere, r, mu);
+    return textureSampleLevel(transmittance_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+fn multiscattering_lut_uv(atmosphere: Atmosphere, r: f32, mu_s: f32) -> vec2<f32> {
+    let altitude = (r - atmosphere.bottom_radius) / (atmosphere.top_radius - atmosphere.bottom_radius);
+    return saturate(vec2<f32>(mu_s * 0.5 + 0.5, altitude));
+}
+
+fn sample_multiscattering(multiscattering_lut: texture_2d<f32>, lut_sampler: sampler, atmosphere: Atmosphere, r: f32, mu_s: f32) -> vec3<f32> {
+    let uv = multiscattering_lut_uv(atmosphere, r, mu_s);
+    return textureSampleLevel(multiscattering_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Integrates the single scattering of the sun light (and the precomputed multiple scattering)
+// along the ray between `start` and `end`. The `origin` is relative to the planet center.
+fn integrate_scattering(
+    atmosphere: Atmosphere,
+    transmittance_lut: texture_2d<f32>,
+    multiscattering_lut: texture_2d<f32>,
+    lut_sampler: sampler,
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+    start: f32,
+    end: f32,
+    sample_count: u32,
+) -> Scattering {
+    let cos_theta = dot(direction, atmosphere.sun_direction);
+    let phase_r   = rayleigh_phase(cos_theta);
+    let phase_m   = mie_phase(cos_theta, atmosphere.mie_asymmetry);
+    let step      = (end - start) / f32(sample_count);
+
+    var inscattering  = vec3<f32>(0.0);
+    var transmittance = vec3<f32>(1.0);
+
+    for (var i = 0u; i < sample_count; i += 1u) {
+        let position = origin + (start + (f32(i) + 0.5) * step) * direction;
+        let r        = length(position);
+        let mu_s     = dot(position / r, atmosphere.sun_direction);
+        let medium   = sample_medium(atmosphere, r - atmosphere.bottom_radius);
+
+        var sun_transmittance = sample_transmittance(transmittance_lut, lut_sampler, atmosphere, r, mu_s);
+        if (ray_sphere_intersect(position, atmosphere.sun_direction, atmosphere.bottom_radius).x > 0.0) {
+            sun_transmittance = vec3<f32>(0.0); // in the shadow of the planet
+        }
+
+        let multiscattering = sample_multiscattering(multiscattering_lut, lut_sampler, atmosphere, r, mu_s);
+        let scattering      = medium.rayleigh_scattering * (phase_r * sun_transmittance + multiscattering) +
+                              medium.mie_scattering * (phase_m * sun_transmittance + multiscattering);
+
+        // integrate analytically over the step, assuming a constant medium
+        let step_transmittance = exp(-medium.extinction * step);
+        let step_scattering    = (scattering - scattering * step_transmittance) / max(medium.extinction, vec3<f32>(1e-12));
+
+        inscattering  += transmittance * step_scattering;
+        transmittance *= step_transmittance;
+    }
+
+    return Scattering(inscattering * atmosphere.sun_illuminance, transmittance);
+}
+
+// Computes the scattering between the view and a point, whose positions are relative to the planet center.
+// The ray is clipped to the atmosphere.
+fn compute_aerial_perspective(
+    atmosphere: Atmosphere,
+    transmittance_lut: texture_2d<f32>,
+    multiscattering_lut: texture_2d<f32>,
+    lut_sampler: sampler,
+    view_position: vec3<f32>,
+    offset: vec3<f32>,
+    sample_count: u32,
+) -> Scattering {
+    let distance     = length(offset);
+    let direction    = offset / distance;
+    let intersection = ray_sphere_intersect(view_position, direction, atmosphere.top_radius);
+
+    let start = max(intersection.x, 0.0);
+    let end   = min(intersection.y, distance);
+
+    if (end <= start) { return Scattering(vec3<f32>(0.0), vec3<f32>(1.0)); }
+
+    return integrate_scattering(atmosphere, transmittance_lut, multiscattering_lut, lut_sampler, view_position, direction, start, end, sample_count);
+}
@@ -0,0 +1,112 @@
+#import bevy_terrain::types::Atmosphere
+#import bevy_terrain::atmosphere::{PI, ray_sphere_intersect, sample_medium, sample_transmittance, transmittance_lut_r_mu}
+
+const TRANSMITTANCE_SAMPLE_COUNT: u32 = 40u;
+const MULTISCATTERING_DIRECTION_COUNT: u32 = 8u; // per axis of the sphere parametrization
+const MULTISCATTERING_SAMPLE_COUNT: u32 = 20u;
+
+@group(0) @binding(0) var<uniform> atmosphere: Atmosphere;
+@group(0) @binding(1) var transmittance_output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var transmittance_lut: texture_2d<f32>;
+@group(0) @binding(3) var lut_sampler: sampler;
+@group(0) @binding(4) var multiscattering_output: texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn transmittance(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let size = textureDimensions(transmittance_output);
+    if (any(invocation_id.xy >= size)) { return; }
+
+    let uv   = (vec2<f32>(invocation_id.xy) + 0.5) / vec2<f32>(size);
+    let r_mu = transmittance_lut_r_mu(atmosphere, uv);
+
+    let origin    = vec3<f32>(0.0, r_mu.x, 0.0);
+    let direction = vec3<f32>(sqrt(1.0 - r_mu.y * r_mu.y), r_mu.y, 0.0);
+    let distance  = max(ray_sphere_intersect(origin, direction, atmosphere.top_radius).y, 0.0);
+    let step      = distance / f32(TRANSMITTANCE_SAMPLE_COUNT);
+
+    var optical_depth = vec3<f32>(0.0);
+
+    for (var i = 0u; i < TRANSMITTANCE_SAMPLE_COUNT; i += 1u) {
+        let position   = origin + (f32(i) + 0.5) * step * direction;
+        optical_depth += sample_medium(atmosphere, length(position) - atmosphere.bottom_radius).extinction * step;
+    }
+
+    textureStore(transmittance_output, invocation_id.xy, vec4<f32>(exp(-optical_depth), 1.0));
+}
+
+fn uniform_sphere_direction(index: vec2<u32>) -> vec3<f32> {
+    let uv        = (vec2<f32>(index) + 0.5) / f32(MULTISCATTERING_DIRECTION_COUNT);
+    let cos_theta = 1.0 - 2.0 * uv.y;
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let phi       = 2.0 * PI * uv.x;
+
+    return vec3<f32>(sin_theta * cos(phi), cos_theta, sin_theta * sin(phi));
+}
+
+// Computes the isotropic multiple scattering contribution (Hillaire 2020).
+// The second order scattering and the transfer factor are integrated over the sphere of directions,
+// which yields the infinite sum of higher orders as a geometric series.
+@compute @workgroup_size(8, 8, 1)
+fn multiscattering(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let size = textureDimensions(multiscattering_output);
+    if (any(invocation_id.xy >= size)) { return; }
+
+    let uv   = (vec2<f32>(invocation_id.xy) + 0.5) / vec2<f32>(size);
+    let mu_s = uv.x * 2.0 - 1.0;
+    let r    = mix(atmosphere.bottom_radius, atmosphere.top_radius, uv.y);
+
+    let origin        = vec3<f32>(0.0, r, 0.0);
+    let sun_direction = vec3<f32>(sqrt(1.0 - mu_s * mu_s), mu_s, 0.0);
+    let phase         = 1.0 / (4.0 * PI);
+    let direction_count = f32(MULTISCATTERING_DIRECTION_COUNT * MULTISCATTERING_DIRECTION_COUNT);
+
+    var second_order = vec3<f32>(0.0);
+    var transfer     = vec3<f32>(0.0);
+
+    for (var x = 0u; x < MULTISCATTERING_DIRECTION_COUNT; x += 1u) {
+        for (var y = 0u; y < MULTISCATTERING_DIRECTION_COUNT; y += 1u) {
+            let direction = uniform_sphere_direction(vec2<u32>(x, y));
+            let ground    = ray_sphere_intersect(origin, direction, atmosphere.bottom_radius);
+            let top       = ray_sphere_intersect(origin, direction, atmosphere.top_radius);
+            let hits_ground = ground.x > 0.0;
+            let distance  = select(max(top.y, 0.0), ground.x, hits_ground);
+            let step      = distance / f32(MULTISCATTERING_SAMPLE_COUNT);
+
+            var luminance     = vec3<f32>(0.0);
+            var luminance_f   = vec3<f32>(0.0);
+            var transmittance = vec3<f32>(1.0);
+
+            for (var i = 0u; i < MULTISCATTERING_SAMPLE_COUNT; i += 1u) {
+                let position = origin + (f32(i) + 0.5) * step * direction;
+                let sample_r = length(position);
+                let medium   = sample_medium(atmosphere, sample_r - atmosphere.bottom_radius);
+
+                let sun_mu            = dot(position / sample_r, sun_direction);
+                let sun_transmittance = sample_transmittance(transmittance_lut, lut_sampler, atmosphere, sample_r, sun_mu);
+                let scattering        = medium.rayleigh_scattering + medium.mie_scattering;
+
+                let step_transmittance = exp(-medium.extinction * step);
+                let integral           = (1.0 - step_transmittance) / max(medium.extinction, vec3<f32>(1e-12));
+
+                luminance     += transmittance * scattering * phase * sun_transmittance * integral;
+                luminance_f   += transmittance * scattering * integral;
+                transmittance *= step_transmittance;
+            }
+
+            if (hits_ground) {
+                // the sun light reflected by the ground with a lambertian brdf
+                let position          = origin + distance * direction;
+                let normal            = normalize(position);
+                let sun_transmittance = sample_transmittance(transmittance_lut, lut_sampler, atmosphere, length(position), dot(normal, sun_direction));
+                luminance += transmittance * sun_transmittance * saturate(dot(normal, sun_direction)) * atmosphere.ground_albedo / PI;
+            }
+
+            second_order += luminance / direction_count;
+            transfer     += luminance_f * phase / direction_count;
+        }
+    }
+
+    let multiscattering = second_order / (1.0 - transfer);
+
+    textureStore(multiscattering_output, invocation_id.xy, vec4<f32>(multiscattering, 1.0));
+}
@@ -0,0 +1,37 @@
+#import bevy_render::view::View
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+#import bevy_terrain::types::Atmosphere
+#import bevy_terrain::atmosphere::{ray_sphere_intersect, integrate_scattering}
+
+const SKY_SAMPLE_COUNT: u32 = 32u;
+
+@group(0) @binding(0) var<uniform> view: View;
+
+@group(1) @binding(0) var<uniform> atmosphere: Atmosphere;
+@group(1) @binding(1) var transmittance_lut: texture_2d<f32>;
+@group(1) @binding(2) var multiscattering_lut: texture_2d<f32>;
+@group(1) @binding(3) var lut_sampler: sampler;
+
+// Renders the sky and the limb of the planet behind the terrain.
+// The scattering is blended with premultiplied alpha, so that the background (e.g. the stars)
+// is attenuated by the transmittance of the atmosphere.
+@fragment
+fn fragment(input: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let ndc_position   = vec4<f32>(input.uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 1.0, 1.0);
+    let world_position = view.world_from_clip * ndc_position;
+    let direction      = normalize(world_position.xyz / world_position.w - view.world_position);
+    let view_position  = view.world_position - atmosphere.center;
+
+    let intersection = ray_sphere_intersect(view_position, direction, atmosphere.top_radius);
+    if (intersection.y <= 0.0) { discard; }
+
+    // end at the ground, where no terrain has been rendered (e.g. it is not loaded yet)
+    let ground = ray_sphere_intersect(view_position, direction, atmosphere.bottom_radius);
+    let start  = max(intersection.x, 0.0);
+    let end    = select(intersection.y, ground.x, ground.x > 0.0);
+
+    let scattering    = integrate_scattering(atmosphere, transmittance_lut, multiscattering_lut, lut_sampler, view_position, direction, start, end, SKY_SAMPLE_COUNT);
+    let transmittance = dot(scattering.transmittance, vec3<f32>(1.0 / 3.0));
+
+    return vec4<f32>(scattering.inscattering * view.exposure, 1.0 - transmittance);
+}
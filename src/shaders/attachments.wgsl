@@ -3,6 +3,13 @@
 #import bevy_terrain::types::{AtlasTile, TangentSpace, AttachmentConfig, SampleUV, WorldCoordinate}
 #import bevy_terrain::bindings::{terrain, terrain_view, terrain_sampler, attachments, height_attachment}
 
+#ifdef NORMAL_MAP
+#import bevy_terrain::types::Coordinate
+#import bevy_terrain::bindings::normal_attachment
+#import bevy_terrain::functions::compute_unit_position
+#import bevy_render::maths::{affine3_to_square, mat2x4_f32_to_mat3x3_unpack}
+#endif
+
 #ifdef FRAGMENT
 fn compute_sample_uv(tile: AtlasTile, attachment: AttachmentConfig) -> SampleUV {
     let uv    = tile.coordinate.uv * attachment.scale + attachment.offset;
@@ -48,7 +55,66 @@ fn sample_height_mask(tile: AtlasTile) -> bool {
 }
 
 #ifdef FRAGMENT
+#ifdef NORMAL_MAP
+// The tangent frame of the normal attachment, whose tangent follows the u axis of the cube face.
+// Mirrors `compute_normal_frame` in `surface.rs`.
+fn compute_normal_frame(coordinate: Coordinate) -> mat3x3<f32> {
+#ifdef SPHERICAL
+    var unit_tangent: vec3<f32>;
+    switch (coordinate.face) {
+        case 0u, 5u: { unit_tangent = vec3(0.0,  0.0, 1.0); }
+        case 1u, 2u: { unit_tangent = vec3(1.0,  0.0, 0.0); }
+        case 3u, 4u: { unit_tangent = vec3(0.0, -1.0, 0.0); }
+        case default: {}
+    }
+    let unit_normal = compute_unit_position(coordinate);
+#else
+    let unit_tangent = vec3<f32>(1.0, 0.0, 0.0);
+    let unit_normal  = vec3<f32>(0.0, 1.0, 0.0);
+#endif
+
+    let position_world_from_unit = affine3_to_square(terrain.world_from_unit);
+    let normal_world_from_unit   = mat2x4_f32_to_mat3x3_unpack(terrain.unit_from_world_transpose_a, terrain.unit_from_world_transpose_b);
+
+    let normal        = normalize(normal_world_from_unit * unit_normal);
+    let world_tangent = (position_world_from_unit * vec4<f32>(unit_tangent, 0.0)).xyz;
+    let tangent       = normalize(world_tangent - dot(world_tangent, normal) * normal);
+    let bitangent     = cross(normal, tangent);
+
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+// Mirrors `decode_octahedral` in `surface.rs`.
+fn decode_octahedral(encoded: vec2<f32>) -> vec3<f32> {
+    let z      = 1.0 - abs(encoded.x) - abs(encoded.y);
+    let folded = (1.0 - abs(encoded.yx)) * select(vec2<f32>(-1.0), vec2<f32>(1.0), encoded >= vec2<f32>(0.0));
+
+    return normalize(vec3<f32>(select(encoded, folded, z < 0.0), z));
+}
+
+// Computes the surface gradient from the precomputed normal attachment, which is baked with a height scale of one.
+// Mirrors `compute_surface_gradient` in `surface.rs`.
+fn sample_normal_gradient(tile: AtlasTile) -> vec3<f32> {
+    let uv = compute_sample_uv(tile, attachments.normal);
+
+#ifdef SAMPLE_GRAD
+    let encoded = textureSampleGrad(normal_attachment, terrain_sampler, uv.uv, tile.index, uv.dx, uv.dy).xy;
+#else
+    let encoded = textureSampleLevel(normal_attachment, terrain_sampler, uv.uv, tile.index, tile.blend_ratio).xy;
+#endif
+
+    let frame  = compute_normal_frame(tile.coordinate);
+    let normal = frame * decode_octahedral(2.0 * encoded - 1.0);
+
+    // the surface gradient is proportional to the height scale
+    return terrain.height_scale * (frame[2] - normal / dot(normal, frame[2]));
+}
+#endif
+
 fn sample_surface_gradient(tile: AtlasTile, tangent_space: TangentSpace) -> vec3<f32> {
+#ifdef NORMAL_MAP
+    return sample_normal_gradient(tile);
+#else
     let attachment = attachments.height;
     let uv         = compute_sample_uv(tile, attachment);
     let scale      = max(length(uv.dx), length(uv.dy));
@@ -104,6 +170,7 @@ fn sample_surface_gradient(tile: AtlasTile, tangent_space: TangentSpace) -> vec3
 //    let height_dy = dpdy(height);
 
     return terrain.height_scale * tangent_space.scale * (height_dx * tangent_space.tangent_x + height_dy * tangent_space.tangent_y);
+#endif
 }
 #endif
 
@@ -1,6 +1,6 @@
 #define_import_path bevy_terrain::bindings
 
-#import bevy_terrain::types::{TerrainView, Terrain, TileTreeEntry, TileCoordinate, GeometryTile, AttachmentConfig, TerrainModelApproximation, IndirectBuffer, PrepassState}
+#import bevy_terrain::types::{TerrainView, Terrain, TileTreeEntry, TileCoordinate, GeometryTile, AttachmentConfig, TerrainModelApproximation, IndirectBuffer, PrepassState, Atmosphere, OverlayData, VirtualTexture}
 #import bevy_render::view::View;
 
 struct Attachments {
@@ -46,3 +46,25 @@ struct Attachments {
 @group(1) @binding(8)  var {5}_attachment: texture_2d_array<f32>;
 @group(1) @binding(9)  var {6}_attachment: texture_2d_array<f32>;
 @group(1) @binding(10) var {7}_attachment: texture_2d_array<f32>;
+
+// terrain feature bindings, the index of each bind group depends on the features of the terrain
+#ifdef ATMOSPHERE
+@group(#{ATMOSPHERE_BIND_GROUP}) @binding(0) var<uniform> atmosphere: Atmosphere;
+@group(#{ATMOSPHERE_BIND_GROUP}) @binding(1) var transmittance_lut: texture_2d<f32>;
+@group(#{ATMOSPHERE_BIND_GROUP}) @binding(2) var multiscattering_lut: texture_2d<f32>;
+@group(#{ATMOSPHERE_BIND_GROUP}) @binding(3) var atmosphere_sampler: sampler;
+#endif
+
+#ifdef OVERLAY
+@group(#{OVERLAY_BIND_GROUP}) @binding(0) var<storage> overlay: OverlayData;
+#endif
+
+// the virtual texture is only sampled and its feedback only written by the fragment shader of the main pass
+#ifdef VIRTUAL_TEXTURE
+#ifdef FRAGMENT
+@group(#{VIRTUAL_TEXTURE_BIND_GROUP}) @binding(0) var<uniform> virtual_texture: VirtualTexture;
+@group(#{VIRTUAL_TEXTURE_BIND_GROUP}) @binding(1) var<storage> page_table: array<u32>;
+@group(#{VIRTUAL_TEXTURE_BIND_GROUP}) @binding(2) var page_cache: texture_2d_array<f32>;
+@group(#{VIRTUAL_TEXTURE_BIND_GROUP}) @binding(3) var<storage, read_write> page_feedback: array<atomic<u32>>;
+#endif
+#endif
@@ -5,5 +5,5 @@ var depth_texture: texture_depth_multisampled_2d;
 
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @builtin(frag_depth) f32 {
-    return textureLoad(depth_texture, vec2<u32>(in.uv * vec2<f32>(textureDimensions(depth_texture))), 0);
+    return textureLoad(depth_texture, vec2<u32>(in.position.xy), 0);
 }
\ No newline at end of file
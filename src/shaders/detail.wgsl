@@ -0,0 +1,189 @@
+#define_import_path bevy_terrain::detail
+
+#import bevy_terrain::types::{Coordinate, TangentSpace}
+#import bevy_terrain::bindings::terrain
+#import bevy_terrain::functions::coordinate_change_lod
+
+const PI: f32 = 3.141592653589793;
+
+struct DetailUV {
+    uv: vec2<f32>,
+    dx: vec2<f32>,
+    dy: vec2<f32>,
+    // the world space size of one repetition of the detail texture
+    size: f32,
+}
+
+// The approximate edge length of a terrain face in world units.
+fn face_size() -> f32 {
+#ifdef SPHERICAL
+    return 0.5 * PI * terrain.scale.x;
+#else
+    return terrain.scale.x;
+#endif
+}
+
+#ifdef FRAGMENT
+// Computes the uv of a detail texture, which repeats roughly every `scale` world units.
+// The repetitions are snapped to a power of two subdivision of the terrain faces.
+// Thus the uv is derived from the integer tile coordinate, which does not jitter, even on planet sized terrains.
+fn compute_detail_uv(coordinate: Coordinate, scale: f32) -> DetailUV {
+    let detail_lod = u32(max(round(log2(face_size() / scale)), 0.0));
+
+    var detail_coordinate = coordinate;
+    coordinate_change_lod(&detail_coordinate, detail_lod);
+
+    return DetailUV(detail_coordinate.uv, detail_coordinate.uv_dx, detail_coordinate.uv_dy, face_size() / exp2(f32(detail_lod)));
+}
+
+// Converts the tangent space normal of a detail texture into a surface gradient.
+fn detail_surface_gradient(detail_uv: DetailUV, normal: vec3<f32>, tangent_space: TangentSpace) -> vec3<f32> {
+    let height_duv = -normal.xy / max(normal.z, 0.001) * detail_uv.size;
+    let height_dx  = dot(height_duv, detail_uv.dx);
+    let height_dy  = dot(height_duv, detail_uv.dy);
+
+    return tangent_space.scale * (height_dx * tangent_space.tangent_x + height_dy * tangent_space.tangent_y);
+}
+
+// The number of stochastic grid cells along one repetition of a detail texture.
+const STOCHASTIC_GRID_SIZE: f32 = 4.0;
+// The sharpness of the transitions between the stochastic samples and the triplanar projections.
+const DETAIL_BLEND_SHARPNESS: f32 = 4.0;
+
+fn hash_offset(vertex: vec2<f32>) -> vec2<f32> {
+    // wrap the vertices, so that the grid is seamless across repetitions
+    let p = vertex - STOCHASTIC_GRID_SIZE * floor(vertex / STOCHASTIC_GRID_SIZE);
+    return fract(sin(vec2<f32>(dot(p, vec2<f32>(127.1, 311.7)), dot(p, vec2<f32>(269.5, 183.3)))) * 43758.5453);
+}
+
+// Samples a detail texture stochastically, to hide its repetition.
+// The uv space is divided into a triangle grid and each vertex of the triangle containing the uv
+// samples the texture with a random offset. These samples are then blended based on the barycentric coordinates.
+fn sample_stochastic(texture: texture_2d_array<f32>, texture_sampler: sampler, layer: u32, detail_uv: DetailUV) -> vec4<f32> {
+    let grid_uv = detail_uv.uv * STOCHASTIC_GRID_SIZE;
+    let base    = floor(grid_uv);
+    let local   = grid_uv - base;
+
+    var vertices: array<vec2<f32>, 3>;
+    var weights: vec3<f32>;
+
+    if (local.x + local.y < 1.0) {
+        vertices = array<vec2<f32>, 3>(base, base + vec2<f32>(1.0, 0.0), base + vec2<f32>(0.0, 1.0));
+        weights  = vec3<f32>(1.0 - local.x - local.y, local.x, local.y);
+    } else {
+        vertices = array<vec2<f32>, 3>(base + vec2<f32>(1.0), base + vec2<f32>(0.0, 1.0), base + vec2<f32>(1.0, 0.0));
+        weights  = vec3<f32>(local.x + local.y - 1.0, 1.0 - local.x, 1.0 - local.y);
+    }
+
+    weights = pow(weights, vec3<f32>(DETAIL_BLEND_SHARPNESS));
+    weights /= dot(weights, vec3<f32>(1.0));
+
+    var value = vec4<f32>(0.0);
+
+    for (var i = 0u; i < 3u; i += 1u) {
+        let uv = detail_uv.uv + hash_offset(vertices[i]);
+        value += weights[i] * textureSampleGrad(texture, texture_sampler, uv, layer, detail_uv.dx, detail_uv.dy);
+    }
+
+    return value;
+}
+
+// Samples a detail texture, either directly or stochastically if STOCHASTIC_SAMPLING is enabled.
+fn sample_detail(texture: texture_2d_array<f32>, texture_sampler: sampler, layer: u32, detail_uv: DetailUV) -> vec4<f32> {
+#ifdef STOCHASTIC_SAMPLING
+    return sample_stochastic(texture, texture_sampler, layer, detail_uv);
+#else
+    return textureSampleGrad(texture, texture_sampler, detail_uv.uv, layer, detail_uv.dx, detail_uv.dy);
+#endif
+}
+
+struct TriplanarUV {
+    // the projections onto the planes facing the u, v and normal direction
+    x: DetailUV,
+    y: DetailUV,
+    z: DetailUV,
+    weights: vec3<f32>,
+    // the world space directions of the u, v and normal axes of the local tangent frame
+    tangent: vec3<f32>,
+    bitangent: vec3<f32>,
+    normal: vec3<f32>,
+}
+
+// Computes the triplanar projection of a detail texture in the local tangent frame, to avoid stretching on steep slopes.
+// The frame is spanned by the surface gradients of the detail uv (derived with the `tangent_space`)
+// and the normal of the terrain shape (`world_normal`), while the height of the terrain serves as the third coordinate.
+// The projections are weighted by the surface `normal`.
+fn compute_triplanar_uv(detail_uv: DetailUV, height: f32, world_normal: vec3<f32>, normal: vec3<f32>, tangent_space: TangentSpace) -> TriplanarUV {
+    let tangent   = normalize(tangent_space.scale * (detail_uv.dx.x * tangent_space.tangent_x + detail_uv.dy.x * tangent_space.tangent_y));
+    let bitangent = normalize(tangent_space.scale * (detail_uv.dx.y * tangent_space.tangent_x + detail_uv.dy.y * tangent_space.tangent_y));
+
+    let h    = height / detail_uv.size;
+    let h_dx = dpdx(height) / detail_uv.size;
+    let h_dy = dpdy(height) / detail_uv.size;
+
+    var weights = pow(abs(vec3<f32>(dot(normal, tangent), dot(normal, bitangent), dot(normal, world_normal))), vec3<f32>(DETAIL_BLEND_SHARPNESS));
+    weights /= max(dot(weights, vec3<f32>(1.0)), 0.0001);
+
+    var triplanar: TriplanarUV;
+    triplanar.x         = DetailUV(vec2<f32>(detail_uv.uv.y, h), vec2<f32>(detail_uv.dx.y, h_dx), vec2<f32>(detail_uv.dy.y, h_dy), detail_uv.size);
+    triplanar.y         = DetailUV(vec2<f32>(detail_uv.uv.x, h), vec2<f32>(detail_uv.dx.x, h_dx), vec2<f32>(detail_uv.dy.x, h_dy), detail_uv.size);
+    triplanar.z         = detail_uv;
+    triplanar.weights   = weights;
+    triplanar.tangent   = tangent;
+    triplanar.bitangent = bitangent;
+    triplanar.normal    = world_normal;
+    return triplanar;
+}
+
+// Samples a detail texture with the triplanar projection.
+fn sample_triplanar(texture: texture_2d_array<f32>, texture_sampler: sampler, layer: u32, triplanar: TriplanarUV) -> vec4<f32> {
+    var value = vec4<f32>(0.0);
+
+    if (triplanar.weights.x > 0.0) { value += triplanar.weights.x * sample_detail(texture, texture_sampler, layer, triplanar.x); }
+    if (triplanar.weights.y > 0.0) { value += triplanar.weights.y * sample_detail(texture, texture_sampler, layer, triplanar.y); }
+    if (triplanar.weights.z > 0.0) { value += triplanar.weights.z * sample_detail(texture, texture_sampler, layer, triplanar.z); }
+
+    return value;
+}
+
+// Converts the tangent space normal of a side projection, whose axes are `axis_u` and `axis_v`, into a surface gradient.
+fn projected_surface_gradient(normal: vec3<f32>, axis_u: vec3<f32>, axis_v: vec3<f32>, surface_normal: vec3<f32>) -> vec3<f32> {
+    let gradient = -(normal.x * axis_u + normal.y * axis_v) / max(normal.z, 0.001);
+    return gradient - dot(gradient, surface_normal) * surface_normal;
+}
+
+// Samples a detail normal texture with the triplanar projection and converts it into a surface gradient.
+fn triplanar_surface_gradient(texture: texture_2d_array<f32>, texture_sampler: sampler, layer: u32, triplanar: TriplanarUV, normal: vec3<f32>, tangent_space: TangentSpace) -> vec3<f32> {
+    var gradient = vec3<f32>(0.0);
+
+    if (triplanar.weights.x > 0.0) {
+        let normal_x = sample_detail(texture, texture_sampler, layer, triplanar.x).xyz * 2.0 - 1.0;
+        gradient += triplanar.weights.x * projected_surface_gradient(normal_x, triplanar.bitangent, triplanar.normal, normal);
+    }
+    if (triplanar.weights.y > 0.0) {
+        let normal_y = sample_detail(texture, texture_sampler, layer, triplanar.y).xyz * 2.0 - 1.0;
+        gradient += triplanar.weights.y * projected_surface_gradient(normal_y, triplanar.tangent, triplanar.normal, normal);
+    }
+    if (triplanar.weights.z > 0.0) {
+        let normal_z = sample_detail(texture, texture_sampler, layer, triplanar.z).xyz * 2.0 - 1.0;
+        gradient += triplanar.weights.z * detail_surface_gradient(triplanar.z, normal_z, tangent_space);
+    }
+
+    return gradient;
+}
+#endif
+
+// Blends the weights of the layers based on their heights, so that higher layers poke through lower ones.
+// A smaller blend depth results in sharper transitions.
+fn blend_by_height(weights: vec4<f32>, heights: vec4<f32>, blend_depth: f32) -> vec4<f32> {
+    let layer_heights = heights + weights;
+    let threshold     = max(max(layer_heights.x, layer_heights.y), max(layer_heights.z, layer_heights.w)) - blend_depth;
+    let blended       = max(layer_heights - threshold, vec4<f32>(0.0)) * step(vec4<f32>(0.0001), weights);
+
+    return blended / max(dot(blended, vec4<f32>(1.0)), 0.0001);
+}
+
+// Fades out details between the start and end distance.
+fn distance_fade(view_distance: f32, start: f32, end: f32) -> f32 {
+    return 1.0 - saturate((view_distance - start) / max(end - start, 0.0001));
+}
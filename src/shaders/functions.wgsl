@@ -5,10 +5,12 @@
 #import bevy_render::maths::{affine3_to_square, mat2x4_f32_to_mat3x3_unpack}
 
 const SIGMA = 0.87 * 0.87;
+const TAU: f32 = 6.283185307179586;
 
 fn high_precision(view_distance: f32) -> bool {
 #ifdef HIGH_PRECISION
-    return view_distance < terrain_view.precision_distance;
+    // the surface approximation is not available for flattened views
+    return view_distance < terrain_view.precision_distance && terrain_view.flat_projection == 0u;
 #else
     return false;
 #endif
@@ -16,6 +18,11 @@ fn high_precision(view_distance: f32) -> bool {
 
 #ifdef VERTEX
 fn compute_coordinate(vertex_index: u32) -> Coordinate {
+    return compute_morphed_coordinate(vertex_index, 0.0);
+}
+
+// shifts the morph ratio of the vertex by the morph offset, e.g. to reconstruct its position in the previous frame
+fn compute_morphed_coordinate(vertex_index: u32, morph_offset: f32) -> Coordinate {
     // use first and last indices of the rows twice, to form degenerate triangles
     let tile_index   = vertex_index / terrain_view.vertices_per_tile;
     let column_index = vertex_index % terrain_view.vertices_per_tile / terrain_view.vertices_per_row;
@@ -29,7 +36,7 @@ fn compute_coordinate(vertex_index: u32) -> Coordinate {
     let morph_ratio = mix(mix(tile.morph_ratios.x, tile.morph_ratios.y, tile_uv.x),
                           mix(tile.morph_ratios.z, tile.morph_ratios.w, tile_uv.x), tile_uv.y);
 
-    return Coordinate(tile.face, tile.lod, tile.xy, mix(tile_uv, even_uv, morph_ratio));
+    return Coordinate(tile.face, tile.lod, tile.xy, mix(tile_uv, even_uv, saturate(morph_ratio + morph_offset)));
 }
 #endif
 
@@ -72,15 +79,19 @@ fn compute_world_coordinate(coordinate: Coordinate, height: f32, view_distance:
 }
 #endif
 
-fn compute_world_coordinate_imprecise(coordinate: Coordinate, height: f32) -> WorldCoordinate {
+fn compute_unit_position(coordinate: Coordinate) -> vec3<f32> {
     let uv = (vec2<f32>(coordinate.xy) + coordinate.uv) / exp2(f32(coordinate.lod));
 
+    return compute_face_unit_position(coordinate.face, uv);
+}
+
+fn compute_face_unit_position(face: u32, uv: vec2<f32>) -> vec3<f32> {
 #ifdef SPHERICAL
     let xy = (2.0 * uv - 1.0) / sqrt(1.0 - 4.0 * SIGMA * (uv - 1.0) * uv);
 
     // this is faster than the CPU SIDE_MATRICES approach
     var unit_position: vec3<f32>;
-    switch (coordinate.face) {
+    switch (face) {
         case 0u: { unit_position = vec3( -1.0, -xy.y,  xy.x); }
         case 1u: { unit_position = vec3( xy.x, -xy.y,   1.0); }
         case 2u: { unit_position = vec3( xy.x,   1.0,  xy.y); }
@@ -90,11 +101,68 @@ fn compute_world_coordinate_imprecise(coordinate: Coordinate, height: f32) -> Wo
         case default: {}
     }
 
-    unit_position   = normalize(unit_position);
-    let unit_normal = unit_position;
+    return normalize(unit_position);
+#else
+    return vec3<f32>(uv.x - 0.5, 0.0, uv.y - 0.5);
+#endif
+}
+
+// Computes the distance between the position and the view, which determines the level of detail.
+// Orthographic views use the same distance everywhere.
+fn compute_view_distance(world_position: vec3<f32>) -> f32 {
+    if (terrain_view.orthographic_distance > 0.0) { return terrain_view.orthographic_distance; }
+
+    return distance(world_position, terrain_view.world_position);
+}
+
+#ifdef SPHERICAL
+// Computes the geodetic latitude in radians, which is the angle between the surface normal of the shape and the equatorial plane.
+// The surface normal is proportional to the unit position divided by the scale of the shape.
+fn compute_geodetic_latitude(unit_position: vec3<f32>) -> f32 {
+    let normal = unit_position * (terrain.scale.x / terrain.scale);
+    return atan2(normal.y, length(normal.xz));
+}
+
+// Projects the unit position onto the equirectangular map, where x is the longitude and negative z the latitude.
+// Mirrors `TerrainProjection::project`.
+// The longitude is kept continuous within each tile, so that tiles crossing the antimeridian
+// overhang the edge of the map instead of being torn apart (except the tiles of lod zero containing the poles).
+fn compute_equirectangular_position(coordinate: Coordinate, unit_position: vec3<f32>) -> vec3<f32> {
+    let center_uv       = (vec2<f32>(coordinate.xy) + 0.5) / exp2(f32(coordinate.lod));
+    let center_position = compute_face_unit_position(coordinate.face, center_uv);
+    let center_lon      = atan2(center_position.z, -center_position.x);
+
+    let lat        = compute_geodetic_latitude(unit_position);
+    let lon_offset = atan2(unit_position.z, -unit_position.x) - center_lon;
+    var lon        = center_lon + lon_offset - TAU * round(lon_offset / TAU);
+    lon            = select(lon, center_lon, length(unit_position.xz) < 0.000001); // the longitude of the poles is undefined
+
+    return vec3<f32>(lon, 0.0, -lat);
+}
+#endif
+
+// Computes the geodetic latitude in radians, which is zero on planar terrains.
+// Mirrors `Coordinate::lat_lon`.
+fn compute_latitude(coordinate: Coordinate) -> f32 {
+#ifdef SPHERICAL
+    return compute_geodetic_latitude(compute_unit_position(coordinate));
+#else
+    return 0.0;
+#endif
+}
+
+fn compute_world_coordinate_imprecise(coordinate: Coordinate, height: f32) -> WorldCoordinate {
+    var unit_position = compute_unit_position(coordinate);
+
+#ifdef SPHERICAL
+    var unit_normal = unit_position;
+
+    if (terrain_view.flat_projection != 0u) {
+        unit_position = compute_equirectangular_position(coordinate, unit_position);
+        unit_normal   = vec3<f32>(0.0, 1.0, 0.0);
+    }
 #else
-    let unit_position = vec3<f32>(uv.x - 0.5, 0.0, uv.y - 0.5);
-    let unit_normal   = vec3<f32>(0.0, 1.0, 0.0);
+    let unit_normal = vec3<f32>(0.0, 1.0, 0.0);
 #endif
 
     let position_world_from_unit = affine3_to_square(terrain.world_from_unit);
@@ -103,7 +171,7 @@ fn compute_world_coordinate_imprecise(coordinate: Coordinate, height: f32) -> Wo
     let normal_world_from_unit = mat2x4_f32_to_mat3x3_unpack(terrain.unit_from_world_transpose_a, terrain.unit_from_world_transpose_b);
     let world_normal           = normalize(normal_world_from_unit * unit_normal);
 
-    let view_distance = distance(world_position + height * world_normal, terrain_view.world_position);
+    let view_distance = compute_view_distance(world_position + height * world_normal);
 
     return WorldCoordinate(world_position, world_normal, view_distance);
 }
@@ -122,7 +190,7 @@ fn compute_world_coordinate_precise(coordinate: Coordinate, height: f32) -> Worl
                          approximation.p_uu * u * u + approximation.p_uv * u * v + approximation.p_vv * v * v;
     let world_normal = normalize(cross(approximation.p_v, approximation.p_u)); // normal at viewer coordinate good enough?
 
-    let view_distance = distance(world_position + height * world_normal, terrain_view.world_position);
+    let view_distance = compute_view_distance(world_position + height * world_normal);
 
     return WorldCoordinate(world_position, world_normal, view_distance);
 }
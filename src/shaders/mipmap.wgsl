@@ -1,30 +1,65 @@
 @group(0) @binding(0) var<uniform> index: u32;
+#ifdef UINT_TEXTURE
+@group(0) @binding(1) var parent_texture: texture_2d_array<u32>;
+#else
 @group(0) @binding(1) var parent_texture: texture_2d_array<f32>;
+#endif
 
-#ifdef RGB8U
+#ifdef R8U
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<r8unorm, write>;
+#else ifdef RG8U
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<rg8unorm, write>;
+#else ifdef RGB8U
 @group(0) @binding(2) var child_texture: texture_storage_2d_array<rgba8unorm, write>;
 #else ifdef RGBA8U 
 @group(0) @binding(2) var child_texture: texture_storage_2d_array<rgba8unorm, write>;
 #else ifdef R16U
 @group(0) @binding(2) var child_texture: texture_storage_2d_array<r16uint, write>;
 #else ifdef R16I
-@group(0) @binding(2) var child_texture: texture_storage_2d_array<r16sint, write>;
-#else ifdef Rg16U
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<r16uint, write>;
+#else ifdef RG16U
 @group(0) @binding(2) var child_texture: texture_storage_2d_array<rg16uint, write>;
+#else ifdef R16F
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<r16float, write>;
+#else ifdef RG16F
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<rg16float, write>;
+#else ifdef RGBA16F
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<rgba16float, write>;
 #else ifdef R32F
 @group(0) @binding(2) var child_texture: texture_storage_2d_array<r32float, write>;
+#else ifdef RG32F
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<rg32float, write>;
+#else ifdef RGBA32F
+@group(0) @binding(2) var child_texture: texture_storage_2d_array<rgba32float, write>;
 #endif
 
+fn load_parent(coord: vec2<u32>) -> vec4<f32> {
+    let data = textureLoad(parent_texture, coord, index, 0);
+
+#ifdef R16I
+    // the signed values are stored as two's complement in the lower 16 bits
+    return vec4<f32>((vec4<i32>(data) << vec4<u32>(16u)) >> vec4<u32>(16u));
+#else
+    return vec4<f32>(data);
+#endif
+}
+
 @compute @workgroup_size(8, 8, 1)
 fn main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let coord = invocation_id.xy;
 
-    let data00 = textureLoad(parent_texture, 2 * coord + vec2<u32>(0, 0), index, 0);
-    let data01 = textureLoad(parent_texture, 2 * coord + vec2<u32>(0, 1), index, 0);
-    let data10 = textureLoad(parent_texture, 2 * coord + vec2<u32>(1, 0), index, 0);
-    let data11 = textureLoad(parent_texture, 2 * coord + vec2<u32>(1, 1), index, 0);
+    let data00 = load_parent(2 * coord + vec2<u32>(0, 0));
+    let data01 = load_parent(2 * coord + vec2<u32>(0, 1));
+    let data10 = load_parent(2 * coord + vec2<u32>(1, 0));
+    let data11 = load_parent(2 * coord + vec2<u32>(1, 1));
 
     let data = 0.25 * (data00 + data01 + data10 + data11);
 
+#ifdef R16I
+    textureStore(child_texture, coord, index, vec4<u32>(vec4<i32>(round(data))) & vec4<u32>(0xffffu));
+#else ifdef UINT_TEXTURE
+    textureStore(child_texture, coord, index, vec4<u32>(round(data)));
+#else
     textureStore(child_texture, coord, index, data);
+#endif
 }
@@ -4,6 +4,11 @@ use itertools::Itertools;
 
 pub const DEFAULT_VERTEX_SHADER: &str = "embedded://bevy_terrain/shaders/render/vertex.wgsl";
 pub const DEFAULT_FRAGMENT_SHADER: &str = "embedded://bevy_terrain/shaders/render/fragment.wgsl";
+pub const PBR_SHADER: &str = "embedded://bevy_terrain/shaders/render/pbr.wgsl";
+pub const SPLAT_SHADER: &str = "embedded://bevy_terrain/shaders/render/splat.wgsl";
+pub const TOPOGRAPHIC_SHADER: &str = "embedded://bevy_terrain/shaders/render/topographic.wgsl";
+pub(crate) const WATER_SHADER: &str = "embedded://bevy_terrain/shaders/render/water.wgsl";
+pub(crate) const PREPASS_SHADER: &str = "embedded://bevy_terrain/shaders/render/prepass.wgsl";
 pub const PREPARE_PREPASS_SHADER: &str =
     "embedded://bevy_terrain/shaders/tiling_prepass/prepare_prepass.wgsl";
 pub const REFINE_TILES_SHADER: &str =
@@ -15,6 +20,14 @@ pub const REFINE_TILES_SHADER: &str =
 pub(crate) const PICKING_SHADER: &str = "embedded://bevy_terrain/shaders/picking.wgsl";
 pub(crate) const DEPTH_COPY_SHADER: &str = "embedded://bevy_terrain/shaders/depth_copy.wgsl";
 pub(crate) const MIP_SHADER: &str = "embedded://bevy_terrain/shaders/mipmap.wgsl";
+pub(crate) const ATMOSPHERE_LUT_SHADER: &str =
+    "embedded://bevy_terrain/shaders/atmosphere/lut.wgsl";
+pub(crate) const SCATTER_GENERATE_SHADER: &str =
+    "embedded://bevy_terrain/shaders/scatter/generate.wgsl";
+pub(crate) const SCATTER_RENDER_SHADER: &str =
+    "embedded://bevy_terrain/shaders/scatter/render.wgsl";
+pub(crate) const ATMOSPHERE_SKY_SHADER: &str =
+    "embedded://bevy_terrain/shaders/atmosphere/sky.wgsl";
 
 #[derive(Default, Resource)]
 pub(crate) struct InternalShaders(Vec<Handle<Shader>>);
@@ -60,13 +73,29 @@ pub(crate) fn load_terrain_shaders(app: &mut App, attachments: &[AttachmentLabel
     embedded_asset!(app, "attachments.wgsl");
     embedded_asset!(app, "functions.wgsl");
     embedded_asset!(app, "debug.wgsl");
+    embedded_asset!(app, "detail.wgsl");
+    embedded_asset!(app, "overlay.wgsl");
+    embedded_asset!(app, "virtual_texture.wgsl");
+    embedded_asset!(app, "topographic.wgsl");
     embedded_asset!(app, "render/vertex.wgsl");
     embedded_asset!(app, "render/fragment.wgsl");
+    embedded_asset!(app, "render/prepass.wgsl");
+    embedded_asset!(app, "render/pbr_fragment.wgsl");
+    embedded_asset!(app, "render/pbr.wgsl");
+    embedded_asset!(app, "render/splat.wgsl");
+    embedded_asset!(app, "render/topographic.wgsl");
+    embedded_asset!(app, "render/water.wgsl");
     embedded_asset!(app, "tiling_prepass/prepare_prepass.wgsl");
     embedded_asset!(app, "tiling_prepass/refine_tiles.wgsl");
     embedded_asset!(app, "picking.wgsl");
     embedded_asset!(app, "depth_copy.wgsl");
     embedded_asset!(app, "mipmap.wgsl");
+    embedded_asset!(app, "atmosphere/atmosphere.wgsl");
+    embedded_asset!(app, "atmosphere/lut.wgsl");
+    embedded_asset!(app, "atmosphere/sky.wgsl");
+    embedded_asset!(app, "scatter/scatter.wgsl");
+    embedded_asset!(app, "scatter/generate.wgsl");
+    embedded_asset!(app, "scatter/render.wgsl");
 
     load_bindings_shader(app, attachments);
 
@@ -77,8 +106,15 @@ pub(crate) fn load_terrain_shaders(app: &mut App, attachments: &[AttachmentLabel
             "embedded://bevy_terrain/shaders/attachments.wgsl",
             "embedded://bevy_terrain/shaders/functions.wgsl",
             "embedded://bevy_terrain/shaders/debug.wgsl",
+            "embedded://bevy_terrain/shaders/detail.wgsl",
+            "embedded://bevy_terrain/shaders/overlay.wgsl",
+            "embedded://bevy_terrain/shaders/virtual_texture.wgsl",
+            "embedded://bevy_terrain/shaders/topographic.wgsl",
+            "embedded://bevy_terrain/shaders/atmosphere/atmosphere.wgsl",
+            "embedded://bevy_terrain/shaders/scatter/scatter.wgsl",
             "embedded://bevy_terrain/shaders/render/vertex.wgsl",
             "embedded://bevy_terrain/shaders/render/fragment.wgsl",
+            "embedded://bevy_terrain/shaders/render/pbr_fragment.wgsl",
         ],
     );
 }
@@ -0,0 +1,153 @@
+#define_import_path bevy_terrain::overlay
+
+#import bevy_terrain::types::Coordinate
+#import bevy_terrain::bindings::overlay
+
+const POLYGON_CENTER_BIT: u32 = 0x80000000u;
+const SHAPE_POLYGON_BIT: u32  = 1u;
+const NO_SHAPE: u32           = 0xFFFFFFFFu;
+
+// The ends of the segment are relative to the cell of the fragment and measured in cells.
+struct OverlaySegment {
+    start: vec2<f32>,
+    end: vec2<f32>,
+    meters_per_cell: f32,
+    shape: u32,
+}
+
+struct OverlayShape {
+    fill: vec4<f32>,
+    stroke: vec4<f32>,
+    width: f32,
+    flags: u32,
+}
+
+fn load_f32(index: u32) -> f32 {
+    return bitcast<f32>(overlay.data[index]);
+}
+
+fn load_vec2(index: u32) -> vec2<f32> {
+    return vec2<f32>(load_f32(index), load_f32(index + 1u));
+}
+
+fn load_vec4(index: u32) -> vec4<f32> {
+    return vec4<f32>(load_vec2(index), load_vec2(index + 2u));
+}
+
+fn overlay_segment(index: u32, cell: vec2<u32>) -> OverlaySegment {
+    let offset = overlay.segment_offset + 8u * index;
+    let origin = vec2<i32>(bitcast<i32>(overlay.data[offset]), bitcast<i32>(overlay.data[offset + 1u]));
+    let shift  = vec2<f32>(origin - vec2<i32>(cell));
+
+    return OverlaySegment(shift + load_vec2(offset + 2u), shift + load_vec2(offset + 4u), load_f32(offset + 6u), overlay.data[offset + 7u]);
+}
+
+fn overlay_shape(index: u32) -> OverlayShape {
+    let offset = overlay.shape_offset + 10u * index;
+
+    return OverlayShape(load_vec4(offset), load_vec4(offset + 4u), load_f32(offset + 8u), overlay.data[offset + 9u]);
+}
+
+fn segment_distance(position: vec2<f32>, segment: OverlaySegment) -> f32 {
+    let direction = segment.end - segment.start;
+    let t         = saturate(dot(position - segment.start, direction) / dot(direction, direction));
+
+    return distance(position, segment.start + t * direction);
+}
+
+fn cross2(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+// Whether the line from a to b crosses the segment. Both ends are half open, so that the crossing of
+// two consecutive segments at their shared point is only counted once.
+fn segment_crosses(a: vec2<f32>, b: vec2<f32>, segment: OverlaySegment) -> bool {
+    let d     = b - a;
+    let e     = segment.end - segment.start;
+    let denom = cross2(d, e);
+
+    if (denom == 0.0) { return false; }
+
+    let t = cross2(segment.start - a, e) / denom;
+    let s = cross2(segment.start - a, d) / denom;
+
+    return t >= 0.0 && t < 1.0 && s >= 0.0 && s < 1.0;
+}
+
+fn blend_layer(color: vec4<f32>, layer: vec4<f32>, coverage: f32) -> vec4<f32> {
+    return vec4<f32>(mix(color.rgb, layer.rgb, layer.a * coverage), color.a);
+}
+
+fn blend_shape(color: vec4<f32>, shape_index: u32, inside: bool, coverage: f32) -> vec4<f32> {
+    if (shape_index == NO_SHAPE) { return color; }
+
+    let shape = overlay_shape(shape_index);
+
+    var result = color;
+    if (inside && (shape.flags & SHAPE_POLYGON_BIT) != 0u) { result = blend_layer(result, shape.fill, 1.0); }
+    return blend_layer(result, shape.stroke, coverage);
+}
+
+// Blends the shapes of the overlay, which cover the coordinate, over the color.
+// The entries of each cell are grouped by shape, so that the shapes are blended in order.
+fn sample_overlay(coordinate: Coordinate, color: vec4<f32>) -> vec4<f32> {
+    let grid_size = overlay.grid_size;
+    let lod_size  = 1u << coordinate.lod;
+    let footprint = max(length(coordinate.uv_dx), length(coordinate.uv_dy)) * f32(grid_size) / f32(lod_size);
+
+    // The position of the fragment is split into its cell and the offset inside of that cell.
+    // Both are derived from the integer tile coordinate, so that the offset stays precise
+    // even for small tiles on large faces.
+    let scaled   = coordinate.xy * grid_size;
+    var position = (vec2<f32>(scaled % lod_size) + coordinate.uv * f32(grid_size)) / f32(lod_size);
+    let carry    = floor(position);
+    let raw_cell = scaled / lod_size + vec2<u32>(carry);
+    let cell     = min(raw_cell, vec2<u32>(grid_size - 1u));
+    position     = position - carry + vec2<f32>(raw_cell - cell);
+
+    let cell_center = vec2<f32>(0.5);
+    let cell_index  = 2u * ((coordinate.face * grid_size + cell.y) * grid_size + cell.x);
+    let first       = overlay.entry_offset + overlay.data[cell_index];
+    let count       = overlay.data[cell_index + 1u];
+
+    var result      = color;
+    var shape_index = NO_SHAPE;
+    var inside      = false;
+    var coverage    = 0.0;
+
+    for (var i = 0u; i < count; i += 1u) {
+        let entry  = overlay.data[first + i];
+        let center = (entry & POLYGON_CENTER_BIT) != 0u;
+
+        var segment: OverlaySegment;
+        if (center) { segment.shape = entry & ~POLYGON_CENTER_BIT; }
+        else        { segment       = overlay_segment(entry, cell); }
+
+        if (segment.shape != shape_index) {
+            result      = blend_shape(result, shape_index, inside, coverage);
+            shape_index = segment.shape;
+            inside      = false;
+            coverage    = 0.0;
+        }
+
+        // the cell center lies inside of the polygon
+        if (center) {
+            inside = !inside;
+            continue;
+        }
+
+        // the side of the polygon changes with every edge between the cell center and the fragment
+        if (segment_crosses(cell_center, position, segment)) { inside = !inside; }
+
+        let shape = overlay_shape(segment.shape);
+        if (shape.stroke.a > 0.0) {
+            let distance   = segment_distance(position, segment) * segment.meters_per_cell;
+            let pixel_size = footprint * segment.meters_per_cell;
+            let half_width = 0.5 * max(shape.width, pixel_size);
+
+            coverage = max(coverage, 1.0 - smoothstep(half_width - 0.5 * pixel_size, half_width + 0.5 * pixel_size, distance));
+        }
+    }
+
+    return blend_shape(result, shape_index, inside, coverage);
+}
@@ -1,9 +1,16 @@
-struct PickingData {
-    cursor_coords: vec2<f32>,
+struct PickingPoint {
+    coords: vec2<f32>,
+    pixel: vec2<u32>,
     depth: f32,
     stencil: u32,
+    id: u32,
+}
+
+struct PickingData {
     world_from_clip: mat4x4<f32>,
     cell: vec3<i32>,
+    count: u32,
+    points: array<PickingPoint, #{MAX_PICKING_POINTS}>,
 }
 
 @group(0) @binding(0)
@@ -13,9 +20,13 @@ var depth_texture: texture_depth_multisampled_2d;
 @group(0) @binding(2)
 var stencil_texture: texture_multisampled_2d<u32>;
 
-@compute @workgroup_size(1, 1, 1)
+@compute @workgroup_size(#{MAX_PICKING_POINTS}, 1, 1)
 fn pick(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let coords = vec2<f32>(picking_data.cursor_coords.x, 1.0 - picking_data.cursor_coords.y) * vec2<f32>(textureDimensions(depth_texture));
-    picking_data.depth = textureLoad(depth_texture, vec2<u32>(coords), 0);
-    picking_data.stencil = textureLoad(stencil_texture, vec2<u32>(coords), 0).x;
-}
\ No newline at end of file
+    let index = invocation_id.x;
+    if (index >= picking_data.count) { return; }
+
+    let coords = picking_data.points[index].pixel;
+
+    picking_data.points[index].depth = textureLoad(depth_texture, coords, 0);
+    picking_data.points[index].stencil = textureLoad(stencil_texture, coords, 0).x;
+}
@@ -7,6 +7,7 @@
 #import bevy_terrain::debug::{show_data_lod, show_geometry_lod, show_tile_tree, show_pixels}
 #import bevy_pbr::mesh_view_bindings::view
 #import bevy_pbr::pbr_types::{PbrInput, pbr_input_new}
+#import bevy_pbr::mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT
 #import bevy_pbr::pbr_functions::{calculate_view, apply_pbr_lighting}
 
 struct FragmentInput {
@@ -51,6 +52,7 @@ fn fragment_output(info: ptr<function, FragmentInfo>, output: ptr<function, Frag
     pbr_input.material.base_color           = color;
     pbr_input.material.perceptual_roughness = 1.0;
     pbr_input.material.reflectance          = vec3<f32>(0.0);
+    pbr_input.flags                         = MESH_FLAGS_SHADOW_RECEIVER_BIT;
     pbr_input.frag_coord                    = (*info).clip_position;
     pbr_input.world_position                = world_position;
     pbr_input.world_normal                  = (*info).world_coordinate.normal;
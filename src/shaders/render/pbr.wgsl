@@ -0,0 +1,23 @@
+#import bevy_terrain::fragment::{FragmentInput, FragmentOutput, fragment_info, fragment_debug}
+#import bevy_terrain::functions::lookup_tile
+#import bevy_terrain::attachments::{sample_height_mask, sample_surface_gradient}
+#import bevy_terrain::pbr_fragment::{pbr_input_from_terrain_material, apply_terrain_overlay, apply_terrain_lighting}
+
+@fragment
+fn fragment(input: FragmentInput) -> FragmentOutput {
+    var info = fragment_info(input);
+
+    let tile             = lookup_tile(info.coordinate, info.blend);
+    let mask             = sample_height_mask(tile);
+    let surface_gradient = sample_surface_gradient(tile, info.tangent_space);
+
+    if (mask) { discard; }
+
+    var pbr_input = pbr_input_from_terrain_material(&info, surface_gradient);
+    apply_terrain_overlay(&info, &pbr_input);
+
+    var output: FragmentOutput;
+    output.color = apply_terrain_lighting(pbr_input);
+    fragment_debug(&info, &output, tile, surface_gradient);
+    return output;
+}
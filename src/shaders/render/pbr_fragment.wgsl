@@ -0,0 +1,107 @@
+#define_import_path bevy_terrain::pbr_fragment
+
+#import bevy_terrain::fragment::FragmentInfo
+#import bevy_terrain::functions::apply_height
+#import bevy_pbr::pbr_types::{PbrInput, pbr_input_new, STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT}
+#import bevy_pbr::mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT
+#import bevy_pbr::mesh_view_bindings::view
+#import bevy_pbr::pbr_functions::{calculate_view, apply_pbr_lighting, main_pass_post_lighting_processing}
+
+#ifdef OVERLAY
+#import bevy_terrain::overlay::sample_overlay
+#endif
+
+#ifdef VIRTUAL_TEXTURE
+#import bevy_terrain::virtual_texture::sample_virtual_texture
+#endif
+
+#ifdef ATMOSPHERE
+#import bevy_terrain::bindings::{atmosphere, transmittance_lut, multiscattering_lut, atmosphere_sampler}
+#import bevy_terrain::atmosphere::compute_aerial_perspective
+
+const AERIAL_PERSPECTIVE_SAMPLE_COUNT: u32 = 8u;
+#endif
+
+const TERRAIN_PBR_MATERIAL_FLAGS_FOG_ENABLED_BIT: u32 = 1u;
+
+struct TerrainPbrMaterial {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    perceptual_roughness: f32,
+    metallic: f32,
+    reflectance: f32,
+    flags: u32,
+}
+
+@group(3) @binding(0) var<uniform> material: TerrainPbrMaterial;
+
+// Creates the pbr input of the fragment from the terrain pbr material.
+// The base color is multiplied by the virtual texture of the terrain, if it has one.
+// Extensions of the material can override any of its fields, before the lighting is applied.
+fn pbr_input_from_terrain_material(info: ptr<function, FragmentInfo>, surface_gradient: vec3<f32>) -> PbrInput {
+    let world_position = vec4<f32>(apply_height((*info).world_coordinate, (*info).height), 1.0);
+
+    var pbr_input: PbrInput                 = pbr_input_new();
+    pbr_input.material.base_color           = material.base_color;
+    pbr_input.material.emissive             = material.emissive;
+    pbr_input.material.perceptual_roughness = material.perceptual_roughness;
+    pbr_input.material.metallic             = material.metallic;
+    pbr_input.material.reflectance          = vec3<f32>(material.reflectance);
+    pbr_input.flags                         = MESH_FLAGS_SHADOW_RECEIVER_BIT;
+    pbr_input.frag_coord                    = (*info).clip_position;
+    pbr_input.world_position                = world_position;
+    pbr_input.world_normal                  = (*info).world_coordinate.normal;
+    pbr_input.N                             = normalize((*info).world_coordinate.normal - surface_gradient);
+    pbr_input.is_orthographic               = view.clip_from_view[3].w == 1.0;
+    pbr_input.V                             = calculate_view(world_position, pbr_input.is_orthographic);
+
+    if ((material.flags & TERRAIN_PBR_MATERIAL_FLAGS_FOG_ENABLED_BIT) != 0u) {
+        pbr_input.material.flags |= STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT;
+    }
+
+#ifdef VIRTUAL_TEXTURE
+    pbr_input.material.base_color *= sample_virtual_texture((*info).coordinate, (*info).clip_position);
+#endif
+
+    return pbr_input;
+}
+
+// Drapes the shapes of the terrain overlay onto the base color of the material.
+// This has to be called after the material has chosen its base color and before the lighting is applied.
+fn apply_terrain_overlay(info: ptr<function, FragmentInfo>, pbr_input: ptr<function, PbrInput>) {
+#ifdef OVERLAY
+    (*pbr_input).material.base_color = sample_overlay((*info).coordinate, (*pbr_input).material.base_color);
+#endif
+}
+
+#ifdef ATMOSPHERE
+// Attenuates the color by the atmosphere between the view and the fragment and adds the light scattered
+// towards the view. The offset to the view is taken from the high-precision world position.
+fn apply_aerial_perspective(pbr_input: PbrInput, color: vec4<f32>) -> vec4<f32> {
+    let view_position = view.world_position - atmosphere.center;
+    let offset        = pbr_input.world_position.xyz - view.world_position;
+
+    let scattering = compute_aerial_perspective(atmosphere, transmittance_lut, multiscattering_lut, atmosphere_sampler,
+                                                view_position, offset, AERIAL_PERSPECTIVE_SAMPLE_COUNT);
+
+    return vec4<f32>(color.rgb * scattering.transmittance + scattering.inscattering * view.exposure, color.a);
+}
+#endif
+
+// Applies the pbr lighting of bevy (direct and clustered lights, shadows, environment maps),
+// followed by the aerial perspective of the atmosphere and the fog.
+fn apply_terrain_lighting(pbr_input: PbrInput) -> vec4<f32> {
+#ifdef LIGHTING
+    var color = apply_pbr_lighting(pbr_input);
+#else
+    var color = pbr_input.material.base_color;
+#endif
+
+#ifdef ATMOSPHERE
+    color = apply_aerial_perspective(pbr_input, color);
+#endif
+
+    color = main_pass_post_lighting_processing(pbr_input, color);
+
+    return vec4<f32>(color.rgb, 1.0);
+}
@@ -0,0 +1,139 @@
+#import bevy_terrain::types::{Coordinate, WorldCoordinate, TangentSpace}
+#import bevy_terrain::bindings::{terrain_view, geometry_tiles, view}
+#import bevy_terrain::functions::{compute_coordinate, compute_morphed_coordinate, compute_world_coordinate, compute_blend, compute_morph, compute_tangent_space, lookup_tile, apply_height}
+#import bevy_terrain::attachments::{sample_height, sample_height_mask, sample_surface_gradient}
+
+#ifdef PREPASS_MOTION_VECTORS
+// mirrors the `PreviousViewData` of bevy
+struct PreviousView {
+    view_from_world: mat4x4<f32>,
+    clip_from_world: mat4x4<f32>,
+    clip_from_view: mat4x4<f32>,
+    world_from_clip: mat4x4<f32>,
+    view_from_clip: mat4x4<f32>,
+}
+
+@group(0) @binding(1) var<uniform> previous_view: PreviousView;
+#endif
+
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tile_uv: vec2<f32>,
+    @location(1) tile_index: u32,
+    @location(2) view_distance: f32,
+    @location(3) height: f32,
+#ifdef PREPASS_MOTION_VECTORS
+    @location(4) world_position: vec4<f32>,
+    @location(5) previous_world_position: vec4<f32>,
+#endif
+}
+
+#ifdef PREPASS_FRAGMENT
+struct FragmentOutput {
+#ifdef PREPASS_NORMALS
+    @location(0) normal: vec4<f32>,
+#endif
+#ifdef PREPASS_MOTION_VECTORS
+    @location(1) motion_vector: vec2<f32>,
+#endif
+}
+#endif
+
+#ifdef VERTEX
+#ifdef PREPASS_MOTION_VECTORS
+fn previous_view_position() -> vec3<f32> {
+    let view_from_world = previous_view.view_from_world;
+    let rotation        = mat3x3<f32>(view_from_world[0].xyz, view_from_world[1].xyz, view_from_world[2].xyz);
+
+    return -(transpose(rotation) * view_from_world[3].xyz);
+}
+
+// Reconstructs the position of the vertex in the previous frame.
+// The morph ratio is shifted by the change caused by the movement of the view,
+// while the selected tiles and their data are assumed to be unchanged.
+fn compute_previous_world_position(vertex_index: u32, lod: u32, world_position: vec3<f32>, view_distance: f32) -> vec3<f32> {
+    let previous_distance = view_distance + distance(world_position, previous_view_position()) - distance(world_position, view.world_position);
+    let morph_offset      = compute_morph(lod, previous_distance) - compute_morph(lod, view_distance);
+
+    if (morph_offset == 0.0) { return world_position; }
+
+    let tile_index       = vertex_index / terrain_view.vertices_per_tile;
+    let coordinate       = compute_morphed_coordinate(vertex_index, morph_offset);
+    let world_coordinate = compute_world_coordinate(coordinate, tile_index, coordinate.uv);
+    let tile             = lookup_tile(coordinate, compute_blend(world_coordinate.view_distance));
+
+    return apply_height(world_coordinate, sample_height(tile));
+}
+#endif
+
+@vertex
+fn vertex(input: VertexInput) -> VertexOutput {
+    let tile_index       = input.vertex_index / terrain_view.vertices_per_tile;
+    let coordinate       = compute_coordinate(input.vertex_index);
+    let world_coordinate = compute_world_coordinate(coordinate, tile_index, coordinate.uv);
+    let tile             = lookup_tile(coordinate, compute_blend(world_coordinate.view_distance));
+    let height           = sample_height(tile);
+    let world_position   = apply_height(world_coordinate, height);
+
+    var output: VertexOutput;
+    output.clip_position = view.clip_from_world * vec4<f32>(world_position, 1.0);
+    output.tile_uv       = coordinate.uv;
+    output.tile_index    = tile_index;
+    output.view_distance = world_coordinate.view_distance;
+    output.height        = height;
+
+#ifdef PREPASS_MOTION_VECTORS
+    let previous_world_position    = compute_previous_world_position(input.vertex_index, coordinate.lod, world_position, world_coordinate.view_distance);
+    output.world_position          = vec4<f32>(world_position, 1.0);
+    output.previous_world_position = vec4<f32>(previous_world_position, 1.0);
+#endif
+
+    return output;
+}
+#endif
+
+#ifdef FRAGMENT
+#ifdef PREPASS_FRAGMENT
+@fragment
+fn fragment(input: VertexOutput) -> FragmentOutput {
+#else
+@fragment
+fn fragment(input: VertexOutput) {
+#endif
+    let coordinate       = compute_coordinate(input.tile_index, input.tile_uv);
+    let world_coordinate = compute_world_coordinate(coordinate, input.height, input.view_distance);
+    let tile             = lookup_tile(coordinate, compute_blend(world_coordinate.view_distance));
+
+    if (sample_height_mask(tile)) { discard; }
+
+#ifdef PREPASS_FRAGMENT
+    var output: FragmentOutput;
+
+#ifdef PREPASS_NORMALS
+    let tangent_space    = compute_tangent_space(world_coordinate);
+    let surface_gradient = sample_surface_gradient(tile, tangent_space);
+    let normal           = normalize(world_coordinate.normal - surface_gradient);
+
+    // encoded like the normals of the bevy prepass
+    output.normal = vec4<f32>(normal * 0.5 + vec3<f32>(0.5), 1.0);
+#endif
+
+#ifdef PREPASS_MOTION_VECTORS
+    let clip_position_t          = view.unjittered_clip_from_world * input.world_position;
+    let clip_position            = clip_position_t.xy / clip_position_t.w;
+    let previous_clip_position_t = previous_view.clip_from_world * input.previous_world_position;
+    let previous_clip_position   = previous_clip_position_t.xy / previous_clip_position_t.w;
+
+    // these motion vectors are used as offsets to uv positions and are stored
+    // in the range -1,1 to allow offsetting from the one corner to the diagonally-opposite corner in uv coordinates
+    output.motion_vector = (clip_position - previous_clip_position) * vec2<f32>(0.5, -0.5);
+#endif
+
+    return output;
+#endif
+}
+#endif
@@ -0,0 +1,177 @@
+#import bevy_terrain::types::AtlasTile
+#import bevy_terrain::bindings::{attachments, splat_attachment, terrain_sampler}
+#import bevy_terrain::fragment::{FragmentInput, FragmentOutput, fragment_info, fragment_debug}
+#import bevy_terrain::functions::{lookup_tile, compute_latitude}
+#import bevy_terrain::attachments::{compute_sample_uv, sample_height_mask, sample_surface_gradient, compute_slope}
+#import bevy_terrain::detail::{compute_detail_uv, detail_surface_gradient, blend_by_height, distance_fade, sample_detail, compute_triplanar_uv, sample_triplanar, triplanar_surface_gradient}
+#import bevy_terrain::pbr_fragment::{pbr_input_from_terrain_material, apply_terrain_overlay, apply_terrain_lighting}
+
+const SPLAT_FLAGS_ALBEDO_TEXTURES_BIT: u32 = 1u;
+const SPLAT_FLAGS_NORMAL_TEXTURES_BIT: u32 = 2u;
+
+struct SplatLayer {
+    color: vec4<f32>,
+    scale: f32,
+    perceptual_roughness: f32,
+    metallic: f32,
+}
+
+struct MaterialRule {
+    slope: vec2<f32>,
+    height: vec2<f32>,
+    layer: u32,
+    latitude_falloff: f32,
+    slope_blend: f32,
+    height_blend: f32,
+}
+
+struct MaterialRules {
+    rules: array<MaterialRule, 8>,
+    rule_count: u32,
+    base_layer: u32,
+}
+
+struct SplatMaterial {
+    layers: array<SplatLayer, 4>,
+    blend_depth: f32,
+    fade_start: f32,
+    fade_end: f32,
+    flags: u32,
+    rules: MaterialRules,
+}
+
+@group(3) @binding(100) var<uniform> splat_material: SplatMaterial;
+@group(3) @binding(101) var albedo_textures: texture_2d_array<f32>;
+@group(3) @binding(102) var detail_sampler: sampler;
+@group(3) @binding(103) var normal_textures: texture_2d_array<f32>;
+
+fn layer_weights(layer: u32) -> vec4<f32> {
+    return select(vec4<f32>(0.0), vec4<f32>(1.0), vec4<u32>(0u, 1u, 2u, 3u) == vec4<u32>(layer));
+}
+
+fn range_weight(value: f32, range: vec2<f32>, blend: f32) -> f32 {
+    return saturate((value - range.x) / blend + 1.0) * saturate((range.y - value) / blend + 1.0);
+}
+
+// Mirrors `MaterialRule::coverage`.
+fn rule_coverage(rule: MaterialRule, slope: f32, height: f32, latitude: f32) -> f32 {
+    let adjusted_height = height + rule.latitude_falloff * abs(latitude);
+
+    return range_weight(slope, rule.slope, rule.slope_blend) * range_weight(adjusted_height, rule.height, rule.height_blend);
+}
+
+// Mirrors `TerrainMaterialRules::evaluate`, with the slope and latitude in degrees.
+fn evaluate_rules(slope: f32, height: f32, latitude: f32) -> vec4<f32> {
+    let rules   = splat_material.rules;
+    var weights = layer_weights(rules.base_layer);
+
+    for (var i = 0u; i < rules.rule_count; i += 1u) {
+        let rule = rules.rules[i];
+        weights  = mix(weights, layer_weights(rule.layer), rule_coverage(rule, slope, height, latitude));
+    }
+
+    return weights;
+}
+
+fn sample_splat(tile: AtlasTile) -> vec4<f32> {
+    let uv = compute_sample_uv(tile, attachments.splat);
+
+#ifdef SAMPLE_GRAD
+    let weights = textureSampleGrad(splat_attachment, terrain_sampler, uv.uv, tile.index, uv.dx, uv.dy);
+#else
+    let weights = textureSampleLevel(splat_attachment, terrain_sampler, uv.uv, tile.index, tile.blend_ratio);
+#endif
+
+    return weights;
+}
+
+@fragment
+fn fragment(input: FragmentInput) -> FragmentOutput {
+    var info = fragment_info(input);
+
+    let tile             = lookup_tile(info.coordinate, info.blend);
+    let mask             = sample_height_mask(tile);
+    let surface_gradient = sample_surface_gradient(tile, info.tangent_space);
+
+    if (mask) { discard; }
+
+    var pbr_input = pbr_input_from_terrain_material(&info, surface_gradient);
+
+    let fade           = distance_fade(info.world_coordinate.view_distance, splat_material.fade_start, splat_material.fade_end);
+    let albedo_enabled = (splat_material.flags & SPLAT_FLAGS_ALBEDO_TEXTURES_BIT) != 0u && fade > 0.0;
+    let normal_enabled = (splat_material.flags & SPLAT_FLAGS_NORMAL_TEXTURES_BIT) != 0u && fade > 0.0;
+
+    var weights = sample_splat(tile);
+    let weight_sum = dot(weights, vec4<f32>(1.0));
+
+    if (weight_sum < 0.0001) {
+        // there is no splat data, thus the layers are assigned by the rules
+        let slope    = degrees(compute_slope(info.world_coordinate.normal, surface_gradient));
+        let latitude = degrees(compute_latitude(info.coordinate));
+        weights      = evaluate_rules(slope, info.height, latitude);
+    } else {
+        weights /= weight_sum;
+    }
+
+    var albedos: array<vec4<f32>, 4>;
+    var gradients: array<vec3<f32>, 4>;
+    var heights = vec4<f32>(0.0);
+
+    for (var i = 0u; i < 4u; i += 1u) {
+        let layer     = splat_material.layers[i];
+        let detail_uv = compute_detail_uv(info.coordinate, layer.scale);
+
+#ifdef TRIPLANAR_MAPPING
+        let triplanar = compute_triplanar_uv(detail_uv, info.height, info.world_coordinate.normal, pbr_input.N, info.tangent_space);
+#endif
+
+        albedos[i]   = vec4<f32>(1.0);
+        gradients[i] = vec3<f32>(0.0);
+
+        if (albedo_enabled) {
+#ifdef TRIPLANAR_MAPPING
+            albedos[i] = sample_triplanar(albedo_textures, detail_sampler, i, triplanar);
+#else
+            albedos[i] = sample_detail(albedo_textures, detail_sampler, i, detail_uv);
+#endif
+            heights[i] = albedos[i].a;
+        }
+        if (normal_enabled) {
+#ifdef TRIPLANAR_MAPPING
+            gradients[i] = triplanar_surface_gradient(normal_textures, detail_sampler, i, triplanar, pbr_input.N, info.tangent_space);
+#else
+            let normal   = sample_detail(normal_textures, detail_sampler, i, detail_uv).xyz * 2.0 - 1.0;
+            gradients[i] = detail_surface_gradient(detail_uv, normal, info.tangent_space);
+#endif
+        }
+    }
+
+    // the height based blending fades out together with the detail textures
+    weights = mix(weights, blend_by_height(weights, heights, splat_material.blend_depth), fade);
+
+    var color           = vec4<f32>(0.0);
+    var roughness       = 0.0;
+    var metallic        = 0.0;
+    var detail_gradient = vec3<f32>(0.0);
+
+    for (var i = 0u; i < 4u; i += 1u) {
+        let layer = splat_material.layers[i];
+
+        // far away, only the color of the layer remains
+        color           += weights[i] * layer.color * mix(vec4<f32>(1.0), vec4<f32>(albedos[i].rgb, 1.0), fade);
+        roughness       += weights[i] * layer.perceptual_roughness;
+        metallic        += weights[i] * layer.metallic;
+        detail_gradient += weights[i] * fade * gradients[i];
+    }
+
+    pbr_input.material.base_color           = vec4<f32>(color.rgb, 1.0);
+    pbr_input.material.perceptual_roughness = roughness;
+    pbr_input.material.metallic             = metallic;
+    pbr_input.N                             = normalize(info.world_coordinate.normal - surface_gradient - detail_gradient);
+    apply_terrain_overlay(&info, &pbr_input);
+
+    var output: FragmentOutput;
+    output.color = apply_terrain_lighting(pbr_input);
+    fragment_debug(&info, &output, tile, surface_gradient);
+    return output;
+}
@@ -0,0 +1,73 @@
+#import bevy_terrain::bindings::terrain
+#import bevy_terrain::fragment::{FragmentInput, FragmentOutput, fragment_info, fragment_debug}
+#import bevy_terrain::functions::lookup_tile
+#import bevy_terrain::attachments::{sample_height, sample_height_mask, sample_surface_gradient}
+#import bevy_terrain::topographic::{contour_lines, hypsometric_tint, hillshade}
+#import bevy_terrain::pbr_fragment::{pbr_input_from_terrain_material, apply_terrain_overlay, apply_terrain_lighting}
+
+const TOPOGRAPHIC_FLAGS_GRADIENT_BIT: u32   = 1u;
+const TOPOGRAPHIC_FLAGS_TINT_RANGE_BIT: u32 = 2u;
+const TOPOGRAPHIC_FLAGS_CONTOURS_BIT: u32   = 4u;
+const TOPOGRAPHIC_FLAGS_HILLSHADE_BIT: u32  = 8u;
+
+struct TopographicMaterial {
+    contour_color: vec4<f32>,
+    major_contour_color: vec4<f32>,
+    tint_range: vec2<f32>,
+    contour_interval: f32,
+    major_contour_every: u32,
+    contour_width: f32,
+    major_contour_width: f32,
+    hillshade_azimuth: f32,
+    hillshade_altitude: f32,
+    hillshade_strength: f32,
+    flags: u32,
+}
+
+@group(3) @binding(100) var<uniform> topographic_material: TopographicMaterial;
+@group(3) @binding(101) var gradient: texture_2d<f32>;
+@group(3) @binding(102) var gradient_sampler: sampler;
+
+@fragment
+fn fragment(input: FragmentInput) -> FragmentOutput {
+    var info = fragment_info(input);
+
+    let tile             = lookup_tile(info.coordinate, info.blend);
+    let mask             = sample_height_mask(tile);
+    let surface_gradient = sample_surface_gradient(tile, info.tangent_space);
+    let height           = sample_height(tile);
+
+    if (mask) { discard; }
+
+    var pbr_input = pbr_input_from_terrain_material(&info, surface_gradient);
+    var color     = pbr_input.material.base_color;
+    let flags     = topographic_material.flags;
+
+    if ((flags & TOPOGRAPHIC_FLAGS_GRADIENT_BIT) != 0u) {
+        var range = vec2<f32>(terrain.min_height, terrain.max_height);
+        if ((flags & TOPOGRAPHIC_FLAGS_TINT_RANGE_BIT) != 0u) { range = topographic_material.tint_range; }
+
+        color = hypsometric_tint(gradient, gradient_sampler, height, range);
+    }
+
+    if ((flags & TOPOGRAPHIC_FLAGS_HILLSHADE_BIT) != 0u) {
+        let shade = hillshade(pbr_input.N, info.world_coordinate.normal, topographic_material.hillshade_azimuth, topographic_material.hillshade_altitude);
+        color     = vec4<f32>(color.rgb * mix(1.0, shade, topographic_material.hillshade_strength), color.a);
+    }
+
+    if ((flags & TOPOGRAPHIC_FLAGS_CONTOURS_BIT) != 0u) {
+        let lines = contour_lines(height, topographic_material.contour_interval, topographic_material.major_contour_every,
+                                  topographic_material.contour_width, topographic_material.major_contour_width);
+
+        color = mix(color, topographic_material.contour_color, topographic_material.contour_color.a * lines.x);
+        color = mix(color, topographic_material.major_contour_color, topographic_material.major_contour_color.a * lines.y);
+    }
+
+    pbr_input.material.base_color = vec4<f32>(color.rgb, 1.0);
+    apply_terrain_overlay(&info, &pbr_input);
+
+    var output: FragmentOutput;
+    output.color = apply_terrain_lighting(pbr_input);
+    fragment_debug(&info, &output, tile, surface_gradient);
+    return output;
+}
@@ -42,6 +42,12 @@ fn vertex_output(info: ptr<function, VertexInfo>, height: f32) -> VertexOutput {
     output.tile_index    = (*info).tile_index;
     output.view_distance = (*info).world_coordinate.view_distance;
     output.height        = height;
+
+#ifdef DEPTH_CLAMP_ORTHO
+    // clamp occluders in front of the near plane of the directional light onto it
+    output.clip_position.z = min(output.clip_position.z, 1.0);
+#endif
+
     return output;
 }
 
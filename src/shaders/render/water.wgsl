@@ -0,0 +1,138 @@
+#import bevy_terrain::types::AtlasTile
+#import bevy_terrain::bindings::{attachments, terrain_sampler}
+#import bevy_terrain::functions::{lookup_tile, apply_height}
+#import bevy_terrain::attachments::{compute_sample_uv, sample_height, sample_height_mask}
+
+#ifdef VERTEX
+#import bevy_terrain::vertex::{VertexInput, VertexOutput, vertex_info, vertex_output}
+#endif
+
+#ifdef FRAGMENT
+#import bevy_terrain::fragment::{FragmentInput, FragmentOutput, fragment_info, fragment_debug}
+#import bevy_terrain::detail::{DetailUV, compute_detail_uv, detail_surface_gradient, distance_fade}
+#import bevy_terrain::pbr_fragment::{apply_terrain_overlay, apply_terrain_lighting}
+#import bevy_pbr::pbr_types::{PbrInput, pbr_input_new, STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT}
+#import bevy_pbr::mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT
+#import bevy_pbr::mesh_view_bindings::{view, globals}
+#import bevy_pbr::pbr_functions::calculate_view
+#endif
+
+#ifdef WATER_MASK
+#import bevy_terrain::bindings::water_mask_attachment
+#endif
+
+const TAU: f32 = 6.283185307179586;
+
+struct Water {
+    shallow_color: vec4<f32>,
+    deep_color: vec4<f32>,
+    sea_level: f32,
+    depth_scale: f32,
+    wave_scale: f32,
+    wave_strength: f32,
+    wave_speed: f32,
+    perceptual_roughness: f32,
+    reflectance: f32,
+}
+
+// the bindings start at 100 like the ones of the material extensions, to avoid collisions with `bevy_terrain::pbr_fragment`
+@group(3) @binding(100) var<uniform> water: Water;
+
+#ifdef WATER_MASK
+fn sample_water_mask(tile: AtlasTile) -> bool {
+    let uv = compute_sample_uv(tile, attachments.water_mask);
+
+#ifdef FRAGMENT
+    return textureSampleLevel(water_mask_attachment, terrain_sampler, uv.uv, tile.index, tile.blend_ratio).x > 0.5;
+#else
+    return textureSampleLevel(water_mask_attachment, terrain_sampler, uv.uv, tile.index, 0.0).x > 0.5;
+#endif
+}
+#endif
+
+// The water surface lies at the sea level, or at the terrain itself where the water mask marks
+// water above the sea level (e.g. lakes and rivers).
+fn water_height(tile: AtlasTile) -> f32 {
+#ifdef WATER_MASK
+    if (sample_water_mask(tile)) { return max(water.sea_level, sample_height(tile)); }
+#endif
+
+    return water.sea_level;
+}
+
+#ifdef VERTEX
+@vertex
+fn vertex(input: VertexInput) -> VertexOutput {
+    var info = vertex_info(input);
+
+    let tile = lookup_tile(info.coordinate, info.blend);
+
+    return vertex_output(&info, water_height(tile));
+}
+#endif
+
+#ifdef FRAGMENT
+// The wave moves with the wave speed (in world units per second) along its wave vector.
+fn wave_slope(detail_uv: DetailUV, wave_vector: vec2<f32>, weight: f32) -> vec2<f32> {
+    let phase = TAU * (dot(wave_vector, detail_uv.uv) - water.wave_speed * length(wave_vector) * globals.time / detail_uv.size);
+    return weight * normalize(wave_vector) * cos(phase);
+}
+
+// Computes the tangent space normal of a sum of sine waves, which travel over the water surface.
+// The wave vectors are integers, so that the waves are seamless across the repetitions of the detail uv.
+fn wave_normal(detail_uv: DetailUV) -> vec3<f32> {
+    let slope = wave_slope(detail_uv, vec2<f32>( 1.0,  2.0), 0.4) +
+                wave_slope(detail_uv, vec2<f32>( 3.0, -1.0), 0.3) +
+                wave_slope(detail_uv, vec2<f32>(-2.0,  5.0), 0.2) +
+                wave_slope(detail_uv, vec2<f32>( 7.0,  3.0), 0.1);
+
+    return normalize(vec3<f32>(-water.wave_strength * slope, 1.0));
+}
+
+@fragment
+fn fragment(input: FragmentInput) -> FragmentOutput {
+    var info = fragment_info(input);
+
+    let tile = lookup_tile(info.coordinate, info.blend);
+
+    if (sample_height_mask(tile)) { discard; }
+
+    // the depth of the water is the distance between the water surface and the terrain below
+    let depth = info.height - sample_height(tile);
+
+#ifdef WATER_MASK
+    if (depth <= 0.0 && !sample_water_mask(tile)) { discard; }
+#else
+    if (depth <= 0.0) { discard; }
+#endif
+
+    let absorption = 1.0 - exp(-max(depth, 0.0) / water.depth_scale);
+    let color      = mix(water.shallow_color, water.deep_color, absorption);
+
+    // fade out the waves in the distance, where they would alias
+    let detail_uv        = compute_detail_uv(info.coordinate, water.wave_scale);
+    let fade             = distance_fade(info.world_coordinate.view_distance, 50.0 * water.wave_scale, 200.0 * water.wave_scale);
+    let surface_gradient = fade * detail_surface_gradient(detail_uv, wave_normal(detail_uv), info.tangent_space);
+
+    let world_position = vec4<f32>(apply_height(info.world_coordinate, info.height), 1.0);
+
+    var pbr_input: PbrInput                 = pbr_input_new();
+    pbr_input.material.base_color           = color;
+    pbr_input.material.perceptual_roughness = water.perceptual_roughness;
+    pbr_input.material.reflectance          = vec3<f32>(water.reflectance);
+    pbr_input.material.flags               |= STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT;
+    pbr_input.flags                         = MESH_FLAGS_SHADOW_RECEIVER_BIT;
+    pbr_input.frag_coord                    = info.clip_position;
+    pbr_input.world_position                = world_position;
+    pbr_input.world_normal                  = info.world_coordinate.normal;
+    pbr_input.N                             = normalize(info.world_coordinate.normal - surface_gradient);
+    pbr_input.is_orthographic               = view.clip_from_view[3].w == 1.0;
+    pbr_input.V                             = calculate_view(world_position, pbr_input.is_orthographic);
+    apply_terrain_overlay(&info, &pbr_input);
+
+    var output: FragmentOutput;
+    output.color = apply_terrain_lighting(pbr_input);
+    fragment_debug(&info, &output, tile, surface_gradient);
+    return output;
+}
+#endif
@@ -0,0 +1,114 @@
+#import bevy_terrain::types::{TileCoordinate, Coordinate, AtlasTile, Blend}
+#import bevy_terrain::bindings::{terrain_view, attachments}
+#import bevy_terrain::functions::{compute_world_coordinate, lookup_tile, apply_height}
+#import bevy_terrain::attachments::{sample_height, sample_height_mask}
+#import bevy_terrain::scatter::{ScatterLayer, ScatterInstance, ScatterIndirect, hash, random_float}
+
+#ifdef SCATTER_DENSITY
+#import bevy_terrain::bindings::{terrain_sampler, scatter_density_attachment}
+#import bevy_terrain::attachments::compute_sample_uv
+#endif
+
+const TAU: f32 = 6.283185307179586;
+const NO_CHANNEL: u32 = 0xFFFFFFFFu;
+
+// the bindings start at 100, to avoid collisions with the indirect buffer of the tiling prepass
+@group(2) @binding(100) var<uniform> layer: ScatterLayer;
+@group(2) @binding(101) var<storage> tiles: array<TileCoordinate>;
+@group(2) @binding(102) var<storage, read_write> instances: array<ScatterInstance>;
+@group(2) @binding(103) var<storage, read_write> indirect: ScatterIndirect;
+
+// The seed only depends on the tile and the index of the instance inside of it,
+// so that each tile always produces the same candidates, regardless of when and by which view it is scattered.
+fn instance_seed(tile: TileCoordinate, index: u32) -> u32 {
+    return hash(layer.seed ^ hash(tile.face ^ hash(tile.lod ^ hash(tile.xy.x ^ hash(tile.xy.y ^ hash(index))))));
+}
+
+fn sample_density(tile: AtlasTile) -> f32 {
+#ifdef SCATTER_DENSITY
+    if (layer.density_channel != NO_CHANNEL) {
+        let uv = compute_sample_uv(tile, attachments.scatter_density);
+        return layer.density * textureSampleLevel(scatter_density_attachment, terrain_sampler, uv.uv, tile.index, 0.0)[layer.density_channel];
+    }
+#endif
+
+    return layer.density;
+}
+
+fn surface_position(coordinate: Coordinate, tile: AtlasTile) -> vec3<f32> {
+    var neighbour_tile        = tile;
+    neighbour_tile.coordinate = coordinate;
+
+    return apply_height(compute_world_coordinate(coordinate), sample_height(neighbour_tile));
+}
+
+// Computes the surface normal from the heights one texel apart, at the resolution of the atlas tile.
+fn surface_normal(tile: AtlasTile, position: vec3<f32>, up: vec3<f32>) -> vec3<f32> {
+    let step = 1.0 / attachments.height.center_size;
+
+    var coordinate_u = tile.coordinate;
+    var coordinate_v = tile.coordinate;
+    coordinate_u.uv += vec2<f32>(step, 0.0);
+    coordinate_v.uv += vec2<f32>(0.0, step);
+
+    let normal = normalize(cross(surface_position(coordinate_v, tile) - position,
+                                 surface_position(coordinate_u, tile) - position));
+
+    return select(-normal, normal, dot(normal, up) >= 0.0);
+}
+
+fn frustum_cull(center: vec3<f32>, radius: f32) -> bool {
+    for (var i = 0; i < 6; i = i + 1) {
+        if (dot(terrain_view.half_spaces[i], vec4<f32>(center, 1.0)) + radius < 0.0) { return true; }
+    }
+
+    return false;
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn scatter(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+
+    if (index >= layer.instances_per_tile || invocation_id.y >= layer.tile_count) { return; }
+
+    let tile_coordinate = tiles[invocation_id.y];
+
+    var state = instance_seed(tile_coordinate, index);
+
+    let uv        = vec2<f32>(random_float(&state), random_float(&state));
+    let threshold = random_float(&state);
+    let scale     = mix(layer.scale_range.x, layer.scale_range.y, random_float(&state));
+    let rotation  = TAU * random_float(&state);
+
+    let coordinate       = Coordinate(tile_coordinate.face, tile_coordinate.lod, tile_coordinate.xy, uv);
+    let world_coordinate = compute_world_coordinate(coordinate);
+
+    if (world_coordinate.view_distance > layer.distance) { return; }
+
+    // Always sample the data of the tile lod, so that the instances do not move or pop, when finer data is loaded.
+    // Until this data is loaded, the tile is left empty.
+    let tile = lookup_tile(coordinate, Blend(tile_coordinate.lod, 0.0));
+
+    if (tile.coordinate.lod != tile_coordinate.lod) { return; }
+    if (sample_height_mask(tile)) { return; }
+
+    let height   = sample_height(tile);
+    let position = apply_height(world_coordinate, height);
+    let up       = world_coordinate.normal;
+    let normal   = surface_normal(tile, position, up);
+    let slope    = acos(clamp(dot(normal, up), -1.0, 1.0));
+
+    if (height < layer.height_range.x || height > layer.height_range.y) { return; }
+    if (slope  < layer.slope_range.x  || slope  > layer.slope_range.y)  { return; }
+
+    // thin out the instances towards the scatter distance, instead of ending them abruptly
+    let fade    = 1.0 - smoothstep(0.8 * layer.distance, layer.distance, world_coordinate.view_distance);
+    let density = sample_density(tile) * fade;
+
+    if (threshold >= density) { return; }
+    if (frustum_cull(position, layer.radius * scale)) { return; }
+
+    let instance_index = atomicAdd(&indirect.instance_count, 1u);
+
+    instances[instance_index] = ScatterInstance(position, scale, normalize(mix(up, normal, layer.align_to_normal)), rotation);
+}
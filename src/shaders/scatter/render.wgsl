@@ -0,0 +1,61 @@
+#import bevy_terrain::scatter::{ScatterLayer, ScatterInstance, instance_basis}
+#import bevy_pbr::view_transformations::position_world_to_clip
+
+#ifdef FRAGMENT
+#import bevy_terrain::pbr_fragment::apply_terrain_lighting
+#import bevy_pbr::pbr_types::{PbrInput, pbr_input_new, STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT}
+#import bevy_pbr::mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT
+#import bevy_pbr::mesh_view_bindings::view
+#import bevy_pbr::pbr_functions::calculate_view
+#endif
+
+// the bindings start at 100 like the ones of the material extensions, to avoid collisions with `bevy_terrain::pbr_fragment`
+@group(3) @binding(100) var<storage> instances: array<ScatterInstance>;
+@group(3) @binding(101) var<uniform> layer: ScatterLayer;
+
+struct VertexInput {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+}
+
+@vertex
+fn vertex(input: VertexInput) -> VertexOutput {
+    let instance = instances[input.instance_index];
+    let basis    = instance_basis(instance.up, instance.rotation);
+
+    let world_position = instance.position + basis * (instance.scale * input.position);
+
+    var output: VertexOutput;
+    output.clip_position  = position_world_to_clip(world_position);
+    output.world_position = world_position;
+    output.world_normal   = basis * input.normal;
+    return output;
+}
+
+#ifdef FRAGMENT
+@fragment
+fn fragment(input: VertexOutput) -> @location(0) vec4<f32> {
+    let world_position = vec4<f32>(input.world_position, 1.0);
+
+    var pbr_input: PbrInput                 = pbr_input_new();
+    pbr_input.material.base_color           = layer.color;
+    pbr_input.material.perceptual_roughness = layer.perceptual_roughness;
+    pbr_input.material.flags               |= STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT;
+    pbr_input.flags                         = MESH_FLAGS_SHADOW_RECEIVER_BIT;
+    pbr_input.frag_coord                    = input.clip_position;
+    pbr_input.world_position                = world_position;
+    pbr_input.world_normal                  = normalize(input.world_normal);
+    pbr_input.N                             = pbr_input.world_normal;
+    pbr_input.is_orthographic               = view.clip_from_view[3].w == 1.0;
+    pbr_input.V                             = calculate_view(world_position, pbr_input.is_orthographic);
+
+    return apply_terrain_lighting(pbr_input);
+}
+#endif
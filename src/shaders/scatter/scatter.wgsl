@@ -0,0 +1,61 @@
+#define_import_path bevy_terrain::scatter
+
+struct ScatterLayer {
+    color: vec4<f32>,
+    height_range: vec2<f32>,
+    slope_range: vec2<f32>,
+    scale_range: vec2<f32>,
+    tile_count: u32,
+    instances_per_tile: u32,
+    distance: f32,
+    density: f32,
+    density_channel: u32,
+    align_to_normal: f32,
+    radius: f32,
+    perceptual_roughness: f32,
+    seed: u32,
+}
+
+struct ScatterInstance {
+    position: vec3<f32>,
+    scale: f32,
+    up: vec3<f32>,
+    rotation: f32,
+}
+
+// The arguments of the indirect draw. The instance count is at the same offset for indexed and non-indexed draws.
+struct ScatterIndirect {
+    count: u32,
+    instance_count: atomic<u32>,
+    first: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// PCG hash, see https://www.jcgt.org/published/0009/03/02/
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word  = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Advances the state and returns a random number in the range [0, 1).
+fn random_float(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}
+
+// Rotates the mesh space into the world space, where the y axis of the mesh points along up.
+fn instance_basis(up: vec3<f32>, rotation: f32) -> mat3x3<f32> {
+    // branchless orthonormal basis, see https://jcgt.org/published/0006/01/01/
+    let s = select(-1.0, 1.0, up.z >= 0.0);
+    let a = -1.0 / (s + up.z);
+    let b = up.x * up.y * a;
+    let t = vec3<f32>(1.0 + s * up.x * up.x * a, s * b, -s * up.x);
+    let c = vec3<f32>(b, s + up.y * up.y * a, -up.y);
+
+    let x = cos(rotation) * t + sin(rotation) * c;
+    let z = cross(x, up);
+
+    return mat3x3<f32>(x, up, z);
+}
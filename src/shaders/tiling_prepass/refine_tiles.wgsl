@@ -83,6 +83,8 @@ fn frustum_cull_sphere(coordinate: Coordinate) -> bool {
 fn horizon_cull(coordinate: Coordinate, world_coordinate: WorldCoordinate) -> bool {
     // Todo: implement high precision supprot for culling
     if (coordinate.lod < 3) { return false; }
+    // orthographic and flattened views see the entire hemisphere, respectively the entire map
+    if (terrain_view.orthographic_distance > 0.0 || terrain_view.flat_projection != 0u) { return false; }
     // up to LOD 3, the closest point estimation is not reliable when projecting to adjacent sides
     // to prevent issues with cut of corners, horizon culling is skipped for those cases
     // this still leads to adeqate culling when close to the surface
@@ -0,0 +1,54 @@
+#define_import_path bevy_terrain::topographic
+
+#import bevy_terrain::bindings::terrain
+#import bevy_terrain::functions::inverse_mix
+#import bevy_render::maths::mat2x4_f32_to_mat3x3_unpack
+
+// The coverage of contour lines at every multiple of the interval, with the width in pixels.
+// The lines are anti-aliased using the screen space derivative of the height and fade out,
+// where they would be closer than a few pixels.
+fn contour_line(height: f32, interval: f32, width: f32) -> f32 {
+    let level    = height / interval;
+    let density  = max(fwidth(level), 0.00001);
+    let distance = abs(fract(level - 0.5) - 0.5) / density; // distance to the closest line in pixels
+
+    let coverage = 1.0 - smoothstep(0.5 * width - 0.5, 0.5 * width + 0.5, distance);
+    return coverage * (1.0 - smoothstep(0.15, 0.3, density));
+}
+
+// The coverage of the minor (x) and major (y) contour lines, where every n-th line is a major one.
+fn contour_lines(height: f32, interval: f32, major_every: u32, width: f32, major_width: f32) -> vec2<f32> {
+    let minor = contour_line(height, interval, width);
+    let major = contour_line(height, interval * f32(max(major_every, 1u)), major_width);
+
+    return vec2<f32>(minor, major);
+}
+
+// Colors the height with the gradient texture, which maps the height range from left to right.
+fn hypsometric_tint(gradient: texture_2d<f32>, gradient_sampler: sampler, height: f32, range: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(gradient, gradient_sampler, vec2<f32>(inverse_mix(range.x, range.y, height), 0.5), 0.0);
+}
+
+// Computes the directions towards the east (x) and north (y) of the surface in world space.
+// On planar terrains north points towards the negative z axis of the terrain.
+fn cardinal_directions(up: vec3<f32>) -> mat2x3<f32> {
+    let normal_world_from_unit = mat2x4_f32_to_mat3x3_unpack(terrain.unit_from_world_transpose_a, terrain.unit_from_world_transpose_b);
+
+#ifdef SPHERICAL
+    let east = normalize(cross(normal_world_from_unit * vec3<f32>(0.0, 1.0, 0.0), up));
+#else
+    let east = normalize(normal_world_from_unit * vec3<f32>(1.0, 0.0, 0.0));
+#endif
+
+    return mat2x3<f32>(east, cross(up, east));
+}
+
+// The classic hillshade of cartography, lit from the azimuth (clockwise from north) and the altitude
+// above the horizon, both in radians. Flat surfaces receive the sine of the altitude.
+fn hillshade(normal: vec3<f32>, up: vec3<f32>, azimuth: f32, altitude: f32) -> f32 {
+    let directions = cardinal_directions(up);
+    let horizontal = directions[0] * sin(azimuth) + directions[1] * cos(azimuth);
+    let light      = up * sin(altitude) + horizontal * cos(altitude);
+
+    return saturate(dot(normal, light));
+}
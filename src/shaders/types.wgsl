@@ -27,7 +27,8 @@ struct TerrainView {
     face: u32,
     lod: u32,
     coordinates: array<ViewCoordinate, 6>,
-    height_scale: f32,
+    orthographic_distance: f32, // zero for perspective views
+    flat_projection: u32,
     world_position: vec3<f32>,
     half_spaces: array<vec4<f32>, 6>,
 #ifdef HIGH_PRECISION
@@ -140,3 +141,41 @@ struct SampleUV {
     dy: vec2<f32>,
 #endif
 }
+
+struct Atmosphere {
+    center: vec3<f32>,
+    bottom_radius: f32,
+    top_radius: f32,
+    rayleigh_scattering: vec3<f32>,
+    rayleigh_scale_height: f32,
+    mie_scattering: f32,
+    mie_absorption: f32,
+    mie_scale_height: f32,
+    mie_asymmetry: f32,
+    ozone_absorption: vec3<f32>,
+    ozone_center: f32,
+    ozone_width: f32,
+    ground_albedo: vec3<f32>,
+    sun_direction: vec3<f32>,
+    sun_illuminance: vec3<f32>,
+}
+
+struct OverlayData {
+    grid_size: u32,
+    face_count: u32,
+    entry_offset: u32,
+    segment_offset: u32,
+    shape_offset: u32,
+    data: array<u32>,
+}
+
+struct VirtualTexture {
+    lod_count: u32,
+    page_table_size: u32,
+    feedback_size: u32,
+    frame: u32,
+    texture_size: f32,
+    center_size: f32,
+    scale: f32,
+    offset: f32,
+}
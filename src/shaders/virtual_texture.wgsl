@@ -0,0 +1,102 @@
+#define_import_path bevy_terrain::virtual_texture
+
+#import bevy_terrain::types::Coordinate
+#import bevy_terrain::bindings::{virtual_texture, page_table, page_cache, page_feedback, terrain_sampler}
+#import bevy_terrain::functions::coordinate_change_lod
+
+const NO_PAGE: u32 = 0xFFFFFFFFu;
+// Only one pixel of each block records its page, a different one each frame.
+const FEEDBACK_STRIDE: u32 = 4u;
+
+// PCG hash, see https://www.jcgt.org/published/0009/03/02/
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word  = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// The key of an empty page table entry is zero.
+fn page_key(page: Coordinate) -> u32 {
+    return ((page.face << 8u) | page.lod) + 1u;
+}
+
+fn page_hash(key: u32, xy: vec2<u32>) -> u32 {
+    return hash(key ^ hash(xy.x ^ hash(xy.y)));
+}
+
+// Looks up the slot of the page in the page cache, using the open addressing of the page table.
+fn lookup_page(page: Coordinate) -> u32 {
+    let key  = page_key(page);
+    let mask = virtual_texture.page_table_size - 1u;
+
+    var index = page_hash(key, page.xy) & mask;
+
+    for (var probe = 0u; probe < virtual_texture.page_table_size; probe += 1u) {
+        let entry = 4u * index;
+
+        if (page_table[entry] == 0u) { break; }
+        if (page_table[entry] == key && page_table[entry + 1u] == page.xy.x && page_table[entry + 2u] == page.xy.y) {
+            return page_table[entry + 3u];
+        }
+
+        index = (index + 1u) & mask;
+    }
+
+    return NO_PAGE;
+}
+
+// Selects the finest lod, whose texels are still at least as large as a pixel.
+// The pages have no mipmaps, so this avoids aliasing in the minified case.
+fn compute_page_lod(coordinate: Coordinate) -> u32 {
+    let texels_per_pixel = max(length(coordinate.uv_dx), length(coordinate.uv_dy)) * virtual_texture.center_size;
+    let lod              = f32(coordinate.lod) - log2(max(texels_per_pixel, 1e-10));
+
+    return u32(clamp(floor(lod), 0.0, f32(virtual_texture.lod_count - 1u)));
+}
+
+// Records the page required by the pixel in the feedback buffer, which is read back by the CPU.
+// Pages are hashed into the slots of the buffer and the first pixel to claim a slot wins,
+// so each frame only a sparse subset of the required pages is recorded.
+fn record_feedback(page: Coordinate, frag_coord: vec2<f32>) {
+    let pixel  = vec2<u32>(frag_coord);
+    let offset = vec2<u32>(virtual_texture.frame, virtual_texture.frame / FEEDBACK_STRIDE) % FEEDBACK_STRIDE;
+
+    if (any(pixel % FEEDBACK_STRIDE != offset)) { return; }
+
+    let key  = page_key(page);
+    let slot = 3u * (hash(page_hash(key, page.xy) ^ virtual_texture.frame) % virtual_texture.feedback_size);
+
+    if (atomicLoad(&page_feedback[slot]) != 0u) { return; }
+
+    if (atomicCompareExchangeWeak(&page_feedback[slot], 0u, key).exchanged) {
+        atomicStore(&page_feedback[slot + 1u], page.xy.x);
+        atomicStore(&page_feedback[slot + 2u], page.xy.y);
+    }
+}
+
+// Samples the virtual texture at the coordinate of the fragment.
+// If the required page is not resident yet, the closest coarser page is sampled instead.
+fn sample_virtual_texture(coordinate: Coordinate, frag_coord: vec4<f32>) -> vec4<f32> {
+    if (virtual_texture.page_table_size == 0u) { return vec4<f32>(1.0); }
+
+    var page = coordinate;
+    coordinate_change_lod(&page, compute_page_lod(coordinate));
+
+    record_feedback(page, frag_coord.xy);
+
+    loop {
+        let slot = lookup_page(page);
+
+        if (slot != NO_PAGE) {
+            let uv = virtual_texture.offset + page.uv * virtual_texture.scale;
+            return textureSampleLevel(page_cache, terrain_sampler, uv, slot, 0.0);
+        }
+
+        if (page.lod == 0u) { break; }
+
+        coordinate_change_lod(&page, page.lod - 1u);
+    }
+
+    // the pages of the coarsest lod are pinned in the cache, once they have been loaded
+    return vec4<f32>(1.0);
+}
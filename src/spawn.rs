@@ -4,7 +4,11 @@ use crate::{
     terrain_data::{TileAtlas, TileTree},
     terrain_view::{TerrainViewComponents, TerrainViewConfig},
 };
-use bevy::{ecs::system::SystemState, prelude::*, render::storage::ShaderStorageBuffer};
+use bevy::{
+    ecs::system::SystemState,
+    prelude::*,
+    render::{storage::ShaderStorageBuffer, view::RenderLayers},
+};
 use big_space::floating_origins::BigSpace;
 
 #[derive(Clone)]
@@ -12,7 +16,8 @@ pub(crate) struct TerrainToSpawn<M: Material + Clone> {
     config: Handle<TerrainConfig>,
     view_config: TerrainViewConfig,
     material: M,
-    view: Entity,
+    view: Option<Entity>,
+    render_layers: RenderLayers,
 }
 
 #[derive(Resource)]
@@ -33,6 +38,7 @@ pub(crate) fn spawn_terrains<M: Material>(
                     view_config,
                     material,
                     view,
+                    render_layers,
                 } = terrain;
 
                 let mut state = SystemState::<(
@@ -57,28 +63,39 @@ pub(crate) fn spawn_terrains<M: Material>(
 
                 let config = configs.get(config.id()).unwrap().clone();
 
+                let tile_atlas = match TileAtlas::new(&config, &mut buffers, &settings) {
+                    Ok(tile_atlas) => tile_atlas,
+                    Err(error) => {
+                        error!("Failed to spawn the terrain {}: {error}", config.path);
+                        return;
+                    }
+                };
+
                 let root = big_space.single().unwrap();
 
                 let terrain = commands
                     .spawn((
                         config.shape.transform(),
-                        TileAtlas::new(&config, &mut buffers, &settings),
+                        tile_atlas,
                         MeshMaterial3d(materials.add(material)),
+                        render_layers,
                     ))
                     .id();
 
                 commands.entity(root).add_child(terrain);
 
-                tile_trees.insert(
-                    (terrain, view),
-                    TileTree::new(
-                        &config,
-                        &view_config,
+                if let Some(view) = view {
+                    tile_trees.insert(
                         (terrain, view),
-                        &mut commands,
-                        &mut buffers,
-                    ),
-                );
+                        TileTree::new(
+                            &config,
+                            &view_config,
+                            (terrain, view),
+                            &mut commands,
+                            &mut buffers,
+                        ),
+                    );
+                }
 
                 state.apply(world);
             });
@@ -98,6 +115,14 @@ pub trait SpawnTerrainCommandsExt<M: Material> {
         material: M,
         view: Entity,
     );
+
+    /// Spawns a terrain on the `render_layers`, which is rendered by all [`TerrainCamera`](crate::terrain_view::TerrainCamera)s sharing one of these layers.
+    fn spawn_terrain_on_layers(
+        &mut self,
+        config: Handle<TerrainConfig>,
+        material: M,
+        render_layers: RenderLayers,
+    );
 }
 
 impl<M: Material> SpawnTerrainCommandsExt<M> for Commands<'_, '_> {
@@ -116,8 +141,54 @@ impl<M: Material> SpawnTerrainCommandsExt<M> for Commands<'_, '_> {
                     config,
                     view_config,
                     material,
-                    view,
+                    view: Some(view),
+                    render_layers: default(),
                 });
         });
     }
+
+    fn spawn_terrain_on_layers(
+        &mut self,
+        config: Handle<TerrainConfig>,
+        material: M,
+        render_layers: RenderLayers,
+    ) {
+        self.queue(move |world: &mut World| {
+            world
+                .resource_mut::<TerrainsToSpawn<M>>()
+                .0
+                .push(TerrainToSpawn {
+                    config,
+                    view_config: default(),
+                    material,
+                    view: None,
+                    render_layers,
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spawn_terrain_on_layers_without_view() {
+        let mut world = World::new();
+        world.insert_resource(TerrainsToSpawn::<StandardMaterial>(Vec::new()));
+
+        let render_layers = RenderLayers::layer(1);
+        world.commands().spawn_terrain_on_layers(
+            Handle::default(),
+            StandardMaterial::default(),
+            render_layers.clone(),
+        );
+        world.flush();
+
+        // the views are created by the terrain cameras on the same render layers
+        let terrains = &world.resource::<TerrainsToSpawn<StandardMaterial>>().0;
+        assert_eq!(terrains.len(), 1);
+        assert_eq!(terrains[0].view, None);
+        assert_eq!(terrains[0].render_layers, render_layers);
+    }
 }
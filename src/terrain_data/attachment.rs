@@ -1,15 +1,27 @@
-use crate::math::TileCoordinate;
-use bevy::render::render_resource::TextureFormat;
-use bytemuck::cast_slice;
+use crate::math::{Coordinate, TileCoordinate};
+use bevy::{
+    color::{LinearRgba, Srgba},
+    math::{IVec2, Vec4},
+    platform::collections::HashMap,
+    render::render_resource::TextureFormat,
+};
+use bytemuck::{cast_slice, pod_collect_to_vec};
+use half::f16;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Error, path::PathBuf, str::FromStr};
+use std::{
+    fmt::{self, Error},
+    path::PathBuf,
+    str::FromStr,
+};
 use strum_macros::EnumIter;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Hash, Default)]
 pub enum AttachmentLabel {
     #[default]
     Height,
+    /// The octahedral encoded normals baked by the preprocessor.
+    Normal,
     Custom(String), // Todo: this should not be a heap allocated string
     Empty(usize),
 }
@@ -18,6 +30,7 @@ impl From<&AttachmentLabel> for String {
     fn from(value: &AttachmentLabel) -> Self {
         match value {
             AttachmentLabel::Height => "height".to_string(),
+            AttachmentLabel::Normal => "normal".to_string(),
             AttachmentLabel::Custom(name) => name.clone(),
             AttachmentLabel::Empty(i) => format!("empty_{}", (b'a' + *i as u8) as char).to_string(),
         }
@@ -30,14 +43,23 @@ impl FromStr for AttachmentLabel {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.trim() {
             "height" => Ok(Self::Height),
+            "normal" => Ok(Self::Normal),
             name => Ok(Self::Custom(name.to_string())),
         }
     }
 }
 
 /// The data format of an attachment.
+///
+/// The block compressed formats are encoded by the preprocessor and require the
+/// [`TEXTURE_COMPRESSION_BC`](bevy::render::settings::WgpuFeatures::TEXTURE_COMPRESSION_BC)
+/// feature, which is mostly available on desktop GPUs.
 #[derive(Serialize, Deserialize, EnumIter, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum AttachmentFormat {
+    /// One channel  8 bit unsigned integer
+    R8U,
+    /// Two channels  8 bit unsigned integer
+    Rg8U,
     /// Three channels  8 bit unsigned integer
     Rgb8U,
     /// Four channels  8 bit unsigned integer
@@ -48,19 +70,49 @@ pub enum AttachmentFormat {
     R16I,
     /// Two channels 16 bit unsigned integer
     Rg16U,
+    /// One channel 16 bit float
+    R16F,
+    /// Two channels 16 bit float
+    Rg16F,
+    /// Four channels 16 bit float
+    Rgba16F,
     /// One channel 32 bit float
     R32F,
+    /// Two channels 32 bit float
+    Rg32F,
+    /// Four channels 32 bit float
+    Rgba32F,
+    /// Block compressed RGB color (BC1), 4 bit per pixel
+    Bc1,
+    /// Block compressed single channel (BC4), 4 bit per pixel
+    Bc4,
+    /// Block compressed two channels (BC5), 8 bit per pixel, e.g. for normals
+    Bc5,
+    /// Block compressed RGBA color (BC7), 8 bit per pixel
+    Bc7,
 }
 
 impl FromStr for AttachmentFormat {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.trim() {
-            "rg8u" => Ok(Self::Rgb8U),
+            "r8u" => Ok(Self::R8U),
+            "rg8u" => Ok(Self::Rg8U),
+            "rgb8u" => Ok(Self::Rgb8U),
             "rgba8u" => Ok(Self::Rgba8U),
             "r16u" => Ok(Self::R16U),
             "r16i" => Ok(Self::R16I),
+            "rg16u" => Ok(Self::Rg16U),
+            "r16f" => Ok(Self::R16F),
+            "rg16f" => Ok(Self::Rg16F),
+            "rgba16f" => Ok(Self::Rgba16F),
             "r32f" => Ok(Self::R32F),
+            "rg32f" => Ok(Self::Rg32F),
+            "rgba32f" => Ok(Self::Rgba32F),
+            "bc1" => Ok(Self::Bc1),
+            "bc4" => Ok(Self::Bc4),
+            "bc5" => Ok(Self::Bc5),
+            "bc7" => Ok(Self::Bc7),
             _ => Err(Error),
         }
     }
@@ -69,12 +121,23 @@ impl FromStr for AttachmentFormat {
 impl AttachmentFormat {
     pub(crate) fn render_format(self) -> TextureFormat {
         match self {
+            AttachmentFormat::R8U => TextureFormat::R8Unorm,
+            AttachmentFormat::Rg8U => TextureFormat::Rg8Unorm,
             AttachmentFormat::Rgb8U => TextureFormat::Rgba8UnormSrgb,
             AttachmentFormat::Rgba8U => TextureFormat::Rgba8UnormSrgb,
             AttachmentFormat::R16U => TextureFormat::R16Unorm,
             AttachmentFormat::R16I => TextureFormat::R16Snorm,
             AttachmentFormat::Rg16U => TextureFormat::Rg16Unorm,
+            AttachmentFormat::R16F => TextureFormat::R16Float,
+            AttachmentFormat::Rg16F => TextureFormat::Rg16Float,
+            AttachmentFormat::Rgba16F => TextureFormat::Rgba16Float,
             AttachmentFormat::R32F => TextureFormat::R32Float,
+            AttachmentFormat::Rg32F => TextureFormat::Rg32Float,
+            AttachmentFormat::Rgba32F => TextureFormat::Rgba32Float,
+            AttachmentFormat::Bc1 => TextureFormat::Bc1RgbaUnormSrgb,
+            AttachmentFormat::Bc4 => TextureFormat::Bc4RUnorm,
+            AttachmentFormat::Bc5 => TextureFormat::Bc5RgUnorm,
+            AttachmentFormat::Bc7 => TextureFormat::Bc7RgbaUnormSrgb,
         }
     }
 
@@ -85,20 +148,65 @@ impl AttachmentFormat {
             AttachmentFormat::R16U => TextureFormat::R16Uint,
             AttachmentFormat::R16I => TextureFormat::R16Uint,
             AttachmentFormat::Rg16U => TextureFormat::Rg16Uint,
+            AttachmentFormat::Bc1 => TextureFormat::Bc1RgbaUnorm,
+            AttachmentFormat::Bc7 => TextureFormat::Bc7RgbaUnorm,
             _ => self.render_format(),
         }
     }
 
-    pub(crate) fn pixel_size(self) -> u32 {
+    /// Returns whether the format is block compressed.
+    /// The mipmaps of compressed attachments are generated by the preprocessor,
+    /// since they can not be written by the mip compute shader.
+    pub fn is_compressed(self) -> bool {
+        matches!(
+            self,
+            AttachmentFormat::Bc1
+                | AttachmentFormat::Bc4
+                | AttachmentFormat::Bc5
+                | AttachmentFormat::Bc7
+        )
+    }
+
+    /// The width and height of a block in pixels.
+    pub(crate) fn block_dimension(self) -> u32 {
+        if self.is_compressed() { 4 } else { 1 }
+    }
+
+    /// The size of a block in bytes, which is a single pixel for uncompressed formats.
+    pub(crate) fn block_size(self) -> u32 {
         match self {
+            AttachmentFormat::R8U => 1,
+            AttachmentFormat::Rg8U => 2,
             AttachmentFormat::Rgb8U => 4,
             AttachmentFormat::Rgba8U => 4,
             AttachmentFormat::R16U => 2,
             AttachmentFormat::R16I => 2,
             AttachmentFormat::Rg16U => 4,
+            AttachmentFormat::R16F => 2,
+            AttachmentFormat::Rg16F => 4,
+            AttachmentFormat::Rgba16F => 8,
             AttachmentFormat::R32F => 4,
+            AttachmentFormat::Rg32F => 8,
+            AttachmentFormat::Rgba32F => 16,
+            AttachmentFormat::Bc1 => 8,
+            AttachmentFormat::Bc4 => 8,
+            AttachmentFormat::Bc5 => 16,
+            AttachmentFormat::Bc7 => 16,
         }
     }
+
+    /// The size in bytes of the mip level of a tile.
+    pub(crate) fn mip_size(self, texture_size: u32, mip_level: u32) -> usize {
+        let blocks = (texture_size >> mip_level) / self.block_dimension();
+
+        (blocks * blocks * self.block_size()) as usize
+    }
+
+    /// The file extension of the tiles.
+    /// Compressed tiles are stored as raw blocks, with all mip levels in sequence.
+    pub fn file_extension(self) -> &'static str {
+        if self.is_compressed() { "bc" } else { "tif" }
+    }
 }
 
 /// Configures an attachment.
@@ -134,10 +242,51 @@ impl AttachmentConfig {
     pub fn offset_size(&self) -> u32 {
         self.texture_size - self.border_size
     }
+
+    /// Checks whether the format can store the tiles of this attachment.
+    pub fn validate(&self) -> Result<(), AttachmentError> {
+        if self.format.is_compressed() {
+            let block_dimension = self.format.block_dimension();
+
+            if !(self.texture_size >> self.mip_level_count.saturating_sub(1))
+                .is_multiple_of(block_dimension)
+            {
+                return Err(AttachmentError::CompressedMipLevelSize);
+            }
+            if self.mask {
+                return Err(AttachmentError::CompressedMask);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the coordinate at the center of the `pixel` of the `tile`, where the pixels include the border.
+    /// Border pixels beyond the edges of the cube face lie on the adjacent face (see [`Coordinate::wrapped`]).
+    pub fn pixel_coordinate(
+        &self,
+        tile: TileCoordinate,
+        pixel: IVec2,
+        is_spherical: bool,
+    ) -> Coordinate {
+        let tile_count = (1u64 << tile.lod) as f64;
+        let tile_uv =
+            (pixel.as_dvec2() + 0.5 - self.border_size as f64) / self.center_size() as f64;
+
+        Coordinate::wrapped(
+            tile.face,
+            (tile.xy.as_dvec2() + tile_uv) / tile_count,
+            is_spherical,
+        )
+    }
 }
 
 #[derive(Clone)]
 pub enum AttachmentData {
+    /// One   channel   8 bit
+    R8U(Vec<u8>),
+    /// Two   channels  8 bit
+    Rg8U(Vec<[u8; 2]>),
     /// Three channels  8 bit
     // Rgb8(Vec<(u8, u8, u8)>), Can not be represented currently
     /// Four  channels  8 bit
@@ -148,36 +297,179 @@ pub enum AttachmentData {
     R16I(Vec<i16>),
     /// Two   channels 16 bit
     Rg16U(Vec<[u16; 2]>),
+    /// One   channel  16 bit float
+    R16F(Vec<f16>),
+    /// Two   channels 16 bit float
+    Rg16F(Vec<[f16; 2]>),
+    /// Four  channels 16 bit float
+    Rgba16F(Vec<[f16; 4]>),
+    /// One   channel  32 bit float
     R32F(Vec<f32>),
+    /// Two   channels 32 bit float
+    Rg32F(Vec<[f32; 2]>),
+    /// Four  channels 32 bit float
+    Rgba32F(Vec<[f32; 4]>),
+    /// Block compressed data of all mip levels
+    Compressed(Vec<u8>),
 }
 
 impl AttachmentData {
     pub(crate) fn from_bytes(data: &[u8], format: AttachmentFormat) -> Self {
         match format {
+            AttachmentFormat::R8U => Self::R8U(data.to_vec()),
+            AttachmentFormat::Rg8U => Self::Rg8U(pod_collect_to_vec(data)),
             AttachmentFormat::Rgb8U => Self::Rgba8U(
-                data.chunks(3)
+                data.chunks_exact(3)
                     .map(|chunk| [chunk[0], chunk[1], chunk[2], 255])
                     .collect_vec(),
             ),
-            AttachmentFormat::Rgba8U => Self::Rgba8U(cast_slice(data).to_vec()),
-            AttachmentFormat::R16U => Self::R16U(cast_slice(data).to_vec()),
-            AttachmentFormat::R16I => Self::R16I(cast_slice(data).to_vec()),
-            AttachmentFormat::Rg16U => Self::Rg16U(cast_slice(data).to_vec()),
-            AttachmentFormat::R32F => Self::R32F(cast_slice(data).to_vec()),
+            AttachmentFormat::Rgba8U => Self::Rgba8U(pod_collect_to_vec(data)),
+            AttachmentFormat::R16U => Self::R16U(pod_collect_to_vec(data)),
+            AttachmentFormat::R16I => Self::R16I(pod_collect_to_vec(data)),
+            AttachmentFormat::Rg16U => Self::Rg16U(pod_collect_to_vec(data)),
+            AttachmentFormat::R16F => Self::R16F(pod_collect_to_vec(data)),
+            AttachmentFormat::Rg16F => Self::Rg16F(pod_collect_to_vec(data)),
+            AttachmentFormat::Rgba16F => Self::Rgba16F(pod_collect_to_vec(data)),
+            AttachmentFormat::R32F => Self::R32F(pod_collect_to_vec(data)),
+            AttachmentFormat::Rg32F => Self::Rg32F(pod_collect_to_vec(data)),
+            AttachmentFormat::Rgba32F => Self::Rgba32F(pod_collect_to_vec(data)),
+            AttachmentFormat::Bc1
+            | AttachmentFormat::Bc4
+            | AttachmentFormat::Bc5
+            | AttachmentFormat::Bc7 => Self::Compressed(data.to_vec()),
         }
     }
 
     pub(crate) fn bytes(&self) -> &[u8] {
         match self {
+            AttachmentData::R8U(data) => data,
+            AttachmentData::Rg8U(data) => cast_slice(data),
             AttachmentData::Rgba8U(data) => cast_slice(data),
             AttachmentData::R16U(data) => cast_slice(data),
             AttachmentData::R16I(data) => cast_slice(data),
             AttachmentData::Rg16U(data) => cast_slice(data),
+            AttachmentData::R16F(data) => cast_slice(data),
+            AttachmentData::Rg16F(data) => cast_slice(data),
+            AttachmentData::Rgba16F(data) => cast_slice(data),
             AttachmentData::R32F(data) => cast_slice(data),
+            AttachmentData::Rg32F(data) => cast_slice(data),
+            AttachmentData::Rgba32F(data) => cast_slice(data),
+            AttachmentData::Compressed(data) => data,
+        }
+    }
+
+    /// Returns the value of the pixel at the `index`, as it would be sampled on the GPU.
+    /// Normalized formats are mapped to [0, 1] or [-1, 1] and sRGB colors are converted to linear.
+    /// Compressed attachments can not be sampled on the CPU and return `None`.
+    pub(crate) fn pixel(&self, index: usize) -> Option<Vec4> {
+        let pixel = match self {
+            AttachmentData::R8U(data) => {
+                Vec4::new(data[index] as f32 / u8::MAX as f32, 0.0, 0.0, 1.0)
+            }
+            AttachmentData::Rg8U(data) => {
+                let [r, g] = data[index];
+                Vec4::new(
+                    r as f32 / u8::MAX as f32,
+                    g as f32 / u8::MAX as f32,
+                    0.0,
+                    1.0,
+                )
+            }
+            AttachmentData::Rgba8U(data) => {
+                let [r, g, b, a] = data[index];
+                let color = LinearRgba::from(Srgba::rgba_u8(r, g, b, a));
+                Vec4::new(color.red, color.green, color.blue, color.alpha)
+            }
+            AttachmentData::R16U(data) => {
+                Vec4::new(data[index] as f32 / u16::MAX as f32, 0.0, 0.0, 1.0)
+            }
+            AttachmentData::R16I(data) => Vec4::new(
+                (data[index] as f32 / i16::MAX as f32).max(-1.0),
+                0.0,
+                0.0,
+                1.0,
+            ),
+            AttachmentData::Rg16U(data) => {
+                let [r, g] = data[index];
+                Vec4::new(
+                    r as f32 / u16::MAX as f32,
+                    g as f32 / u16::MAX as f32,
+                    0.0,
+                    1.0,
+                )
+            }
+            AttachmentData::R16F(data) => Vec4::new(data[index].to_f32(), 0.0, 0.0, 1.0),
+            AttachmentData::Rg16F(data) => {
+                let [r, g] = data[index];
+                Vec4::new(r.to_f32(), g.to_f32(), 0.0, 1.0)
+            }
+            AttachmentData::Rgba16F(data) => Vec4::from_array(data[index].map(f16::to_f32)),
+            AttachmentData::R32F(data) => Vec4::new(data[index], 0.0, 0.0, 1.0),
+            AttachmentData::Rg32F(data) => {
+                let [r, g] = data[index];
+                Vec4::new(r, g, 0.0, 1.0)
+            }
+            AttachmentData::Rgba32F(data) => Vec4::from_array(data[index]),
+            AttachmentData::Compressed(_) => return None,
+        };
+
+        Some(pixel)
+    }
+
+    /// Returns whether the pixel at the `index` is masked out.
+    /// The mask is stored in the least significant bit of the first channel, where zero indicates invalid data.
+    pub(crate) fn is_masked(&self, index: usize) -> bool {
+        let bits = match self {
+            AttachmentData::R8U(data) => data[index] as u32,
+            AttachmentData::Rg8U(data) => data[index][0] as u32,
+            AttachmentData::Rgba8U(data) => data[index][0] as u32,
+            AttachmentData::R16U(data) => data[index] as u32,
+            AttachmentData::R16I(data) => data[index] as u16 as u32,
+            AttachmentData::Rg16U(data) => data[index][0] as u32,
+            AttachmentData::R16F(data) => data[index].to_bits() as u32,
+            AttachmentData::Rg16F(data) => data[index][0].to_bits() as u32,
+            AttachmentData::Rgba16F(data) => data[index][0].to_bits() as u32,
+            AttachmentData::R32F(data) => data[index].to_bits(),
+            AttachmentData::Rg32F(data) => data[index][0].to_bits(),
+            AttachmentData::Rgba32F(data) => data[index][0].to_bits(),
+            // compressed attachments can not store a mask
+            AttachmentData::Compressed(_) => return false,
+        };
+
+        bits & 1 == 0
+    }
+}
+
+/// The reasons an attachment config can not be used by a [`TileAtlas`](super::TileAtlas).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttachmentError {
+    /// The smallest mip level of a compressed attachment is not a multiple of the block size.
+    CompressedMipLevelSize,
+    /// Compressed attachments can not store a mask.
+    CompressedMask,
+    /// Compressed attachments can not be sampled on the CPU and thus not be retained there.
+    CompressedCpuAttachment(AttachmentLabel),
+}
+
+impl fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CompressedMipLevelSize => write!(
+                f,
+                "the smallest mip level of a compressed attachment has to be a multiple of the block size"
+            ),
+            Self::CompressedMask => write!(f, "compressed attachments can not store a mask"),
+            Self::CompressedCpuAttachment(label) => write!(
+                f,
+                "the compressed attachment {} can not be retained on the CPU",
+                String::from(label)
+            ),
         }
     }
 }
 
+impl std::error::Error for AttachmentError {}
+
 #[derive(Clone, Debug, Default)]
 pub struct AttachmentTile {
     pub(crate) coordinate: TileCoordinate,
@@ -200,10 +492,12 @@ pub struct Attachment {
     pub(crate) mip_level_count: u32,
     pub(crate) format: AttachmentFormat,
     pub(crate) mask: bool,
+    /// The data of the loaded tiles by their atlas index, if the attachment is retained on the CPU.
+    pub(crate) cpu_tiles: Option<HashMap<u32, AttachmentData>>,
 }
 
 impl Attachment {
-    pub(crate) fn new(config: &AttachmentConfig, path: &str) -> Self {
+    pub(crate) fn new(config: &AttachmentConfig, path: &str) -> Result<Self, AttachmentError> {
         let path = if path.starts_with("assets") {
             path[7..].to_string()
         } else {
@@ -211,7 +505,9 @@ impl Attachment {
         };
         // let path = format!("assets/{path}/data/{name}");
 
-        Self {
+        config.validate()?;
+
+        Ok(Self {
             path: PathBuf::from(path),
             texture_size: config.texture_size,
             center_size: config.center_size(),
@@ -219,6 +515,94 @@ impl Attachment {
             mip_level_count: config.mip_level_count,
             format: config.format,
             mask: config.mask,
+            cpu_tiles: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    fn round_trip(format: AttachmentFormat) {
+        let bytes = (0..64 * format.block_size()).map(|i| i as u8).collect_vec();
+
+        let data = AttachmentData::from_bytes(&bytes, format);
+
+        assert_eq!(data.bytes(), bytes, "{format:?}");
+    }
+
+    #[test]
+    fn round_trip_all_formats() {
+        AttachmentFormat::iter()
+            // three channel data is expanded to four channels
+            .filter(|&format| format != AttachmentFormat::Rgb8U)
+            .for_each(round_trip);
+    }
+
+    #[test]
+    fn parse_all_formats() {
+        for format in AttachmentFormat::iter() {
+            let name = format!("{format:?}").to_lowercase();
+
+            assert_eq!(AttachmentFormat::from_str(&name), Ok(format));
         }
     }
+
+    #[test]
+    fn float_pixels() {
+        let values = [0.5, -2.0, 8.0, 1.0];
+
+        let data = AttachmentData::from_bytes(
+            cast_slice(&values.map(f16::from_f32)),
+            AttachmentFormat::Rgba16F,
+        );
+        assert_eq!(data.pixel(0), Some(Vec4::from_array(values)));
+
+        let data = AttachmentData::from_bytes(cast_slice(&values), AttachmentFormat::Rg32F);
+        assert_eq!(data.pixel(1), Some(Vec4::new(8.0, 1.0, 0.0, 1.0)));
+
+        let data = AttachmentData::from_bytes(&[0, 255], AttachmentFormat::Rg8U);
+        assert_eq!(data.pixel(0), Some(Vec4::new(0.0, 1.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn compressed_pixels() {
+        let data = AttachmentData::from_bytes(&[0; 8], AttachmentFormat::Bc1);
+
+        assert_eq!(data.pixel(0), None);
+        assert!(!data.is_masked(0));
+    }
+
+    #[test]
+    fn validate_compressed_attachments() {
+        let config = AttachmentConfig {
+            texture_size: 16,
+            border_size: 2,
+            mip_level_count: 3,
+            mask: false,
+            format: AttachmentFormat::Bc7,
+        };
+        assert!(Attachment::new(&config, "terrain").is_ok());
+
+        // the fourth mip level is 2x2 pixels, which is smaller than a block
+        let too_many_mip_levels = AttachmentConfig {
+            mip_level_count: 4,
+            ..config.clone()
+        };
+        assert_eq!(
+            Attachment::new(&too_many_mip_levels, "terrain").err(),
+            Some(AttachmentError::CompressedMipLevelSize)
+        );
+
+        let masked = AttachmentConfig {
+            mask: true,
+            ..config
+        };
+        assert_eq!(
+            Attachment::new(&masked, "terrain").err(),
+            Some(AttachmentError::CompressedMask)
+        );
+    }
 }
@@ -9,7 +9,8 @@ use bevy::{
     prelude::default,
     render::{
         render_resource::{binding_types::*, *},
-        renderer::RenderDevice,
+        renderer::{RenderDevice, RenderQueue},
+        settings::WgpuFeatures,
     },
 };
 use itertools::Itertools;
@@ -69,6 +70,8 @@ pub(crate) struct AtlasBufferInfo {
     entries_per_side: u32,
     entries_per_tile: u32,
 
+    /// The number of rows of blocks of a tile, which are pixels for uncompressed formats.
+    pub(crate) rows_per_tile: u32,
     pub(crate) actual_side_size: u32,
     pub(crate) aligned_side_size: u32,
     pub(crate) actual_tile_size: u32,
@@ -78,12 +81,11 @@ pub(crate) struct AtlasBufferInfo {
 }
 
 impl AtlasBufferInfo {
-    fn new(attachment: &Attachment, lod_count: u32) -> Self {
-        // Todo: adjust this code for pixel sizes larger than 4 byte
-        // This approach is currently limited to 1, 2, and 4 byte sized pixels
-        // Extending it to 8 and 16 sized pixels should be quite easy.
-        // However 3, 6, 12 sized pixels do and will not work!
-        // For them to work properly we will need to write into a texture instead of buffer.
+    pub(crate) fn new(attachment: &Attachment, lod_count: u32) -> Self {
+        // The tiles are written into the texture directly (see `write_tile`), which works for any pixel size.
+        // Todo: the write section, which is used by the compute shaders, still packs the pixels into 4 byte entries.
+        // This approach is limited to 1, 2, and 4 byte sized pixels, for larger ones the pixels per entry are zero.
+        // 3, 6, 12 sized pixels do and will not work!
 
         let format = attachment.format;
         let texture_size = attachment.texture_size;
@@ -91,17 +93,19 @@ impl AtlasBufferInfo {
         let center_size = attachment.center_size;
         let mip_level_count = attachment.mip_level_count;
 
-        let pixel_size = format.pixel_size();
+        let block_dimension = format.block_dimension();
+        let block_size = format.block_size();
         let entry_size = mem::size_of::<u32>() as u32;
-        let pixels_per_entry = entry_size / pixel_size;
+        let pixels_per_entry = block_dimension * block_dimension * entry_size / block_size;
 
-        let actual_side_size = texture_size * pixel_size;
+        let rows_per_tile = texture_size / block_dimension;
+        let actual_side_size = rows_per_tile * block_size;
         let aligned_side_size = align_byte_size(actual_side_size);
-        let actual_tile_size = texture_size * actual_side_size;
-        let aligned_tile_size = texture_size * aligned_side_size;
+        let actual_tile_size = rows_per_tile * actual_side_size;
+        let aligned_tile_size = rows_per_tile * aligned_side_size;
 
         let entries_per_side = aligned_side_size / entry_size;
-        let entries_per_tile = texture_size * entries_per_side;
+        let entries_per_tile = rows_per_tile * entries_per_side;
 
         let workgroup_count = UVec3::new(entries_per_side / 8, texture_size / 8, 1);
 
@@ -115,6 +119,7 @@ impl AtlasBufferInfo {
             pixels_per_entry,
             entries_per_side,
             entries_per_tile,
+            rows_per_tile,
             actual_side_size,
             aligned_side_size,
             actual_tile_size,
@@ -141,12 +146,49 @@ impl AtlasBufferInfo {
         }
     }
 
+    /// Writes the data of a tile into the layer `index` of the texture.
+    ///
+    /// Compressed tiles contain their mip chain, which is written as well.
+    /// The mipmaps of the other formats are generated on the GPU afterward.
+    pub(crate) fn write_tile(
+        &self,
+        queue: &RenderQueue,
+        texture: &Texture,
+        index: u32,
+        data: &[u8],
+    ) {
+        let mip_level_count = if self.format.is_compressed() {
+            texture.mip_level_count()
+        } else {
+            1
+        };
+
+        let mut offset = 0;
+
+        for mip_level in 0..mip_level_count {
+            let size = self.format.mip_size(self.texture_size, mip_level);
+
+            queue.write_texture(
+                self.texture_copy_view(texture, index, mip_level),
+                &data[offset..offset + size],
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.actual_side_size >> mip_level),
+                    rows_per_image: Some(self.rows_per_tile >> mip_level),
+                },
+                self.extend_3d(mip_level),
+            );
+
+            offset += size;
+        }
+    }
+
     fn _buffer_copy_view<'a>(&'a self, buffer: &'a Buffer, index: u32) -> TexelCopyBufferInfo<'a> {
         TexelCopyBufferInfo {
             buffer,
             layout: TexelCopyBufferLayout {
                 bytes_per_row: Some(self.aligned_side_size),
-                rows_per_image: Some(self.texture_size),
+                rows_per_image: Some(self.rows_per_tile),
                 offset: self.buffer_size(index) as BufferAddress,
             },
         }
@@ -216,6 +258,21 @@ impl GpuAttachment {
 
         let buffer_info = AtlasBufferInfo::new(attachment, tile_atlas.lod_count);
 
+        let mut usage =
+            TextureUsages::COPY_DST | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING;
+
+        if buffer_info.format.is_compressed() {
+            assert!(
+                device
+                    .features()
+                    .contains(WgpuFeatures::TEXTURE_COMPRESSION_BC),
+                "The {name} attachment is block compressed, but the device does not support BC texture compression."
+            );
+        } else {
+            // the mipmaps are generated by a compute shader
+            usage |= TextureUsages::STORAGE_BINDING;
+        }
+
         let atlas_texture = device.create_texture(&TextureDescriptor {
             label: Some(&format!("{name}_attachment")),
             size: Extent3d {
@@ -227,10 +284,7 @@ impl GpuAttachment {
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: buffer_info.format.processing_format(),
-            usage: TextureUsages::COPY_DST
-                | TextureUsages::COPY_SRC
-                | TextureUsages::TEXTURE_BINDING
-                | TextureUsages::STORAGE_BINDING,
+            usage,
             view_formats: &[buffer_info.format.render_format()],
         });
 
@@ -29,11 +29,18 @@ pub struct GpuTileAtlas {
     pub(crate) upload_tiles: Vec<AttachmentTileWithData>,
     pub(crate) download_tiles: Vec<Task<AttachmentTileWithData>>,
     pub(crate) is_spherical: bool,
+    /// Whether the terrain has a precomputed [`AttachmentLabel::Normal`] attachment, which replaces the normals
+    /// derived from the height attachment.
+    pub(crate) normal_map: bool,
 }
 
 impl GpuTileAtlas {
     pub(crate) fn generate_mip(&self, pass: &mut ComputePass, pipeline_cache: &PipelineCache) {
         for attachment in self.attachments.values() {
+            if attachment.buffer_info.format.is_compressed() {
+                continue;
+            }
+
             let Some(pipeline) = pipeline_cache.get_compute_pipeline(attachment.mip_pipeline)
             else {
                 dbg!("Skipped mipmap generation");
@@ -78,6 +85,9 @@ impl GpuTileAtlas {
             upload_tiles: default(),
             download_tiles: default(),
             is_spherical: tile_atlas.shape.is_spherical(),
+            normal_map: tile_atlas
+                .attachments
+                .contains_key(&AttachmentLabel::Normal),
         }
     }
 
@@ -123,6 +133,11 @@ impl GpuTileAtlas {
             for tile in &gpu_tile_atlas.upload_tiles {
                 let attachment = gpu_tile_atlas.attachments.get_mut(&tile.label).unwrap();
 
+                // the mipmaps of compressed tiles are uploaded alongside them
+                if attachment.buffer_info.format.is_compressed() {
+                    continue;
+                }
+
                 for mip_level in 1..attachment.buffer_info.mip_level_count {
                     attachment.mips_to_generate[mip_level as usize].push(tile.atlas_index);
                 }
@@ -159,6 +174,10 @@ impl GpuTileAtlas {
     ) {
         for gpu_tile_atlas in gpu_tile_atlases.values_mut() {
             for attachment in gpu_tile_atlas.attachments.values_mut() {
+                if attachment.buffer_info.format.is_compressed() {
+                    continue;
+                }
+
                 attachment.mip_pipeline = pipelines.specialize(
                     &pipeline_cache,
                     &mip_pipelines,
@@ -180,19 +199,11 @@ impl GpuTileAtlas {
         for tile in self.upload_tiles.drain(..) {
             let attachment = &self.attachments[&tile.label];
 
-            queue.write_texture(
-                attachment.buffer_info.texture_copy_view(
-                    &attachment.atlas_texture,
-                    tile.atlas_index,
-                    0,
-                ),
+            attachment.buffer_info.write_tile(
+                queue,
+                &attachment.atlas_texture,
+                tile.atlas_index,
                 tile.data.bytes(),
-                TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(attachment.buffer_info.actual_side_size),
-                    rows_per_image: Some(attachment.buffer_info.texture_size),
-                },
-                attachment.buffer_info.extend_3d(0),
             );
         }
     }
@@ -20,16 +20,19 @@ mod gpu_attachment;
 mod gpu_tile_atlas;
 mod tile_atlas;
 mod tile_loader;
+mod tile_sampler;
+mod tile_simulation;
 mod tile_tree;
 
 pub use self::{
-    attachment::{AttachmentConfig, AttachmentFormat, AttachmentLabel},
+    attachment::{AttachmentConfig, AttachmentError, AttachmentFormat, AttachmentLabel},
     gpu_tile_atlas::GpuTileAtlas,
     tile_atlas::TileAtlas,
-    tile_tree::TileTree,
+    tile_simulation::{AtlasOccupancy, SimulationFrame, TileSimulation},
+    tile_tree::{TileTree, TileTreeEntry},
 };
 
-pub(crate) use self::{attachment::*, gpu_attachment::*, tile_loader::*, tile_tree::*};
+pub(crate) use self::{attachment::*, gpu_attachment::*, tile_loader::*};
 
 pub const INVALID_ATLAS_INDEX: u32 = u32::MAX;
 pub const INVALID_LOD: u32 = u32::MAX;
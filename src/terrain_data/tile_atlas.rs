@@ -1,11 +1,11 @@
 use crate::{
-    math::{TerrainShape, TileCoordinate},
+    math::{Coordinate, TerrainShape, TileCoordinate},
     plugin::TerrainSettings,
     render::TerrainUniform,
     terrain::TerrainConfig,
     terrain_data::{
-        Attachment, AttachmentData, AttachmentLabel, AttachmentTile, AttachmentTileWithData,
-        DefaultLoader, TileTree, TileTreeEntry,
+        AtlasOccupancy, Attachment, AttachmentData, AttachmentError, AttachmentLabel,
+        AttachmentTile, AttachmentTileWithData, DefaultLoader, TileTree, TileTreeEntry,
     },
     terrain_view::TerrainViewComponents,
 };
@@ -66,7 +66,7 @@ pub struct TileAtlas {
     pub(crate) attachments: HashMap<AttachmentLabel, Attachment>, // stores the attachment data
     tile_states: HashMap<TileCoordinate, TileState>,
     unused_indices: VecDeque<u32>,
-    existing_tiles: HashSet<TileCoordinate>,
+    pub(crate) existing_tiles: HashSet<TileCoordinate>,
     pub(crate) uploading_tiles: Vec<AttachmentTileWithData>,
     pub(crate) downloading_tiles: Vec<Task<AttachmentTileWithData>>,
     pub(crate) to_load: Vec<AttachmentTile>,
@@ -82,23 +82,37 @@ pub struct TileAtlas {
 
 impl TileAtlas {
     /// Creates a new tile_tree from a terrain config.
+    ///
+    /// Fails if an attachment config is invalid or a compressed attachment should be retained on the CPU.
     pub fn new(
         config: &TerrainConfig,
         buffers: &mut Assets<ShaderStorageBuffer>,
         settings: &TerrainSettings,
-    ) -> Self {
+    ) -> Result<Self, AttachmentError> {
         let attachments = config
             .attachments
             .iter()
-            .map(|(label, attachment)| (label.clone(), Attachment::new(attachment, &config.path)))
-            .collect();
+            .map(|(label, attachment)| {
+                let mut attachment = Attachment::new(attachment, &config.path)?;
+
+                if settings.cpu_attachments.contains(label) {
+                    if attachment.format.is_compressed() {
+                        return Err(AttachmentError::CompressedCpuAttachment(label.clone()));
+                    }
+
+                    attachment.cpu_tiles = Some(default());
+                }
+
+                Ok((label.clone(), attachment))
+            })
+            .collect::<Result<_, _>>()?;
 
         let terrain_buffer = buffers.add(ShaderStorageBuffer::with_size(
             TerrainUniform::min_size().get() as usize,
             RenderAssetUsages::all(),
         ));
 
-        Self {
+        Ok(Self {
             attachments,
             tile_states: default(),
             unused_indices: (0..settings.atlas_size).collect(),
@@ -112,7 +126,7 @@ impl TileAtlas {
             height_scale: 1.0,
             shape: config.shape,
             terrain_buffer,
-        }
+        })
     }
 
     pub(crate) fn get_best_tile(&self, tile_coordinate: TileCoordinate) -> TileTreeEntry {
@@ -144,6 +158,39 @@ impl TileAtlas {
         }
     }
 
+    /// Returns whether all attachments of the tile are loaded.
+    pub(crate) fn is_loaded(&self, tile_coordinate: TileCoordinate) -> bool {
+        self.tile_states
+            .get(&tile_coordinate)
+            .is_some_and(|tile| matches!(tile.state, LoadingState::Loaded))
+    }
+
+    /// Looks up the best loaded tile containing the `coordinate`, with a lod of at most `max_lod`.
+    /// Returns the coordinate and the atlas index of the tile.
+    pub(crate) fn lookup_tile(
+        &self,
+        coordinate: Coordinate,
+        max_lod: u32,
+    ) -> Option<(TileCoordinate, u32)> {
+        for lod in (0..=max_lod.min(self.lod_count - 1)).rev() {
+            let tile_count = 1 << lod;
+            let xy = (coordinate.uv * tile_count as f64)
+                .as_ivec2()
+                .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+            let tile_coordinate = TileCoordinate::new(coordinate.face, lod, xy);
+
+            if let Some(tile) = self
+                .tile_states
+                .get(&tile_coordinate)
+                .filter(|tile| matches!(tile.state, LoadingState::Loaded))
+            {
+                return Some((tile_coordinate, tile.atlas_index));
+            }
+        }
+
+        None
+    }
+
     pub(crate) fn tile_loaded(&mut self, tile: AttachmentTile, data: AttachmentData) {
         if let Some(tile_state) = self.tile_states.get_mut(&tile.coordinate) {
             tile_state.state = match tile_state.state {
@@ -154,6 +201,14 @@ impl TileAtlas {
                 }
             };
 
+            if let Some(cpu_tiles) = self
+                .attachments
+                .get_mut(&tile.label)
+                .and_then(|attachment| attachment.cpu_tiles.as_mut())
+            {
+                cpu_tiles.insert(tile_state.atlas_index, data.clone());
+            }
+
             self.uploading_tiles.push(AttachmentTileWithData {
                 atlas_index: tile_state.atlas_index,
                 label: tile.label,
@@ -171,14 +226,36 @@ impl TileAtlas {
     ) {
         for (&(terrain, _view), tile_tree) in tile_trees.iter_mut() {
             let mut tile_atlas = tile_atlases.get_mut(terrain).unwrap();
+            tile_atlas.apply_requests(tile_tree);
+        }
+    }
 
-            for tile_coordinate in tile_tree.released_tiles.drain(..) {
-                tile_atlas.release_tile(tile_coordinate);
-            }
+    /// Releases and requests the tiles selected by the tile_tree since the last update.
+    pub(crate) fn apply_requests(&mut self, tile_tree: &mut TileTree) {
+        for tile_coordinate in tile_tree.released_tiles.drain(..) {
+            self.release_tile(tile_coordinate);
+        }
 
-            for tile_coordinate in tile_tree.requested_tiles.drain(..) {
-                tile_atlas.request_tile(tile_coordinate);
-            }
+        for tile_coordinate in tile_tree.requested_tiles.drain(..) {
+            self.request_tile(tile_coordinate);
+        }
+    }
+
+    /// Counts the atlas slots, that are requested, cached and free.
+    pub(crate) fn occupancy(&self) -> AtlasOccupancy {
+        let requested = self
+            .tile_states
+            .values()
+            .filter(|tile| tile.requests > 0)
+            .count();
+
+        let cached = self.tile_states.len() - requested;
+
+        AtlasOccupancy {
+            requested,
+            cached,
+            // the indices of cached tiles are reused, once no free ones are left
+            free: self.unused_indices.len() - cached,
         }
     }
 
@@ -192,7 +269,7 @@ impl TileAtlas {
         }
     }
 
-    fn request_tile(&mut self, tile_coordinate: TileCoordinate) {
+    pub(crate) fn request_tile(&mut self, tile_coordinate: TileCoordinate) {
         if !self.existing_tiles.contains(&tile_coordinate) {
             return;
         }
@@ -233,7 +310,7 @@ impl TileAtlas {
         }
     }
 
-    fn release_tile(&mut self, tile_coordinate: TileCoordinate) {
+    pub(crate) fn release_tile(&mut self, tile_coordinate: TileCoordinate) {
         if !self.existing_tiles.contains(&tile_coordinate) {
             return;
         }
@@ -248,3 +325,42 @@ impl TileAtlas {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terrain_data::{AttachmentConfig, AttachmentFormat};
+
+    #[test]
+    fn reject_compressed_cpu_attachments() {
+        let albedo = AttachmentLabel::Custom("albedo".into());
+
+        let mut config = TerrainConfig::default();
+        config.add_attachment(
+            albedo.clone(),
+            AttachmentConfig {
+                texture_size: 16,
+                border_size: 2,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::Bc7,
+            },
+        );
+
+        let settings = TerrainSettings {
+            attachments: vec![albedo.clone()],
+            atlas_size: 1,
+            cpu_attachments: Vec::new(),
+        };
+        assert!(TileAtlas::new(&config, &mut default(), &settings).is_ok());
+
+        let settings = TerrainSettings {
+            cpu_attachments: vec![albedo.clone()],
+            ..settings
+        };
+        assert_eq!(
+            TileAtlas::new(&config, &mut default(), &settings).err(),
+            Some(AttachmentError::CompressedCpuAttachment(albedo))
+        );
+    }
+}
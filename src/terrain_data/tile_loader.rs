@@ -1,10 +1,15 @@
-use crate::terrain_data::{AttachmentData, AttachmentFormat, AttachmentTile, TileAtlas};
+use crate::{
+    formats::decode_tiff,
+    math::TileCoordinate,
+    terrain_data::{AttachmentData, AttachmentFormat, AttachmentTile, TileAtlas},
+};
 use bevy::{
     asset::{AssetServer, Assets, Handle},
     image::Image,
     prelude::*,
 };
 use slab::Slab;
+use std::{fs, io, mem, path::Path};
 
 struct LoadingTile {
     handle: Handle<Image>,
@@ -59,7 +64,8 @@ impl DefaultLoader {
 
                 let path = tile
                     .coordinate
-                    .path(&attachment.path.join(String::from(&tile.label)));
+                    .path(&attachment.path.join(String::from(&tile.label)))
+                    .with_extension(attachment.format.file_extension());
 
                 self.loading_tiles.insert(LoadingTile {
                     handle: asset_server.load(path),
@@ -91,3 +97,41 @@ pub fn start_loading(
         loader.start_loading(&mut tile_atlas, &mut asset_server);
     }
 }
+
+impl TileAtlas {
+    /// Loads the tiles synchronously from the terrain directory, bypassing the asset server.
+    ///
+    /// This is intended for offline tools, which do not run a Bevy app.
+    /// The tiles stay requested and thus the atlas has to be large enough to hold all of them.
+    pub fn load_tiles_blocking(
+        &mut self,
+        terrain_path: &Path,
+        tiles: impl IntoIterator<Item = TileCoordinate>,
+    ) -> io::Result<()> {
+        for tile_coordinate in tiles {
+            self.request_tile(tile_coordinate);
+        }
+
+        for tile in mem::take(&mut self.to_load) {
+            let attachment = &self.attachments[&tile.label];
+            let path = tile
+                .coordinate
+                .path(&terrain_path.join(String::from(&tile.label)))
+                .with_extension(attachment.format.file_extension());
+
+            let bytes = if attachment.format.is_compressed() {
+                fs::read(path)?
+            } else {
+                decode_tiff(fs::read(path)?).map_err(io::Error::other)?.2
+            };
+            let data = AttachmentData::from_bytes(&bytes, attachment.format);
+
+            self.tile_loaded(tile, data);
+        }
+
+        // there is no GPU to upload the tiles to
+        self.uploading_tiles.clear();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    math::{
+        Coordinate, SurfaceSample, TileCoordinate, compute_normal_frame, compute_surface_sample,
+        encode_octahedral,
+    },
+    terrain_data::{Attachment, AttachmentConfig, AttachmentData, AttachmentLabel, TileAtlas},
+};
+use bevy::math::{DVec2, DVec3, IVec2, Vec4};
+use itertools::iproduct;
+
+/// CPU queries of the terrain data.
+///
+/// These use the best currently loaded tile at the queried location and thus only return data
+/// for attachments retained on the CPU (see [`TerrainSettings::cpu_attachments`](crate::plugin::TerrainSettings)),
+/// in areas requested by any [`TileTree`](super::TileTree).
+impl TileAtlas {
+    /// Samples the attachment at the `coordinate` using the best loaded tile with a lod of at most `max_lod`.
+    /// Returns the sampled value and the lod of the tile it was sampled from.
+    pub fn sample_attachment_lod(
+        &self,
+        label: &AttachmentLabel,
+        coordinate: Coordinate,
+        max_lod: u32,
+    ) -> Option<(Vec4, u32)> {
+        let attachment = self.attachments.get(label)?;
+        let cpu_tiles = attachment.cpu_tiles.as_ref()?;
+        let (tile_coordinate, atlas_index) = self.lookup_tile(coordinate, max_lod)?;
+        let data = cpu_tiles.get(&atlas_index)?;
+
+        let tile_uv =
+            coordinate.uv * (1u64 << tile_coordinate.lod) as f64 - tile_coordinate.xy.as_dvec2();
+
+        sample_bilinear(attachment, data, tile_uv).map(|value| (value, tile_coordinate.lod))
+    }
+
+    /// Samples the attachment at the `coordinate` using the best loaded tile.
+    pub fn sample_attachment(
+        &self,
+        label: &AttachmentLabel,
+        coordinate: Coordinate,
+    ) -> Option<Vec4> {
+        self.sample_attachment_lod(label, coordinate, self.lod_count - 1)
+            .map(|(value, _)| value)
+    }
+
+    /// Samples the height at the `coordinate` using the best loaded tile with a lod of at most `max_lod`.
+    /// Mirrors `sample_height` in `attachments.wgsl`.
+    pub fn sample_height_lod(&self, coordinate: Coordinate, max_lod: u32) -> Option<(f32, u32)> {
+        self.sample_attachment_lod(&AttachmentLabel::Height, coordinate, max_lod)
+            .map(|(value, lod)| (self.height_scale * value.x, lod))
+    }
+
+    /// Samples the height at the `coordinate` using the best loaded tile.
+    pub fn sample_height(&self, coordinate: Coordinate) -> Option<f32> {
+        self.sample_height_lod(coordinate, self.lod_count - 1)
+            .map(|(height, _)| height)
+    }
+
+    /// Samples the local geometry of the terrain surface at the `coordinate`.
+    /// The derivatives are computed with a step size of one pixel of the best loaded tile.
+    pub fn sample_surface(&self, coordinate: Coordinate) -> Option<SurfaceSample> {
+        self.sample_surface_lod(coordinate, self.lod_count - 1)
+    }
+
+    /// Samples the local geometry of the terrain surface at the `coordinate`
+    /// using the best loaded tiles with a lod of at most `max_lod`.
+    pub fn sample_surface_lod(
+        &self,
+        coordinate: Coordinate,
+        max_lod: u32,
+    ) -> Option<SurfaceSample> {
+        let (_, lod) = self.sample_height_lod(coordinate, max_lod)?;
+        let attachment = self.attachments.get(&AttachmentLabel::Height)?;
+        let step = 1.0 / ((1u64 << lod) as f64 * attachment.center_size as f64);
+
+        compute_surface_sample(self.shape, coordinate, step, |coordinate| {
+            self.sample_height_lod(coordinate, lod)
+                .map(|(height, _)| height)
+        })
+    }
+
+    /// Samples the normal of the terrain surface in local space.
+    pub fn sample_normal(&self, coordinate: Coordinate) -> Option<DVec3> {
+        self.sample_surface(coordinate).map(|sample| sample.normal)
+    }
+
+    /// Samples the slope of the terrain surface in radians.
+    /// Mirrors `compute_slope` in `attachments.wgsl`.
+    pub fn sample_slope(&self, coordinate: Coordinate) -> Option<f64> {
+        self.sample_surface(coordinate).map(|sample| sample.slope)
+    }
+
+    /// Samples the mean curvature of the terrain surface in 1/m.
+    pub fn sample_curvature(&self, coordinate: Coordinate) -> Option<f64> {
+        self.sample_surface(coordinate)
+            .map(|sample| sample.curvature)
+    }
+
+    /// Bakes the normals into the data of a normal attachment tile (including its border), using the
+    /// height data of the tile atlas.
+    /// The normals are stored in the tangent frame of [`compute_normal_frame`] and octahedral encoded
+    /// as `Rg16U`, which is decoded by `sample_normal_gradient` in `attachments.wgsl`.
+    /// Pixels without height data use the normal of the terrain shape.
+    pub fn bake_normal_tile(
+        &self,
+        tile: TileCoordinate,
+        attachment: &AttachmentConfig,
+    ) -> Vec<[u16; 2]> {
+        let spherical = self.shape.is_spherical();
+
+        iproduct!(0..attachment.texture_size, 0..attachment.texture_size)
+            .map(|(y, x)| {
+                let pixel = IVec2::new(x as i32, y as i32);
+                let coordinate = attachment.pixel_coordinate(tile, pixel, spherical);
+
+                // border pixels on adjacent cube faces are stored in the frame of the face of the tile
+                let frame_coordinate = Coordinate::new(tile.face, coordinate.uv);
+
+                let normal =
+                    self.sample_surface_lod(coordinate, tile.lod)
+                        .map_or(DVec3::Z, |sample| {
+                            compute_normal_frame(self.shape, frame_coordinate, sample.world_normal)
+                                .transpose()
+                                * sample.normal
+                        });
+
+                let encoded = (0.5 * encode_octahedral(normal) + 0.5) * u16::MAX as f64;
+
+                encoded.round().to_array().map(|value| value as u16)
+            })
+            .collect()
+    }
+}
+
+/// Samples the tile data with bilinear filtering, like the linear sampler on the GPU.
+/// Returns `None` if any of the four pixels is masked out.
+fn sample_bilinear(attachment: &Attachment, data: &AttachmentData, tile_uv: DVec2) -> Option<Vec4> {
+    let size = attachment.texture_size as i32;
+    let coord = tile_uv * attachment.center_size as f64 + attachment.border_size as f64 - 0.5;
+    let coord_floor = coord.floor();
+    let t = (coord - coord_floor).as_vec2();
+
+    let mut values = [Vec4::ZERO; 4];
+
+    for (value, offset) in values.iter_mut().zip([
+        IVec2::new(0, 0),
+        IVec2::new(1, 0),
+        IVec2::new(0, 1),
+        IVec2::new(1, 1),
+    ]) {
+        let xy = (coord_floor.as_ivec2() + offset).clamp(IVec2::ZERO, IVec2::splat(size - 1));
+        let index = (xy.y * size + xy.x) as usize;
+
+        if attachment.mask && data.is_masked(index) {
+            return None;
+        }
+
+        *value = data.pixel(index)?;
+    }
+
+    Some(
+        values[0]
+            .lerp(values[1], t.x)
+            .lerp(values[2].lerp(values[3], t.x), t.y),
+    )
+}
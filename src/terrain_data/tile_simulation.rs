@@ -0,0 +1,458 @@
+//! A headless simulation of the tile selection, which drives a [`TileTree`] and its [`TileAtlas`]
+//! without a GPU, a render app or a camera.
+//!
+//! Instead of loading the attachments from disk, the requested tiles are marked as loaded after
+//! a configurable delay. This makes the level of detail selection deterministic, which is useful
+//! for testing and for tuning the [`TerrainViewConfig`] without rendering anything.
+
+use crate::{
+    math::TileCoordinate,
+    plugin::TerrainSettings,
+    terrain::TerrainConfig,
+    terrain_data::{
+        AttachmentData, AttachmentError, AttachmentTile, TileAtlas, TileTree, TileTreeEntry,
+    },
+    terrain_view::TerrainViewConfig,
+};
+use bevy::{math::DVec3, prelude::*};
+use itertools::Itertools;
+use std::collections::VecDeque;
+
+/// The number of atlas slots in a specific state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtlasOccupancy {
+    /// Slots of tiles, that are requested by at least one tile tree.
+    pub requested: usize,
+    /// Slots of tiles, that are no longer requested, but still cached.
+    pub cached: usize,
+    /// Slots that do not hold any tile.
+    pub free: usize,
+}
+
+/// The recorded state of a single step of a [`TileSimulation`].
+///
+/// Only tiles of the terrain are recorded. The tile tree also covers tiles outside of the terrain
+/// (e.g. at coarse lods, where the tree is larger than the terrain), which the tile atlas ignores.
+#[derive(Clone, Debug)]
+pub struct SimulationFrame {
+    /// The local position of the view during this frame.
+    pub view_position: DVec3,
+    /// The tiles newly requested by the tile tree.
+    pub requested_tiles: Vec<TileCoordinate>,
+    /// The tiles released by the tile tree.
+    pub released_tiles: Vec<TileCoordinate>,
+    /// The tiles, that finished loading during this frame.
+    pub loaded_tiles: Vec<TileCoordinate>,
+    /// The occupancy of the tile atlas at the end of the frame.
+    pub occupancy: AtlasOccupancy,
+    /// The best loaded tile of each tile covered by the tile tree.
+    pub entries: Vec<(TileCoordinate, TileTreeEntry)>,
+}
+
+/// Drives a [`TileTree`] and its [`TileAtlas`] with scripted view positions and records
+/// the tile selection of each frame.
+///
+/// Each step mirrors the systems of the terrain plugin: the tile tree computes its requests,
+/// the tile atlas adjusts to them and loads the tiles, and finally the tile tree retrieves
+/// the best loaded tiles.
+pub struct TileSimulation {
+    tile_tree: TileTree,
+    tile_atlas: TileAtlas,
+    /// The number of frames it takes to load a requested tile.
+    load_delay: u32,
+    /// The tiles currently loading, together with the frame they finish loading.
+    loading_tiles: VecDeque<(u32, AttachmentTile)>,
+    frames: Vec<SimulationFrame>,
+}
+
+impl TileSimulation {
+    /// Creates a new simulation of the terrain and the view config.
+    ///
+    /// The `config` has to list all existing tiles, since tiles missing from it are never requested.
+    /// The atlas has to be large enough to hold all tiles requested at the same time.
+    /// Fails if an attachment config is invalid.
+    pub fn new(
+        config: &TerrainConfig,
+        view_config: &TerrainViewConfig,
+        atlas_size: u32,
+    ) -> Result<Self, AttachmentError> {
+        let settings = TerrainSettings {
+            attachments: config.attachments.keys().cloned().collect(),
+            atlas_size,
+            cpu_attachments: Vec::new(),
+        };
+
+        let mut buffers = default();
+
+        Ok(Self {
+            tile_tree: TileTree::new_headless(config, view_config, &mut buffers),
+            tile_atlas: TileAtlas::new(config, &mut buffers, &settings)?,
+            load_delay: 0,
+            loading_tiles: default(),
+            frames: Vec::new(),
+        })
+    }
+
+    /// Sets the number of frames it takes to load a requested tile.
+    /// With a delay of zero, tiles are available in the same frame they are requested.
+    pub fn with_load_delay(mut self, load_delay: u32) -> Self {
+        self.load_delay = load_delay;
+        self
+    }
+
+    /// Advances the simulation by one frame with the view at the local position.
+    pub fn step(&mut self, view_position: DVec3) -> &SimulationFrame {
+        let frame = self.frames.len() as u32;
+
+        self.tile_tree.view_local_position = view_position;
+        self.tile_tree.update();
+
+        let existing_tiles = |tiles: &[TileCoordinate]| {
+            tiles
+                .iter()
+                .copied()
+                .filter(|tile| self.tile_atlas.existing_tiles.contains(tile))
+                .collect_vec()
+        };
+
+        let requested_tiles = existing_tiles(&self.tile_tree.requested_tiles);
+        let released_tiles = existing_tiles(&self.tile_tree.released_tiles);
+
+        self.tile_atlas.apply_requests(&mut self.tile_tree);
+
+        self.loading_tiles.extend(
+            self.tile_atlas
+                .to_load
+                .drain(..)
+                .map(|tile| (frame + self.load_delay, tile)),
+        );
+
+        let mut loaded_tiles = Vec::new();
+
+        while self
+            .loading_tiles
+            .front()
+            .is_some_and(|&(finished, _)| finished <= frame)
+        {
+            let (_, tile) = self.loading_tiles.pop_front().unwrap();
+            let coordinate = tile.coordinate;
+            let format = self.tile_atlas.attachments[&tile.label].format;
+            self.tile_atlas
+                .tile_loaded(tile, AttachmentData::from_bytes(&[], format));
+
+            if self.tile_atlas.is_loaded(coordinate) {
+                loaded_tiles.push(coordinate);
+            }
+        }
+
+        // the data is never uploaded to the GPU
+        self.tile_atlas.uploading_tiles.clear();
+
+        self.tile_tree.adjust(&self.tile_atlas);
+
+        self.frames.push(SimulationFrame {
+            view_position,
+            requested_tiles,
+            released_tiles,
+            loaded_tiles: loaded_tiles.into_iter().unique().collect(),
+            occupancy: self.tile_atlas.occupancy(),
+            entries: self
+                .tile_tree
+                .entries()
+                .filter(|(tile, _)| self.tile_atlas.existing_tiles.contains(tile))
+                .collect(),
+        });
+
+        self.frames.last().unwrap()
+    }
+
+    /// Advances the simulation by one frame for each of the view positions.
+    pub fn run(&mut self, view_positions: impl IntoIterator<Item = DVec3>) -> &[SimulationFrame] {
+        let start = self.frames.len();
+
+        for view_position in view_positions {
+            self.step(view_position);
+        }
+
+        &self.frames[start..]
+    }
+
+    /// All frames recorded so far.
+    pub fn frames(&self) -> &[SimulationFrame] {
+        &self.frames
+    }
+
+    pub fn tile_tree(&self) -> &TileTree {
+        &self.tile_tree
+    }
+
+    pub fn tile_atlas(&self) -> &TileAtlas {
+        &self.tile_atlas
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::{Coordinate, TerrainShape},
+        terrain_data::{AttachmentConfig, AttachmentFormat, AttachmentLabel, INVALID_LOD},
+    };
+    use bevy::platform::collections::HashSet;
+    use std::iter;
+
+    fn config(shape: TerrainShape, lod_count: u32) -> TerrainConfig {
+        let tiles = (0..shape.face_count())
+            .flat_map(|face| {
+                (0..lod_count).flat_map(move |lod| {
+                    let tile_count = 1 << lod;
+                    (0..tile_count)
+                        .cartesian_product(0..tile_count)
+                        .map(move |(x, y)| TileCoordinate::new(face, lod, IVec2::new(x, y)))
+                })
+            })
+            .collect();
+
+        let mut config = TerrainConfig {
+            shape,
+            lod_count,
+            tiles,
+            ..default()
+        };
+        config.add_attachment(
+            AttachmentLabel::Height,
+            AttachmentConfig {
+                texture_size: 8,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::R32F,
+            },
+        );
+
+        config
+    }
+
+    fn simulation(shape: TerrainShape, lod_count: u32) -> TileSimulation {
+        let config = config(shape, lod_count);
+        TileSimulation::new(&config, &default(), config.tiles.len() as u32).unwrap()
+    }
+
+    fn view_tile(shape: TerrainShape, view_position: DVec3, lod: u32) -> TileCoordinate {
+        let coordinate = Coordinate::from_local_position(view_position, shape);
+        let tile_count = 1 << lod;
+        let xy = (coordinate.uv * tile_count as f64)
+            .as_ivec2()
+            .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+
+        TileCoordinate::new(coordinate.face, lod, xy)
+    }
+
+    fn entry(frame: &SimulationFrame, tile: TileCoordinate) -> TileTreeEntry {
+        frame
+            .entries
+            .iter()
+            .find(|(coordinate, _)| *coordinate == tile)
+            .map(|&(_, entry)| entry)
+            .unwrap()
+    }
+
+    /// Checks the invariants, that have to hold for every frame of a single tile tree.
+    fn check_frames(frames: &[SimulationFrame], lod_count: u32) {
+        let mut requested = HashSet::<TileCoordinate>::default();
+
+        for frame in frames {
+            for tile in &frame.released_tiles {
+                assert!(
+                    requested.remove(tile),
+                    "released a tile, that was not requested"
+                );
+            }
+            for &tile in &frame.requested_tiles {
+                assert!(requested.insert(tile), "requested a tile twice");
+            }
+
+            assert_eq!(frame.occupancy.requested, requested.len());
+
+            for &(tile, entry) in &frame.entries {
+                assert!(entry.atlas_lod == INVALID_LOD || entry.atlas_lod <= tile.lod);
+                assert!(tile.lod < lod_count);
+            }
+        }
+    }
+
+    #[test]
+    fn planar_selection() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+        let lod_count = 5;
+        let mut simulation = simulation(shape, lod_count);
+
+        let start = DVec3::new(-400.0, 10.0, -400.0);
+        let end = DVec3::new(400.0, 10.0, 400.0);
+        let path = (0..=20)
+            .map(|i| start.lerp(end, i as f64 / 20.0))
+            .collect_vec();
+
+        let frames = simulation.run(path.clone()).to_vec();
+        check_frames(&frames, lod_count);
+
+        // the root tile is always requested and all tiles are available immediately
+        let first = &frames[0];
+        assert!(first.released_tiles.is_empty());
+        assert!(
+            first
+                .requested_tiles
+                .contains(&TileCoordinate::new(0, 0, IVec2::ZERO))
+        );
+        assert!(
+            first
+                .entries
+                .iter()
+                .all(|(_, entry)| entry.atlas_lod != INVALID_LOD)
+        );
+
+        // the tile below the view is always loaded at the highest lod
+        for (frame, &view_position) in iter::zip(&frames, &path) {
+            let tile = view_tile(shape, view_position, lod_count - 1);
+            assert_eq!(entry(frame, tile).atlas_lod, lod_count - 1);
+        }
+
+        // the detailed tiles around the start are released once the view moved away
+        let start_tile = view_tile(shape, start, lod_count - 1);
+        assert!(
+            frames
+                .iter()
+                .any(|frame| frame.released_tiles.contains(&start_tile))
+        );
+
+        // the coarse layers cover the entire terrain and are never released
+        assert!(
+            frames
+                .iter()
+                .all(|frame| { frame.released_tiles.iter().all(|tile| tile.lod > 0) })
+        );
+    }
+
+    #[test]
+    fn planar_selection_is_deterministic() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+        let path = (0..10)
+            .map(|i| DVec3::new(-300.0 + 60.0 * i as f64, 50.0 + 20.0 * i as f64, 100.0))
+            .collect_vec();
+
+        let a = simulation(shape, 5).run(path.clone()).to_vec();
+        let b = simulation(shape, 5).run(path).to_vec();
+
+        for (a, b) in iter::zip(a, b) {
+            assert_eq!(a.requested_tiles, b.requested_tiles);
+            assert_eq!(a.released_tiles, b.released_tiles);
+            assert_eq!(a.occupancy, b.occupancy);
+            assert_eq!(a.entries, b.entries);
+        }
+    }
+
+    #[test]
+    fn delayed_loading() {
+        let shape = TerrainShape::Plane {
+            side_length: 1000.0,
+        };
+        let lod_count = 4;
+        let load_delay = 2;
+        let config = config(shape, lod_count);
+        let mut simulation = TileSimulation::new(&config, &default(), config.tiles.len() as u32)
+            .unwrap()
+            .with_load_delay(load_delay);
+
+        let view_position = DVec3::new(100.0, 10.0, 100.0);
+        let frames = simulation.run(iter::repeat_n(view_position, 4)).to_vec();
+        check_frames(&frames, lod_count);
+
+        // nothing is available, until the first tiles finished loading
+        for frame in &frames[..load_delay as usize] {
+            assert!(frame.loaded_tiles.is_empty());
+            assert!(
+                frame
+                    .entries
+                    .iter()
+                    .all(|(_, entry)| *entry == TileTreeEntry::default())
+            );
+        }
+
+        let loaded = &frames[load_delay as usize];
+        assert_eq!(loaded.loaded_tiles.len(), frames[0].requested_tiles.len());
+
+        let tile = view_tile(shape, view_position, lod_count - 1);
+        assert_eq!(entry(loaded, tile).atlas_lod, lod_count - 1);
+
+        // a static view does not change its requests
+        assert!(
+            frames[1..].iter().all(|frame| {
+                frame.requested_tiles.is_empty() && frame.released_tiles.is_empty()
+            })
+        );
+    }
+
+    #[test]
+    fn spherical_selection() {
+        let radius = 1000.0;
+        let shape = TerrainShape::Sphere { radius };
+        let lod_count = 4;
+        let mut simulation = simulation(shape, lod_count);
+
+        // orbit once around the sphere just above the surface
+        let path = (0..=24)
+            .map(|i| {
+                let angle = 0.1 + i as f64 / 24.0 * std::f64::consts::TAU;
+                DVec3::new(angle.cos(), 0.3, angle.sin()).normalize() * (radius + 5.0)
+            })
+            .collect_vec();
+
+        let frames = simulation.run(path.clone()).to_vec();
+        check_frames(&frames, lod_count);
+
+        // the root tiles of all six faces are requested
+        for face in 0..6 {
+            assert!(
+                frames[0]
+                    .requested_tiles
+                    .contains(&TileCoordinate::new(face, 0, IVec2::ZERO))
+            );
+        }
+
+        let mut requested = HashSet::<TileCoordinate>::default();
+
+        for (frame, &view_position) in iter::zip(&frames, &path) {
+            for tile in &frame.released_tiles {
+                requested.remove(tile);
+            }
+            requested.extend(&frame.requested_tiles);
+
+            let tile = view_tile(shape, view_position, lod_count - 1);
+            assert_eq!(entry(frame, tile).atlas_lod, lod_count - 1);
+
+            // tiles on the opposite side of the sphere are not requested
+            // (tiles loaded earlier during the orbit remain cached in the atlas though)
+            let opposite = view_tile(shape, -view_position, lod_count - 1);
+            assert!(!requested.contains(&opposite));
+        }
+
+        // the orbit visits multiple faces, which requires releasing detailed tiles
+        let faces = path
+            .iter()
+            .map(|&position| Coordinate::from_local_position(position, shape).face)
+            .unique()
+            .count();
+        assert!(faces > 1);
+        assert!(frames.iter().any(|frame| !frame.released_tiles.is_empty()));
+
+        // the view returned to its start, so only the same tiles remain requested
+        assert_eq!(
+            frames.last().unwrap().occupancy.requested,
+            frames[0].requested_tiles.len()
+        );
+    }
+}
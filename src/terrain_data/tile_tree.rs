@@ -3,7 +3,7 @@ use crate::{
     render::{TerrainViewUniform, TileTreeUniform},
     terrain::TerrainConfig,
     terrain_data::{INVALID_ATLAS_INDEX, INVALID_LOD, TileAtlas},
-    terrain_view::{TerrainViewComponents, TerrainViewConfig},
+    terrain_view::{TerrainProjection, TerrainViewComponents, TerrainViewConfig},
 };
 use bevy::{
     asset::RenderAssetUsages,
@@ -21,6 +21,22 @@ use itertools::{Itertools, iproduct};
 use ndarray::Array4;
 use std::{cmp::Ordering, iter};
 
+/// Computes the size of the pixels of an orthographic view on the terrain, which is
+/// the height of the projected `area` divided by the `viewport_height` in physical pixels.
+/// The area already accounts for the scaling mode and the scale of the projection.
+fn compute_orthographic_pixel_size(area: Rect, viewport_height: u32) -> f64 {
+    area.height() as f64 / viewport_height as f64
+}
+
+/// Computes the view distance of an orthographic view from the size of its pixels on the terrain.
+/// At this distance, a perspective view with the default field of view and the same `viewport_height`
+/// projects its pixels to the same size, so that the tiles are rendered with the same number of pixels.
+fn compute_orthographic_distance(pixel_size: f64, viewport_height: u32) -> f64 {
+    let fov = PerspectiveProjection::default().fov as f64;
+
+    pixel_size * viewport_height as f64 / (2.0 * (0.5 * fov).tan())
+}
+
 /// The current state of a tile of a [`TileTree`].
 ///
 /// This indicates, whether or not the tile should be loaded into the [`TileAtlas`).
@@ -55,12 +71,12 @@ impl Default for TileState {
 /// These entries are synced each frame with their equivalent representations in the
 /// [`GpuTileTree`](super::gpu_tile_tree::GpuTileTree) for access on the GPU.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, ShaderType)]
-pub(crate) struct TileTreeEntry {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ShaderType)]
+pub struct TileTreeEntry {
     /// The atlas index of the best entry.
-    pub(crate) atlas_index: u32,
+    pub atlas_index: u32,
     /// The atlas lod of the best entry.
-    pub(crate) atlas_lod: u32,
+    pub atlas_lod: u32,
 }
 
 impl Default for TileTreeEntry {
@@ -121,6 +137,7 @@ pub struct TileTree {
     pub(crate) precision_distance: f64,
     pub(crate) view_face: u32,
     pub(crate) view_lod: u32,
+    /// The local position of the view in the projection of the terrain.
     pub(crate) view_local_position: DVec3,
     pub(crate) view_world_position: Vec3,
     pub(crate) view_coordinates: [Coordinate; 6],
@@ -128,10 +145,15 @@ pub struct TileTree {
     pub(crate) surface_approximation: [crate::math::SurfaceApproximation; 6],
     pub(crate) approximate_height: f32,
     pub(crate) order: u32,
+    pub(crate) projection: TerrainProjection,
+    /// The constant view distance of orthographic views.
+    pub(crate) orthographic_distance: Option<f64>,
 
     pub(crate) tile_tree_buffer: Handle<ShaderStorageBuffer>,
     pub(crate) terrain_view_buffer: Handle<ShaderStorageBuffer>,
     pub(crate) approximate_height_buffer: Handle<ShaderStorageBuffer>,
+    /// The entity reading back the approximate height, which is despawned together with the tile tree.
+    pub(crate) readback: Option<Entity>,
 }
 
 impl TileTree {
@@ -142,6 +164,42 @@ impl TileTree {
         terrain_view: (Entity, Entity),
         commands: &mut Commands,
         buffers: &mut Assets<ShaderStorageBuffer>, // Todo: solve this dependency with a component hook in the future
+    ) -> Self {
+        let mut tile_tree = Self::new_headless(config, view_config, buffers);
+
+        let readback = commands
+            .spawn((
+                TerrainViewKey(terrain_view),
+                Readback::buffer(tile_tree.approximate_height_buffer.clone_weak()),
+            ))
+            .observe(Self::approximate_height_readback)
+            .id();
+        tile_tree.readback = Some(readback);
+
+        tile_tree
+    }
+
+    /// Releases all tiles of a removed tile tree from the `tile_atlas` and despawns its readback.
+    ///
+    /// The readback has to be despawned right away, since a recreated view would otherwise
+    /// share its [`TerrainViewKey`] with the readback of the new tile tree.
+    pub(crate) fn remove(mut self, tile_atlas: Option<Mut<TileAtlas>>, commands: &mut Commands) {
+        if let Some(mut tile_atlas) = tile_atlas {
+            self.release_all();
+            tile_atlas.apply_requests(&mut self);
+        }
+
+        if let Some(readback) = self.readback {
+            commands.entity(readback).try_despawn();
+        }
+    }
+
+    /// Creates a new tile_tree without registering the readback of the approximate height.
+    /// This is used to drive the tile tree without a GPU (e.g. in the [`TileSimulation`](super::TileSimulation)).
+    pub(crate) fn new_headless(
+        config: &TerrainConfig,
+        view_config: &TerrainViewConfig,
+        buffers: &mut Assets<ShaderStorageBuffer>,
     ) -> Self {
         let data = Array4::default((
             config.shape.face_count() as usize,
@@ -163,13 +221,6 @@ impl TileTree {
         approximate_height_buffer.buffer_description.usage |= BufferUsages::COPY_SRC;
         let approximate_height_buffer = buffers.add(approximate_height_buffer);
 
-        commands
-            .spawn((
-                TerrainViewKey(terrain_view),
-                Readback::buffer(approximate_height_buffer.clone_weak()),
-            ))
-            .observe(Self::approximate_height_readback);
-
         let face_size = config.shape.face_size();
 
         Self {
@@ -209,9 +260,12 @@ impl TileTree {
             surface_approximation: default(),
             approximate_height: 0.0,
             order: view_config.order,
+            projection: view_config.projection,
+            orthographic_distance: None,
             tile_tree_buffer,
             terrain_view_buffer,
             approximate_height_buffer,
+            readback: None,
         }
     }
 
@@ -233,7 +287,11 @@ impl TileTree {
             .as_ivec2()
     }
 
-    fn compute_tile_distance(&self, tile: TileCoordinate, view_coordinate: Coordinate) -> f64 {
+    pub(crate) fn compute_tile_distance(
+        &self,
+        tile: TileCoordinate,
+        view_coordinate: Coordinate,
+    ) -> f64 {
         let tile_count = (tile.lod as f64).exp2();
         let view_tile_xy = Self::compute_tree_xy(view_coordinate, tile_count);
         let tile_offset = view_tile_xy.as_ivec2() - tile.xy;
@@ -251,15 +309,47 @@ impl TileTree {
             Ordering::Equal => offset.y,
         };
 
-        let tile_local_position =
-            Coordinate::new(tile.face, (tile.xy.as_dvec2() + offset) / tile_count)
-                .local_position(self.shape, self.approximate_height);
+        // the distance is measured in the projection of the terrain, like `compute_view_distance` in `functions.wgsl`
+        let tile_local_position = self.projection.project(
+            self.shape,
+            Coordinate::new(tile.face, (tile.xy.as_dvec2() + offset) / tile_count),
+            self.approximate_height,
+        );
 
         tile_local_position.distance(self.view_local_position)
     }
 
-    fn update(&mut self) {
-        let view_coordinate = Coordinate::from_local_position(self.view_local_position, self.shape);
+    /// Selects all tiles of the lod, whose closest point lies within the distance of the view.
+    pub(crate) fn tiles_within_distance(&self, lod: u32, distance: f64) -> Vec<TileCoordinate> {
+        let tile_count = 1 << lod;
+        // the tiles of a cube sphere shrink towards the corners of the faces, so this overestimates the range
+        let range = ((2.0 * distance * tile_count as f64 / self.shape.face_size()).ceil() as i32)
+            .saturating_add(1)
+            .min(tile_count);
+
+        (0..self.shape.face_count())
+            .flat_map(|face| {
+                let view_coordinate = self.view_coordinates[face as usize];
+                let view_xy = Self::compute_tree_xy(view_coordinate, tile_count as f64).as_ivec2();
+                let min = (view_xy - range).max(IVec2::ZERO);
+                let max = (view_xy + range).min(IVec2::splat(tile_count - 1));
+
+                iproduct!(min.x..=max.x, min.y..=max.y)
+                    .map(move |(x, y)| TileCoordinate::new(face, lod, IVec2::new(x, y)))
+                    .filter(move |&tile| {
+                        self.compute_tile_distance(tile, view_coordinate) < distance
+                    })
+            })
+            .collect()
+    }
+
+    /// Updates the tile states based on the current view position,
+    /// while selecting newly requested and released tiles.
+    pub(crate) fn update(&mut self) {
+        // the tile tree is always refined on the terrain shape, even if the view is flattened
+        let (view_coordinate, _) = self
+            .projection
+            .unproject(self.shape, self.view_local_position);
         self.view_face = view_coordinate.face;
 
         for face in 0..self.shape.face_count() {
@@ -276,8 +366,9 @@ impl TileTree {
                         xy: origin + IVec2::new(x as i32, y as i32),
                     };
 
-                    let tile_distance =
-                        self.compute_tile_distance(tile_coordinate, view_coordinate);
+                    let tile_distance = self.orthographic_distance.unwrap_or_else(|| {
+                        self.compute_tile_distance(tile_coordinate, view_coordinate)
+                    });
                     let load_distance = self.load_distance / (tile_coordinate.lod as f64).exp2();
 
                     let state = if lod == 0 || tile_distance < load_distance {
@@ -324,13 +415,16 @@ impl TileTree {
     /// Traverses all tile_trees and updates the tile states,
     /// while selecting newly requested and released tiles.
     pub(crate) fn compute_requests(
-        camera: Query<&Camera>,
+        camera: Query<(&Camera, &Projection)>,
         mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
         grids: Grids,
         views: Query<(&Transform, &GridCell)>,
     ) {
         for (&(_, view), tile_tree) in tile_trees.iter_mut() {
-            let camera = camera.get(view).unwrap();
+            // light views are updated by the terrain shadows
+            let Ok((camera, projection)) = camera.get(view) else {
+                continue;
+            };
             let grid = grids.parent_grid(view).unwrap();
             let (transform, cell) = views.get(view).unwrap();
 
@@ -345,6 +439,14 @@ impl TileTree {
                 .map(|space| space.normal_d());
 
             tile_tree.view_local_position = grid.grid_position_double(cell, transform);
+            tile_tree.orthographic_distance = match (projection, camera.physical_viewport_size()) {
+                (Projection::Orthographic(orthographic), Some(viewport_size)) => {
+                    let pixel_size =
+                        compute_orthographic_pixel_size(orthographic.area, viewport_size.y);
+                    Some(compute_orthographic_distance(pixel_size, viewport_size.y))
+                }
+                _ => None,
+            };
             tile_tree.view_world_position = transform.translation;
             tile_tree.half_spaces = half_spaces;
             tile_tree.update();
@@ -359,13 +461,44 @@ impl TileTree {
     ) {
         for (&(terrain, _view), tile_tree) in tile_trees.iter_mut() {
             let tile_atlas = tile_atlases.get(terrain).unwrap();
+            tile_tree.adjust(tile_atlas);
+        }
+    }
 
-            for (tile, entry) in iter::zip(&tile_tree.tiles, &mut tile_tree.data) {
-                *entry = tile_atlas.get_best_tile(tile.coordinate);
+    /// Updates the entries with the best available tiles of the tile atlas.
+    pub(crate) fn adjust(&mut self, tile_atlas: &TileAtlas) {
+        for (tile, entry) in iter::zip(&self.tiles, &mut self.data) {
+            *entry = tile_atlas.get_best_tile(tile.coordinate);
+        }
+    }
+
+    /// Releases all tiles requested by the tile_tree, e.g. before it is removed.
+    pub(crate) fn release_all(&mut self) {
+        for tile in &mut self.tiles {
+            if tile.state == RequestState::Requested {
+                tile.state = RequestState::Released;
+
+                // tiles, that have not been requested from the tile atlas yet, can be dropped
+                if let Some(index) = self
+                    .requested_tiles
+                    .iter()
+                    .position(|&coordinate| coordinate == tile.coordinate)
+                {
+                    self.requested_tiles.swap_remove(index);
+                } else {
+                    self.released_tiles.push(tile.coordinate);
+                }
             }
         }
     }
 
+    /// Iterates over the tiles currently covered by the tile_tree together with their entries.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (TileCoordinate, TileTreeEntry)> + '_ {
+        iter::zip(&self.tiles, &self.data)
+            .filter(|(tile, _)| tile.coordinate != TileCoordinate::INVALID)
+            .map(|(tile, &entry)| (tile.coordinate, entry))
+    }
+
     pub fn generate_surface_approximation(mut tile_trees: ResMut<TerrainViewComponents<TileTree>>) {
         for tile_tree in tile_trees.values_mut() {
             tile_tree.surface_approximation = tile_tree.view_coordinates.map(|view_coordinate| {
@@ -396,11 +529,103 @@ impl TileTree {
 
     pub fn approximate_height_readback(
         trigger: Trigger<ReadbackComplete>,
+        mut commands: Commands,
         terrain_view: Query<&TerrainViewKey>,
         mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
     ) {
         let TerrainViewKey(terrain_view) = terrain_view.get(trigger.target()).unwrap();
-        let tile_tree = tile_trees.get_mut(terrain_view).unwrap();
-        tile_tree.approximate_height = trigger.event().to_shader_type();
+
+        if let Some(tile_tree) = tile_trees.get_mut(terrain_view) {
+            tile_tree.approximate_height = trigger.event().to_shader_type();
+        } else {
+            // the tile tree has been removed
+            commands.entity(trigger.target()).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn tile_tree(shape: TerrainShape, projection: TerrainProjection) -> TileTree {
+        let config = TerrainConfig {
+            shape,
+            lod_count: 8,
+            ..default()
+        };
+        let view_config = TerrainViewConfig {
+            projection,
+            ..default()
+        };
+
+        TileTree::new_headless(&config, &view_config, &mut default())
+    }
+
+    /// Computes the distance of the tile containing the `coordinate` at the `lod` to the view.
+    fn tile_distance(tile_tree: &TileTree, coordinate: Coordinate, lod: u32) -> f64 {
+        let tile_count = 1 << lod;
+        let xy = (coordinate.uv * tile_count as f64)
+            .as_ivec2()
+            .clamp(IVec2::ZERO, IVec2::splat(tile_count - 1));
+        let tile = TileCoordinate::new(coordinate.face, lod, xy);
+
+        let (view_coordinate, _) = tile_tree
+            .projection
+            .unproject(tile_tree.shape, tile_tree.view_local_position);
+
+        tile_tree.compute_tile_distance(tile, view_coordinate.project_to_face(tile.face))
+    }
+
+    #[test]
+    fn orthographic_pixel_size() {
+        let fov = PerspectiveProjection::default().fov as f64;
+        let area = Rect::new(-500.0, -250.0, 500.0, 250.0);
+
+        for viewport_height in [720, 1440] {
+            let pixel_size = compute_orthographic_pixel_size(area, viewport_height);
+            let distance = compute_orthographic_distance(pixel_size, viewport_height);
+
+            // a perspective view projects its pixels to the same size at this distance
+            let perspective_pixel_size =
+                2.0 * distance * (0.5 * fov).tan() / viewport_height as f64;
+            assert!((perspective_pixel_size - pixel_size).abs() < 1e-9);
+        }
+
+        // zooming out (doubling the scale) doubles the distance
+        let zoomed_out = Rect::new(-1000.0, -500.0, 1000.0, 500.0);
+        assert_eq!(
+            compute_orthographic_distance(compute_orthographic_pixel_size(zoomed_out, 720), 720),
+            2.0 * compute_orthographic_distance(compute_orthographic_pixel_size(area, 720), 720)
+        );
+    }
+
+    #[test]
+    fn flat_tile_distance() {
+        let shape = TerrainShape::Sphere { radius: 1000.0 };
+        let lod = 7;
+        let tile_size = shape.face_size() / (1 << lod) as f64;
+
+        let mut shape_tile_tree = tile_tree(shape, TerrainProjection::Shape);
+        let mut flat_tile_tree = tile_tree(shape, TerrainProjection::Equirectangular);
+
+        // the view hovers above the map at the equator
+        let view_coordinate = Coordinate::from_lat_lon(0.0, 0.0, shape);
+        flat_tile_tree.view_local_position =
+            TerrainProjection::Equirectangular.project(shape, view_coordinate, 10.0);
+
+        // the tile below the view is as far away as the view is above the map
+        let distance = tile_distance(&flat_tile_tree, view_coordinate, lod);
+        assert!((distance - 10.0).abs() < 1e-6);
+
+        // distant tiles are measured along the map, as on the GPU, not along the sphere
+        let coordinate = Coordinate::from_lat_lon(0.0, 90.0, shape);
+        let distance = tile_distance(&flat_tile_tree, coordinate, lod);
+        assert!((distance - FRAC_PI_2 * 1000.0).abs() < tile_size);
+
+        shape_tile_tree.view_local_position = view_coordinate.local_position(shape, 10.0);
+        let distance = tile_distance(&shape_tile_tree, coordinate, lod);
+        assert!((distance - 1010.0_f64.hypot(1000.0)).abs() < tile_size);
     }
 }
@@ -1,6 +1,17 @@
 //! Types for configuring terrain views.
 
-use bevy::{platform::collections::HashMap, prelude::*};
+use crate::{
+    math::{Coordinate, TerrainShape},
+    terrain::TerrainConfig,
+    terrain_data::{TileAtlas, TileTree},
+};
+use bevy::{
+    math::DVec3,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::{storage::ShaderStorageBuffer, view::RenderLayers},
+};
+use itertools::Itertools;
 
 /// Resource that stores components that are associated to a terrain entity and a view entity.
 #[derive(Deref, DerefMut, Resource)]
@@ -43,6 +54,8 @@ pub struct TerrainViewConfig {
     pub precision_distance: f64,
     pub view_lod: u32,
     pub order: u32,
+    /// The projection of the terrain in this view.
+    pub projection: TerrainProjection,
 }
 
 impl Default for TerrainViewConfig {
@@ -61,6 +74,337 @@ impl Default for TerrainViewConfig {
             precision_distance: 0.001,
             view_lod: 10,
             order: 0,
+            projection: TerrainProjection::Shape,
+        }
+    }
+}
+
+impl TerrainViewConfig {
+    /// A cheaper configuration for the light views, which render the terrain into shadow maps.
+    ///
+    /// Shadow maps have a much lower resolution than the screen, so the terrain is subdivided
+    /// less aggressively and with a coarser tile grid.
+    pub fn shadow() -> Self {
+        Self {
+            tree_size: 8,
+            geometry_tile_count: 100000,
+            grid_size: 8,
+            morph_distance: 16.0,
+            blend_distance: 2.5,
+            ..default()
+        }
+    }
+}
+
+/// The projection of a terrain in a terrain view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainProjection {
+    /// Renders the terrain in its shape.
+    #[default]
+    Shape,
+    /// Flattens spherical terrains into an equirectangular map in the xz-plane of the terrain (e.g. for a 2D world map).
+    /// The x-axis points east and the negative z-axis north, both scaled by the equatorial radius,
+    /// while the height is applied along the y-axis.
+    /// Mirrors `compute_world_coordinate_imprecise` in `functions.wgsl`.
+    /// Planar terrains are not affected.
+    Equirectangular,
+}
+
+impl TerrainProjection {
+    pub(crate) fn is_flat(self, shape: TerrainShape) -> bool {
+        self == TerrainProjection::Equirectangular && shape.is_spherical()
+    }
+
+    /// Computes the local position of the `coordinate` and `height` in this projection.
+    pub fn project(self, shape: TerrainShape, coordinate: Coordinate, height: f32) -> DVec3 {
+        if self.is_flat(shape) {
+            let lat_lon = coordinate.lat_lon(shape);
+            let radius = shape.scale().x;
+
+            DVec3::new(
+                lat_lon.y.to_radians() * radius,
+                height as f64,
+                -lat_lon.x.to_radians() * radius,
+            )
+        } else {
+            coordinate.local_position(shape, height)
+        }
+    }
+
+    /// Computes the coordinate and height of the local position in this projection.
+    pub fn unproject(self, shape: TerrainShape, local_position: DVec3) -> (Coordinate, f32) {
+        if self.is_flat(shape) {
+            let radius = shape.scale().x;
+            let lat = (-local_position.z / radius).to_degrees().clamp(-90.0, 90.0);
+            let lon = (local_position.x / radius).to_degrees();
+
+            (
+                Coordinate::from_lat_lon(lat, lon, shape),
+                local_position.y as f32,
+            )
+        } else {
+            let coordinate = Coordinate::from_local_position(local_position, shape);
+            let base = coordinate.local_position(shape, 0.0);
+            let up = coordinate.local_position(shape, 1.0) - base;
+
+            (coordinate, (local_position - base).dot(up) as f32)
+        }
+    }
+}
+
+/// Renders all terrains, which share a [`RenderLayers`] with the camera, into this 3D camera.
+///
+/// Each terrain camera gets its own terrain views with the level of detail of its view config.
+/// This works for any 3D camera, e.g. split-screen views, minimaps or cameras rendering into an image.
+/// Changing the view config recreates the terrain views of the camera.
+#[derive(Component, Clone, Default)]
+pub struct TerrainCamera {
+    pub view_config: TerrainViewConfig,
+}
+
+impl TerrainCamera {
+    /// Creates the views of all terrain cameras onto the terrains on their render layers
+    /// and removes the ones of cameras, that have been removed, changed or no longer share a layer with the terrain.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn update_views(
+        mut commands: Commands,
+        mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
+        mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+        mut tile_atlases: Query<(Entity, &mut TileAtlas, Option<&RenderLayers>)>,
+        cameras: Query<(Entity, Ref<TerrainCamera>, Option<&RenderLayers>), With<Camera3d>>,
+        mut removed_cameras: RemovedComponents<TerrainCamera>,
+    ) {
+        let removed_cameras = removed_cameras.read().collect::<HashSet<_>>();
+        let default_layers = RenderLayers::default();
+
+        let shares_layer = |terrain_layers: Option<&RenderLayers>,
+                            camera_layers: Option<&RenderLayers>| {
+            terrain_layers
+                .unwrap_or(&default_layers)
+                .intersects(camera_layers.unwrap_or(&default_layers))
+        };
+
+        let removed_views = tile_trees
+            .keys()
+            .filter(|&&(terrain, view)| {
+                removed_cameras.contains(&view)
+                    || cameras.get(view).is_ok_and(|(_, camera, camera_layers)| {
+                        let terrain_layers = tile_atlases
+                            .get(terrain)
+                            .ok()
+                            .and_then(|(_, _, layers)| layers);
+
+                        (camera.is_changed() && !camera.is_added())
+                            || !shares_layer(terrain_layers, camera_layers)
+                    })
+            })
+            .copied()
+            .collect_vec();
+
+        for terrain_view @ (terrain, _) in removed_views {
+            if let Some(tile_tree) = tile_trees.remove(&terrain_view) {
+                let tile_atlas = tile_atlases
+                    .get_mut(terrain)
+                    .ok()
+                    .map(|(_, tile_atlas, _)| tile_atlas);
+                tile_tree.remove(tile_atlas, &mut commands);
+            }
+        }
+
+        for (terrain, tile_atlas, terrain_layers) in &tile_atlases {
+            for (camera, terrain_camera, camera_layers) in &cameras {
+                // the views of changed cameras are recreated in the next frame,
+                // once the render world has dropped the old ones
+                if tile_trees.contains_key(&(terrain, camera))
+                    || (terrain_camera.is_changed() && !terrain_camera.is_added())
+                    || !shares_layer(terrain_layers, camera_layers)
+                {
+                    continue;
+                }
+
+                // the tile tree only depends on the shape and the lod count of the terrain
+                let config = TerrainConfig {
+                    shape: tile_atlas.shape,
+                    lod_count: tile_atlas.lod_count,
+                    ..default()
+                };
+
+                tile_trees.insert(
+                    (terrain, camera),
+                    TileTree::new(
+                        &config,
+                        &terrain_camera.view_config,
+                        (terrain, camera),
+                        &mut commands,
+                        &mut buffers,
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        plugin::TerrainSettings,
+        terrain_data::{AttachmentConfig, AttachmentFormat, AttachmentLabel},
+    };
+    use bevy::{math::DVec2, render::gpu_readback::Readback};
+
+    fn spawn_terrain(world: &mut World, render_layers: Option<RenderLayers>) -> Entity {
+        let mut config = TerrainConfig {
+            shape: TerrainShape::Plane {
+                side_length: 1000.0,
+            },
+            lod_count: 2,
+            ..default()
+        };
+        config.add_attachment(
+            AttachmentLabel::Height,
+            AttachmentConfig {
+                texture_size: 8,
+                border_size: 1,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::R32F,
+            },
+        );
+
+        let tile_atlas =
+            TileAtlas::new(&config, &mut default(), &TerrainSettings::default()).unwrap();
+
+        let mut terrain = world.spawn(tile_atlas);
+        if let Some(render_layers) = render_layers {
+            terrain.insert(render_layers);
+        }
+        terrain.id()
+    }
+
+    fn spawn_camera(world: &mut World, render_layers: Option<RenderLayers>) -> Entity {
+        let mut camera = world.spawn((Camera3d::default(), TerrainCamera::default()));
+        if let Some(render_layers) = render_layers {
+            camera.insert(render_layers);
+        }
+        camera.id()
+    }
+
+    /// Runs the view update and returns the terrain views.
+    fn update(world: &mut World, schedule: &mut Schedule) -> HashSet<(Entity, Entity)> {
+        schedule.run(world);
+        world.clear_trackers();
+
+        let tile_trees = world.resource::<TerrainViewComponents<TileTree>>();
+
+        // each tile tree has exactly one readback
+        let readbacks = world
+            .iter_entities()
+            .filter(|entity| entity.contains::<Readback>())
+            .map(|entity| entity.id())
+            .collect::<HashSet<_>>();
+        let tile_tree_readbacks = tile_trees
+            .values()
+            .map(|tile_tree| tile_tree.readback.unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(readbacks, tile_tree_readbacks);
+
+        tile_trees.keys().copied().collect()
+    }
+
+    #[test]
+    fn views_per_render_layer() {
+        let mut world = World::new();
+        world.init_resource::<TerrainViewComponents<TileTree>>();
+        world.init_resource::<Assets<ShaderStorageBuffer>>();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(TerrainCamera::update_views);
+
+        let overlay_layers = RenderLayers::layer(1);
+        let terrain = spawn_terrain(&mut world, None);
+        let overlay_terrain = spawn_terrain(&mut world, Some(overlay_layers.clone()));
+        let camera = spawn_camera(&mut world, None);
+        let overlay_camera = spawn_camera(&mut world, Some(overlay_layers));
+
+        // each camera only views the terrains on its render layers
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, camera), (overlay_terrain, overlay_camera)])
+        );
+
+        // moving a camera to another layer moves its views
+        world
+            .entity_mut(overlay_camera)
+            .insert(RenderLayers::from_layers(&[0, 1]));
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([
+                (terrain, camera),
+                (terrain, overlay_camera),
+                (overlay_terrain, overlay_camera)
+            ])
+        );
+
+        // the views of removed cameras are removed
+        world.entity_mut(overlay_camera).despawn();
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, camera)])
+        );
+    }
+
+    #[test]
+    fn recreate_changed_views() {
+        let mut world = World::new();
+        world.init_resource::<TerrainViewComponents<TileTree>>();
+        world.init_resource::<Assets<ShaderStorageBuffer>>();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(TerrainCamera::update_views);
+
+        let terrain = spawn_terrain(&mut world, None);
+        let camera = spawn_camera(&mut world, None);
+
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, camera)])
+        );
+
+        // changing the view config removes the view and recreates it in the next frame,
+        // while the readback of the old view is despawned
+        world
+            .get_mut::<TerrainCamera>(camera)
+            .unwrap()
+            .view_config
+            .grid_size = 8;
+        assert!(update(&mut world, &mut schedule).is_empty());
+        assert_eq!(
+            update(&mut world, &mut schedule),
+            HashSet::from_iter([(terrain, camera)])
+        );
+
+        let tile_trees = world.resource::<TerrainViewComponents<TileTree>>();
+        assert_eq!(tile_trees[&(terrain, camera)].grid_size, 8);
+    }
+
+    #[test]
+    fn equirectangular_round_trip() {
+        let shape = TerrainShape::Sphere { radius: 6371000.0 };
+
+        for (lat, lon) in [(0.0, 0.0), (45.0, -120.0), (-60.0, 170.0), (10.0, 90.0)] {
+            let coordinate = Coordinate::from_lat_lon(lat, lon, shape);
+            let position = TerrainProjection::Equirectangular.project(shape, coordinate, 100.0);
+            let (unprojected, height) =
+                TerrainProjection::Equirectangular.unproject(shape, position);
+
+            assert!(
+                (unprojected.lat_lon(shape) - DVec2::new(lat, lon))
+                    .abs()
+                    .max_element()
+                    < 1e-9
+            );
+            assert_eq!(height, 100.0);
         }
     }
 }
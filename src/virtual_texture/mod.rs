@@ -0,0 +1,757 @@
+//! Virtual texturing of high-resolution imagery.
+//!
+//! The attachments of the [`TileAtlas`] share the resolution of the geometry tiles, which limits
+//! them to a few meters per pixel on a global terrain.
+//! A [`TerrainVirtualTexture`] stores an additional color texture of nearly arbitrary resolution
+//! in pages, which are streamed independently of the tile atlas.
+//!
+//! The virtual texture consists of three parts working together:
+//! - The feedback: the terrain fragment shader records the page each pixel requires into a
+//!   feedback buffer, which is read back to the CPU every frame.
+//! - The page cache: a fixed number of physical pages stored in an array texture.
+//!   Required pages, which are not resident, are loaded with the asset server and replace the
+//!   least recently used pages. The pages of lod 0 are never evicted.
+//! - The page table: a hash table mapping the resident pages to their slot in the page cache,
+//!   which the shader uses to find the best resident page of each pixel.
+//!
+//! The pages are stored like the tiles of an attachment (`{lod}/{x}_{y}/{page}.tif`),
+//! where the page of lod 0 covers a whole face.
+//! The base color of the [`TerrainPbrMaterial`](crate::material::TerrainPbrMaterial)
+//! and its extensions is multiplied by the virtual texture.
+
+use crate::{
+    math::TileCoordinate,
+    terrain::TerrainComponents,
+    terrain_data::{
+        AtlasBufferInfo, Attachment, AttachmentConfig, AttachmentData, AttachmentError,
+        AttachmentFormat, TileAtlas,
+    },
+    util::GpuBuffer,
+};
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::{
+        MainWorld, Render, RenderApp, RenderSet,
+        gpu_readback::{Readback, ReadbackComplete},
+        render_asset::{RenderAssetUsages, RenderAssets, prepare_assets},
+        render_resource::{
+            binding_types::{
+                storage_buffer_read_only_sized, storage_buffer_sized, texture_2d_array,
+                uniform_buffer,
+            },
+            *,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+    },
+};
+use bytemuck::cast_slice;
+use slab::Slab;
+use std::cmp::Reverse;
+
+/// The number of values of an entry in the page table (key, x, y, slot).
+const PAGE_TABLE_ENTRY_SIZE: usize = 4;
+/// The number of values of a slot in the feedback buffer (key, x, y).
+const FEEDBACK_SLOT_SIZE: usize = 3;
+const MAX_LOADING_PAGES: usize = 32;
+
+/// A high-resolution color texture of a terrain, which is streamed in pages.
+///
+/// It has to be added to the terrain entity.
+#[derive(Component, Clone, Debug)]
+pub struct TerrainVirtualTexture {
+    /// The directory of the pages.
+    pub path: String,
+    /// The size, border and format of the pages. The pages are sampled without mipmaps.
+    pub page: AttachmentConfig,
+    /// The number of lods of the pages, where the page of lod 0 covers a whole face.
+    pub lod_count: u32,
+    /// The number of pages, which are resident at once.
+    /// This is limited by the maximum number of array layers of the device (usually 256).
+    pub cache_size: u32,
+    /// The number of slots of the feedback buffer.
+    pub feedback_size: u32,
+}
+
+impl Default for TerrainVirtualTexture {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            page: AttachmentConfig {
+                texture_size: 516,
+                border_size: 2,
+                mip_level_count: 1,
+                mask: false,
+                format: AttachmentFormat::Rgba8U,
+            },
+            lod_count: 16,
+            cache_size: 256,
+            feedback_size: 4096,
+        }
+    }
+}
+
+/// The PCG hash, which has to match `bevy_terrain::virtual_texture::hash`.
+/// See <https://www.jcgt.org/published/0009/03/02/>.
+fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// The key of the page in the page table and the feedback buffer.
+/// The key of empty entries is zero.
+fn page_key(page: TileCoordinate) -> u32 {
+    ((page.face << 8) | page.lod) + 1
+}
+
+fn page_hash(page: TileCoordinate) -> u32 {
+    hash(page_key(page) ^ hash(page.xy.x as u32 ^ hash(page.xy.y as u32)))
+}
+
+/// The page table has at least twice as many entries as there are pages in the cache,
+/// which keeps the probe sequences short.
+fn page_table_size(cache_size: u32) -> usize {
+    (2 * cache_size as usize).next_power_of_two()
+}
+
+struct ResidentPage {
+    slot: u32,
+    last_used: u32,
+}
+
+/// Assigns the pages to the slots of the page cache.
+struct PageCache {
+    pages: HashMap<TileCoordinate, ResidentPage>,
+    free_slots: Vec<u32>,
+}
+
+impl PageCache {
+    fn new(size: u32) -> Self {
+        Self {
+            pages: default(),
+            free_slots: (0..size).rev().collect(),
+        }
+    }
+
+    fn contains(&self, page: TileCoordinate) -> bool {
+        self.pages.contains_key(&page)
+    }
+
+    /// Returns the number of slots, which are free or hold a page that can be evicted in the frame.
+    fn available_slots(&self, frame: u32) -> usize {
+        self.free_slots.len()
+            + self
+                .pages
+                .iter()
+                .filter(|(page, resident)| page.lod > 0 && resident.last_used < frame)
+                .count()
+    }
+
+    /// Marks the page as used in the frame and returns whether it is resident.
+    fn touch(&mut self, page: TileCoordinate, frame: u32) -> bool {
+        self.pages
+            .get_mut(&page)
+            .map(|resident| resident.last_used = frame)
+            .is_some()
+    }
+
+    /// Inserts the page and returns its slot.
+    ///
+    /// If the cache is full, the least recently used page is evicted.
+    /// Pages of lod 0 and pages used in the current frame are never evicted,
+    /// in which case no slot is available.
+    fn insert(&mut self, page: TileCoordinate, frame: u32) -> Option<u32> {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                let (&evicted, _) = self
+                    .pages
+                    .iter()
+                    .filter(|(page, resident)| page.lod > 0 && resident.last_used < frame)
+                    .min_by_key(|(_, resident)| resident.last_used)?;
+
+                self.pages.remove(&evicted).unwrap().slot
+            }
+        };
+
+        self.pages.insert(
+            page,
+            ResidentPage {
+                slot,
+                last_used: frame,
+            },
+        );
+
+        Some(slot)
+    }
+
+    /// Builds the page table, a hash table with open addressing and linear probing.
+    fn page_table(&self, size: usize) -> Vec<u32> {
+        let mut page_table = vec![0; PAGE_TABLE_ENTRY_SIZE * size];
+
+        for (&page, resident) in &self.pages {
+            let mut index = page_hash(page) as usize & (size - 1);
+
+            while page_table[PAGE_TABLE_ENTRY_SIZE * index] != 0 {
+                index = (index + 1) & (size - 1);
+            }
+
+            page_table[PAGE_TABLE_ENTRY_SIZE * index..][..PAGE_TABLE_ENTRY_SIZE].copy_from_slice(
+                &[
+                    page_key(page),
+                    page.xy.x as u32,
+                    page.xy.y as u32,
+                    resident.slot,
+                ],
+            );
+        }
+
+        page_table
+    }
+}
+
+struct LoadingPage {
+    handle: Handle<Image>,
+    page: TileCoordinate,
+}
+
+/// Marks the entity, which reads back the feedback of the virtual texture of a terrain.
+#[derive(Component)]
+struct VirtualTextureFeedback(Entity);
+
+/// Streams the pages of the [`TerrainVirtualTexture`] of a terrain.
+///
+/// The required pages are requested by the feedback of the shader and loaded
+/// the same way as the tiles of the [`TileAtlas`].
+#[derive(Component)]
+pub struct VirtualTexture {
+    attachment: Attachment,
+    lod_count: u32,
+    face_count: u32,
+    page_table_size: usize,
+    frame: u32,
+    cache: PageCache,
+    requested: HashSet<TileCoordinate>,
+    missing: HashSet<TileCoordinate>,
+    to_load: Vec<TileCoordinate>,
+    loading_pages: Slab<LoadingPage>,
+    pub(crate) uploading_pages: Vec<(u32, AttachmentData)>,
+    /// The page table, if it has changed since the last frame.
+    pub(crate) page_table: Option<Vec<u32>>,
+    pub(crate) feedback_buffer: Handle<ShaderStorageBuffer>,
+}
+
+impl VirtualTexture {
+    fn new(
+        config: &TerrainVirtualTexture,
+        face_count: u32,
+        buffers: &mut Assets<ShaderStorageBuffer>,
+    ) -> Result<Self, AttachmentError> {
+        let mut feedback_buffer = ShaderStorageBuffer::with_size(
+            FEEDBACK_SLOT_SIZE * config.feedback_size as usize * size_of::<u32>(),
+            RenderAssetUsages::all(),
+        );
+        feedback_buffer.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+
+        let mut virtual_texture = Self {
+            attachment: Attachment::new(&config.page, &config.path)?,
+            lod_count: config.lod_count,
+            face_count,
+            page_table_size: page_table_size(config.cache_size),
+            frame: 0,
+            cache: PageCache::new(config.cache_size),
+            requested: default(),
+            missing: default(),
+            to_load: default(),
+            loading_pages: Slab::with_capacity(MAX_LOADING_PAGES),
+            uploading_pages: default(),
+            page_table: None,
+            feedback_buffer: buffers.add(feedback_buffer),
+        };
+
+        // the pages of lod 0 are the fallback of all other pages
+        for face in 0..face_count {
+            virtual_texture.request(TileCoordinate::new(face, 0, IVec2::ZERO));
+        }
+
+        Ok(virtual_texture)
+    }
+
+    fn request(&mut self, page: TileCoordinate) {
+        if !self.cache.contains(page)
+            && !self.missing.contains(&page)
+            && self.requested.insert(page)
+        {
+            self.to_load.push(page);
+        }
+    }
+
+    /// Requests the pages recorded in the feedback, together with their coarser pages,
+    /// which are sampled until the page itself is loaded.
+    fn process_feedback(&mut self, feedback: &[u32]) {
+        // pages, which are no longer required, are not loaded anymore
+        for page in self.to_load.drain(..) {
+            self.requested.remove(&page);
+        }
+
+        for slot in feedback.chunks_exact(FEEDBACK_SLOT_SIZE) {
+            let (key, x, y) = (slot[0], slot[1], slot[2]);
+
+            if key == 0 {
+                continue;
+            }
+
+            let mut page = TileCoordinate::new(
+                (key - 1) >> 8,
+                (key - 1) & 0xFF,
+                IVec2::new(x as i32, y as i32),
+            );
+
+            if page.face >= self.face_count || page.lod >= self.lod_count {
+                continue;
+            }
+
+            loop {
+                if !self.cache.touch(page, self.frame) {
+                    self.request(page);
+                }
+
+                match page.parent() {
+                    Some(parent) => page = parent,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    fn finish_loading(&mut self, asset_server: &AssetServer, images: &Assets<Image>) {
+        let mut loaded_pages = Vec::new();
+
+        self.loading_pages.retain(|_, loading_page| {
+            if asset_server.is_loaded(loading_page.handle.id()) {
+                let image = images.get(loading_page.handle.id()).unwrap();
+                let data = AttachmentData::from_bytes(
+                    image.data.as_ref().unwrap(),
+                    self.attachment.format,
+                );
+                loaded_pages.push((loading_page.page, data));
+
+                false
+            } else if asset_server
+                .load_state(loading_page.handle.id())
+                .is_failed()
+            {
+                // the page does not exist, e.g. because the imagery does not cover this region
+                self.requested.remove(&loading_page.page);
+                self.missing.insert(loading_page.page);
+
+                false
+            } else {
+                true
+            }
+        });
+
+        let mut changed = false;
+
+        for (page, data) in loaded_pages {
+            self.requested.remove(&page);
+
+            if let Some(slot) = self.cache.insert(page, self.frame) {
+                self.uploading_pages.push((slot, data));
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.page_table = Some(self.cache.page_table(self.page_table_size));
+        }
+    }
+
+    fn start_loading(&mut self, asset_server: &AssetServer) {
+        // the coarse pages are loaded first, since they are the fallback of the finer ones
+        self.to_load.sort_unstable_by_key(|page| Reverse(page.lod));
+
+        // pages are only loaded, if they can be inserted into the cache,
+        // otherwise they would be dropped and requested again
+        let max_loading_pages = MAX_LOADING_PAGES.min(self.cache.available_slots(self.frame));
+
+        while self.loading_pages.len() < max_loading_pages {
+            let Some(page) = self.to_load.pop() else {
+                break;
+            };
+
+            self.loading_pages.insert(LoadingPage {
+                handle: asset_server.load(
+                    page.path(&self.attachment.path)
+                        .with_extension(self.attachment.format.file_extension()),
+                ),
+                page,
+            });
+        }
+    }
+
+    /// Initializes the [`VirtualTexture`] of newly created terrains.
+    pub(crate) fn initialize(
+        mut commands: Commands,
+        mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+        terrains: Query<(Entity, &TerrainVirtualTexture, &TileAtlas), Added<TerrainVirtualTexture>>,
+    ) {
+        for (terrain, config, tile_atlas) in &terrains {
+            let virtual_texture =
+                match VirtualTexture::new(config, tile_atlas.shape.face_count(), &mut buffers) {
+                    Ok(virtual_texture) => virtual_texture,
+                    Err(error) => {
+                        error!(
+                            "Failed to create the virtual texture {}: {error}",
+                            config.path
+                        );
+                        continue;
+                    }
+                };
+
+            commands
+                .spawn((
+                    VirtualTextureFeedback(terrain),
+                    Readback::buffer(virtual_texture.feedback_buffer.clone_weak()),
+                ))
+                .observe(Self::feedback_readback);
+
+            commands.entity(terrain).insert(virtual_texture);
+        }
+    }
+
+    pub(crate) fn update(
+        mut virtual_textures: Query<&mut VirtualTexture>,
+        asset_server: Res<AssetServer>,
+        images: Res<Assets<Image>>,
+    ) {
+        for mut virtual_texture in &mut virtual_textures {
+            virtual_texture.frame += 1;
+            virtual_texture.finish_loading(&asset_server, &images);
+            virtual_texture.start_loading(&asset_server);
+        }
+    }
+
+    fn feedback_readback(
+        trigger: Trigger<ReadbackComplete>,
+        mut commands: Commands,
+        feedback: Query<&VirtualTextureFeedback>,
+        mut virtual_textures: Query<&mut VirtualTexture>,
+    ) {
+        let &VirtualTextureFeedback(terrain) = feedback.get(trigger.target()).unwrap();
+
+        if let Ok(mut virtual_texture) = virtual_textures.get_mut(terrain) {
+            let feedback = trigger
+                .event()
+                .0
+                .chunks_exact(size_of::<u32>())
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect::<Vec<_>>();
+
+            virtual_texture.process_feedback(&feedback);
+        } else {
+            // the virtual texture has been removed
+            commands.entity(trigger.target()).despawn();
+        }
+    }
+}
+
+/// The virtual texture config data that is available in shaders.
+#[derive(Default, ShaderType)]
+pub(crate) struct VirtualTextureUniform {
+    lod_count: u32,
+    page_table_size: u32,
+    feedback_size: u32,
+    frame: u32,
+    texture_size: f32,
+    center_size: f32,
+    scale: f32,
+    offset: f32,
+}
+
+/// The layout of the bind group of a virtual texture, which is used by the terrain pipelines of
+/// terrains with a virtual texture.
+pub(crate) fn virtual_texture_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        "virtual_texture_layout",
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::FRAGMENT,
+            (
+                uniform_buffer::<VirtualTextureUniform>(false),
+                storage_buffer_read_only_sized(false, None),
+                texture_2d_array(TextureSampleType::Float { filterable: true }),
+                storage_buffer_sized(false, None),
+            ),
+        ),
+    )
+}
+
+/// The render world representation of a [`TerrainVirtualTexture`].
+pub struct GpuVirtualTexture {
+    /// Binds the virtual texture to the terrain pipelines, once the feedback buffer is prepared.
+    pub(crate) bind_group: Option<BindGroup>,
+    virtual_texture_buffer: GpuBuffer<VirtualTextureUniform>,
+    page_table_buffer: GpuBuffer<()>,
+    page_cache_view: TextureView,
+    page_feedback_buffer: Handle<ShaderStorageBuffer>,
+    page_cache: Texture,
+    buffer_info: AtlasBufferInfo,
+    feedback_size: usize,
+    upload_pages: Vec<(u32, AttachmentData)>,
+    page_table: Option<Vec<u32>>,
+}
+
+impl GpuVirtualTexture {
+    fn new(
+        device: &RenderDevice,
+        config: &TerrainVirtualTexture,
+        virtual_texture: &VirtualTexture,
+    ) -> Self {
+        let buffer_info = AtlasBufferInfo::new(&virtual_texture.attachment, config.lod_count);
+
+        let page_cache = device.create_texture(&TextureDescriptor {
+            label: Some("page_cache"),
+            size: Extent3d {
+                width: buffer_info.texture_size,
+                height: buffer_info.texture_size,
+                depth_or_array_layers: config.cache_size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: buffer_info.format.processing_format(),
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[buffer_info.format.render_format()],
+        });
+
+        let page_cache_view = page_cache.create_view(&TextureViewDescriptor {
+            format: Some(buffer_info.format.render_format()),
+            dimension: Some(TextureViewDimension::D2Array),
+            usage: Some(TextureUsages::TEXTURE_BINDING),
+            ..default()
+        });
+
+        let page_table_buffer = GpuBuffer::empty_sized_labeled(
+            "page_table_buffer",
+            device,
+            (PAGE_TABLE_ENTRY_SIZE * virtual_texture.page_table_size * size_of::<u32>())
+                as BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        );
+
+        let virtual_texture_buffer = GpuBuffer::empty_labeled(
+            "virtual_texture_buffer",
+            device,
+            BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        );
+
+        Self {
+            bind_group: None,
+            virtual_texture_buffer,
+            page_table_buffer,
+            page_cache_view,
+            page_feedback_buffer: virtual_texture.feedback_buffer.clone(),
+            page_cache,
+            buffer_info,
+            feedback_size: FEEDBACK_SLOT_SIZE * config.feedback_size as usize * size_of::<u32>(),
+            upload_pages: default(),
+            page_table: None,
+        }
+    }
+
+    /// Extracts the pages that have finished loading and the changes of the page table.
+    pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        mut main_world: ResMut<MainWorld>,
+        mut gpu_virtual_textures: ResMut<TerrainComponents<GpuVirtualTexture>>,
+    ) {
+        let mut virtual_textures =
+            main_world.query::<(Entity, &TerrainVirtualTexture, &mut VirtualTexture)>();
+
+        gpu_virtual_textures
+            .retain(|&terrain, _| virtual_textures.get(&main_world, terrain).is_ok());
+
+        for (terrain, config, mut virtual_texture) in virtual_textures.iter_mut(&mut main_world) {
+            let gpu_virtual_texture = gpu_virtual_textures
+                .entry(terrain)
+                .or_insert_with(|| GpuVirtualTexture::new(&device, config, &virtual_texture));
+
+            gpu_virtual_texture
+                .upload_pages
+                .append(&mut virtual_texture.uploading_pages);
+
+            if let Some(page_table) = virtual_texture.page_table.take() {
+                gpu_virtual_texture.page_table = Some(page_table);
+            }
+
+            let buffer_info = &gpu_virtual_texture.buffer_info;
+
+            gpu_virtual_texture
+                .virtual_texture_buffer
+                .set_value(VirtualTextureUniform {
+                    lod_count: config.lod_count,
+                    page_table_size: virtual_texture.page_table_size as u32,
+                    feedback_size: config.feedback_size,
+                    frame: virtual_texture.frame,
+                    texture_size: buffer_info.texture_size as f32,
+                    center_size: buffer_info.center_size as f32,
+                    scale: buffer_info.center_size as f32 / buffer_info.texture_size as f32,
+                    offset: buffer_info.border_size as f32 / buffer_info.texture_size as f32,
+                });
+        }
+    }
+
+    /// Uploads the loaded pages and the page table and clears the feedback buffer.
+    ///
+    /// This runs before the terrains are queued, so that the bind group is available to their pipelines.
+    pub(crate) fn prepare(
+        device: Res<RenderDevice>,
+        queue: Res<RenderQueue>,
+        buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+        mut gpu_virtual_textures: ResMut<TerrainComponents<GpuVirtualTexture>>,
+    ) {
+        for gpu_virtual_texture in gpu_virtual_textures.values_mut() {
+            let buffer_info = &gpu_virtual_texture.buffer_info;
+
+            for (slot, data) in gpu_virtual_texture.upload_pages.drain(..) {
+                buffer_info.write_tile(&queue, &gpu_virtual_texture.page_cache, slot, data.bytes());
+            }
+
+            if let Some(page_table) = gpu_virtual_texture.page_table.take() {
+                gpu_virtual_texture
+                    .page_table_buffer
+                    .update_bytes(&queue, cast_slice(&page_table));
+            }
+
+            gpu_virtual_texture.virtual_texture_buffer.update(&queue);
+
+            let Some(feedback_buffer) = buffers.get(&gpu_virtual_texture.page_feedback_buffer)
+            else {
+                continue;
+            };
+
+            // the feedback of the previous frame has already been copied for the readback
+            queue.write_buffer(
+                &feedback_buffer.buffer,
+                0,
+                &vec![0; gpu_virtual_texture.feedback_size],
+            );
+
+            if gpu_virtual_texture.bind_group.is_none() {
+                gpu_virtual_texture.bind_group = Some(device.create_bind_group(
+                    "virtual_texture_bind_group",
+                    &virtual_texture_layout(&device),
+                    &BindGroupEntries::sequential((
+                        &gpu_virtual_texture.virtual_texture_buffer,
+                        gpu_virtual_texture.page_table_buffer.as_entire_binding(),
+                        &gpu_virtual_texture.page_cache_view,
+                        feedback_buffer.buffer.as_entire_binding(),
+                    )),
+                ));
+            }
+        }
+    }
+}
+
+/// This plugin streams the [`TerrainVirtualTexture`] of each terrain.
+///
+/// It has to be added after the [`TerrainPlugin`](crate::plugin::TerrainPlugin).
+pub struct TerrainVirtualTexturePlugin;
+
+impl Plugin for TerrainVirtualTexturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (VirtualTexture::initialize, VirtualTexture::update).chain(),
+        );
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainComponents<GpuVirtualTexture>>()
+            .add_systems(ExtractSchedule, GpuVirtualTexture::extract)
+            .add_systems(
+                Render,
+                GpuVirtualTexture::prepare
+                    .in_set(RenderSet::PrepareAssets)
+                    .after(prepare_assets::<GpuShaderStorageBuffer>),
+            );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Mirrors `lookup_page` of the shader.
+    fn lookup_page(page_table: &[u32], size: usize, page: TileCoordinate) -> Option<u32> {
+        let mut index = page_hash(page) as usize & (size - 1);
+
+        for _ in 0..size {
+            let entry = &page_table[PAGE_TABLE_ENTRY_SIZE * index..][..PAGE_TABLE_ENTRY_SIZE];
+
+            if entry[0] == 0 {
+                break;
+            }
+            if entry[..3] == [page_key(page), page.xy.x as u32, page.xy.y as u32] {
+                return Some(entry[3]);
+            }
+
+            index = (index + 1) & (size - 1);
+        }
+
+        None
+    }
+
+    #[test]
+    fn evict_least_recently_used_page() {
+        let root = TileCoordinate::new(0, 0, IVec2::ZERO);
+        let first = TileCoordinate::new(0, 1, IVec2::new(0, 0));
+        let second = TileCoordinate::new(0, 1, IVec2::new(1, 0));
+        let third = TileCoordinate::new(0, 1, IVec2::new(0, 1));
+
+        let mut cache = PageCache::new(3);
+        assert_eq!(cache.insert(root, 0), Some(0));
+        assert_eq!(cache.insert(first, 0), Some(1));
+        assert_eq!(cache.insert(second, 1), Some(2));
+        assert!(cache.touch(first, 2));
+
+        // the root is pinned and the first page has been used more recently than the second one
+        assert_eq!(cache.insert(third, 3), Some(2));
+        assert!(!cache.contains(second));
+
+        // pages used in the current frame are not evicted
+        assert!(cache.touch(first, 4));
+        assert!(cache.touch(third, 4));
+        assert_eq!(cache.available_slots(4), 0);
+        assert_eq!(cache.insert(second, 4), None);
+
+        // in the next frame, both pages can be evicted again
+        assert_eq!(cache.available_slots(5), 2);
+    }
+
+    #[test]
+    fn lookup_resident_pages() {
+        let pages = (0..64)
+            .map(|i| TileCoordinate::new(i % 6, 12, IVec2::new(i as i32 * 37, 4000 - i as i32)))
+            .collect::<Vec<_>>();
+
+        let mut cache = PageCache::new(64);
+        for &page in &pages {
+            cache.insert(page, 0);
+        }
+
+        let size = page_table_size(64);
+        let page_table = cache.page_table(size);
+
+        for page in pages {
+            assert_eq!(
+                lookup_page(&page_table, size, page),
+                Some(cache.pages[&page].slot)
+            );
+        }
+
+        let missing = TileCoordinate::new(0, 12, IVec2::new(1, 1));
+        assert_eq!(lookup_page(&page_table, size, missing), None);
+    }
+}
@@ -0,0 +1,435 @@
+//! Water surfaces rendered on top of the terrain.
+//!
+//! The water is drawn with the same tile tree geometry as the terrain, whose vertices are placed on
+//! the water surface instead. This works for both planar and spherical terrains.
+//! The surface lies at the sea level of the [`TerrainWater`] and optionally at the terrain itself,
+//! where the `water_mask` attachment marks water (e.g. lakes and rivers).
+//! The water is colored by its depth, computed from the height attachment (e.g. bathymetry), and
+//! lit with the physically based lighting of Bevy, which adds sun glints and the reflections of
+//! environment maps to the procedural wave normals.
+
+use crate::{
+    debug::DebugTerrain,
+    render::{
+        DrawTerrainCommand, GpuTerrainView, SetTerrainBindGroup, SetTerrainFeatureBindGroups,
+        SetTerrainViewBindGroup, TERRAIN_DEPTH_FORMAT, TerrainFeatureLayouts, TerrainFeatures,
+        TerrainItem, TerrainPipelineFlags, TerrainPipelineKey, TerrainTilingPrepassPipelines,
+    },
+    shaders::WATER_SHADER,
+    terrain::TerrainComponents,
+    terrain_data::GpuTileAtlas,
+    terrain_view::TerrainViewComponents,
+    util::GpuBuffer,
+};
+use bevy::{
+    ecs::{
+        query::ROQueryItem,
+        system::{SystemParamItem, lifetimeless::SRes},
+    },
+    image::BevyDefault,
+    pbr::{
+        MeshPipeline, MeshPipelineViewLayoutKey, RenderViewLightProbes, SetMeshViewBindGroup,
+        ShadowFilteringMethod,
+    },
+    prelude::*,
+    render::{
+        Extract, Render, RenderApp, RenderSet,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{binding_types::uniform_buffer, *},
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::MainEntity,
+        view::RetainedViewEntity,
+    },
+};
+
+/// The water of a terrain.
+///
+/// Add this component to the terrain entity, to render a water surface on top of it.
+/// If the water mask is enabled, the `water_mask` attachment (e.g. an
+/// [`R16U`](crate::terrain_data::AttachmentFormat::R16U) attachment) has to be part of the
+/// [`TerrainSettings`](crate::plugin::TerrainSettings).
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default, Debug)]
+pub struct TerrainWater {
+    /// The height of the water surface in meters.
+    pub sea_level: f32,
+    /// The color of shallow water.
+    pub shallow_color: Color,
+    /// The color of deep water.
+    pub deep_color: Color,
+    /// The depth in meters, at which the color has mostly changed from shallow to deep.
+    pub depth_scale: f32,
+    /// The world space size of one repetition of the wave pattern.
+    pub wave_scale: f32,
+    /// The steepness of the waves.
+    pub wave_strength: f32,
+    /// The speed of the waves in world units per second.
+    pub wave_speed: f32,
+    /// The perceived roughness of the water surface.
+    pub perceptual_roughness: f32,
+    /// The specular intensity of the water surface.
+    pub reflectance: f32,
+    /// Whether the `water_mask` attachment marks additional water above the sea level.
+    pub water_mask: bool,
+}
+
+impl Default for TerrainWater {
+    fn default() -> Self {
+        Self {
+            sea_level: 0.0,
+            shallow_color: Color::srgb(0.1, 0.45, 0.5),
+            deep_color: Color::srgb(0.01, 0.04, 0.12),
+            depth_scale: 30.0,
+            wave_scale: 50.0,
+            wave_strength: 0.15,
+            wave_speed: 2.0,
+            perceptual_roughness: 0.1,
+            reflectance: 0.35,
+            water_mask: false,
+        }
+    }
+}
+
+/// The water data that is available in shaders.
+#[derive(Clone, Default, ShaderType)]
+pub struct WaterUniform {
+    shallow_color: Vec4,
+    deep_color: Vec4,
+    sea_level: f32,
+    depth_scale: f32,
+    wave_scale: f32,
+    wave_strength: f32,
+    wave_speed: f32,
+    perceptual_roughness: f32,
+    reflectance: f32,
+}
+
+impl From<&TerrainWater> for WaterUniform {
+    fn from(water: &TerrainWater) -> Self {
+        Self {
+            shallow_color: LinearRgba::from(water.shallow_color).to_vec4(),
+            deep_color: LinearRgba::from(water.deep_color).to_vec4(),
+            sea_level: water.sea_level,
+            depth_scale: water.depth_scale.max(f32::EPSILON),
+            wave_scale: water.wave_scale,
+            wave_strength: water.wave_strength,
+            wave_speed: water.wave_speed,
+            perceptual_roughness: water.perceptual_roughness,
+            reflectance: water.reflectance,
+        }
+    }
+}
+
+/// The render world representation of a [`TerrainWater`].
+pub struct GpuWater {
+    water_buffer: GpuBuffer<WaterUniform>,
+    water_bind_group: BindGroup,
+    water_mask: bool,
+}
+
+impl GpuWater {
+    fn new(device: &RenderDevice, pipeline: &TerrainWaterPipeline) -> Self {
+        let water_buffer = GpuBuffer::empty_labeled(
+            "water_buffer",
+            device,
+            BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        );
+
+        let water_bind_group = device.create_bind_group(
+            "water_bind_group",
+            &pipeline.water_layout,
+            &BindGroupEntries::with_indices(((100, &water_buffer),)),
+        );
+
+        Self {
+            water_buffer,
+            water_bind_group,
+            water_mask: false,
+        }
+    }
+
+    pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        pipeline: Res<TerrainWaterPipeline>,
+        mut gpu_waters: ResMut<TerrainComponents<GpuWater>>,
+        waters: Extract<Query<(Entity, &TerrainWater)>>,
+    ) {
+        gpu_waters.retain(|terrain, _| waters.contains(*terrain));
+
+        for (terrain, water) in &waters {
+            let gpu_water = gpu_waters
+                .entry(terrain)
+                .or_insert_with(|| GpuWater::new(&device, &pipeline));
+
+            gpu_water.water_mask = water.water_mask;
+            gpu_water.water_buffer.set_value(water.into());
+        }
+    }
+
+    pub(crate) fn prepare(
+        queue: Res<RenderQueue>,
+        mut gpu_waters: ResMut<TerrainComponents<GpuWater>>,
+    ) {
+        for gpu_water in gpu_waters.values_mut() {
+            gpu_water.water_buffer.update(&queue);
+        }
+    }
+}
+
+/// The pipeline used to render the water of the terrain entities.
+#[derive(Resource)]
+pub struct TerrainWaterPipeline {
+    view_layout: BindGroupLayout,
+    view_layout_multisampled: BindGroupLayout,
+    terrain_layout: BindGroupLayout,
+    terrain_view_layout: BindGroupLayout,
+    water_layout: BindGroupLayout,
+    feature_layouts: TerrainFeatureLayouts,
+    shader: Handle<Shader>,
+}
+
+impl FromWorld for TerrainWaterPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let mesh_pipeline = world.resource::<MeshPipeline>();
+        let prepass_pipelines = world.resource::<TerrainTilingPrepassPipelines>();
+
+        let water_layout = device.create_bind_group_layout(
+            "water_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::VERTEX_FRAGMENT,
+                ((100, uniform_buffer::<WaterUniform>(false)),),
+            ),
+        );
+
+        Self {
+            view_layout: mesh_pipeline
+                .get_view_layout(MeshPipelineViewLayoutKey::empty())
+                .clone(),
+            view_layout_multisampled: mesh_pipeline
+                .get_view_layout(MeshPipelineViewLayoutKey::MULTISAMPLED)
+                .clone(),
+            terrain_layout: prepass_pipelines.terrain_layout.clone(),
+            terrain_view_layout: prepass_pipelines.terrain_view_layout.clone(),
+            water_layout,
+            feature_layouts: TerrainFeatureLayouts::new(device),
+            shader: world.load_asset(WATER_SHADER),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for TerrainWaterPipeline {
+    type Key = TerrainPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = key.flags.shader_defs();
+
+        let mut bind_group_layout = match key.flags.msaa_samples() {
+            1 => vec![self.view_layout.clone()],
+            _ => {
+                shader_defs.push("MULTISAMPLED".into());
+                vec![self.view_layout_multisampled.clone()]
+            }
+        };
+
+        bind_group_layout.push(self.terrain_layout.clone());
+        bind_group_layout.push(self.terrain_view_layout.clone());
+        bind_group_layout.push(self.water_layout.clone());
+        self.feature_layouts
+            .specialize(key.flags, &mut bind_group_layout, &mut shader_defs);
+
+        let mut vertex_shader_defs = shader_defs.clone();
+        vertex_shader_defs.push("VERTEX".into());
+        let mut fragment_shader_defs = shader_defs;
+        fragment_shader_defs.push("FRAGMENT".into());
+
+        RenderPipelineDescriptor {
+            label: Some("terrain_water_pipeline".into()),
+            layout: bind_group_layout,
+            push_constant_ranges: default(),
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs: vertex_shader_defs,
+                buffers: Vec::new(),
+            },
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: key.flags.polygon_mode(),
+                conservative: false,
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+            },
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: fragment_shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            // the water covers the terrain at equal depth (where the water mask places it on the terrain),
+            // regardless of the order in which both are drawn
+            depth_stencil: Some(DepthStencilState {
+                format: TERRAIN_DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: StencilState {
+                    front: StencilFaceState {
+                        compare: CompareFunction::GreaterEqual,
+                        fail_op: StencilOperation::Keep,
+                        depth_fail_op: StencilOperation::Keep,
+                        pass_op: StencilOperation::Replace,
+                    },
+                    back: StencilFaceState::IGNORE,
+                    read_mask: !0,
+                    write_mask: !0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: key.flags.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+pub struct SetWaterBindGroup<const I: usize>;
+
+impl<const I: usize, P: PhaseItem> RenderCommand<P> for SetWaterBindGroup<I> {
+    type Param = SRes<TerrainComponents<GpuWater>>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        _: ROQueryItem<'w, Self::ViewQuery>,
+        _: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        gpu_waters: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(gpu_water) = gpu_waters.into_inner().get(&item.main_entity().id()) else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_bind_group(I, &gpu_water.water_bind_group, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+/// The draw function of the water. It draws the terrain geometry with the water bind group instead
+/// of the material bind group.
+pub(crate) type DrawTerrainWater = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetTerrainBindGroup<1>,
+    SetTerrainViewBindGroup<2>,
+    SetWaterBindGroup<3>,
+    SetTerrainFeatureBindGroups,
+    DrawTerrainCommand,
+);
+
+/// Queues the water of all terrain entities with a [`TerrainWater`].
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn queue_terrain_water(
+    draw_functions: Res<DrawFunctions<TerrainItem>>,
+    debug: Option<Res<DebugTerrain>>,
+    pipeline_cache: Res<PipelineCache>,
+    water_pipeline: Res<TerrainWaterPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TerrainWaterPipeline>>,
+    mut terrain_phases: ResMut<ViewSortedRenderPhases<TerrainItem>>,
+    gpu_waters: Res<TerrainComponents<GpuWater>>,
+    gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
+    gpu_terrain_views: Res<TerrainViewComponents<GpuTerrainView>>,
+    terrain_features: TerrainFeatures,
+    views: Query<(
+        MainEntity,
+        &Msaa,
+        Option<&ShadowFilteringMethod>,
+        Has<RenderViewLightProbes<EnvironmentMapLight>>,
+    )>,
+) {
+    let draw_function = draw_functions.read().get_id::<DrawTerrainWater>().unwrap();
+
+    for (view, msaa, shadow_filter_method, environment_map) in &views {
+        let Some(terrain_phase) = terrain_phases.get_mut(&RetainedViewEntity {
+            main_entity: view.into(),
+            auxiliary_entity: Entity::PLACEHOLDER.into(),
+            subview_index: 0,
+        }) else {
+            continue;
+        };
+
+        for (&terrain, gpu_water) in gpu_waters.iter() {
+            let (Some(gpu_tile_atlas), Some(gpu_terrain_view)) = (
+                gpu_tile_atlases.get(&terrain),
+                gpu_terrain_views.get(&(terrain, view)),
+            ) else {
+                continue;
+            };
+
+            let mut flags = TerrainPipelineFlags::main_pass(
+                msaa,
+                shadow_filter_method,
+                environment_map,
+                gpu_tile_atlas.is_spherical,
+                debug.as_deref(),
+            ) | terrain_features.flags(terrain);
+            if gpu_water.water_mask {
+                flags |= TerrainPipelineFlags::WATER_MASK;
+            }
+
+            let key = TerrainPipelineKey { flags };
+
+            let pipeline = pipelines.specialize(&pipeline_cache, &water_pipeline, key);
+
+            terrain_phase.add(TerrainItem {
+                representative_entity: (terrain, terrain.into()), // technically wrong
+                draw_function,
+                pipeline,
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::None,
+                order: gpu_terrain_view.order,
+            })
+        }
+    }
+}
+
+/// Renders the water of all terrains with a [`TerrainWater`].
+/// This plugin has to be added after the [`TerrainPlugin`](crate::plugin::TerrainPlugin).
+pub struct TerrainWaterPlugin;
+
+impl Plugin for TerrainWaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TerrainWater>();
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainComponents<GpuWater>>()
+            .init_resource::<SpecializedRenderPipelines<TerrainWaterPipeline>>()
+            .add_render_command::<TerrainItem, DrawTerrainWater>()
+            .add_systems(ExtractSchedule, GpuWater::extract)
+            .add_systems(
+                Render,
+                (
+                    GpuWater::prepare.in_set(RenderSet::Prepare),
+                    queue_terrain_water.in_set(RenderSet::QueueMeshes),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<TerrainWaterPipeline>();
+    }
+}